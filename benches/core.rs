@@ -0,0 +1,79 @@
+// benchmarks for the emulator's hot paths; run with `cargo bench`
+
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    Criterion
+};
+
+use chip8_rust::config::Config;
+use chip8_rust::cpu::CPU;
+use chip8_rust::framebuffer::Framebuffer;
+use chip8_rust::headless::{
+    Headless,
+    Silence
+};
+
+fn assemble(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+fn run_frames(rom: &[u8], frames: usize) -> CPU {
+    let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), Config::default());
+    cpu.load_rom(rom).unwrap();
+    cpu.run_loop().unwrap();
+    cpu
+}
+
+// register arithmetic only, so this measures fetch, decode and dispatch
+fn dispatch(c: &mut Criterion) {
+    let rom = assemble(&[
+        0x7001, 0x8014, 0x8125, 0x8206, 0x830e, 0x4000, 0x3000, 0x1200
+    ]);
+    c.bench_function("dispatch 60 frames of ALU ops", |b| b.iter(|| run_frames(black_box(&rom), 60)));
+}
+
+// a 15 row sprite, drawn fully on screen and wrapping around the corner
+fn draw(c: &mut Criterion) {
+    let sprite = [0xa5u8; 15];
+    let mut fb = Framebuffer::new();
+    c.bench_function("draw 8x15 sprite", |b| b.iter(|| fb.draw(black_box(&sprite), 10, 10)));
+    c.bench_function("draw 8x15 sprite wrapping", |b| b.iter(|| fb.draw(black_box(&sprite), 60, 28)));
+}
+
+// something shaped like a game's main loop: clear, draw a few sprites
+// at moving positions, poll a key and the delay timer
+fn game_frame(c: &mut Criterion) {
+    let rom = assemble(&[
+        0x00e0,         // 200: clear
+        0x6a00,         // 202: VA = 0 (digit)
+        0xfa29,         // 204: I = sprite for VA
+        0xd125,         // 206: draw at (V1, V2)
+        0x7108,         // 208: V1 += 8
+        0x7a01,         // 20a: VA += 1
+        0x3a08,         // 20c: done 8 digits?
+        0x1204,         // 20e: no, next one
+        0x7201,         // 210: V2 += 1
+        0xe59e,         // 212: skip if key V5 down
+        0xf007,         // 214: V0 = DT
+        0x6303,         // 216: V3 = 3
+        0xf315,         // 218: DT = V3
+        0x1200          // 21a: again
+    ]);
+    c.bench_function("game loop 60 frames", |b| b.iter(|| run_frames(black_box(&rom), 60)));
+}
+
+// nothing but 15 row sprites, as in a ROM that redraws everything each frame
+fn sprite_heavy(c: &mut Criterion) {
+    let rom = assemble(&[
+        0xa300,         // 200: I = 300
+        0xd12f,         // 202: draw 15 rows at (V1, V2)
+        0x7103,         // 204: V1 += 3
+        0x1202          // 206: again
+    ]);
+    c.bench_function("sprite-heavy 60 frames", |b| b.iter(|| run_frames(black_box(&rom), 60)));
+}
+
+criterion_group!(benches, dispatch, draw, game_frame, sprite_heavy);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+#![no_main]
+
+// loads arbitrary bytes as a ROM and runs them headless for a few frames;
+// CPU errors are fine, panics (eg. out of bounds RAM accesses) are not
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8_rust::config::Config;
+use chip8_rust::cpu::CPU;
+use chip8_rust::headless::{
+    Headless,
+    Silence
+};
+
+const FRAMES: usize = 30;
+
+fuzz_target!(|rom: &[u8]| {
+    let mut cpu = CPU::new(Box::new(Headless::new(FRAMES)), Box::new(Silence), Config::default());
+    if cpu.load_rom(rom).is_ok() {
+        let _ = cpu.run_loop();
+    }
+});
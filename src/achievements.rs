@@ -0,0 +1,237 @@
+// lightweight RetroAchievements-style tracking: a file for a ROM lists
+// named conditions on its RAM and registers, and the first time one holds,
+// it's announced on screen and written to a log with the time, so it isn't
+// announced again on later runs. one achievement per line:
+//
+//   Centurion: RAM[0x3a0] >= 100
+//   Perfect: RAM[0x3a0] == 255 && V3 == 0
+//
+// comparisons are ==, !=, <, <=, > and >=, numbers are decimal or 0x hex,
+// and # starts a comment
+
+use std::{
+    collections::BTreeSet,
+    fs::{
+        self,
+        OpenOptions
+    },
+    io::Write,
+    path::PathBuf,
+    time::{
+        SystemTime,
+        UNIX_EPOCH
+    }
+};
+
+use crate::cpu::CPU;
+use crate::hooks::Hooks;
+use crate::i18n::Text;
+
+// how long an announcement stays on screen: 3 seconds of 480 Hz frames
+const MESSAGE_FRAMES: u32 = 3 * 480;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Ram(usize),
+    Register(usize),
+    Number(u32)
+}
+
+impl Operand {
+    fn value(&self, cpu: &CPU) -> u32 {
+        match *self {
+            Operand::Ram(addr) => cpu.ram().get(addr).copied().unwrap_or(0) as u32,
+            Operand::Register(x) => cpu.v()[x] as u32,
+            Operand::Number(n) => n
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok()
+    }
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let upper = text.to_ascii_uppercase();
+    if let Some(addr) = upper.strip_prefix("RAM[").and_then(|rest| rest.strip_suffix(']')) {
+        return parse_number(&addr.to_ascii_lowercase()).map(|a| Operand::Ram(a as usize));
+    }
+    if let Some(x) = upper.strip_prefix('V') {
+        if x.len() == 1 {
+            return usize::from_str_radix(x, 16).ok().map(Operand::Register);
+        }
+    }
+    parse_number(&text.to_ascii_lowercase()).map(Operand::Number)
+}
+
+// a comparison: left operand, operator, right operand
+type Clause = (Operand, &'static str, Operand);
+
+fn holds(clause: &Clause, cpu: &CPU) -> bool {
+    let (a, b) = (clause.0.value(cpu), clause.2.value(cpu));
+    match clause.1 {
+        "==" => a == b,
+        "!=" => a != b,
+        "<=" => a <= b,
+        ">=" => a >= b,
+        "<" => a < b,
+        _ => a > b
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Achievement {
+    pub name: String,
+    // all of these have to hold at once
+    clauses: Vec<Clause>
+}
+
+impl Achievement {
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        self.clauses.iter().all(|c| holds(c, cpu))
+    }
+}
+
+fn parse_clause(text: &str) -> Option<Clause> {
+    // two-character operators first, so `<=` isn't read as `<`
+    for op in ["==", "!=", "<=", ">=", "<", ">"] {
+        if let Some((left, right)) = text.split_once(op) {
+            return Some((parse_operand(left.trim())?, op, parse_operand(right.trim())?));
+        }
+    }
+    None
+}
+
+pub fn parse_achievements(text: &str) -> Result<Vec<Achievement>, String> {
+    let mut achievements = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad = || format!("line {}: expected `<name>: <condition>`, eg. `Centurion: RAM[0x3a0] >= 100`, got `{}`", n + 1, line);
+        let (name, condition) = line.split_once(':').ok_or_else(bad)?;
+        let clauses = condition.split("&&").map(|c| parse_clause(c.trim())).collect::<Option<Vec<_>>>().ok_or_else(bad)?;
+        if name.trim().is_empty() {
+            return Err(bad());
+        }
+        achievements.push(Achievement { name: String::from(name.trim()), clauses });
+    }
+    Ok(achievements)
+}
+
+pub struct AchievementWatcher {
+    achievements: Vec<Achievement>,
+    // names already earned, on this run or an earlier one
+    unlocked: BTreeSet<String>,
+    // `<unix time> <name>` for every achievement earned
+    log: Option<PathBuf>,
+    message_left: u32
+}
+
+impl AchievementWatcher {
+    // watch for achievements, reading and appending to log if given
+    pub fn new(achievements: Vec<Achievement>, log: Option<PathBuf>) -> AchievementWatcher {
+        let mut unlocked = BTreeSet::new();
+        if let Some(text) = log.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+            for line in text.lines() {
+                if let Some((_, name)) = line.split_once(' ') {
+                    unlocked.insert(String::from(name));
+                }
+            }
+        }
+        AchievementWatcher { achievements, unlocked, log, message_left: 0 }
+    }
+
+    pub fn unlocked(&self) -> &BTreeSet<String> {
+        &self.unlocked
+    }
+
+    fn unlock(&mut self, cpu: &mut CPU, name: String) -> Result<(), String> {
+        println!("Achievement unlocked: {}", name);
+        if let Some(path) = &self.log {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| writeln!(file, "{} {}", timestamp, name))
+                .map_err(|why| format!("Could not record achievement in {}: {}", path.display(), why))?;
+        }
+        cpu.set_overlay(vec![String::from(cpu.config().language.text(Text::Achievement)), name.clone()]);
+        self.message_left = MESSAGE_FRAMES;
+        self.unlocked.insert(name);
+        Ok(())
+    }
+}
+
+impl Hooks for AchievementWatcher {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if self.message_left > 0 {
+            self.message_left -= 1;
+            if self.message_left == 0 {
+                cpu.set_overlay(Vec::new());
+            }
+        }
+        let earned: Vec<String> = self.achievements.iter()
+            .filter(|a| !self.unlocked.contains(&a.name) && a.holds(cpu))
+            .map(|a| a.name.clone())
+            .collect();
+        for name in earned {
+            self.unlock(cpu, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn parses_conditions() {
+        let parsed = parse_achievements("# lives\nCenturion: RAM[0x3A0] >= 100\nPerfect: ram[930] == 0xff && vE != 0\n").unwrap();
+        assert_eq!(parsed[0].clauses, [(Operand::Ram(0x3a0), ">=", Operand::Number(100))]);
+        assert_eq!(parsed[1].clauses, [
+            (Operand::Ram(930), "==", Operand::Number(0xff)),
+            (Operand::Register(0xe), "!=", Operand::Number(0))
+        ]);
+        assert!(parse_achievements("RAM[0x3a0] >= 100").is_err());
+        assert!(parse_achievements("x: RAM[0x3a0] => 100").is_err());
+    }
+
+    #[test]
+    fn unlocks_once_and_remembers() {
+        let log = env::temp_dir().join(format!("chip8-achievements-test-{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let achievements = parse_achievements("Centurion: RAM[0x3a0] >= 100").unwrap();
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+
+        let mut watcher = AchievementWatcher::new(achievements.clone(), Some(log.clone()));
+        watcher.on_frame(&mut cpu).unwrap();
+        assert!(watcher.unlocked().is_empty());
+        cpu.set_ram(0x3a0, 100);
+        watcher.on_frame(&mut cpu).unwrap();
+        watcher.on_frame(&mut cpu).unwrap();
+        assert_eq!(cpu.overlay(), ["ACHIEVEMENT!", "Centurion"]);
+        for _ in 0..MESSAGE_FRAMES {
+            watcher.on_frame(&mut cpu).unwrap();
+        }
+        assert!(cpu.overlay().is_empty());
+
+        let text = fs::read_to_string(&log).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        assert!(text.ends_with(" Centurion\n"));
+        // a later run knows it's been earned already
+        let watcher = AchievementWatcher::new(achievements, Some(log.clone()));
+        assert!(watcher.unlocked().contains("Centurion"));
+        fs::remove_file(&log).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+// a lightweight "what is it doing" view: the instruction about to run and
+// the one after it, disassembled in the bottom corner of the screen, best
+// with the CPU in slow motion (see CPU::set_slow_motion)
+
+use crate::cpu::CPU;
+use crate::disasm::mnemonic;
+use crate::hooks::Hooks;
+
+// the overlay's 5 lines, the last 2 of them ours
+const BLANK_LINES: usize = 3;
+
+#[derive(Default)]
+pub struct Annotator {
+    // what we last showed, so we know the overlay is still ours
+    shown: Vec<String>
+}
+
+impl Annotator {
+    pub fn new() -> Annotator {
+        Annotator::default()
+    }
+
+    // eg. ">200 LD V0, 0x05" for the instruction at addr
+    fn line(cpu: &CPU, addr: usize, marker: char) -> String {
+        let ram = cpu.ram();
+        match (ram.get(addr), ram.get(addr + 1)) {
+            (Some(&hi), Some(&lo)) => {
+                let op = u16::from_be_bytes([hi, lo]);
+                let text = mnemonic(op).unwrap_or_else(|| format!("{:04X}", op));
+                format!("{}{:03X} {}", marker, addr, text)
+            },
+            _ => String::new()
+        }
+    }
+}
+
+impl Hooks for Annotator {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        // leave the screen to anything else showing text, eg. the menu
+        if !cpu.overlay().is_empty() && cpu.overlay() != self.shown.as_slice() {
+            return Ok(());
+        }
+        let mut lines = vec![String::new(); BLANK_LINES];
+        lines.push(Annotator::line(cpu, cpu.pc(), '>'));
+        lines.push(Annotator::line(cpu, cpu.pc() + 2, ' '));
+        if lines != self.shown {
+            cpu.set_overlay(lines.clone());
+            self.shown = lines;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn shows_the_next_instructions() {
+        let mut cpu = CPU::new(Box::new(Headless::new(1)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0x60, 0x05, 0x12, 0x02]).unwrap();
+        cpu.add_hooks(Box::new(Annotator::new()));
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.overlay()[3..], [">202 JP 0x202", " 204 0000"]);
+
+        // other text isn't drawn over
+        cpu.set_overlay(vec![String::from("PAUSED")]);
+        let mut annotator = Annotator::new();
+        annotator.on_frame(&mut cpu).unwrap();
+        assert_eq!(cpu.overlay(), ["PAUSED"]);
+    }
+}
@@ -0,0 +1,823 @@
+// the built-in assembler: turns source into ROM bytes loaded at 0x200
+//
+// there are two front ends sharing one back end: `assemble` reads the
+// Cowgod-style syntax the disassembler writes (`LD V0, 0x05`, `label:`),
+// `assemble_octo` reads a subset of Octo (https://github.com/JohnEarnest/Octo)
+
+use std::collections::HashMap;
+
+use crate::disasm::ENTRY_POINT;
+
+// an address operand: known now, or a label that may be defined later
+#[derive(Clone)]
+enum Target {
+    Addr(u16),
+    Label(String)
+}
+
+// bytes emitted so far plus what's needed to fill in label addresses
+struct Program {
+    bytes: Vec<u8>,
+    labels: HashMap<String, u16>,
+    // (offset of an instruction, label its low 12 bits should point to,
+    // line, or whether it's the whole 16-bit address after an F000)
+    fixups: Vec<(usize, String, usize, bool)>
+}
+
+impl Program {
+    fn new() -> Program {
+        Program { bytes: Vec::new(), labels: HashMap::new(), fixups: Vec::new() }
+    }
+
+    // address the next byte will be loaded at
+    fn here(&self) -> u16 {
+        (ENTRY_POINT + self.bytes.len()) as u16
+    }
+
+    fn define(&mut self, name: &str, line: usize) -> Result<(), String> {
+        if self.labels.insert(String::from(name), self.here()).is_some() {
+            return Err(format!("line {}: label `{}` defined twice", line, name));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.bytes.push(b);
+    }
+
+    fn op(&mut self, op: u16) {
+        self.bytes.extend_from_slice(&op.to_be_bytes());
+    }
+
+    // emit prefix | addr, resolving the address later if it's a label
+    fn addr_op(&mut self, prefix: u16, target: Target, line: usize) {
+        match target {
+            Target::Addr(a) => self.op(prefix | (a & 0xfff)),
+            Target::Label(name) => {
+                self.fixups.push((self.bytes.len(), name, line, false));
+                self.op(prefix);
+            }
+        }
+    }
+
+    // XO-CHIP's F000, followed by the whole address to load I with
+    fn long_load(&mut self, target: Target, line: usize) {
+        self.op(0xf000);
+        match target {
+            Target::Addr(a) => self.op(a),
+            Target::Label(name) => {
+                self.fixups.push((self.bytes.len(), name, line, true));
+                self.op(0);
+            }
+        }
+    }
+
+    // point the instruction at offset to addr
+    fn patch(&mut self, offset: usize, addr: u16) {
+        self.bytes[offset] = (self.bytes[offset] & 0xf0) | ((addr >> 8) & 0xf) as u8;
+        self.bytes[offset + 1] = addr as u8;
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        for (offset, name, line, long) in std::mem::take(&mut self.fixups) {
+            match self.labels.get(&name) {
+                Some(addr) if long => self.bytes[offset..offset + 2].copy_from_slice(&addr.to_be_bytes()),
+                Some(addr) => { let addr = *addr; self.patch(offset, addr); },
+                None => return Err(format!("line {}: unknown label `{}`", line, name))
+            }
+        }
+        Ok(self.bytes)
+    }
+}
+
+// decimal, 0x hex or 0b binary; negative numbers wrap like Octo does
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text)
+    };
+    let lower = digits.to_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    }
+    else if let Some(bin) = lower.strip_prefix("0b") {
+        i32::from_str_radix(bin, 2).ok()?
+    }
+    else {
+        lower.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        _ => false
+    }
+}
+
+fn byte_value(value: i32, line: usize) -> Result<u16, String> {
+    if (-128..=255).contains(&value) {
+        Ok((value as u16) & 0xff)
+    }
+    else {
+        Err(format!("line {}: {} does not fit in a byte", line, value))
+    }
+}
+
+// Cowgod syntax
+
+#[derive(Clone, Copy, PartialEq)]
+enum Special {
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    Pitch,
+    B,
+    R
+}
+
+enum Operand {
+    Reg(u16),
+    Named(Special),
+    Value(Target),
+    // `LONG addr`, XO-CHIP's 16-bit address for F000
+    Long(Target),
+    // `Vx - Vy`, XO-CHIP's register ranges
+    Range(u16, u16)
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let rest = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    if rest.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(rest, 16).ok()
+}
+
+fn parse_operand(text: &str, line: usize) -> Result<Operand, String> {
+    if let Some(r) = parse_register(text) {
+        return Ok(Operand::Reg(r));
+    }
+    let special = match text.to_uppercase().as_str() {
+        "I" => Some(Special::I),
+        "[I]" => Some(Special::IndirectI),
+        "DT" => Some(Special::DT),
+        "ST" => Some(Special::ST),
+        "K" => Some(Special::K),
+        "F" => Some(Special::F),
+        "HF" => Some(Special::HF),
+        "PITCH" => Some(Special::Pitch),
+        "B" => Some(Special::B),
+        "R" => Some(Special::R),
+        _ => None
+    };
+    if let Some(s) = special {
+        return Ok(Operand::Named(s));
+    }
+    if let Some((x, y)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (parse_register(x.trim()), parse_register(y.trim())) {
+            return Ok(Operand::Range(x, y));
+        }
+    }
+    if let Some(rest) = text.get(..5).filter(|word| word.eq_ignore_ascii_case("long ")).map(|_| &text[5..]) {
+        if let Operand::Value(t) = parse_operand(rest.trim(), line)? {
+            return Ok(Operand::Long(t));
+        }
+    }
+    if let Some(n) = parse_number(text) {
+        return Ok(Operand::Value(Target::Addr(n as u16)));
+    }
+    if is_identifier(text) {
+        return Ok(Operand::Value(Target::Label(String::from(text))));
+    }
+    Err(format!("line {}: cannot understand operand `{}`", line, text))
+}
+
+fn value_byte(target: &Target, line: usize) -> Result<u16, String> {
+    match target {
+        Target::Addr(n) => byte_value(*n as i16 as i32, line),
+        Target::Label(name) => Err(format!("line {}: expected a number, got `{}`", line, name))
+    }
+}
+
+// assemble Cowgod-style source, one instruction per line:
+// `[label:] MNEMONIC [operand, ...] [; comment]`, plus `DB`/`DW` for data
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut prog = Program::new();
+
+    for (n, raw) in source.lines().enumerate() {
+        let line = n + 1;
+        let mut text = raw.split(';').next().unwrap().trim();
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if is_identifier(label) {
+                prog.define(label, line)?;
+                text = text[colon + 1..].trim();
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(space) => (&text[..space], text[space..].trim()),
+            None => (text, "")
+        };
+        let mnemonic = mnemonic.to_uppercase();
+
+        if mnemonic == "DB" || mnemonic == "DW" {
+            for item in rest.split(',').map(str::trim) {
+                let value = parse_number(item).ok_or_else(|| format!("line {}: expected a number, got `{}`", line, item))?;
+                if mnemonic == "DB" {
+                    prog.byte(byte_value(value, line)? as u8);
+                }
+                else {
+                    prog.op(value as u16);
+                }
+            }
+            continue;
+        }
+
+        let operands = if rest.is_empty() {
+            Vec::new()
+        }
+        else {
+            rest.split(',').map(|o| parse_operand(o.trim(), line)).collect::<Result<Vec<_>, _>>()?
+        };
+        let bad = || format!("line {}: invalid operands for {}: `{}`", line, mnemonic, rest);
+
+        use Operand::*;
+        match (mnemonic.as_str(), operands.as_slice()) {
+            ("CLS", []) => prog.op(0x00e0),
+            ("RET", []) => prog.op(0x00ee),
+            ("SCD", [Value(Target::Addr(n))]) if *n < 16 => prog.op(0x00c0 | n),
+            ("SCR", []) => prog.op(0x00fb),
+            ("SCL", []) => prog.op(0x00fc),
+            ("EXIT", []) => prog.op(0x00fd),
+            ("LOW", []) => prog.op(0x00fe),
+            ("HIGH", []) => prog.op(0x00ff),
+            ("PLANE", [Value(Target::Addr(n))]) if *n < 4 => prog.op(0xf001 | n << 8),
+            ("AUDIO", []) => prog.op(0xf002),
+            ("SYS", [Value(t)]) => prog.addr_op(0x0000, t.clone(), line),
+            ("JP", [Value(t)]) => prog.addr_op(0x1000, t.clone(), line),
+            ("CALL", [Value(t)]) => prog.addr_op(0x2000, t.clone(), line),
+            ("LD", [Named(Special::I), Value(t)]) => prog.addr_op(0xa000, t.clone(), line),
+            ("LD", [Named(Special::I), Long(t)]) => prog.long_load(t.clone(), line),
+            ("JP", [Reg(0), Value(t)]) => prog.addr_op(0xb000, t.clone(), line),
+            ("SE", [Reg(x), Value(v)]) => prog.op(0x3000 | x << 8 | value_byte(v, line)?),
+            ("SNE", [Reg(x), Value(v)]) => prog.op(0x4000 | x << 8 | value_byte(v, line)?),
+            ("SE", [Reg(x), Reg(y)]) => prog.op(0x5000 | x << 8 | y << 4),
+            ("SNE", [Reg(x), Reg(y)]) => prog.op(0x9000 | x << 8 | y << 4),
+            ("LD", [Reg(x), Value(v)]) => prog.op(0x6000 | x << 8 | value_byte(v, line)?),
+            ("ADD", [Reg(x), Value(v)]) => prog.op(0x7000 | x << 8 | value_byte(v, line)?),
+            ("LD", [Reg(x), Reg(y)]) => prog.op(0x8000 | x << 8 | y << 4),
+            ("OR", [Reg(x), Reg(y)]) => prog.op(0x8001 | x << 8 | y << 4),
+            ("AND", [Reg(x), Reg(y)]) => prog.op(0x8002 | x << 8 | y << 4),
+            ("XOR", [Reg(x), Reg(y)]) => prog.op(0x8003 | x << 8 | y << 4),
+            ("ADD", [Reg(x), Reg(y)]) => prog.op(0x8004 | x << 8 | y << 4),
+            ("SUB", [Reg(x), Reg(y)]) => prog.op(0x8005 | x << 8 | y << 4),
+            ("SHR", [Reg(x)]) => prog.op(0x8006 | x << 8 | x << 4),
+            ("SHR", [Reg(x), Reg(y)]) => prog.op(0x8006 | x << 8 | y << 4),
+            ("SUBN", [Reg(x), Reg(y)]) => prog.op(0x8007 | x << 8 | y << 4),
+            ("SHL", [Reg(x)]) => prog.op(0x800e | x << 8 | x << 4),
+            ("SHL", [Reg(x), Reg(y)]) => prog.op(0x800e | x << 8 | y << 4),
+            ("RND", [Reg(x), Value(v)]) => prog.op(0xc000 | x << 8 | value_byte(v, line)?),
+            ("DRW", [Reg(x), Reg(y), Value(Target::Addr(h))]) if *h < 16 => prog.op(0xd000 | x << 8 | y << 4 | h),
+            ("SKP", [Reg(x)]) => prog.op(0xe09e | x << 8),
+            ("SKNP", [Reg(x)]) => prog.op(0xe0a1 | x << 8),
+            ("LD", [Reg(x), Named(Special::DT)]) => prog.op(0xf007 | x << 8),
+            ("LD", [Reg(x), Named(Special::K)]) => prog.op(0xf00a | x << 8),
+            ("LD", [Named(Special::DT), Reg(x)]) => prog.op(0xf015 | x << 8),
+            ("LD", [Named(Special::ST), Reg(x)]) => prog.op(0xf018 | x << 8),
+            ("ADD", [Named(Special::I), Reg(x)]) => prog.op(0xf01e | x << 8),
+            ("LD", [Named(Special::F), Reg(x)]) => prog.op(0xf029 | x << 8),
+            ("LD", [Named(Special::HF), Reg(x)]) => prog.op(0xf030 | x << 8),
+            ("LD", [Named(Special::Pitch), Reg(x)]) => prog.op(0xf03a | x << 8),
+            ("LD", [Named(Special::B), Reg(x)]) => prog.op(0xf033 | x << 8),
+            ("LD", [Named(Special::IndirectI), Reg(x)]) => prog.op(0xf055 | x << 8),
+            ("LD", [Reg(x), Named(Special::IndirectI)]) => prog.op(0xf065 | x << 8),
+            ("LD", [Named(Special::IndirectI), Range(x, y)]) => prog.op(0x5002 | x << 8 | y << 4),
+            ("LD", [Range(x, y), Named(Special::IndirectI)]) => prog.op(0x5003 | x << 8 | y << 4),
+            ("LD", [Named(Special::R), Reg(x)]) => prog.op(0xf075 | x << 8),
+            ("LD", [Reg(x), Named(Special::R)]) => prog.op(0xf085 | x << 8),
+            _ => return Err(bad())
+        }
+    }
+
+    prog.finish()
+}
+
+// Octo syntax
+
+struct Token<'a> {
+    text: &'a str,
+    line: usize
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (n, raw) in source.lines().enumerate() {
+        let text = raw.split('#').next().unwrap();
+        tokens.extend(text.split_whitespace().map(|t| Token { text: t, line: n + 1 }));
+    }
+    tokens
+}
+
+// open control structures, innermost last
+enum Block {
+    // `if ... begin`, with the offset of the jump past the taken branch
+    If(usize),
+    // `else`, with the offset of the jump past the else branch
+    Else(usize),
+    // `loop`, with its start address and the jumps out of it from `while`
+    Loop(u16, Vec<usize>)
+}
+
+struct Octo<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    prog: Program,
+    consts: HashMap<String, i32>,
+    aliases: HashMap<String, u16>,
+    blocks: Vec<Block>
+}
+
+impl<'a> Octo<'a> {
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos.saturating_sub(1)).map(|t| t.line).unwrap_or(0)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.text)
+            },
+            None => Err(format!("line {}: unexpected end of source", self.line()))
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        let got = self.next()?;
+        if got != word {
+            return Err(format!("line {}: expected `{}`, got `{}`", self.line(), word, got));
+        }
+        Ok(())
+    }
+
+    fn register(&self, text: &str) -> Option<u16> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    fn expect_register(&mut self) -> Result<u16, String> {
+        let text = self.next()?;
+        self.register(text).ok_or_else(|| format!("line {}: expected a register, got `{}`", self.line(), text))
+    }
+
+    fn number(&self, text: &str) -> Option<i32> {
+        parse_number(text).or_else(|| self.consts.get(text).copied())
+    }
+
+    fn expect_byte(&mut self) -> Result<u16, String> {
+        let text = self.next()?;
+        match self.number(text) {
+            Some(n) => byte_value(n, self.line()),
+            None => Err(format!("line {}: expected a number, got `{}`", self.line(), text))
+        }
+    }
+
+    fn expect_target(&mut self) -> Result<Target, String> {
+        let text = self.next()?;
+        if let Some(n) = self.number(text) {
+            return Ok(Target::Addr(n as u16));
+        }
+        if is_identifier(text) {
+            return Ok(Target::Label(String::from(text)));
+        }
+        Err(format!("line {}: expected an address or label, got `{}`", self.line(), text))
+    }
+
+    // parse `vx == n`, `vx != vy`, `vx key`, `vx -key` and return the
+    // instructions that skip the next one when the condition is false,
+    // and when it is true
+    fn condition(&mut self) -> Result<(u16, u16), String> {
+        let x = self.expect_register()?;
+        let cmp = self.next()?;
+        let skips = match cmp {
+            "key" => (0xe0a1 | x << 8, 0xe09e | x << 8),
+            "-key" => (0xe09e | x << 8, 0xe0a1 | x << 8),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (eq, ne) = match self.register(rhs) {
+                    Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+                    None => match self.number(rhs) {
+                        Some(n) => {
+                            let n = byte_value(n, self.line())?;
+                            (0x3000 | x << 8 | n, 0x4000 | x << 8 | n)
+                        },
+                        None => return Err(format!("line {}: cannot compare with `{}`", self.line(), rhs))
+                    }
+                };
+                // `==` holds exactly when the skip-if-equal instruction skips
+                if cmp == "==" { (ne, eq) } else { (eq, ne) }
+            },
+            _ => return Err(format!("line {}: unsupported comparison `{}`", self.line(), cmp))
+        };
+        Ok(skips)
+    }
+
+    // `vx <op> <rhs>`
+    fn assignment(&mut self, x: u16) -> Result<(), String> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let line = self.line();
+        if let Some(y) = self.register(rhs) {
+            let code = match op {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xe,
+                _ => return Err(format!("line {}: unsupported operator `{}`", line, op))
+            };
+            self.prog.op(0x8000 | x << 8 | y << 4 | code);
+            return Ok(());
+        }
+        match (op, rhs) {
+            (":=", "key") => self.prog.op(0xf00a | x << 8),
+            (":=", "delay") => self.prog.op(0xf007 | x << 8),
+            (":=", "random") => {
+                let mask = self.expect_byte()?;
+                self.prog.op(0xc000 | x << 8 | mask);
+            },
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                let n = match self.number(rhs) {
+                    Some(n) => n,
+                    None => return Err(format!("line {}: expected a register or number, got `{}`", line, rhs))
+                };
+                match op {
+                    ":=" => self.prog.op(0x6000 | x << 8 | byte_value(n, line)?),
+                    "+=" => self.prog.op(0x7000 | x << 8 | byte_value(n, line)?),
+                    _ => self.prog.op(0x7000 | x << 8 | byte_value(-n, line)?)
+                }
+            },
+            _ => return Err(format!("line {}: unsupported operator `{}` with `{}`", line, op, rhs))
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, word: &'a str) -> Result<(), String> {
+        let line = self.line();
+        match word {
+            ":" => {
+                let name = self.next()?;
+                self.prog.define(name, line)?;
+            },
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let n = self.number(value).ok_or_else(|| format!("line {}: expected a number, got `{}`", line, value))?;
+                self.consts.insert(String::from(name), n);
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.expect_register()?;
+                self.aliases.insert(String::from(name), reg);
+            },
+            ":byte" => {
+                let b = self.expect_byte()?;
+                self.prog.byte(b as u8);
+            },
+            "clear" => self.prog.op(0x00e0),
+            "scroll-down" => {
+                let n = self.expect_byte()?;
+                if n > 0xf {
+                    return Err(format!("line {}: cannot scroll down {} rows, 15 at most", line, n));
+                }
+                self.prog.op(0x00c0 | n);
+            },
+            "scroll-right" => self.prog.op(0x00fb),
+            "scroll-left" => self.prog.op(0x00fc),
+            "exit" => self.prog.op(0x00fd),
+            "lores" => self.prog.op(0x00fe),
+            "hires" => self.prog.op(0x00ff),
+            "plane" => {
+                let n = self.expect_byte()?;
+                if n > 3 {
+                    return Err(format!("line {}: there is no plane {}, only 0 to 3", line, n));
+                }
+                self.prog.op(0xf001 | n << 8);
+            },
+            "audio" => self.prog.op(0xf002),
+            "return" | ";" => self.prog.op(0x00ee),
+            "jump" => {
+                let t = self.expect_target()?;
+                self.prog.addr_op(0x1000, t, line);
+            },
+            "jump0" => {
+                let t = self.expect_target()?;
+                self.prog.addr_op(0xb000, t, line);
+            },
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let h = self.expect_byte()?;
+                if h > 0xf {
+                    return Err(format!("line {}: sprite height {} is more than 15", line, h));
+                }
+                self.prog.op(0xd000 | x << 8 | y << 4 | h);
+            },
+            "bcd" => { let x = self.expect_register()?; self.prog.op(0xf033 | x << 8); },
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                // `save vx - vy` is XO-CHIP's register range
+                if self.tokens.get(self.pos).map(|t| t.text) == Some("-") {
+                    self.pos += 1;
+                    let y = self.expect_register()?;
+                    self.prog.op(if word == "save" { 0x5002 } else { 0x5003 } | x << 8 | y << 4);
+                }
+                else {
+                    self.prog.op(if word == "save" { 0xf055 } else { 0xf065 } | x << 8);
+                }
+            },
+            "saveflags" => { let x = self.expect_register()?; self.prog.op(0xf075 | x << 8); },
+            "loadflags" => { let x = self.expect_register()?; self.prog.op(0xf085 | x << 8); },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                let op = match word {
+                    "delay" => 0xf015,
+                    "buzzer" => 0xf018,
+                    _ => 0xf03a
+                };
+                self.prog.op(op | x << 8);
+            },
+            "i" => {
+                let op = self.next()?;
+                match op {
+                    ":=" => {
+                        let font = match self.tokens.get(self.pos).map(|t| t.text) {
+                            Some("hex") => Some(0xf029),
+                            Some("bighex") => Some(0xf030),
+                            _ => None
+                        };
+                        if self.tokens.get(self.pos).map(|t| t.text) == Some("long") {
+                            self.pos += 1;
+                            let t = self.expect_target()?;
+                            self.prog.long_load(t, line);
+                        }
+                        else if let Some(font) = font {
+                            self.pos += 1;
+                            let x = self.expect_register()?;
+                            self.prog.op(font | x << 8);
+                        }
+                        else {
+                            let t = self.expect_target()?;
+                            self.prog.addr_op(0xa000, t, line);
+                        }
+                    },
+                    "+=" => {
+                        let x = self.expect_register()?;
+                        self.prog.op(0xf01e | x << 8);
+                    },
+                    _ => return Err(format!("line {}: unsupported operator `{}` on i", line, op))
+                }
+            },
+            "if" => {
+                let (skip_if_false, skip_if_true) = self.condition()?;
+                match self.next()? {
+                    "then" => self.prog.op(skip_if_false),
+                    "begin" => {
+                        // skip the jump past the block when the condition holds
+                        self.prog.op(skip_if_true);
+                        let offset = self.prog.bytes.len();
+                        self.prog.op(0x1000);
+                        self.blocks.push(Block::If(offset));
+                    },
+                    other => return Err(format!("line {}: expected `then` or `begin`, got `{}`", line, other))
+                }
+            },
+            "else" => {
+                match self.blocks.pop() {
+                    Some(Block::If(offset)) => {
+                        let jump = self.prog.bytes.len();
+                        self.prog.op(0x1000);
+                        let here = self.prog.here();
+                        self.prog.patch(offset, here);
+                        self.blocks.push(Block::Else(jump));
+                    },
+                    _ => return Err(format!("line {}: `else` without `if ... begin`", line))
+                }
+            },
+            "end" => {
+                match self.blocks.pop() {
+                    Some(Block::If(offset)) | Some(Block::Else(offset)) => {
+                        let here = self.prog.here();
+                        self.prog.patch(offset, here);
+                    },
+                    _ => return Err(format!("line {}: `end` without `if ... begin`", line))
+                }
+            },
+            "loop" => {
+                let here = self.prog.here();
+                self.blocks.push(Block::Loop(here, Vec::new()));
+            },
+            "while" => {
+                // skip the jump out of the loop while the condition holds
+                let (_, skip_if_true) = self.condition()?;
+                self.prog.op(skip_if_true);
+                let offset = self.prog.bytes.len();
+                self.prog.op(0x1000);
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop(..))) {
+                    Some(Block::Loop(_, breaks)) => breaks.push(offset),
+                    _ => return Err(format!("line {}: `while` outside of a loop", line))
+                }
+            },
+            "again" => {
+                match self.blocks.pop() {
+                    Some(Block::Loop(start, breaks)) => {
+                        self.prog.op(0x1000 | start);
+                        let here = self.prog.here();
+                        for offset in breaks {
+                            self.prog.patch(offset, here);
+                        }
+                    },
+                    _ => return Err(format!("line {}: `again` without `loop`", line))
+                }
+            },
+            _ => {
+                if let Some(x) = self.register(word) {
+                    return self.assignment(x);
+                }
+                if let Some(n) = self.number(word) {
+                    self.prog.byte(byte_value(n, line)? as u8);
+                    return Ok(());
+                }
+                if is_identifier(word) {
+                    // a bare label name calls it
+                    self.prog.addr_op(0x2000, Target::Label(String::from(word)), line);
+                    return Ok(());
+                }
+                return Err(format!("line {}: cannot understand `{}`", line, word));
+            }
+        }
+        Ok(())
+    }
+}
+
+// assemble a subset of Octo: labels (`: name`), `:const`, `:alias`,
+// `:byte`, register arithmetic (`v0 += 5`, `v1 := random 7`), `i := label`,
+// `sprite`, `if ... then`, `if ... begin ... else ... end`,
+// `loop ... while ... again`, calls by bare label name and raw bytes;
+// execution starts at `main`
+pub fn assemble_octo(source: &str) -> Result<Vec<u8>, String> {
+    let mut octo = Octo {
+        tokens: tokenize(source),
+        pos: 0,
+        prog: Program::new(),
+        consts: HashMap::new(),
+        aliases: HashMap::new(),
+        blocks: Vec::new()
+    };
+
+    // like Octo, start with a jump to main unless main comes first
+    let main_first = matches!(octo.tokens.as_slice(), [colon, name, ..] if colon.text == ":" && name.text == "main");
+    if !main_first {
+        octo.prog.addr_op(0x1000, Target::Label(String::from("main")), 1);
+    }
+
+    while octo.pos < octo.tokens.len() {
+        let word = octo.next()?;
+        octo.statement(word)?;
+    }
+    if !octo.blocks.is_empty() {
+        return Err(String::from("unterminated `begin` or `loop` at end of source"));
+    }
+    octo.prog.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn cowgod_syntax() {
+        let rom = assemble("
+            start:  CLS
+                    LD V0, 0x05     ; comment
+                    LD I, sprite
+                    DRW V0, V1, 2
+                    JP start
+            sprite: DB 0xf0, 0x90
+        ").unwrap();
+        assert_eq!(rom, [0x00, 0xe0, 0x60, 0x05, 0xa2, 0x0a, 0xd0, 0x12, 0x12, 0x00, 0xf0, 0x90]);
+    }
+
+    #[test]
+    fn disassembly_round_trips() {
+        let rom = [
+            0x22, 0x0a, 0xa2, 0x12, 0xf0, 0x65, 0x12, 0x02, 0xff, 0xff,
+            0x60, 0x01, 0x8a, 0xb6, 0xe1, 0x9e, 0x00, 0xee, 0xf0, 0x90
+        ];
+        assert_eq!(assemble(&disassemble(&rom)).unwrap(), rom);
+    }
+
+    #[test]
+    fn superchip_round_trips() {
+        let rom = [
+            0x00, 0xff, 0x00, 0xc3, 0x00, 0xfb, 0x00, 0xfc, 0xf2, 0x30,
+            0xd1, 0x20, 0xf3, 0x75, 0xf3, 0x85, 0x00, 0xfe, 0x12, 0x00
+        ];
+        assert_eq!(assemble(&disassemble(&rom)).unwrap(), rom);
+    }
+
+    #[test]
+    fn xochip_round_trips() {
+        let rom = [0xf3, 0x01, 0xd1, 0x25, 0xf1, 0x01, 0xf0, 0x02, 0xf4, 0x3a, 0x5a, 0x32, 0x51, 0x33, 0x12, 0x00];
+        assert_eq!(assemble(&disassemble(&rom)).unwrap(), rom);
+        assert_eq!(assemble_octo(": main plane 3 plane 0 audio").unwrap(), [0xf3, 0x01, 0xf0, 0x01, 0xf0, 0x02]);
+        assert!(assemble_octo(": main plane 4").unwrap_err().contains("no plane 4"));
+        // F000 and its address, skipped over as one
+        let rom = [0x30, 0x01, 0xf0, 0x00, 0x02, 0x08, 0x12, 0x00, 0xff];
+        let listing = disassemble(&rom);
+        assert!(listing.contains("LD I, LONG data_208"), "{}", listing);
+        assert_eq!(assemble(&listing).unwrap(), rom);
+        assert_eq!(assemble_octo(": main i := long main").unwrap(), [0xf0, 0x00, 0x02, 0x00]);
+        assert_eq!(assemble_octo(": main save v3 - v1 load v2 - v5 save v2").unwrap(), [0x53, 0x12, 0x52, 0x53, 0xf2, 0x55]);
+    }
+
+    #[test]
+    fn octo_syntax() {
+        let rom = assemble_octo("
+            : main
+                clear
+                v0 := 5          # a comment
+                v0 += v1
+                i := box
+                sprite v0 v1 2
+                if v0 == 3 then v2 := 1
+                draw
+                jump main
+            : draw
+                return
+            : box 0xf0 0x90
+        ").unwrap();
+        assert_eq!(rom, [
+            0x00, 0xe0,
+            0x60, 0x05,
+            0x80, 0x14,
+            0xa2, 0x14,
+            0xd0, 0x12,
+            0x40, 0x03,
+            0x62, 0x01,
+            0x22, 0x12,
+            0x12, 0x00,
+            0x00, 0xee,
+            0xf0, 0x90
+        ]);
+    }
+
+    #[test]
+    fn octo_control_flow() {
+        let rom = assemble_octo("
+            : wait
+                loop
+                    v3 += 1
+                    while v3 != 10
+                again
+                if v0 key begin
+                    v1 := 1
+                else
+                    v1 := 2
+                end
+                return
+            : main
+                wait
+        ").unwrap();
+        assert_eq!(rom, [
+            0x12, 0x16, // jump main
+            0x73, 0x01, // 202: loop
+            0x43, 0x0a,
+            0x12, 0x0a,
+            0x12, 0x02, // again
+            0xe0, 0x9e, // 20a: if v0 key begin
+            0x12, 0x12,
+            0x61, 0x01,
+            0x12, 0x14, // else
+            0x61, 0x02,
+            0x00, 0xee, // 214: end
+            0x22, 0x02  // 216: main
+        ]);
+    }
+}
@@ -1,10 +1,32 @@
+use std::sync::{
+    Arc,
+    Mutex
+};
+use std::time::Duration;
+
 use rodio::{
+    buffer::SamplesBuffer,
+    OutputStream,
+    OutputStreamHandle,
     Sink,
-    OutputStream
+    Source
+};
+
+use chip8_rust::frontend::Buzzer;
+use chip8_rust::tone::{
+    PATTERN_LEN,
+    Voice
 };
 
+const SAMPLE_RATE: u32 = 44100;
+
 pub struct Audio {
     sink: Sink,
+    voice: Arc<Mutex<Voice>>,
+    // a sink of its own for each digitised sound, since a stopped sink
+    // stays stopped
+    sample: Mutex<Option<Sink>>,
+    handle: OutputStreamHandle,
     _stream: OutputStream
 }
 
@@ -18,17 +40,79 @@ impl Audio {
             Ok(v) => v,
             Err(err) => { return Err(err.to_string()); }
         };
-        sink.append(rodio::source::SineWave::new(440.0));
+        let voice = Arc::new(Mutex::new(Voice::new()));
+        sink.append(VoiceSource(voice.clone()));
         sink.pause();
-        let ret = Audio {sink, _stream: stream};
+        let ret = Audio {sink, voice, sample: Mutex::new(None), handle: stream_handle, _stream: stream};
         Ok(ret)
     }
+}
 
-    pub fn play(&self) {
+impl Buzzer for Audio {
+    fn play(&self) {
         self.sink.play();
     }
 
-    pub fn pause(&self) {
+    fn pause(&self) {
         self.sink.pause();
     }
+
+    fn set_pattern(&self, pattern: [u8; PATTERN_LEN]) {
+        self.voice.lock().unwrap().set_pattern(pattern);
+    }
+
+    fn set_pitch(&self, pitch: u8) {
+        self.voice.lock().unwrap().set_pitch(pitch);
+    }
+
+    fn play_sample(&self, samples: &[u8], rate: u32, looping: bool) {
+        let sink = match Sink::try_new(&self.handle) {
+            Ok(sink) => sink,
+            Err(_) => return
+        };
+        let samples: Vec<f32> = samples.iter().map(|s| (*s as f32 - 128.0) / 128.0).collect();
+        let buffer = SamplesBuffer::new(1, rate.max(1), samples);
+        if looping {
+            sink.append(buffer.repeat_infinite());
+        }
+        else {
+            sink.append(buffer);
+        }
+        // dropping the sink of the last one stops it
+        *self.sample.lock().unwrap() = Some(sink);
+    }
+
+    fn stop_sample(&self) {
+        self.sample.lock().unwrap().take();
+    }
+}
+
+// the voice's samples, shared with the CPU's thread so it can change
+// what's played while it plays
+struct VoiceSource(Arc<Mutex<Voice>>);
+
+impl Iterator for VoiceSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.0.lock().unwrap().next_sample(SAMPLE_RATE))
+    }
+}
+
+impl Source for VoiceSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
 }
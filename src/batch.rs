@@ -0,0 +1,170 @@
+// running a whole library of ROMs headless at once, for a quick check
+// that a change to the emulator didn't break any of them
+
+use std::{
+    panic::{
+        self,
+        AssertUnwindSafe
+    },
+    path::PathBuf,
+    sync::{
+        mpsc,
+        Arc,
+        Mutex
+    },
+    thread,
+    time::Duration
+};
+
+use crate::config::{
+    Config,
+    UnknownOpcodePolicy
+};
+use crate::cpu::{
+    Exit,
+    CPU
+};
+use crate::headless::{
+    Headless,
+    Silence
+};
+
+// how long a ROM may wait on Fx0A before the run gives up on it
+pub const KEY_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    // ran for all the frames asked for
+    Finished,
+    // ended itself with 00FD
+    Exited,
+    // stopped on an Fx0A at this address that no key came for
+    WaitingForInput(usize),
+    // stopped with an error, or took the emulator down with it
+    Crashed(String)
+}
+
+impl Status {
+    // a short word for the report
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Finished => "ok",
+            Status::Exited => "exited",
+            Status::WaitingForInput(_) => "waiting",
+            Status::Crashed(_) => "crashed"
+        }
+    }
+}
+
+// how one ROM's run went
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub status: Status,
+    pub frames: u64,
+    // unrecognized instructions executed, counting repeats
+    pub unknown_opcodes: u64,
+    // Framebuffer::hash of the screen it ended on
+    pub screen: u64
+}
+
+// run one ROM headless for the given number of frames, quietly: unknown
+// instructions are only counted, and steps aren't printed
+pub fn run_rom(rom: &[u8], frames: usize, config: Config) -> Outcome {
+    let config = Config {
+        key_wait_timeout: Some(KEY_WAIT_TIMEOUT),
+        unknown_opcode: UnknownOpcodePolicy::Ignore,
+        log_steps: false,
+        ..config
+    };
+    let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), config);
+    // a panic is a bug in the emulator, but one ROM finding it shouldn't
+    // stop the others from being run
+    let result = match cpu.load_rom(rom) {
+        Ok(()) => match panic::catch_unwind(AssertUnwindSafe(|| cpu.run_loop())) {
+            Ok(result) => result.map_err(|err| err.to_string()),
+            Err(_) => Err(String::from("the emulator panicked"))
+        },
+        Err(err) => Err(err.to_string())
+    };
+    let counters = cpu.counters();
+    let status = match result {
+        Ok(Exit::Closed) => Status::Finished,
+        Ok(Exit::RomExited) => Status::Exited,
+        Ok(Exit::WaitingForInput(pc)) => Status::WaitingForInput(pc),
+        Err(err) => Status::Crashed(err)
+    };
+    Outcome { status, frames: counters.frames, unknown_opcodes: counters.unknown_opcodes, screen: cpu.framebuffer().hash() }
+}
+
+// run every ROM across the given number of threads, reading each with
+// load; outcomes come back in the order of paths
+pub fn run_all<F>(paths: &[PathBuf], frames: usize, config: &Config, threads: usize, load: F) -> Vec<Outcome>
+where
+    F: Fn(&PathBuf) -> Result<Vec<u8>, String> + Send + Sync + 'static
+{
+    let jobs = Arc::new(Mutex::new(paths.iter().cloned().enumerate().collect::<Vec<_>>()));
+    let load = Arc::new(load);
+    let (done_in, done) = mpsc::channel();
+    let workers: Vec<_> = (0..threads.max(1).min(paths.len())).map(|_| {
+        let (jobs, load, done_in, config) = (jobs.clone(), load.clone(), done_in.clone(), *config);
+        thread::spawn(move || loop {
+            let job = jobs.lock().unwrap().pop();
+            let (j, path) = match job {
+                Some(job) => job,
+                None => break
+            };
+            let outcome = match load(&path) {
+                Ok(rom) => run_rom(&rom, frames, config),
+                Err(err) => Outcome { status: Status::Crashed(err), frames: 0, unknown_opcodes: 0, screen: 0 }
+            };
+            if done_in.send((j, outcome)).is_err() {
+                break;
+            }
+        })
+    }).collect();
+    drop(done_in);
+
+    let mut outcomes: Vec<Option<Outcome>> = vec![None; paths.len()];
+    for (j, outcome) in done {
+        outcomes[j] = Some(outcome);
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    outcomes.into_iter().map(|outcome| outcome.expect("every ROM is run")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(program: &[u16]) -> Vec<u8> {
+        program.iter().flat_map(|op| op.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn outcomes_are_reported_per_rom() {
+        let roms = [
+            // draws the font's 0, then loops
+            rom(&[0xa000, 0xd015, 0x1204]),
+            // an unknown instruction, then a jump out of memory
+            rom(&[0xe0a0, 0x1fff]),
+            // waits for a key
+            rom(&[0xf00a]),
+            // ends itself
+            rom(&[0x00fd])
+        ];
+        let paths: Vec<PathBuf> = (0..roms.len()).map(|j| PathBuf::from(j.to_string())).collect();
+        let outcomes = run_all(&paths, 3000, &Config::default(), 2, move |path| {
+            Ok(roms[path.to_str().unwrap().parse::<usize>().unwrap()].clone())
+        });
+
+        assert_eq!(outcomes[0].status, Status::Finished);
+        assert_eq!(outcomes[0].frames, 3000);
+        assert_ne!(outcomes[0].screen, outcomes[1].screen);
+        assert!(matches!(outcomes[1].status, Status::Crashed(_)));
+        assert_eq!(outcomes[1].unknown_opcodes, 1);
+        assert_eq!(outcomes[2].status, Status::WaitingForInput(0x200));
+        assert_eq!(outcomes[3].status, Status::Exited);
+    }
+}
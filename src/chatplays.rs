@@ -0,0 +1,319 @@
+// "chat plays": keypad votes from many people, eg. a Twitch or IRC chat
+// bridged to stdin or a TCP socket, combined into key presses. a vote is a
+// line ending in a key, `5` or `alice 5`, and anything else is chat and
+// ignored. in majority mode the most popular key of each second is
+// pressed, with one vote per named voter; in queue mode every vote is
+// pressed in turn. the tally is shown along the bottom of the screen
+
+use std::{
+    collections::VecDeque,
+    io::{
+        self,
+        BufRead,
+        BufReader
+    },
+    net::{
+        SocketAddr,
+        TcpListener
+    },
+    sync::mpsc::{
+        self,
+        Receiver,
+        Sender
+    },
+    thread
+};
+
+use crate::cpu::CPU;
+use crate::hooks::Hooks;
+
+// frames (at 480 Hz) per round of majority voting
+const VOTE_WINDOW: u32 = 480;
+// how long a key is held, and then left up before the next queued press
+const PRESS_FRAMES: u32 = 80;
+// votes past this many waiting are dropped, so the queue can't fall minutes behind
+const QUEUE_LIMIT: usize = 32;
+// keys listed in the tally
+const TALLY_KEYS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatMode {
+    // press the key with the most votes at the end of each round
+    Majority,
+    // press every voted key in the order the votes came in
+    Queue
+}
+
+impl ChatMode {
+    pub fn from_name(name: &str) -> Option<ChatMode> {
+        match name {
+            "majority" => Some(ChatMode::Majority),
+            "queue" => Some(ChatMode::Queue),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vote {
+    pub voter: Option<String>,
+    pub key: usize
+}
+
+// a chat line as a vote, if it is one
+pub fn parse_vote(line: &str) -> Option<Vote> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (voter, key) = match words.as_slice() {
+        [key] => (None, key),
+        [voter, key] => (Some(voter.trim_end_matches(':')), key),
+        _ => return None
+    };
+    if key.len() != 1 {
+        return None;
+    }
+    let key = usize::from_str_radix(key, 16).ok()?;
+    Some(Vote { voter: voter.map(String::from), key })
+}
+
+fn read_votes<R: BufRead>(reader: R, votes: Sender<Vote>) {
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return
+        };
+        if let Some(vote) = parse_vote(&line) {
+            if votes.send(vote).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+pub struct ChatPlays {
+    mode: ChatMode,
+    votes: Receiver<Vote>,
+    // this round's votes, in majority mode
+    ballots: Vec<Vote>,
+    round_left: u32,
+    // keys still to press, in queue mode
+    queue: VecDeque<usize>,
+    // the key being held and for how many more frames
+    held: Option<(usize, u32)>,
+    // frames until the next queued press
+    gap: u32,
+    shown: Vec<String>
+}
+
+impl ChatPlays {
+    fn new(mode: ChatMode, votes: Receiver<Vote>) -> ChatPlays {
+        ChatPlays {
+            mode,
+            votes,
+            ballots: Vec::new(),
+            round_left: VOTE_WINDOW,
+            queue: VecDeque::new(),
+            held: None,
+            gap: 0,
+            shown: Vec::new()
+        }
+    }
+
+    // votes from the emulator's standard input
+    pub fn from_stdin(mode: ChatMode) -> ChatPlays {
+        let (votes_in, votes) = mpsc::channel();
+        thread::spawn(move || read_votes(io::stdin().lock(), votes_in));
+        ChatPlays::new(mode, votes)
+    }
+
+    // votes from any number of TCP connections to addr; returns the
+    // address actually listened on too
+    pub fn listen(mode: ChatMode, addr: &str) -> Result<(ChatPlays, SocketAddr), String> {
+        let listener = TcpListener::bind(addr).map_err(|why| format!("Could not listen on {}: {}", addr, why))?;
+        let addr = listener.local_addr().map_err(|why| why.to_string())?;
+        let (votes_in, votes) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let votes_in = votes_in.clone();
+                thread::spawn(move || read_votes(BufReader::new(stream), votes_in));
+            }
+        });
+        Ok((ChatPlays::new(mode, votes), addr))
+    }
+
+    fn vote(&mut self, vote: Vote) {
+        match self.mode {
+            ChatMode::Majority => {
+                // named voters can change their mind, but not vote twice
+                let earlier = self.ballots.iter_mut().find(|b| b.voter.is_some() && b.voter == vote.voter);
+                match earlier {
+                    Some(ballot) => ballot.key = vote.key,
+                    None => self.ballots.push(vote)
+                }
+            },
+            ChatMode::Queue => {
+                if self.queue.len() < QUEUE_LIMIT {
+                    self.queue.push_back(vote.key);
+                }
+            }
+        }
+    }
+
+    // (key, votes) for every key voted for this round, most popular first
+    // and the lower key first among equals
+    fn tally(&self) -> Vec<(usize, usize)> {
+        let mut counts = [0; 16];
+        for ballot in &self.ballots {
+            counts[ballot.key] += 1;
+        }
+        let mut tally: Vec<(usize, usize)> = counts.iter().copied().enumerate().filter(|(_, n)| *n > 0).collect();
+        tally.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        tally
+    }
+
+    fn press(&mut self, cpu: &mut CPU, key: usize) {
+        if let Some((held, _)) = self.held {
+            cpu.inject_key(held, false);
+        }
+        cpu.inject_key(key, true);
+        self.held = Some((key, PRESS_FRAMES));
+    }
+
+    fn status(&self) -> String {
+        match self.mode {
+            ChatMode::Majority => self.tally().iter()
+                .take(TALLY_KEYS)
+                .map(|(key, n)| format!("{:X}:{}", key, n))
+                .collect::<Vec<_>>()
+                .join(" "),
+            ChatMode::Queue if self.queue.is_empty() => String::new(),
+            ChatMode::Queue => {
+                let keys: String = self.queue.iter().take(10).map(|k| format!("{:X}", k)).collect();
+                format!("NEXT: {}", keys)
+            }
+        }
+    }
+}
+
+impl Hooks for ChatPlays {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let votes: Vec<Vote> = self.votes.try_iter().collect();
+        for vote in votes {
+            self.vote(vote);
+        }
+
+        if let Some((key, left)) = self.held {
+            if left <= 1 {
+                cpu.inject_key(key, false);
+                self.held = None;
+                self.gap = PRESS_FRAMES;
+            }
+            else {
+                self.held = Some((key, left - 1));
+            }
+        }
+
+        match self.mode {
+            ChatMode::Majority => {
+                self.round_left -= 1;
+                if self.round_left == 0 {
+                    if let Some(&(key, _)) = self.tally().first() {
+                        self.press(cpu, key);
+                    }
+                    self.ballots.clear();
+                    self.round_left = VOTE_WINDOW;
+                }
+            },
+            ChatMode::Queue => {
+                if self.gap > 0 {
+                    self.gap -= 1;
+                }
+                else if self.held.is_none() {
+                    if let Some(key) = self.queue.pop_front() {
+                        self.press(cpu, key);
+                    }
+                }
+            }
+        }
+
+        // along the bottom line, out of the way as far as possible
+        let status = self.status();
+        let lines = if status.is_empty() { Vec::new() } else { vec![String::new(), String::new(), String::new(), String::new(), status] };
+        if lines != self.shown {
+            cpu.set_overlay(lines.clone());
+            self.shown = lines;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    fn cpu() -> CPU {
+        CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default())
+    }
+
+    fn vote(voter: &str, key: usize) -> Vote {
+        Vote { voter: Some(String::from(voter)), key }
+    }
+
+    #[test]
+    fn parses_votes() {
+        assert_eq!(parse_vote("5"), Some(Vote { voter: None, key: 5 }));
+        assert_eq!(parse_vote("alice: a"), Some(vote("alice", 0xa)));
+        assert_eq!(parse_vote("hello there"), None);
+        assert_eq!(parse_vote("alice 12"), None);
+        assert_eq!(parse_vote("gg"), None);
+    }
+
+    #[test]
+    fn majority_presses_the_most_popular_key() {
+        let (votes_in, votes) = mpsc::channel();
+        let mut chat = ChatPlays::new(ChatMode::Majority, votes);
+        let mut cpu = cpu();
+        for v in [vote("a", 1), vote("b", 2), vote("c", 2), vote("a", 3), vote("a", 1)] {
+            votes_in.send(v).unwrap();
+        }
+        votes_in.send(Vote { voter: None, key: 3 }).unwrap();
+        chat.on_frame(&mut cpu).unwrap();
+        // voter a changed their mind twice, so only their last vote counts
+        assert_eq!(chat.tally(), [(2, 2), (1, 1), (3, 1)]);
+        assert_eq!(cpu.overlay()[4], "2:2 1:1 3:1");
+
+        for _ in 1..VOTE_WINDOW {
+            chat.on_frame(&mut cpu).unwrap();
+        }
+        assert!(cpu.injected_keys()[2]);
+        assert!(cpu.overlay().is_empty());
+        for _ in 0..PRESS_FRAMES {
+            chat.on_frame(&mut cpu).unwrap();
+        }
+        assert!(!cpu.injected_keys()[2]);
+    }
+
+    #[test]
+    fn queue_presses_every_key_in_turn() {
+        let (votes_in, votes) = mpsc::channel();
+        let mut chat = ChatPlays::new(ChatMode::Queue, votes);
+        let mut cpu = cpu();
+        votes_in.send(vote("a", 4)).unwrap();
+        votes_in.send(vote("a", 4)).unwrap();
+        votes_in.send(vote("b", 6)).unwrap();
+
+        let mut presses = Vec::new();
+        let mut before = [false; 16];
+        for _ in 0..6 * PRESS_FRAMES {
+            chat.on_frame(&mut cpu).unwrap();
+            let now = *cpu.injected_keys();
+            presses.extend((0..16).filter(|&k| now[k] && !before[k]));
+            before = now;
+        }
+        assert_eq!(presses, [4, 4, 6]);
+    }
+}
@@ -0,0 +1,181 @@
+// the classic "infinite lives" workflow: narrow down where a game keeps a
+// number by searching RAM again and again as it changes, bookmark the
+// addresses that look right, and freeze them to fixed values every frame
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet
+};
+
+// what a search keeps, comparing each address with the last search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "changed" => Some(Filter::Changed),
+            "unchanged" => Some(Filter::Unchanged),
+            "increased" => Some(Filter::Increased),
+            "decreased" => Some(Filter::Decreased),
+            _ => u8::from_str_radix(name.trim_start_matches("0x"), 16).ok().map(Filter::Equal)
+        }
+    }
+
+    fn keeps(&self, before: u8, now: u8) -> bool {
+        match self {
+            Filter::Equal(value) => now == *value,
+            Filter::Changed => now != before,
+            Filter::Unchanged => now == before,
+            Filter::Increased => now > before,
+            Filter::Decreased => now < before
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    // addresses that passed every search since the last reset, and RAM as
+    // it was then; None before the first search
+    candidates: Option<Vec<usize>>,
+    last_ram: Vec<u8>,
+    bookmarks: BTreeSet<usize>,
+    frozen: BTreeMap<usize, u8>
+}
+
+impl Cheats {
+    // keep the candidates that pass filter, and return how many are left;
+    // the first search starts from every address, and with nothing to
+    // compare to yet, changed/increased/... only note what RAM holds now
+    pub fn search(&mut self, ram: &[u8], filter: Filter) -> usize {
+        let first = self.candidates.is_none();
+        let candidates = self.candidates.get_or_insert_with(|| (0..ram.len()).collect());
+        if !first || matches!(filter, Filter::Equal(_)) {
+            let last_ram = &self.last_ram;
+            candidates.retain(|&addr| filter.keeps(last_ram.get(addr).copied().unwrap_or(0), ram[addr]));
+        }
+        self.last_ram = ram.to_vec();
+        candidates.len()
+    }
+
+    // forget the searches so far
+    pub fn reset_search(&mut self) {
+        self.candidates = None;
+        self.last_ram.clear();
+    }
+
+    pub fn candidates(&self) -> Option<&[usize]> {
+        self.candidates.as_deref()
+    }
+
+    pub fn bookmarks(&self) -> &BTreeSet<usize> {
+        &self.bookmarks
+    }
+
+    pub fn bookmark(&mut self, addr: usize) {
+        self.bookmarks.insert(addr);
+    }
+
+    pub fn unbookmark(&mut self, addr: usize) {
+        self.bookmarks.remove(&addr);
+    }
+
+    pub fn frozen(&self) -> &BTreeMap<usize, u8> {
+        &self.frozen
+    }
+
+    pub fn freeze(&mut self, addr: usize, value: u8) {
+        self.frozen.insert(addr, value);
+    }
+
+    pub fn thaw(&mut self, addr: usize) {
+        self.frozen.remove(&addr);
+    }
+
+    // put the frozen values back, whatever the ROM did to them
+    pub fn apply(&self, ram: &mut [u8]) {
+        for (&addr, &value) in &self.frozen {
+            if let Some(byte) = ram.get_mut(addr) {
+                *byte = value;
+            }
+        }
+    }
+}
+
+// parse a cheats file: one `<address> <value>` per line, both in hex, eg.
+// `3a0 09`, to freeze; blank lines and anything after a # are skipped
+pub fn parse_cheats(text: &str) -> Result<BTreeMap<usize, u8>, String> {
+    let mut frozen = BTreeMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let cheat = match fields.as_slice() {
+            [addr, value] => {
+                let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16).ok();
+                let value = u8::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+                addr.zip(value)
+            },
+            _ => None
+        };
+        match cheat {
+            Some((addr, value)) => {
+                frozen.insert(addr, value);
+            },
+            None => {
+                return Err(format!("line {}: expected `<hex address> <hex value>`, got `{}`", n + 1, line));
+            }
+        }
+    }
+    Ok(frozen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_narrow_down() {
+        let mut cheats = Cheats::default();
+        let mut ram = vec![3, 3, 0, 3];
+        assert_eq!(cheats.search(&ram, Filter::Equal(3)), 3);
+        // lose a life
+        ram[1] = 2;
+        ram[3] = 7;
+        assert_eq!(cheats.search(&ram, Filter::Decreased), 1);
+        assert_eq!(cheats.candidates(), Some(&[1][..]));
+        cheats.reset_search();
+        assert_eq!(cheats.candidates(), None);
+        assert_eq!(cheats.search(&ram, Filter::Changed), 4);
+    }
+
+    #[test]
+    fn frozen_values_are_written_back() {
+        let mut cheats = Cheats::default();
+        cheats.freeze(1, 9);
+        cheats.freeze(5000, 1);
+        let mut ram = vec![0; 4];
+        cheats.apply(&mut ram);
+        assert_eq!(ram, [0, 9, 0, 0]);
+        cheats.thaw(1);
+        assert_eq!(cheats.frozen().keys().copied().collect::<Vec<_>>(), [5000]);
+    }
+
+    #[test]
+    fn parses_cheats_files() {
+        let frozen = parse_cheats("# lives\n3a0 09\n\n0x3a1 0xff # energy\n").unwrap();
+        assert_eq!(frozen.into_iter().collect::<Vec<_>>(), [(0x3a0, 9), (0x3a1, 0xff)]);
+        assert!(parse_cheats("3a0").is_err());
+        assert!(parse_cheats("3a0 100").is_err());
+        assert_eq!(Filter::from_name("decreased"), Some(Filter::Decreased));
+        assert_eq!(Filter::from_name("0a"), Some(Filter::Equal(10)));
+    }
+}
@@ -0,0 +1,139 @@
+// CHIP-8X, the VIP's CHIP-8 for its VP-590 colour card and VP-580 second
+// keypad: the screen stays 64x32 and one bit a pixel, but each 8 pixel
+// wide strip of a row has a foreground colour, and the whole screen a
+// background colour
+
+use crate::framebuffer::{
+    HEIGHT,
+    WIDTH
+};
+
+// the colour card's eight colours, by number
+pub const COLOURS: [u32; 8] = [
+    0x000000, // black
+    0xff0000, // red
+    0x0000ff, // blue
+    0xff00ff, // violet
+    0x00ff00, // green
+    0xffff00, // yellow
+    0x00ffff, // aqua
+    0xffffff  // white
+];
+// the background colours 02A0 steps through, in order
+const BACKGROUNDS: [u8; 4] = [2, 0, 4, 1];
+// the foreground until a program colours the screen
+const DEFAULT_FOREGROUND: u8 = 1;
+// how wide the strips are, and how tall a zone Bxy0 colours
+const ZONE_WIDTH: usize = 8;
+const ZONE_HEIGHT: usize = 4;
+const ZONES_ACROSS: usize = WIDTH / ZONE_WIDTH;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColourZones {
+    // index into BACKGROUNDS
+    background: usize,
+    // a colour number for every strip, row by row
+    foreground: [u8; ZONES_ACROSS * HEIGHT]
+}
+
+impl ColourZones {
+    pub fn new() -> ColourZones {
+        ColourZones { background: 0, foreground: [DEFAULT_FOREGROUND; ZONES_ACROSS * HEIGHT] }
+    }
+
+    // 02A0
+    pub fn next_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    // Bxyn: colour the strips from (x & 0xf) to (x & 0xf) + (x >> 4),
+    // wrapping, on n rows from row y, or if n is 0 on the 4 row zones from
+    // (y & 0xf) to (y & 0xf) + (y >> 4)
+    pub fn colour(&mut self, x: u8, y: u8, n: usize, colour: u8) {
+        let columns = (0..=(x >> 4) as usize).map(|j| ((x & 0xf) as usize + j) % ZONES_ACROSS);
+        let rows: Vec<usize> = if n == 0 {
+            (0..=(y >> 4) as usize)
+                .flat_map(|j| {
+                    let top = ((y & 0xf) as usize + j) * ZONE_HEIGHT % HEIGHT;
+                    top..top + ZONE_HEIGHT
+                })
+                .collect()
+        }
+        else {
+            (0..n).map(|j| (y as usize + j) % HEIGHT).collect()
+        };
+        for column in columns {
+            for row in &rows {
+                self.foreground[row * ZONES_ACROSS + column] = colour & 7;
+            }
+        }
+    }
+
+    // the colour of the pixel at (x, y), lit or not, as 0xRRGGBB
+    pub fn rgb(&self, x: usize, y: usize, lit: bool) -> u32 {
+        if lit {
+            COLOURS[self.foreground[(y % HEIGHT) * ZONES_ACROSS + (x % WIDTH) / ZONE_WIDTH] as usize]
+        }
+        else {
+            COLOURS[BACKGROUNDS[self.background] as usize]
+        }
+    }
+
+    // the background, then every strip's colour, a hex digit each, to
+    // keep in a save state
+    pub fn to_hex(&self) -> String {
+        let mut hex = format!("{:x}", self.background);
+        hex.extend(self.foreground.iter().map(|colour| char::from_digit(*colour as u32, 16).unwrap_or('0')));
+        hex
+    }
+
+    pub fn from_hex(hex: &str) -> Option<ColourZones> {
+        let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+        if digits.len() != 1 + ZONES_ACROSS * HEIGHT || digits[0] as usize >= BACKGROUNDS.len() || digits[1..].iter().any(|d| *d > 7) {
+            return None;
+        }
+        let mut zones = ColourZones { background: digits[0] as usize, ..ColourZones::new() };
+        for (colour, digit) in zones.foreground.iter_mut().zip(&digits[1..]) {
+            *colour = *digit as u8;
+        }
+        Some(zones)
+    }
+}
+
+impl Default for ColourZones {
+    fn default() -> ColourZones {
+        ColourZones::new()
+    }
+}
+
+// 5xy1: add the nibbles of Vx and Vy apart, each one wrapping at 8 like
+// the VIP's octal-minded interpreter did
+pub fn add_nibbles(vx: u8, vy: u8) -> u8 {
+    ((vx & 0x77) + (vy & 0x77)) & 0x77
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_and_background() {
+        let mut zones = ColourZones::new();
+        assert_eq!((zones.rgb(0, 0, true), zones.rgb(0, 0, false)), (COLOURS[1], COLOURS[2]));
+        zones.next_background();
+        assert_eq!(zones.rgb(0, 0, false), COLOURS[0]);
+        // strips 1 and 2 of zone rows 0 and 1 (pixel rows 0-7) go green
+        zones.colour(0x11, 0x10, 0, 4);
+        assert_eq!(zones.rgb(8, 7, true), COLOURS[4]);
+        assert_eq!(zones.rgb(23, 0, true), COLOURS[4]);
+        assert_eq!(zones.rgb(24, 0, true), COLOURS[1]);
+        assert_eq!(zones.rgb(8, 8, true), COLOURS[1]);
+        // just row 10 of strip 7, yellow
+        zones.colour(0x07, 10, 1, 5);
+        assert_eq!(zones.rgb(63, 10, true), COLOURS[5]);
+        assert_eq!(zones.rgb(63, 11, true), COLOURS[1]);
+        assert_eq!(add_nibbles(0x35, 0x46), 0x73);
+        assert_eq!(ColourZones::from_hex(&zones.to_hex()), Some(zones));
+        assert_eq!(ColourZones::from_hex("0"), None);
+    }
+}
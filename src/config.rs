@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use crate::cpu::RAM_SIZE;
+use crate::i18n::Language;
+use crate::megachip::MEGA_RAM_SIZE;
+use crate::quirks::Quirks;
+
+// what to do when a ROM writes into the interpreter area (below 0x200),
+// where the font sprites live
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteProtection {
+    // allow the write, like real hardware
+    Off,
+    // allow the write but print a warning with the responsible PC
+    Log,
+    // stop the CPU with an error
+    Reject
+}
+
+impl WriteProtection {
+    pub fn from_name(name: &str) -> Option<WriteProtection> {
+        match name {
+            "off" => Some(WriteProtection::Off),
+            "log" => Some(WriteProtection::Log),
+            "reject" => Some(WriteProtection::Reject),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            WriteProtection::Off => "off",
+            WriteProtection::Log => "log",
+            WriteProtection::Reject => "reject"
+        }
+    }
+}
+
+// what to do when the CPU meets an instruction it does not know
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownOpcodePolicy {
+    // print a warning and move on to the next instruction
+    Warn,
+    // stop the CPU with an error
+    Halt,
+    // silently treat it as a no-op
+    Ignore
+}
+
+impl UnknownOpcodePolicy {
+    pub fn from_name(name: &str) -> Option<UnknownOpcodePolicy> {
+        match name {
+            "warn" => Some(UnknownOpcodePolicy::Warn),
+            "halt" => Some(UnknownOpcodePolicy::Halt),
+            "ignore" => Some(UnknownOpcodePolicy::Ignore),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnknownOpcodePolicy::Warn => "warn",
+            UnknownOpcodePolicy::Halt => "halt",
+            UnknownOpcodePolicy::Ignore => "ignore"
+        }
+    }
+}
+
+// what to do when an instruction reads or writes memory past the end of RAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryPolicy {
+    // carry on reading from address 0
+    Wrap,
+    // stop the CPU with an error
+    Error
+}
+
+impl MemoryPolicy {
+    pub fn from_name(name: &str) -> Option<MemoryPolicy> {
+        match name {
+            "wrap" => Some(MemoryPolicy::Wrap),
+            "error" => Some(MemoryPolicy::Error),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryPolicy::Wrap => "wrap",
+            MemoryPolicy::Error => "error"
+        }
+    }
+}
+
+// where the built-in font sprites live in RAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontLayout {
+    // digit n at 0xn0 - 0xn4
+    Spaced,
+    // digits packed together from 0x000, 5 bytes each, like most interpreters
+    Standard
+}
+
+impl FontLayout {
+    pub fn from_name(name: &str) -> Option<FontLayout> {
+        match name {
+            "spaced" => Some(FontLayout::Spaced),
+            "standard" => Some(FontLayout::Standard),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontLayout::Spaced => "spaced",
+            FontLayout::Standard => "standard"
+        }
+    }
+
+    // distance in bytes between consecutive digits
+    pub fn stride(&self) -> usize {
+        match self {
+            FontLayout::Spaced => 0x10,
+            FontLayout::Standard => 5
+        }
+    }
+
+    // where SUPER-CHIP's big digits (Fx30) start, right after the small ones
+    pub fn big_font_start(&self) -> usize {
+        self.stride() * 16
+    }
+}
+
+// the machine the ROM was written for, where it changes what instructions
+// mean rather than just how they behave (see Quirks for that)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    // CHIP-8, with SUPER-CHIP's and XO-CHIP's additions
+    Chip8,
+    // the VIP's CHIP-8X, for its colour card and second keypad (see chip8x)
+    Chip8X,
+    // CHIP-8 with MegaChip's colour screen and 16MB of memory (see megachip)
+    MegaChip
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "chip8x" => Some(Platform::Chip8X),
+            "megachip" => Some(Platform::MegaChip),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::Chip8X => "chip8x",
+            Platform::MegaChip => "megachip"
+        }
+    }
+
+    // where ROMs are loaded and start running; CHIP-8X's interpreter is
+    // bigger, so its programs start a page later
+    pub fn program_start(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::MegaChip => 0x200,
+            Platform::Chip8X => 0x300
+        }
+    }
+
+    // how many bytes of memory the machine has, where addresses wrap
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::Chip8X => RAM_SIZE,
+            Platform::MegaChip => MEGA_RAM_SIZE
+        }
+    }
+}
+
+// how often the keypad is read from the frontend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeySampling {
+    // once per frame, so every instruction in a frame sees the same keys
+    Frame,
+    // before every instruction
+    Instruction
+}
+
+impl KeySampling {
+    pub fn from_name(name: &str) -> Option<KeySampling> {
+        match name {
+            "frame" => Some(KeySampling::Frame),
+            "instruction" => Some(KeySampling::Instruction),
+            _ => None
+        }
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeySampling::Frame => "frame",
+            KeySampling::Instruction => "instruction"
+        }
+    }
+}
+
+// instructions run for every 60 Hz frame, unless set with --speed: 9 for
+// each of the 8 screen updates in a frame
+pub const DEFAULT_SPEED: u32 = 72;
+// the speeds the speed hotkeys step through, from about what the VIP ran
+// to what Octo demos expect
+const SPEEDS: [u32; 14] = [7, 10, 15, 20, 30, 50, 72, 100, 200, 500, 1000, 2000, 5000, 10000];
+
+// the speed after speed in SPEEDS, or the one before it
+pub fn step_speed(speed: u32, faster: bool) -> u32 {
+    if faster {
+        SPEEDS.iter().copied().find(|s| *s > speed).unwrap_or(speed)
+    }
+    else {
+        SPEEDS.iter().copied().rev().find(|s| *s < speed).unwrap_or(speed)
+    }
+}
+
+// everything that can be configured about how the CPU runs
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub font_protection: WriteProtection,
+    pub unknown_opcode: UnknownOpcodePolicy,
+    pub memory: MemoryPolicy,
+    pub font_layout: FontLayout,
+    pub key_sampling: KeySampling,
+    // instructions run a 60 Hz frame
+    pub speed: u32,
+    // warn about things real interpreters tolerate but that are
+    // most likely bugs in the ROM
+    pub strict: bool,
+    // validate the machine state after every instruction
+    pub check_invariants: bool,
+    // load as much of an oversized ROM as fits instead of refusing it
+    pub truncate_rom: bool,
+    // count every executed instruction by address, for --opcode-stats
+    pub opcode_stats: bool,
+    // remember the address and height of every sprite drawn
+    pub record_sprites: bool,
+    // give up on an Fx0A key wait after this much frontend time
+    pub key_wait_timeout: Option<Duration>,
+    // seed for Cxkk, so runs can be repeated; random if not set
+    pub random_seed: Option<u64>,
+    // pause while the frontend is in the background
+    pub pause_on_focus_loss: bool,
+    // don't run a loop that only waits for the delay timer to reach zero,
+    // just wait for it (see CPU::in_delay_loop)
+    pub skip_delay_loops: bool,
+    // do what the well-known 0nnn machine code routines do instead of
+    // treating the calls as unknown instructions (see native)
+    pub stub_native_calls: bool,
+    // print every instruction as it runs
+    pub log_steps: bool,
+    // what the menu and messages over the screen are in
+    pub language: Language
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            platform: Platform::Chip8,
+            quirks: Quirks::default(),
+            font_protection: WriteProtection::Off,
+            unknown_opcode: UnknownOpcodePolicy::Warn,
+            memory: MemoryPolicy::Wrap,
+            font_layout: FontLayout::Spaced,
+            key_sampling: KeySampling::Frame,
+            speed: DEFAULT_SPEED,
+            strict: false,
+            check_invariants: false,
+            truncate_rom: false,
+            opcode_stats: false,
+            record_sprites: false,
+            key_wait_timeout: None,
+            random_seed: None,
+            pause_on_focus_loss: true,
+            skip_delay_loops: false,
+            stub_native_calls: false,
+            log_steps: true,
+            language: Language::English
+        }
+    }
+}
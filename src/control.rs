@@ -0,0 +1,314 @@
+// a small text protocol for controlling a running emulator from outside
+// (WebSocket, sockets, ...): one command per message or line, and the
+// machine state sent back as JSON
+//
+//   pause | resume | step          stop, restart, or run one instruction
+//   break <addr> | clear <addr>    set or remove a breakpoint (hex address)
+//   key <key> <down|up>            press or release keypad key 0-f
+//   search <value|changed|unchanged|increased|decreased>
+//                                  narrow down the addresses that could
+//                                  hold a number, eg. the lives left
+//   search reset                   start searching from scratch
+//   bookmark <addr> | unbookmark <addr>
+//                                  remember an address, or forget it
+//   freeze <addr> <value> | thaw <addr>
+//                                  keep a byte of RAM at a value (in hex)
+//   reset                          start the ROM over
+//   save state | load state        keep the whole machine, or go back to it
+//   save slot <n> | load slot <n>  the same with numbered slot 1-10, kept
+//                                  next to the ROM between runs
+//   dump [dir]                     write RAM and the registers to files in
+//                                  dir, or the current directory, like F12
+//   palette                        switch to the next screen colours
+//   quirk <name>                   turn a quirk on or off, eg. i-overflow,
+//                                  or by the start of its name, eg. shift
+//   speed <ipf>                    run ipf instructions a frame
+//   quit                           stop the emulator
+//
+// the longer forms `save state <n>`, `load state <n>`, `set speed <ipf>`
+// and `toggle quirk <name>` work too
+
+use std::path::Path;
+
+use crate::cheats::Filter;
+use crate::cpu::CPU;
+use crate::quirks::{
+    Quirk,
+    QUIRKS
+};
+use crate::states::SLOT_COUNT;
+
+// search matches listed in cheats_json
+const CHEAT_MATCHES_SHOWN: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Pause,
+    Resume,
+    Step,
+    Break(usize),
+    Clear(usize),
+    Key(usize, bool),
+    Search(Filter),
+    ResetSearch,
+    Bookmark(usize),
+    Unbookmark(usize),
+    Freeze(usize, u8),
+    Thaw(usize),
+    Reset,
+    SaveState,
+    LoadState,
+    SaveSlot(usize),
+    LoadSlot(usize),
+    Dump(Option<String>),
+    Palette,
+    ToggleQuirk(Quirk),
+    Speed(u32),
+    Quit
+}
+
+fn parse_addr(text: &str) -> Option<usize> {
+    usize::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+}
+
+// the quirk called name, or the only one whose name starts with it
+fn parse_quirk(name: &str) -> Option<Quirk> {
+    let mut starting = QUIRKS.iter().copied().filter(|quirk| quirk.name().starts_with(name));
+    match (Quirk::from_name(name), starting.next(), starting.next()) {
+        (Some(quirk), _, _) | (None, Some(quirk), None) => Some(quirk),
+        _ => None
+    }
+}
+
+fn parse_slot(text: &str) -> Option<usize> {
+    text.parse().ok().filter(|slot| (1..=SLOT_COUNT).contains(slot))
+}
+
+pub fn parse_command(text: &str) -> Result<Command, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let command = match words.as_slice() {
+        ["pause"] => Some(Command::Pause),
+        ["resume"] => Some(Command::Resume),
+        ["step"] => Some(Command::Step),
+        ["break", addr] => parse_addr(addr).map(Command::Break),
+        ["clear", addr] => parse_addr(addr).map(Command::Clear),
+        ["key", key, action] => {
+            let key = usize::from_str_radix(key, 16).ok().filter(|k| *k < 16);
+            match (key, *action) {
+                (Some(k), "down") => Some(Command::Key(k, true)),
+                (Some(k), "up") => Some(Command::Key(k, false)),
+                _ => None
+            }
+        },
+        ["search", "reset"] => Some(Command::ResetSearch),
+        ["search", filter] => Filter::from_name(filter).map(Command::Search),
+        ["bookmark", addr] => parse_addr(addr).map(Command::Bookmark),
+        ["unbookmark", addr] => parse_addr(addr).map(Command::Unbookmark),
+        ["freeze", addr, value] => {
+            let value = u8::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+            parse_addr(addr).zip(value).map(|(addr, value)| Command::Freeze(addr, value))
+        },
+        ["thaw", addr] => parse_addr(addr).map(Command::Thaw),
+        ["reset"] => Some(Command::Reset),
+        ["save", "state"] => Some(Command::SaveState),
+        ["load", "state"] => Some(Command::LoadState),
+        ["save", "slot" | "state", slot] => parse_slot(slot).map(Command::SaveSlot),
+        ["load", "slot" | "state", slot] => parse_slot(slot).map(Command::LoadSlot),
+        ["dump"] => Some(Command::Dump(None)),
+        ["dump", dir] => Some(Command::Dump(Some(String::from(*dir)))),
+        ["palette"] => Some(Command::Palette),
+        ["quirk", name] | ["toggle", "quirk", name] => parse_quirk(name).map(Command::ToggleQuirk),
+        ["speed", ipf] | ["set", "speed", ipf] => ipf.parse().ok().filter(|s| *s > 0).map(Command::Speed),
+        ["quit"] => Some(Command::Quit),
+        _ => None
+    };
+    command.ok_or_else(|| format!("unknown command `{}`", text.trim()))
+}
+
+// carry out a command; an error says why it couldn't be
+pub fn apply(command: Command, cpu: &mut CPU) -> Result<(), String> {
+    match command {
+        Command::Pause => cpu.set_paused(true),
+        Command::Resume => cpu.set_paused(false),
+        Command::Step => cpu.request_step(),
+        Command::Break(addr) => cpu.add_breakpoint(addr),
+        Command::Clear(addr) => cpu.remove_breakpoint(addr),
+        Command::Key(key, down) => cpu.inject_key(key, down),
+        Command::Search(filter) => {
+            cpu.search_ram(filter);
+        },
+        Command::ResetSearch => cpu.cheats_mut().reset_search(),
+        Command::Bookmark(addr) => cpu.cheats_mut().bookmark(addr),
+        Command::Unbookmark(addr) => cpu.cheats_mut().unbookmark(addr),
+        Command::Freeze(addr, value) => cpu.cheats_mut().freeze(addr, value),
+        Command::Thaw(addr) => cpu.cheats_mut().thaw(addr),
+        Command::Reset => cpu.reset(),
+        Command::SaveState => cpu.save_state(),
+        Command::LoadState => return cpu.load_state().map_err(|err| err.localized(cpu.config().language)),
+        Command::SaveSlot(slot) => return cpu.save_slot(slot).map_err(|err| err.localized(cpu.config().language)),
+        Command::LoadSlot(slot) => return cpu.load_slot(slot).map_err(|err| err.localized(cpu.config().language)),
+        Command::Dump(dir) => {
+            cpu.dump_memory(Path::new(dir.as_deref().unwrap_or(".")))?;
+        },
+        Command::Palette => cpu.next_palette(),
+        Command::ToggleQuirk(quirk) => {
+            cpu.toggle_quirk(quirk);
+        },
+        Command::Speed(speed) => cpu.set_speed(speed),
+        Command::Quit => cpu.quit()
+    }
+    Ok(())
+}
+
+// {"type": "state", ...} with whether the CPU is paused, the breakpoints
+// and the registers
+pub fn state_json(cpu: &CPU) -> String {
+    let breakpoints: Vec<String> = cpu.breakpoints().iter().map(|b| b.to_string()).collect();
+    format!(
+        "{{\"type\": \"state\", \"paused\": {}, \"breakpoints\": [{}], \"registers\": {}}}",
+        cpu.paused(), breakpoints.join(", "), cpu.registers_json().trim_end()
+    )
+}
+
+// {"type": "cheats", ...} with how many addresses the search has left
+// (null before the first search) and the first few of them, the bookmarks
+// with their current values, and the frozen addresses with theirs
+pub fn cheats_json(cpu: &CPU) -> String {
+    let cheats = cpu.cheats();
+    let (count, matches) = match cheats.candidates() {
+        Some(candidates) => {
+            let first: Vec<String> = candidates.iter().take(CHEAT_MATCHES_SHOWN).map(|a| a.to_string()).collect();
+            (candidates.len().to_string(), first.join(", "))
+        },
+        None => (String::from("null"), String::new())
+    };
+    let pairs = |values: Vec<(usize, u8)>| {
+        values.iter().map(|(addr, value)| format!("[{}, {}]", addr, value)).collect::<Vec<_>>().join(", ")
+    };
+    let bookmarks = pairs(cheats.bookmarks().iter().map(|&a| (a, cpu.ram().get(a).copied().unwrap_or(0))).collect());
+    let frozen = pairs(cheats.frozen().iter().map(|(&a, &v)| (a, v)).collect());
+    format!(
+        "{{\"type\": \"cheats\", \"count\": {}, \"matches\": [{}], \"bookmarks\": [{}], \"frozen\": [{}]}}",
+        count, matches, bookmarks, frozen
+    )
+}
+
+// {"type": "frame", ...} with the screen as hex, a row at a time, each
+// byte being 8 pixels with the leftmost in the top bit; width and height
+// change when the ROM switches resolution
+pub fn frame_json(cpu: &CPU) -> String {
+    let framebuffer = cpu.framebuffer();
+    let hex: String = framebuffer.pixels()
+        .chunks(8)
+        .map(|bits| bits.iter().fold(0u8, |byte, &on| (byte << 1) | on as u8))
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{{\"type\": \"frame\", \"width\": {}, \"height\": {}, \"pixels\": \"{}\"}}", framebuffer.width(), framebuffer.height(), hex)
+}
+
+// {"type": "error", "message": ...} for a command we couldn't follow
+pub fn error_json(message: &str) -> String {
+    let escaped: String = message.chars().flat_map(|c| match c {
+        '"' | '\\' => vec!['\\', c],
+        c if c.is_control() => vec![' '],
+        c => vec![c]
+    }).collect();
+    format!("{{\"type\": \"error\", \"message\": \"{}\"}}", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_command("pause"), Ok(Command::Pause));
+        assert_eq!(parse_command(" step\n"), Ok(Command::Step));
+        assert_eq!(parse_command("break 2a4"), Ok(Command::Break(0x2a4)));
+        assert_eq!(parse_command("clear 0x200"), Ok(Command::Clear(0x200)));
+        assert_eq!(parse_command("key f down"), Ok(Command::Key(0xf, true)));
+        assert!(parse_command("key 10 down").is_err());
+        assert!(parse_command("jump").is_err());
+        assert_eq!(parse_command("search 03"), Ok(Command::Search(Filter::Equal(3))));
+        assert_eq!(parse_command("search decreased"), Ok(Command::Search(Filter::Decreased)));
+        assert_eq!(parse_command("search reset"), Ok(Command::ResetSearch));
+        assert_eq!(parse_command("freeze 3a0 9"), Ok(Command::Freeze(0x3a0, 9)));
+        assert_eq!(parse_command("thaw 3a0"), Ok(Command::Thaw(0x3a0)));
+        assert!(parse_command("freeze 3a0 100").is_err());
+        assert_eq!(parse_command("save state"), Ok(Command::SaveState));
+        assert_eq!(parse_command("dump"), Ok(Command::Dump(None)));
+        assert_eq!(parse_command("dump /tmp/x"), Ok(Command::Dump(Some(String::from("/tmp/x")))));
+        assert_eq!(parse_command("save slot 3"), Ok(Command::SaveSlot(3)));
+        assert_eq!(parse_command("load slot 10"), Ok(Command::LoadSlot(10)));
+        assert!(parse_command("load slot 0").is_err());
+        assert!(parse_command("save slot 11").is_err());
+        assert_eq!(parse_command("save state 3"), Ok(Command::SaveSlot(3)));
+        assert_eq!(parse_command("load state 3"), Ok(Command::LoadSlot(3)));
+        assert_eq!(parse_command("quirk i-overflow"), Ok(Command::ToggleQuirk(Quirk::IOverflow)));
+        assert!(parse_command("quirk none").is_err());
+        assert_eq!(parse_command("toggle quirk shift"), Ok(Command::ToggleQuirk(Quirk::ShiftVy)));
+        assert_eq!(parse_command("quirk clip"), Ok(Command::ToggleQuirk(Quirk::Clip)));
+        assert!(parse_command("toggle quirk shift-x").is_err());
+        assert_eq!(parse_command("speed 1000"), Ok(Command::Speed(1000)));
+        assert_eq!(parse_command("set speed 2"), Ok(Command::Speed(2)));
+        assert!(parse_command("speed 0").is_err());
+    }
+
+    #[test]
+    fn cheats_as_json() {
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        assert_eq!(cheats_json(&cpu), "{\"type\": \"cheats\", \"count\": null, \"matches\": [], \"bookmarks\": [], \"frozen\": []}");
+        cpu.set_ram(0x300, 0xab);
+        apply(parse_command("search ab").unwrap(), &mut cpu).unwrap();
+        apply(parse_command("bookmark 300").unwrap(), &mut cpu).unwrap();
+        apply(parse_command("freeze 301 1").unwrap(), &mut cpu).unwrap();
+        assert_eq!(cheats_json(&cpu), concat!(
+            "{\"type\": \"cheats\", \"count\": 1, \"matches\": [768], ",
+            "\"bookmarks\": [[768, 171]], \"frozen\": [[769, 1]]}"
+        ));
+    }
+
+    #[test]
+    fn slots() {
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        let err = apply(parse_command("save slot 2").unwrap(), &mut cpu).unwrap_err();
+        assert_eq!(err, "Could not use slot 2: there is nowhere to keep save slots");
+
+        let dir = std::env::temp_dir().join(format!("chip8-states-{}-slots", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        cpu.set_state_dir(dir.clone());
+        apply(parse_command("save slot 2").unwrap(), &mut cpu).unwrap();
+        cpu.set_v(0, 9);
+        apply(parse_command("load slot 2").unwrap(), &mut cpu).unwrap();
+        assert_eq!(cpu.v()[0], 0);
+        assert_eq!(apply(parse_command("load slot 5").unwrap(), &mut cpu), Err(String::from("Slot 5 is empty")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dumps_memory() {
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0x12, 0x34]).unwrap();
+        let dir = std::env::temp_dir().join(format!("chip8-dump-{}-dumps_memory", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        apply(parse_command(&format!("dump {}", dir.display())).unwrap(), &mut cpu).unwrap();
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        let ram = files.iter().find(|path| path.extension().is_some_and(|e| e == "bin")).unwrap();
+        assert_eq!(&std::fs::read(ram).unwrap()[0x200..0x202], &[0x12, 0x34]);
+        assert_eq!(files.len(), 2);
+        assert!(apply(parse_command("dump /nonexistent/dir").unwrap(), &mut cpu).unwrap_err().starts_with("Could not dump memory"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_are_escaped() {
+        assert_eq!(error_json("bad \"x\"\n"), "{\"type\": \"error\", \"message\": \"bad \\\"x\\\" \"}");
+    }
+}
@@ -0,0 +1,237 @@
+// two-player co-op over TCP: both instances run the same ROM in lockstep,
+// and every frame each sends the other the keys held down locally, so both
+// machines see the union of the two keypads on the same frame. the host
+// picks the seed for Cxkk when the guest connects, and time is counted in
+// frames rather than read from the clock, so the two stay identical
+//
+// the guest opens with `chip8-coop 1 <rom hash>`, and the host answers
+// `seed <seed>`, or `error <why>` if the ROMs differ; both in hex, one line
+// each. after that, each frame is four bytes each way: the held keys as a
+// big-endian bitmask, key n in bit n, with CHIP-8X's second keypad in bits
+// 16 to 31
+
+use std::{
+    io::{
+        BufRead,
+        BufReader,
+        Read,
+        Write
+    },
+    net::{
+        TcpListener,
+        TcpStream
+    },
+    time::Duration
+};
+
+use crate::framebuffer::Framebuffer;
+use crate::frontend::{
+    Display,
+    Frontend,
+    Hotkey,
+    Input,
+    Status,
+    REFRESH_INTERVAL
+};
+use crate::util::fnv1a;
+
+const GREETING: &str = "chip8-coop 2";
+
+// a frontend that shares its keypad with a partner's
+pub struct Coop {
+    inner: Box<dyn Frontend>,
+    peer: TcpStream,
+    // what both machines see this frame
+    keys: [bool; 16],
+    second: [bool; 16],
+    frames: u32,
+    connected: bool
+}
+
+fn rom_hash(rom: &[u8]) -> u64 {
+    fnv1a(rom.iter().copied())
+}
+
+fn read_line(stream: &TcpStream) -> Result<String, String> {
+    // a byte at a time, so nothing after the line gets buffered away
+    let mut line = String::new();
+    BufReader::with_capacity(1, stream).read_line(&mut line).map_err(|why| why.to_string())?;
+    Ok(String::from(line.trim_end()))
+}
+
+impl Coop {
+    // wait for a guest on listener; returns the frontend and the seed to
+    // give the CPU
+    pub fn host(listener: &TcpListener, inner: Box<dyn Frontend>, rom: &[u8]) -> Result<(Coop, u64), String> {
+        let (mut peer, _) = listener.accept().map_err(|why| why.to_string())?;
+        let hello = read_line(&peer)?;
+        let fail = |why: std::io::Error| why.to_string();
+        match hello.strip_prefix(GREETING).map(str::trim).map(|h| u64::from_str_radix(h, 16)) {
+            Some(Ok(hash)) if hash == rom_hash(rom) => (),
+            Some(Ok(_)) => {
+                let _ = writeln!(peer, "error the other player is running a different ROM");
+                return Err(String::from("the other player is running a different ROM"));
+            },
+            _ => return Err(format!("unexpected greeting `{}`", hello))
+        }
+        let seed = rand::random::<u64>();
+        writeln!(peer, "seed {:016x}", seed).map_err(fail)?;
+        Ok((Coop::new(inner, peer)?, seed))
+    }
+
+    // connect to a host at addr; returns the frontend and the seed to give
+    // the CPU
+    pub fn join(addr: &str, inner: Box<dyn Frontend>, rom: &[u8]) -> Result<(Coop, u64), String> {
+        let mut peer = TcpStream::connect(addr).map_err(|why| format!("Could not connect to {}: {}", addr, why))?;
+        writeln!(peer, "{} {:016x}", GREETING, rom_hash(rom)).map_err(|why| why.to_string())?;
+        let reply = read_line(&peer)?;
+        if let Some(why) = reply.strip_prefix("error ") {
+            return Err(String::from(why));
+        }
+        let seed = reply.strip_prefix("seed ")
+            .and_then(|s| u64::from_str_radix(s, 16).ok())
+            .ok_or_else(|| format!("unexpected reply `{}`", reply))?;
+        Ok((Coop::new(inner, peer)?, seed))
+    }
+
+    fn new(inner: Box<dyn Frontend>, peer: TcpStream) -> Result<Coop, String> {
+        peer.set_nodelay(true).map_err(|why| why.to_string())?;
+        Ok(Coop { inner, peer, keys: [false; 16], second: [false; 16], frames: 0, connected: true })
+    }
+
+    // send our keys and get theirs, both keypads together
+    fn exchange(&mut self, keys: [bool; 32]) -> std::io::Result<[bool; 32]> {
+        let mask = keys.iter().enumerate().fold(0u32, |mask, (n, &down)| mask | ((down as u32) << n));
+        self.peer.write_all(&mask.to_be_bytes())?;
+        let mut theirs = [0; 4];
+        self.peer.read_exact(&mut theirs)?;
+        let theirs = u32::from_be_bytes(theirs);
+        let mut keys = [false; 32];
+        for (n, key) in keys.iter_mut().enumerate() {
+            *key = theirs & (1 << n) != 0;
+        }
+        Ok(keys)
+    }
+}
+
+impl Display for Coop {
+    fn refresh(&mut self, framebuffer: &Framebuffer) -> Result<(), String> {
+        self.inner.refresh(framebuffer)?;
+        self.frames += 1;
+        if !self.connected {
+            return Ok(());
+        }
+        let mut local = [false; 32];
+        local[..16].copy_from_slice(&self.inner.handle_key_events());
+        local[16..].copy_from_slice(&self.inner.second_keypad());
+        match self.exchange(local) {
+            Ok(remote) => {
+                for n in 0..16 {
+                    self.keys[n] = local[n] || remote[n];
+                    self.second[n] = local[16 + n] || remote[16 + n];
+                }
+            },
+            Err(_) => {
+                println!("The other player disconnected");
+                self.connected = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn elapsed(&self) -> Duration {
+        REFRESH_INTERVAL * self.frames
+    }
+
+    fn set_overlay(&mut self, lines: &[String]) {
+        self.inner.set_overlay(lines);
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.inner.set_status(status);
+    }
+
+    fn next_palette(&mut self) {
+        self.inner.next_palette();
+    }
+}
+
+impl Input for Coop {
+    fn is_open(&self) -> bool {
+        self.connected && self.inner.is_open()
+    }
+
+    fn handle_key_events(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    fn second_keypad(&self) -> [bool; 16] {
+        self.second
+    }
+
+    // the pause menu could pause, reset or rewind only one side
+    fn hotkeys(&self) -> Vec<Hotkey> {
+        self.inner.hotkeys().into_iter().filter(|k| *k == Hotkey::DumpMemory).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::headless::{
+        Headless,
+        KeyEvent
+    };
+
+    fn pressing(key: usize, frame: u32) -> Box<dyn Frontend> {
+        Box::new(Headless::with_script(10, vec![KeyEvent { frame, key, down: true }]))
+    }
+
+    #[test]
+    fn partners_share_keys_and_seed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let host = thread::spawn(move || {
+            let (mut coop, seed) = Coop::host(&listener, pressing(5, 1), b"rom").unwrap();
+            let mut seen = Vec::new();
+            for _ in 0..3 {
+                coop.refresh(&Framebuffer::new()).unwrap();
+                seen.push(coop.handle_key_events());
+            }
+            assert!(coop.second_keypad()[3]);
+            (seed, seen, coop.elapsed())
+        });
+        // key 13 is key 3 of the second keypad
+        let guest = Headless::with_script(10, vec![
+            KeyEvent { frame: 2, key: 0xa, down: true },
+            KeyEvent { frame: 1, key: 0x13, down: true }
+        ]);
+        let (mut coop, seed) = Coop::join(&addr, Box::new(guest), b"rom").unwrap();
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            coop.refresh(&Framebuffer::new()).unwrap();
+            seen.push(coop.handle_key_events());
+        }
+        // the guest's second keypad reaches the host, and not as keypad one
+        assert!(coop.second_keypad()[3]);
+
+        let (host_seed, host_seen, host_elapsed) = host.join().unwrap();
+        assert_eq!(seed, host_seed);
+        assert_eq!(seen, host_seen);
+        assert_eq!(host_elapsed, REFRESH_INTERVAL * 3);
+        let down = |keys: &[bool; 16]| keys.iter().enumerate().filter(|(_, &d)| d).map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(seen.iter().map(down).collect::<Vec<_>>(), [vec![5], vec![5, 0xa], vec![5, 0xa]]);
+    }
+
+    #[test]
+    fn different_roms_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let host = thread::spawn(move || Coop::host(&listener, pressing(0, 0), b"one").map(|_| ()));
+        let joined = Coop::join(&addr, pressing(0, 0), b"two");
+        assert_eq!(joined.err().unwrap(), "the other player is running a different ROM");
+        assert!(host.join().unwrap().is_err());
+    }
+}
@@ -1,17 +1,121 @@
-use minifb::Key;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+    VecDeque
+};
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::time::Duration;
+
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng
+};
 
-use crate::audio::Audio;
-use crate::window::Window;
+use crate::cheats::{
+    Cheats,
+    Filter
+};
+use crate::config::{
+    step_speed,
+    Config,
+    KeySampling,
+    MemoryPolicy,
+    Platform,
+    UnknownOpcodePolicy,
+    WriteProtection
+};
+use crate::control::{
+    apply,
+    parse_command,
+    Command
+};
+use crate::chip8x::{
+    add_nibbles,
+    ColourZones
+};
+use crate::decode::{
+    decode,
+    decode_chip8x,
+    decode_megachip,
+    Instruction::*
+};
+use crate::error::Chip8Error;
+use crate::framebuffer::{
+    Framebuffer,
+    Resolution
+};
+use crate::hooks::{
+    Hooks,
+    Notice
+};
+use crate::i18n::Text;
+use crate::keypad::Keypad;
+use crate::megachip::{
+    font_sprite,
+    sample_header,
+    Blend,
+    MegaScreen,
+    SAMPLE_HEADER_LEN
+};
+use crate::menu::{
+    Menu,
+    MenuItem
+};
+use crate::prompt::Prompt;
+use crate::native::{
+    known_routine,
+    NativeRoutine
+};
+use crate::quirks::Quirk;
+use crate::sprites::sprite_len;
+use crate::states::{
+    slot_path,
+    SaveState
+};
+use crate::steps::{
+    ExecutedOp,
+    Watch
+};
+use crate::dump::write_memory_dump;
+use crate::frontend::{
+    Buzzer,
+    Frontend,
+    Hotkey,
+    Status,
+    REFRESH_INTERVAL
+};
+use crate::trace::{
+    log_info,
+    log_trace,
+    log_warn,
+    span
+};
+use crate::tone::PATTERN_LEN;
 use crate::util::{
-    get_bit,
-    get_hex_digits
+    fnv1a,
+    get_bit
 };
 
-const RAM_SIZE: usize = 4096;
-const REGISTER_COUNT: usize = 16;
-const STACK_SIZE: usize = 16;
-const RUNLOOP_TIMER_DEFAULT: usize = 8;
-const PROGRAM_START: usize = 0x200;
+pub const RAM_SIZE: usize = 4096;
+pub const REGISTER_COUNT: usize = 16;
+pub const STACK_SIZE: usize = 16;
+// screen updates in a 60 Hz frame, the unit speeds are given in
+const UPDATES_PER_FRAME: u32 = 8;
+const TIMER_HZ: u128 = 60;
+// how many recently executed instructions to remember for diagnostics
+const HISTORY_LEN: usize = 32;
+// how long saving or loading a slot is shown: 2 seconds of 480 Hz frames
+const SLOT_NOTICE_FRAMES: u32 = 2 * 480;
+pub const PROGRAM_START: usize = 0x200;
+// ROMs for the COSMAC VIP's 64x64 interpreter start by jumping over the
+// part of it loaded with them (1260), and are run from 2c0
+const TWO_PAGE_ENTRY: [u8; 2] = [0x12, 0x60];
+const TWO_PAGE_START: usize = 0x2c0;
 
 // the ith element of this vector is a vector of bytes
 // representing the numbers in CHIP-8 format
@@ -34,8 +138,76 @@ const RAM_DIGITS: [[u8; 5]; 16] = [
     [0xf0, 0x80, 0xf0, 0x80, 0x80]
 ];
 
+// SUPER-CHIP's 8x10 digits for Fx30, kept after the small ones; SUPER-CHIP
+// 1.1 only had 0-9, A-F are Octo's
+const RAM_BIG_DIGITS: [[u8; 10]; 16] = [
+    [0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff],
+    [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff],
+    [0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff],
+    [0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff],
+    [0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03],
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff],
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff],
+    [0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18],
+    [0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff],
+    [0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff],
+    [0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3],
+    [0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc],
+    [0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c],
+    [0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc],
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff],
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0]
+];
+// MegaChip's megaon, which looks like a call to machine code at 011
+// unless the platform is MegaChip
+const MEGACHIP_ON: u16 = 0x0011;
+// how many registers Fx75 and Fx85 keep, like the HP48's RPL user flags
+pub const FLAG_COUNT: usize = 8;
+
+// the registers from Vx to Vy, counting down if x > y (XO-CHIP's 5xy2
+// and 5xy3)
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() }
+}
+
+// why run_loop stopped without an error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    // the frontend was closed
+    Closed,
+    // an Fx0A at this address waited longer than the configured timeout
+    WaitingForInput(usize),
+    // the ROM ended itself with 00FD
+    RomExited
+}
+
+// what CPU::step did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    // ran an instruction
+    Ran,
+    // an Fx0A is waiting for a key, so nothing ran
+    WaitingForKey,
+    // the run is over
+    Halted(Exit)
+}
+
+// running totals since the CPU was created, eg. for monitoring
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+    pub instructions: u64,
+    pub frames: u64,
+    pub draws: u64,
+    pub unknown_opcodes: u64,
+    // None if the audio backend can't tell
+    pub audio_underruns: Option<u64>
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     ram: [u8; RAM_SIZE],
+    // MegaChip's memory past the first 4K, empty on other platforms
+    high_ram: Vec<u8>,
     v: [u8; REGISTER_COUNT],
     i: usize,
     dt: u8,
@@ -43,14 +215,164 @@ pub struct CPU {
     stack: [usize; STACK_SIZE],
     sp: usize,
     pc: usize,
-    win: Window,
-    audio: Audio
+    framebuffer: Framebuffer,
+    keypad: Keypad,
+    // registers saved by Fx75, outliving resets
+    flags: [u8; FLAG_COUNT],
+    // (address, instruction) of the last few instructions executed
+    history: VecDeque<(usize, u16)>,
+    // how often each unrecognized (address, instruction) was met
+    unknown_opcodes: BTreeMap<(usize, u16), u32>,
+    // how often each (address, 0nnn) call into machine code was met
+    native_calls: BTreeMap<(usize, u16), u32>,
+    // how often each (address, instruction) ran, if config.opcode_stats is set
+    executed: BTreeMap<(usize, u16), u64>,
+    // (I, n) of every Dxyn sprite drawn, if config.record_sprites is set
+    sprites_drawn: BTreeSet<(usize, usize)>,
+    // keys held down by something other than the frontend, eg. a script
+    injected_keys: [bool; 16],
+    // text shown over the screen
+    overlay: Vec<String>,
+    // stop executing instructions and ticking timers, but keep showing frames
+    paused: bool,
+    // stop before executing an instruction at any of these addresses
+    breakpoints: BTreeSet<usize>,
+    // run one instruction even though paused
+    step_requested: bool,
+    // resumed at this address, so don't stop at a breakpoint there again
+    resumed_at: Option<usize>,
+    // paused because the frontend lost focus, so resume when it's back
+    paused_for_focus: bool,
+    // the pause menu, while it's open
+    menu: Option<Menu>,
+    // the command palette, while it's open, and whether to resume once
+    // it's closed
+    prompt: Option<Prompt>,
+    resume_after_prompt: bool,
+    // run an instruction only every this many loop iterations, and how
+    // many are left until the next one
+    slow_motion: u32,
+    slow_motion_wait: u32,
+    // what the pause menu's save state kept
+    saved_state: Option<Box<SaveState>>,
+    // where the numbered save slots are kept, if anywhere
+    state_dir: Option<PathBuf>,
+    // the ROM as loaded, to start it over with
+    rom: Vec<u8>,
+    // stop running, eg. chosen from the pause menu
+    quit: bool,
+    // note what each instruction changes, for iter_steps, and what the
+    // last one did
+    watch_steps: bool,
+    last_step: Option<ExecutedOp>,
+    // reset or restored since the last frame, so any key wait is over
+    restarted: bool,
+    // where the run loop is, kept between calls to step
+    run_state: LoopState,
+    hooks: Vec<Box<dyn Hooks>>,
+    counters: Counters,
+    // source of Cxkk's random numbers
+    rng: StdRng,
+    // RAM searches, bookmarks and frozen addresses
+    cheats: Cheats,
+    win: Box<dyn Frontend>,
+    audio: Box<dyn Buzzer>,
+    config: Config
+}
+
+// see CPU::iter_steps; whatever stopped it early, an error or a key wait
+// that timed out, is kept in error
+pub struct Steps<'a> {
+    cpu: &'a mut CPU,
+    pub error: Option<Chip8Error>
+}
+
+impl Iterator for Steps<'_> {
+    type Item = ExecutedOp;
+
+    fn next(&mut self) -> Option<ExecutedOp> {
+        while self.error.is_none() {
+            match self.cpu.step() {
+                Ok(Step::Ran) => return self.cpu.last_step.take(),
+                Ok(Step::WaitingForKey) => (),
+                Ok(Step::Halted(Exit::WaitingForInput(pc))) => self.error = Some(Chip8Error::KeyWaitTimedOut { pc }),
+                Ok(Step::Halted(Exit::Closed)) | Ok(Step::Halted(Exit::RomExited)) => return None,
+                Err(err) => self.error = Some(err)
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Steps<'_> {
+    fn drop(&mut self) {
+        self.cpu.watch_steps = false;
+    }
+}
+
+// what the run loop keeps between times round it
+struct LoopState {
+    executing: bool,
+    // address and value of the last instruction fetched, for error reporting
+    last_instruction: Option<(usize, u16)>,
+    waiting_for_keypress: bool,
+    store_keypress_in: usize,
+    // the key pressed during the wait, which ends it once released, as on
+    // the VIP
+    wait_key: Option<usize>,
+    // where and when the current key wait started
+    key_wait_pc: usize,
+    key_wait_started: Duration,
+    // iterations left until the screen is refreshed, each running at most
+    // one instruction, and the eighths of an instruction a frame's speed
+    // didn't share out evenly, carried to the next update
+    instructions_left: u32,
+    owed: u32,
+    // the timers tick at 60 Hz of frontend time, catching up on
+    // any ticks missed because the host was slow
+    timers_started: Duration,
+    timer_ticks: u128,
+    // read the keypad before the first instruction, then as configured
+    sample_keys: bool,
+    // a sprite was drawn with the display-wait quirk on, so nothing more
+    // runs until the next frame
+    waiting_for_frame: bool
+}
+
+impl LoopState {
+    fn new(now: Duration, speed: u32) -> LoopState {
+        let mut state = LoopState {
+            executing: true,
+            last_instruction: None,
+            waiting_for_keypress: false,
+            store_keypress_in: 0x0,
+            wait_key: None,
+            key_wait_pc: 0,
+            key_wait_started: Duration::ZERO,
+            instructions_left: 0,
+            owed: 0,
+            timers_started: now,
+            timer_ticks: 0,
+            sample_keys: true,
+            waiting_for_frame: false
+        };
+        state.next_update(speed);
+        state
+    }
+
+    // share out the instructions for the next screen update
+    fn next_update(&mut self, speed: u32) {
+        self.owed += speed;
+        self.instructions_left = self.owed / UPDATES_PER_FRAME;
+        self.owed %= UPDATES_PER_FRAME;
+    }
 }
 
 impl CPU {
-    pub fn new(win: Window, audio: Audio) -> CPU {
+    pub fn new(win: Box<dyn Frontend>, audio: Box<dyn Buzzer>, config: Config) -> CPU {
         let mut ret = CPU {
             ram: [0; RAM_SIZE],
+            high_ram: vec![0; config.platform.memory_size() - RAM_SIZE],
             // registers
             v: [0; REGISTER_COUNT],
             // memory address register
@@ -64,349 +386,2334 @@ impl CPU {
             sp: 0,
             // program counter
             pc: PROGRAM_START,
+            framebuffer: Framebuffer::new(),
+            keypad: Keypad::default(),
+            flags: [0; FLAG_COUNT],
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            unknown_opcodes: BTreeMap::new(),
+            native_calls: BTreeMap::new(),
+            executed: BTreeMap::new(),
+            sprites_drawn: BTreeSet::new(),
+            injected_keys: [false; 16],
+            overlay: Vec::new(),
+            paused: false,
+            breakpoints: BTreeSet::new(),
+            step_requested: false,
+            resumed_at: None,
+            paused_for_focus: false,
+            menu: None,
+            prompt: None,
+            resume_after_prompt: false,
+            slow_motion: 1,
+            slow_motion_wait: 0,
+            saved_state: None,
+            state_dir: None,
+            rom: Vec::new(),
+            quit: false,
+            watch_steps: false,
+            last_step: None,
+            restarted: false,
+            run_state: LoopState::new(win.elapsed(), config.speed),
+            hooks: Vec::new(),
+            counters: Counters::default(),
+            rng: match config.random_seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy()
+            },
+            cheats: Cheats::default(),
             win,
-            audio
+            audio,
+            config
         };
         ret.preload_ram();
         ret
     }
 
-    pub fn load_rom(&mut self, rom: &Vec<u8>) -> Result<(), &str> {
-        if PROGRAM_START + rom.len() >= RAM_SIZE {
-            return Err("Out of memory: program too large");
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.write_rom(rom)?;
+        self.start();
+        Ok(())
+    }
+
+    fn write_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let start = self.config.platform.program_start();
+        let available = self.memory_size() - start;
+        let mut rom = rom;
+        if rom.len() > available {
+            if !self.config.truncate_rom {
+                return Err(Chip8Error::RomTooLarge { size: rom.len(), available, start });
+            }
+            log_warn!("program is {} bytes, only loading the first {}", rom.len(), available);
+            rom = &rom[..available];
+        }
+        self.load_bytes(start, rom);
+        self.rom = rom.to_vec();
+        Ok(())
+    }
+
+    // put bytes in memory from addr on, into high RAM past the first 4K
+    fn load_bytes(&mut self, addr: usize, bytes: &[u8]) {
+        let (low, high) = bytes.split_at(bytes.len().min(RAM_SIZE.saturating_sub(addr)));
+        self.ram[addr..addr + low.len()].copy_from_slice(low);
+        let high_start = (addr + low.len()).saturating_sub(RAM_SIZE);
+        self.high_ram[high_start..high_start + high.len()].copy_from_slice(high);
+    }
+
+    // how many bytes of memory the platform has
+    fn memory_size(&self) -> usize {
+        RAM_SIZE + self.high_ram.len()
+    }
+
+    // the ROM loaded last
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    // load a different ROM and start it, eg. after the splash
+    pub fn swap_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.load_rom(rom)?;
+        self.reset();
+        Ok(())
+    }
+
+    // put a new version of the ROM in memory and carry on from where the
+    // machine was, eg. after editing its source
+    pub fn patch_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let old_len = self.rom.len();
+        self.write_rom(rom)?;
+        // what's left of a longer old version
+        let start = self.config.platform.program_start();
+        let left = old_len.saturating_sub(self.rom.len());
+        self.load_bytes(start + self.rom.len(), &vec![0; left]);
+        Ok(())
+    }
+
+    // start the ROM over, as if it had just been loaded
+    pub fn reset(&mut self) {
+        self.ram = [0; RAM_SIZE];
+        self.high_ram.fill(0);
+        self.preload_ram();
+        let start = self.config.platform.program_start();
+        let rom = std::mem::take(&mut self.rom);
+        self.load_bytes(start, &rom);
+        self.rom = rom;
+        self.v = [0; REGISTER_COUNT];
+        self.i = 0;
+        self.dt = 0;
+        self.st = 0;
+        self.stack = [0; STACK_SIZE];
+        self.sp = 0;
+        self.keypad = Keypad::default();
+        self.restarted = true;
+        self.audio.stop_sample();
+        self.start();
+    }
+
+    // where and in which screen mode the ROM starts
+    fn start(&mut self) {
+        self.framebuffer.select_planes(1);
+        let chip8x = self.config.platform == Platform::Chip8X;
+        self.framebuffer.set_colour_zones(if chip8x { Some(ColourZones::new()) } else { None });
+        self.framebuffer.set_mega_screen(None);
+        if !chip8x && self.rom.starts_with(&TWO_PAGE_ENTRY) {
+            self.pc = TWO_PAGE_START;
+            self.framebuffer.set_resolution(Resolution::TwoPage);
         }
-        for (j, c) in rom.into_iter().enumerate() {
-            self.ram[j + PROGRAM_START] = *c;
+        else {
+            self.pc = self.config.platform.program_start();
+            self.framebuffer.set_resolution(Resolution::Low);
         }
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.config.speed
+    }
+
+    // run this many instructions a 60 Hz frame, from the next screen
+    // update on
+    pub fn set_speed(&mut self, speed: u32) {
+        self.config.speed = speed;
+    }
+
+    // keep the whole machine in memory, to go back to with load_state
+    pub fn save_state(&mut self) {
+        self.saved_state = Some(Box::new(self.snapshot()));
+    }
+
+    pub fn load_state(&mut self) -> Result<(), Chip8Error> {
+        let snapshot = self.saved_state.take().ok_or(Chip8Error::NoSavedState)?;
+        self.restore(&snapshot);
+        self.saved_state = Some(snapshot);
+        self.restarted = true;
+        Ok(())
+    }
+
+    pub fn saved_state(&self) -> Option<&SaveState> {
+        self.saved_state.as_deref()
+    }
+
+    // keep the numbered save slots as files in dir, made when first saved to
+    pub fn set_state_dir(&mut self, dir: PathBuf) {
+        self.state_dir = Some(dir);
+    }
+
+    // write the whole machine to one of the numbered slots (1 to 10 from
+    // the hotkeys)
+    pub fn save_slot(&mut self, slot: usize) -> Result<(), Chip8Error> {
+        let failed = |err: std::io::Error| Chip8Error::StateIo { slot, why: err.to_string() };
+        let dir = self.state_dir.as_ref().ok_or(Chip8Error::NoStateDir { slot })?;
+        fs::create_dir_all(dir).map_err(failed)?;
+        let text = self.snapshot().to_text(fnv1a(self.rom.iter().copied()));
+        fs::write(slot_path(dir, slot), text).map_err(failed)
+    }
+
+    // write RAM and the registers to a new pair of files in dir (see dump),
+    // returning the path of the RAM file
+    pub fn dump_memory(&self, dir: &Path) -> Result<PathBuf, String> {
+        let path = write_memory_dump(self, dir).map_err(|why| format!("Could not dump memory: {}", why))?;
+        log_info!("{}", self.config.language.fill(Text::MemoryDumped, &[&path.display()]));
+        Ok(path)
+    }
+
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), Chip8Error> {
+        let dir = self.state_dir.as_ref().ok_or(Chip8Error::NoStateDir { slot })?;
+        let text = fs::read_to_string(slot_path(dir, slot)).map_err(|_| Chip8Error::SlotEmpty { slot })?;
+        let state = SaveState::parse(&text, fnv1a(self.rom.iter().copied()), self.config.platform)
+            .map_err(|why| Chip8Error::BadState { slot, why })?;
+        self.restore(&state);
+        self.restarted = true;
         Ok(())
     }
 
+    pub fn next_palette(&mut self) {
+        self.win.next_palette();
+    }
+
+    // flip a quirk while the ROM runs, and return whether it is now on
+    pub fn toggle_quirk(&mut self, quirk: Quirk) -> bool {
+        self.config.quirks.toggle(quirk)
+    }
+
+    // stop running at the end of this frame
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+
+    fn snapshot(&self) -> SaveState {
+        SaveState {
+            ram: self.ram,
+            high_ram: self.high_ram.clone(),
+            v: self.v,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            stack: self.stack,
+            sp: self.sp,
+            pc: self.pc,
+            framebuffer: self.framebuffer.clone(),
+            platform: self.config.platform
+        }
+    }
+
+    fn restore(&mut self, snapshot: &SaveState) {
+        self.ram = snapshot.ram;
+        self.high_ram = snapshot.high_ram.clone();
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        self.stack = snapshot.stack;
+        self.sp = snapshot.sp;
+        self.pc = snapshot.pc;
+        self.framebuffer = snapshot.framebuffer.clone();
+        self.keypad = Keypad::default();
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    // the registers kept by Fx75, eg. to save them between runs
+    pub fn flags(&self) -> [u8; FLAG_COUNT] {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: [u8; FLAG_COUNT]) {
+        self.flags = flags;
+    }
+
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    // every unrecognized instruction met so far, keyed by
+    // (address, instruction), with the number of times it was executed
+    pub fn unknown_opcodes(&self) -> &BTreeMap<(usize, u16), u32> {
+        &self.unknown_opcodes
+    }
+
+    // how often each (address, 0nnn) call into machine code was met,
+    // whether it was stubbed or not
+    pub fn native_calls(&self) -> &BTreeMap<(usize, u16), u32> {
+        &self.native_calls
+    }
+
+    // every (address, instruction) executed with the number of times it
+    // ran; empty unless config.opcode_stats is set
+    pub fn executed(&self) -> &BTreeMap<(usize, u16), u64> {
+        &self.executed
+    }
+
+    // every (address, height) drawn with Dxyn; empty unless
+    // config.record_sprites is set
+    pub fn sprites_drawn(&self) -> &BTreeSet<(usize, usize)> {
+        &self.sprites_drawn
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // write straight into RAM, ignoring font protection
+    pub fn set_ram(&mut self, addr: usize, value: u8) {
+        self.ram[addr % RAM_SIZE] = value;
+    }
+
+    pub fn v(&self) -> &[u8] {
+        &self.v
+    }
+
+    pub fn set_v(&mut self, reg: usize, value: u8) {
+        self.v[reg & 0xf] = value;
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc % self.memory_size();
+    }
+
+    pub fn set_i(&mut self, i: usize) {
+        self.i = i % self.memory_size();
+    }
+
+    // hold a key down (or let go of it) on top of whatever the frontend reports
+    pub fn inject_key(&mut self, key: usize, down: bool) {
+        self.injected_keys[key & 0xf] = down;
+    }
+
+    pub fn counters(&self) -> Counters {
+        Counters { audio_underruns: self.audio.underruns(), ..self.counters }
+    }
+
+    pub fn injected_keys(&self) -> &[bool; 16] {
+        &self.injected_keys
+    }
+
+    pub fn overlay(&self) -> &[String] {
+        &self.overlay
+    }
+
+    // replace the text shown over the screen; no lines hides it
+    pub fn set_overlay(&mut self, lines: Vec<String>) {
+        self.win.set_overlay(&lines);
+        self.overlay = lines;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused && !paused {
+            self.resumed_at = Some(self.pc);
+        }
+        self.paused = paused;
+    }
+
+    // run at most this many instructions a second, or at full speed; the
+    // screen and timers carry on as usual in between
+    pub fn set_slow_motion(&mut self, instructions_per_second: Option<u32>) {
+        // every screen update takes at least one iteration
+        let frames_per_second = (Duration::from_secs(1).as_micros() / REFRESH_INTERVAL.as_micros()) as u32;
+        let iterations_per_second = (self.config.speed / UPDATES_PER_FRAME).max(1) * frames_per_second;
+        self.slow_motion = match instructions_per_second {
+            Some(ips) => (iterations_per_second / ips.max(1)).max(1),
+            None => 1
+        };
+        self.slow_motion_wait = 0;
+    }
+
+    // while paused, execute just the next instruction
+    pub fn request_step(&mut self) {
+        self.step_requested = true;
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.remove(&addr);
+    }
+
+    // run these hooks after every instruction and frame from now on, after
+    // any added before
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    // narrow down the cheat search with filter; returns how many addresses are left
+    pub fn search_ram(&mut self, filter: Filter) -> usize {
+        self.cheats.search(&self.ram, filter)
+    }
+
+    pub fn add_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks.push(hooks);
+    }
+
+    // call one of the hooks, handing them the whole machine
+    fn run_hook(&mut self, hook: fn(&mut dyn Hooks, &mut CPU) -> Result<(), String>) -> Result<(), Chip8Error> {
+        let mut hooks = std::mem::take(&mut self.hooks);
+        let result = hooks.iter_mut().try_for_each(|h| hook(h.as_mut(), self));
+        self.hooks = hooks;
+        result.map_err(Chip8Error::Hook)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    // where each subroutine on the call stack was called from, innermost
+    // last; it returns to the instruction after
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // the most recently executed instructions as (address, instruction),
+    // oldest first
+    pub fn history(&self) -> impl Iterator<Item = &(usize, u16)> {
+        self.history.iter()
+    }
+
     fn preload_ram(&mut self) {
-        // store each number n at 0xn0 - 0xn4
+        // store each number n at n * stride onwards
+        let stride = self.config.font_layout.stride();
         for (j, d) in RAM_DIGITS.iter().enumerate() {
             for (k, b) in d.iter().enumerate() {
-                self.ram[(0x10 * j) + k] = *b;
+                self.ram[(stride * j) + k] = *b;
             }
         }
+        let big_font = self.config.font_layout.big_font_start();
+        for (j, d) in RAM_BIG_DIGITS.iter().enumerate() {
+            let start = big_font + d.len() * j;
+            self.ram[start..start + d.len()].copy_from_slice(d);
+        }
     }
 
-    pub fn run_loop(&mut self) -> Result<(), &str> {
-        let mut executing = true;
-        let mut waiting_for_keypress = false;
-        let mut store_keypress_in: usize = 0x0;
-        // run once every 8 iterations, ie. 60Hz
-        let mut time_to_runloop: usize = RUNLOOP_TIMER_DEFAULT;
-
-        while self.win.is_open() && !self.win.is_key_down(Key::Escape) && self.pc <= RAM_SIZE {
-            //for (i, pixel) in display.iter_mut().enumerate() {
-            //    *pixel = if ram[i + 512] == 0 { PX_OFF } else { PX_ON };
-            //}
-
-            let keys_pressed = self.win.handle_key_events();
+    // read a byte from RAM on behalf of the instruction at pc,
+    // applying the configured policy for addresses past the end
+    fn read_ram(&self, addr: usize) -> Result<u8, Chip8Error> {
+        if let Some(byte) = addr.checked_sub(RAM_SIZE).and_then(|high| self.high_ram.get(high)) {
+            return Ok(*byte);
+        }
+        if addr >= RAM_SIZE && self.config.memory == MemoryPolicy::Error {
+            return Err(Chip8Error::OutOfBounds { addr, pc: self.pc, write: false });
+        }
+        Ok(self.ram[addr % RAM_SIZE])
+    }
 
-            for (j, k) in keys_pressed.iter().enumerate() {
-                if *k {
-                    if waiting_for_keypress {
-                        executing = true;
-                        waiting_for_keypress = false;
-                        self.v[store_keypress_in] = j as u8;
-                        break;
-                    }
-                    println!("{:01x} pressed!", j);
+    // write a byte to RAM on behalf of the instruction at pc,
+    // applying the configured protection of the interpreter area and
+    // policy for addresses past the end
+    fn write_ram(&mut self, addr: usize, val: u8) -> Result<(), Chip8Error> {
+        if let Some(byte) = addr.checked_sub(RAM_SIZE).and_then(|high| self.high_ram.get_mut(high)) {
+            *byte = val;
+            return Ok(());
+        }
+        if addr >= RAM_SIZE && self.config.memory == MemoryPolicy::Error {
+            return Err(Chip8Error::OutOfBounds { addr, pc: self.pc, write: true });
+        }
+        let addr = addr % RAM_SIZE;
+        if addr < PROGRAM_START {
+            match self.config.font_protection {
+                WriteProtection::Off => (),
+                WriteProtection::Log => {
+                    log_warn!("write of {:02x} to protected address {:03x} by instruction at {:03x}", val, addr, self.pc);
+                },
+                WriteProtection::Reject => {
+                    return Err(Chip8Error::ProtectedWrite { addr, value: val, pc: self.pc });
                 }
             }
+        }
+        self.ram[addr] = val;
+        Ok(())
+    }
 
-            // get the instruction (2 bytes) out of RAM
-            let b1 = self.ram[self.pc] as u16;
-            let b2 = self.ram[self.pc + 1] as u16;
-            let instruction = (b1 * 256) + b2;
-            
-            // flag to keep track of whether to move to next instruction
-            // or not; in most cases we will, but sometimes not
-            let mut next_instruction = true;
-
-
-            if executing {
-                println!("{:03x}, {:04x}, {:04x}, {:02x?}", self.pc, instruction, self.i, self.v);
-                // all instruction comments below will follow the format wxyz for
-                // referring to instruction
-                match instruction {
-                    0x00e0 => {
-                        // clear display
-                        self.win.clear_screen();
-                    },
-                    0x00ee => {
-                        // return from subroutine
-                        if self.sp == 0 {
-                            return Err("Stack empty, cannot return from subroutine!");
-                        }
-                        self.sp -= 1;
-                        self.pc = self.stack[self.sp];
-                    },
-                    0x1000..=0x1fff => {
-                        // jump to memory location xyz
-                        self.pc = get_hex_digits(&instruction, 3, 0);
-                        next_instruction = false;
-                    },
-                    0x2000..=0x2fff => {
-                        // call memory location xyz as subroutine (that will eventually return)
-                        let loc = get_hex_digits(&instruction, 3, 0);
-                        if self.sp == STACK_SIZE {
-                            return Err("Stack full, cannot push!");
-                        }
-                        self.stack[self.sp] = self.pc;
-                        self.sp += 1;
-                        self.pc = loc;
-                        next_instruction = false;
-                    },
-                    0x3000..=0x3fff => {
-                        // skip next instruction if Vx == yz
-                        let val = get_hex_digits(&instruction, 2, 0);
-                        let reg = get_hex_digits(&instruction, 1, 2);
-                        if self.v[reg] == val as u8 {
-                            self.pc += 2;
-                        }
-                    },
-                    0x4000..=0x4fff => {
-                        // skip next instruction if Vx != yz
-                        let val = get_hex_digits(&instruction, 2, 0);
-                        let reg = get_hex_digits(&instruction, 1, 2);
-                        if self.v[reg] != val as u8 {
-                            self.pc += 2;
-                        }
-                    },
-                    0x5000..=0x5fff => {
-                        // skip next instruction if Vx == Vy
-                        let reg1 = get_hex_digits(&instruction, 1, 2);
-                        let reg2 = get_hex_digits(&instruction, 1, 1);
-                        if self.v[reg1] == self.v[reg2] {
-                            self.pc += 2;
-                        }
-                    },
-                    0x6000..=0x6fff => {
-                        // load value yz into Vx
-                        let val = get_hex_digits(&instruction, 2, 0);
-                        let reg = get_hex_digits(&instruction, 1, 2);
-                        self.v[reg] = val as u8;
-                    },
-                    0x7000..=0x7fff => {
-                        // add value yz to Vx
-                        let val = get_hex_digits(&instruction, 2, 0);
-                        let reg = get_hex_digits(&instruction, 1, 2);
-                        // we need to ignore overflows in adding in this case
-                        self.v[reg] = self.v[reg].overflowing_add(val as u8).0;
-                    },
-                    0x8000..=0x8fff => {
-                        // this seems to be a wrapper for all sorts
-                        // of binary operations on Vx and Vy determined by z
-                        let lsb = get_hex_digits(&instruction, 1, 0);
-                        let reg1 = get_hex_digits(&instruction, 1, 2);
-                        let reg2 = get_hex_digits(&instruction, 1, 1);
-
-                        match lsb {
-                            0x0 => {
-                                // set Vx = Vy
-                                self.v[reg1] = self.v[reg2];
-                            },
-                            0x1 => {
-                                // set Vx = Vx OR Vy
-                                self.v[reg1] |= self.v[reg2];
-                            },
-                            0x2 => {
-                                // set Vx = Vx AND Vy
-                                self.v[reg1] &= self.v[reg2];
-                            },
-                            0x3 => {
-                                // set Vx = Vx XOR Vy
-                                self.v[reg1] ^= self.v[reg2];
-                            },
-                            0x4 => {
-                                // set Vx = Vx + Vy (and VF to 1 if overflow else 0)
-                                let (res, over) = self.v[reg1].overflowing_add(self.v[reg2]);
-                                self.v[reg1] = res;
-                                self.v[0xf] = if over {1} else {0};
-                            },
-                            0x5 => {
-                                // set Vx = Vx - Vy (and VF to 0 if borrow else 1)
-                                let (res, over) = self.v[reg1].overflowing_sub(self.v[reg2]);
-                                self.v[reg1] = res;
-                                self.v[0xf] = if over {0} else {1};
-                            },
-                            0x6 => {
-                                // right shift Vx 1 bit (and VF to value of bit lost)
-                                let res = self.v[reg1].overflowing_shr(1).0;
-                                self.v[0xf] = get_bit(&self.v[reg1], 0);
-                                self.v[reg1] = res;
-                            },
-                            0x7 => {
-                                // set Vx = Vy - Vx (and VF to 0 if borrow else 1)
-                                let (res, over) = self.v[reg2].overflowing_sub(self.v[reg1]);
-                                self.v[reg1] = res;
-                                self.v[0xf] = if over {0} else {1};
-                            },
-                            0xe => {
-                                // left shift Vx 1 bit (and VF to value of bit lost)
-                                let res = self.v[reg1].overflowing_shl(1).0;
-                                self.v[0xf] = get_bit(&self.v[reg1], 7);
-                                self.v[reg1] = res;
-                            },
-                            _ => {
-                                println!("Warning: unrecognized instruction: {:04x}", instruction);
-                            }
-                        };
-                    },
-                    0x9000..=0x9fff => {
-                        // skip next instruction if Vx != Vy
-                        let reg1 = get_hex_digits(&instruction, 1, 2);
-                        let reg2 = get_hex_digits(&instruction, 1, 1);
-                        if self.v[reg1] != self.v[reg2] {
-                            self.pc += 2;
-                        }
-                    },
-                    0xa000..=0xafff => {
-                        // load value xyz into register I
-                        self.i = get_hex_digits(&instruction, 3, 0);
-                    },
-                    0xb000..=0xbfff => {
-                        // jump to memory location xyz + V0
-                        self.pc = get_hex_digits(&instruction, 3, 0) + self.v[0] as usize;
-                        next_instruction = false;
-                    },
-                    0xc000..=0xcfff => {
-                        // set Vx = random byte AND yz
-                        let rnd = rand::random::<u8>();
-                        let val = get_hex_digits(&instruction, 2, 0);
-                        let reg = get_hex_digits(&instruction, 1, 2);
-                        self.v[reg] = rnd & val as u8;
-                    },
-                    0xd000..=0xdfff => {
-                        // get z bytes and draw them starting at (Vx, Vy)
-                        let reg1 = get_hex_digits(&instruction, 1, 2);
-                        let reg2 = get_hex_digits(&instruction, 1, 1);
-                        let init_x = self.v[reg1];
-                        let init_y = self.v[reg2];
-                        let mut byte_count = get_hex_digits(&instruction, 1, 0);
-                        let mut bytes_to_print: Vec<u8> = Vec::new();
-                        let mut j = 0;
-                        while byte_count > 0 {
-                            bytes_to_print.push(self.ram[self.i + j]);
-                            byte_count -= 1;
-                            j += 1;
-                        }
-                        // collision byte -- 1 if any ON pixels were set to OFF, 0 otherwise
-                        self.v[0xf] = self.win.draw(&bytes_to_print, init_x, init_y);
-                    },
-                    0xe000..=0xff65 => {
-                        // these last few instructions are a bit arbitrarily named
-                        // so let's check each nibble individually
-                        let d1 = get_hex_digits(&instruction, 1, 3);
-                        let d2 = get_hex_digits(&instruction, 1, 2);
-                        let d3 = get_hex_digits(&instruction, 1, 1);
-                        let d4 = get_hex_digits(&instruction, 1, 0);
-
-                        if d1 == 0xe && d3 == 0x9 && d4 == 0xe {
-                            // skip instruction if keycode Vx is pressed
-                            if keys_pressed[self.v[d2] as usize] {
-                                self.pc += 2;
-                            }
-                        }
+    // whether PC is at a loop that does nothing but wait for the delay
+    // timer, while it's still counting down:
+    //
+    //   loop: LD Vx, DT
+    //         SE Vx, 0
+    //         JP loop
+    //
+    // running it changes nothing the ROM can see until the timer reaches
+    // zero, so with config.skip_delay_loops we don't
+    fn in_delay_loop(&self) -> bool {
+        if !self.config.skip_delay_loops || self.dt == 0 || self.pc + 5 >= RAM_SIZE {
+            return false;
+        }
+        let op = |addr: usize| u16::from_be_bytes([self.ram[addr], self.ram[addr + 1]]);
+        let (read, skip, jump) = (op(self.pc), op(self.pc + 2), op(self.pc + 4));
+        let x = (read >> 8) & 0xf;
+        read & 0xf0ff == 0xf007 && skip == 0x3000 | x << 8 && jump == 0x1000 | self.pc as u16
+    }
 
-                        else if d1 == 0xe && d3 == 0xa && d4 == 0x1 {
-                            // skip instruction if keycode Vx is not pressed
-                            if !keys_pressed[self.v[d2] as usize] {
-                                self.pc += 2;
-                            }
-                        }
+    // apply the configured policy to an instruction we do not recognize
+    fn unknown_instruction(&mut self, instruction: u16) -> Result<(), Chip8Error> {
+        if self.config.unknown_opcode == UnknownOpcodePolicy::Halt {
+            return Err(Chip8Error::UnknownInstruction { instruction, pc: self.pc });
+        }
+        self.counters.unknown_opcodes += 1;
+        let count = self.unknown_opcodes.entry((self.pc, instruction)).or_insert(0);
+        *count += 1;
+        // only warn the first time, a ROM may run the same one in a loop
+        if *count == 1 && self.config.unknown_opcode == UnknownOpcodePolicy::Warn {
+            log_warn!("unrecognized instruction {:04x} at {:03x}", instruction, self.pc);
+        }
+        Ok(())
+    }
 
-                        else if d1 == 0xf && d3 == 0x0 && d4 == 0x7 {
-                            // set Vx to delay timer value
-                            self.v[d2] = self.dt;
-                        }
+    // after 8xy1, 8xy2 and 8xy3, the COSMAC VIP left VF at 0
+    fn logic_quirk(&mut self) {
+        if self.config.quirks.logic_resets_vf {
+            self.v[0xf] = 0;
+        }
+    }
 
-                        else if d1 == 0xf && d3 == 0x0 && d4 == 0xa {
-                            // stop execution until keypress
-                            executing = false;
-                            waiting_for_keypress = true;
-                            store_keypress_in = d2;
-                        }
+    // how many pixels a scroll of n moves the screen by
+    fn scroll_distance(&self, n: usize) -> usize {
+        if self.config.quirks.half_scroll && self.framebuffer.resolution() != Resolution::High {
+            n / 2
+        }
+        else {
+            n
+        }
+    }
 
-                        else if d1 == 0xf && d3 == 0x1 && d4 == 0x5 {
-                            // set delay timer value to Vx
-                            self.dt = self.v[d2];
-                        }
+    // 0nnn, a call into 1802 machine code: done for the routines we know
+    // if config.stub_native_calls is set, otherwise handled like an
+    // unknown instruction, but noted apart
+    fn native_call(&mut self, instruction: u16) -> Result<(), Chip8Error> {
+        let addr = (instruction & 0xfff) as usize;
+        // whatever follows is written for MegaChip's 256x192 colour screen
+        // and 16MB of memory, which only --platform=megachip has, so
+        // carrying on would only run garbage
+        if instruction == MEGACHIP_ON {
+            return Err(Chip8Error::MegaChip { pc: self.pc });
+        }
+        let count = self.native_calls.entry((self.pc, instruction)).or_insert(0);
+        *count += 1;
+        match known_routine(addr) {
+            Some(NativeRoutine::HiresClear) if self.config.stub_native_calls || self.framebuffer.resolution() == Resolution::TwoPage => {
+                self.framebuffer.clear();
+            },
+            _ => match self.config.unknown_opcode {
+                UnknownOpcodePolicy::Halt => {
+                    return Err(Chip8Error::MachineCode { addr, pc: self.pc });
+                },
+                UnknownOpcodePolicy::Warn if *count == 1 => {
+                    log_warn!("call to machine code at {:03x} from {:03x} ignored", addr, self.pc);
+                },
+                _ => ()
+            }
+        }
+        Ok(())
+    }
 
-                        else if d1 == 0xf && d3 == 0x1 && d4 == 0x8 {
-                            // set sound timer value to Vx
-                            self.st = self.v[d2];
-                        }
+    // step over the instruction after the one at pc, which takes 4 bytes
+    // if it's XO-CHIP's F000 or MegaChip's 01nn with its address
+    fn skip_next(&mut self) {
+        let next = self.pc + 2;
+        let megachip = self.config.platform == Platform::MegaChip;
+        let long = self.ram.get(next..next + 2) == Some(&[0xf0, 0x00]) || (megachip && self.ram.get(next) == Some(&0x01));
+        self.pc += if long { 4 } else { 2 };
+    }
 
-                        else if d1 == 0xf && d3 == 0x1 && d4 == 0xe {
-                            // i += Vx
-                            self.i += self.v[d2] as usize;
-                        }
+    // the key named by register reg; only the low nibble counts,
+    // since buggy ROMs do put larger values in there
+    fn key_in(&self, reg: usize) -> usize {
+        let val = self.v[reg];
+        if val > 0xf && self.config.strict {
+            log_warn!("key {:02x} in V{:X} at {:03x} is out of range, using {:x}", val, reg, self.pc, val & 0xf);
+        }
+        (val & 0xf) as usize
+    }
 
-                        else if d1 == 0xf && d3 == 0x2 && d4 == 0x9 {
-                            // set i = location of sprite representing
-                            // digit Vx in memory
-                            self.i = (0x10 * self.v[d2]) as usize;
-                        }
+    // human-readable summary of the registers, timers and stack
+    pub fn state_dump(&self) -> String {
+        let regs: Vec<String> = self.v.iter().enumerate()
+            .map(|(j, v)| format!("V{:X}={:02x}", j, v))
+            .collect();
+        let frames: Vec<String> = self.stack[..self.sp].iter()
+            .map(|addr| format!("{:03x}", addr))
+            .collect();
+        format!(
+            "PC={:03x} I={:03x} SP={} DT={:02x} ST={:02x}\n{}\nstack: [{}]",
+            self.pc, self.i, self.sp, self.dt, self.st, regs.join(" "), frames.join(", ")
+        )
+    }
 
-                        else if d1 == 0xf && d3 == 0x3 && d4 == 0x3 {
-                            // store digits of Vx in memory locations
-                            // i (hundreds), i+1 (tens), i+2 (ones)
-                            self.ram[self.i] = self.v[d2] / 100;
-                            self.ram[self.i+1] = (self.v[d2] % 100) / 10;
-                            self.ram[self.i+2] = self.v[d2] % 10;
-                        }
+    // the registers, timers and stack as a JSON object, eg. for tools
+    // reading a memory dump
+    pub fn registers_json(&self) -> String {
+        let list = |values: Vec<String>| values.join(", ");
+        format!(
+            "{{\n  \"pc\": {},\n  \"i\": {},\n  \"v\": [{}],\n  \"sp\": {},\n  \"stack\": [{}],\n  \"dt\": {},\n  \"st\": {}\n}}\n",
+            self.pc, self.i,
+            list(self.v.iter().map(|v| v.to_string()).collect()),
+            self.sp,
+            list(self.stack[..self.sp].iter().map(|a| a.to_string()).collect()),
+            self.dt, self.st
+        )
+    }
 
-                        else if d1 == 0xf && d3 == 0x5 && d4 == 0x5 {
-                            // store [V0, Vx] in memory locations [i, i+x]
-                            for j in 0..=d2 {
-                                self.ram[self.i+j] = self.v[j];
-                            }
-                        }
+    // pause, and silence the buzzer, while the frontend is in the
+    // background, then carry on when it comes back
+    fn follow_focus(&mut self) {
+        let focused = self.win.focused();
+        if !focused && !self.paused {
+            log_info!("Paused while the window is in the background");
+            self.set_paused(true);
+            self.paused_for_focus = true;
+            self.audio.pause();
+        }
+        else if focused && self.paused_for_focus {
+            self.paused_for_focus = false;
+            self.set_paused(false);
+        }
+    }
 
-                        else if d1 == 0xf && d3 == 0x6 && d4 == 0x5 {
-                            // load [V0, Vx] from memory locations [i, i+x]
-                            for j in 0..=d2 {
-                                self.v[j] = self.ram[self.i+j];
-                            }
-                        }
-                        
-                        else {
-                            println!("Warning: unrecognized instruction: {:04x}", instruction);
-                        }
-                    },
-                    _ => {
-                        println!("Warning: unrecognized instruction: {:04x}", instruction);
-                    }
+    // act on a key meant for the emulator rather than the ROM
+    fn hotkey(&mut self, key: Hotkey) {
+        if let Some(prompt) = self.prompt.take() {
+            self.prompt_key(prompt, key);
+            return;
+        }
+        match (key, self.menu) {
+            (Hotkey::DumpMemory, _) => {
+                if let Err(err) = self.dump_memory(Path::new(".")) {
+                    eprintln!("{}", err);
+                }
+            },
+            (Hotkey::Faster, _) | (Hotkey::Slower, _) => {
+                self.set_speed(step_speed(self.config.speed, key == Hotkey::Faster));
+                log_info!("{}", self.config.language.fill(Text::SpeedChanged, &[&self.config.speed]));
+            },
+            (Hotkey::SaveSlot(slot), None) | (Hotkey::LoadSlot(slot), None) => {
+                let (result, done) = match key {
+                    Hotkey::SaveSlot(_) => (self.save_slot(slot), Text::SlotSaved),
+                    _ => (self.load_slot(slot), Text::SlotLoaded)
                 };
+                let lang = self.config.language;
+                let line = result.map_or_else(|err| err.localized(lang), |()| lang.fill(done, &[&slot]));
+                log_info!("{}", line);
+                self.set_overlay(vec![line.clone()]);
+                self.add_hooks(Box::new(Notice::new(vec![line], SLOT_NOTICE_FRAMES)));
+            },
+            (Hotkey::Palette, None) => {
+                let prompt = Prompt::default();
+                self.resume_after_prompt = !self.paused;
+                self.set_paused(true);
+                self.audio.pause();
+                self.set_overlay(prompt.lines());
+                self.prompt = Some(prompt);
+            },
+            (Hotkey::Menu, None) => {
+                let menu = Menu::default();
+                self.set_paused(true);
+                self.audio.pause();
+                self.set_overlay(menu.lines(self.config.language));
+                self.menu = Some(menu);
+            },
+            (Hotkey::Menu, Some(_)) => self.close_menu(),
+            (Hotkey::MenuUp, Some(mut menu)) | (Hotkey::MenuDown, Some(mut menu)) => {
+                if key == Hotkey::MenuUp { menu.up() } else { menu.down() }
+                self.set_overlay(menu.lines(self.config.language));
+                self.menu = Some(menu);
+            },
+            (Hotkey::MenuSelect, Some(menu)) => self.choose(menu.selected()),
+            _ => ()
+        }
+    }
 
-                // update program counter if necessary
-                if next_instruction {
-                    self.pc += 2;
+    // a key for the open command palette; it stays open to show how the
+    // command went, until closed with the palette key
+    fn prompt_key(&mut self, mut prompt: Prompt, key: Hotkey) {
+        match key {
+            Hotkey::Palette => {
+                self.set_overlay(Vec::new());
+                if self.resume_after_prompt {
+                    self.set_paused(false);
                 }
-            }
+                return;
+            },
+            Hotkey::Type(c) => prompt.type_char(c),
+            Hotkey::Backspace => prompt.backspace(),
+            Hotkey::Complete => prompt.complete(),
+            Hotkey::MenuUp => prompt.up(),
+            Hotkey::MenuDown => prompt.down(),
+            Hotkey::MenuSelect => {
+                let text = prompt.take();
+                let outcome = parse_command(&text).and_then(|command| {
+                    // pausing and resuming take effect once the palette is closed
+                    match command {
+                        Command::Pause => self.resume_after_prompt = false,
+                        Command::Resume => self.resume_after_prompt = true,
+                        _ => apply(command, self)?
+                    }
+                    Ok(())
+                });
+                prompt.set_message(match outcome {
+                    Ok(()) => String::from(self.config.language.text(Text::Done)),
+                    Err(err) => err
+                });
+            },
+            _ => ()
+        }
+        self.set_overlay(prompt.lines());
+        self.prompt = Some(prompt);
+    }
 
-            if time_to_runloop == 0 {
-                if self.dt > 0 { self.dt -= 1; }
-                
-                if self.st > 0 {
-                    self.audio.play();
-                    self.st -= 1;
-                }
-                else if self.st == 0 {
-                    self.audio.pause();
-                }
-                
-                self.win.refresh();
-                
-                time_to_runloop = RUNLOOP_TIMER_DEFAULT;
-            }
-            else {
-                time_to_runloop -= 1;
-            }
+    fn close_menu(&mut self) {
+        self.menu = None;
+        self.set_overlay(Vec::new());
+        self.set_paused(false);
+    }
+
+    // do what was picked from the pause menu
+    fn choose(&mut self, item: MenuItem) {
+        match item {
+            MenuItem::Resume => self.close_menu(),
+            MenuItem::Reset => {
+                self.reset();
+                self.close_menu();
+            },
+            MenuItem::SaveState => {
+                self.save_state();
+                self.close_menu();
+            },
+            MenuItem::LoadState => match self.load_state() {
+                Ok(()) => self.close_menu(),
+                Err(err) => log_info!("{}", err.localized(self.config.language))
+            },
+            MenuItem::Palette => self.next_palette(),
+            MenuItem::Quit => self.quit()
+        }
+    }
+
+    // things that must hold between instructions; a violation means a
+    // bug in the emulator (or a state only a broken ROM could reach)
+    fn check_invariants(&self) -> Result<(), String> {
+        if self.sp > STACK_SIZE {
+            return Err(format!("SP {} is past the end of the stack", self.sp));
+        }
+        if self.i >= self.memory_size() {
+            return Err(format!("I {:03x} is outside RAM", self.i));
+        }
+        if !self.pc.is_multiple_of(2) {
+            return Err(format!("PC {:03x} is not aligned to an instruction", self.pc));
+        }
+        if self.pc + 1 >= RAM_SIZE {
+            return Err(format!("PC {:03x} is outside RAM", self.pc));
         }
         Ok(())
     }
+
+    // run the machine as run_loop does, an executed instruction at a time,
+    // until the frontend is closed or something goes wrong
+    pub fn iter_steps(&mut self) -> Steps<'_> {
+        self.run_state = LoopState::new(self.win.elapsed(), self.config.speed);
+        self.watch_steps = true;
+        Steps { cpu: self, error: None }
+    }
+
+    pub fn run_loop(&mut self) -> Result<Exit, Chip8Error> {
+        self.run_state = LoopState::new(self.win.elapsed(), self.config.speed);
+        loop {
+            if let Step::Halted(exit) = self.step()? {
+                return Ok(exit);
+            }
+        }
+    }
+
+    // run exactly one instruction, along with the frames and timer ticks
+    // due before it, for frontends that drive the CPU themselves. While an
+    // Fx0A has no key yet, returns WaitingForKey after one time round the
+    // loop instead, so the caller gets a chance to do something else
+    pub fn step(&mut self) -> Result<Step, Chip8Error> {
+        let executed = self.counters.instructions;
+        while self.win.is_open() && !self.quit {
+            if let Some(exit) = self.iterate()? {
+                return Ok(Step::Halted(exit));
+            }
+            if self.counters.instructions != executed {
+                return Ok(Step::Ran);
+            }
+            if self.run_state.waiting_for_keypress {
+                return Ok(Step::WaitingForKey);
+            }
+        }
+        Ok(Step::Halted(Exit::Closed))
+    }
+
+    // one time round the run loop: at most one instruction, and a frame
+    // once the screen update's share of the speed has run; Some when the
+    // loop is over
+    fn iterate(&mut self) -> Result<Option<Exit>, Chip8Error> {
+        if self.run_state.sample_keys || self.config.key_sampling == KeySampling::Instruction {
+            let mut keys = self.win.handle_key_events();
+            for (key, injected) in keys.iter_mut().zip(&self.injected_keys) {
+                *key |= injected;
+            }
+            self.keypad.update(keys);
+            self.run_state.sample_keys = false;
+            for (j, p) in self.keypad.pressed.iter().enumerate() {
+                if *p {
+                    log_trace!("{:01x} pressed!", j);
+                }
+            }
+        }
+
+        if self.run_state.waiting_for_keypress {
+            if let Some(key) = self.run_state.wait_key {
+                if !self.keypad.down[key] {
+                    self.run_state.executing = true;
+                    self.run_state.waiting_for_keypress = false;
+                    self.run_state.wait_key = None;
+                    self.v[self.run_state.store_keypress_in] = key as u8;
+                }
+            }
+            else if let Some(key) = self.keypad.first_pressed() {
+                self.run_state.wait_key = Some(key);
+                // each press only ends one wait
+                self.keypad.pressed = [false; 16];
+            }
+            else if let Some(timeout) = self.config.key_wait_timeout {
+                if self.win.elapsed() - self.run_state.key_wait_started >= timeout {
+                    return Ok(Some(Exit::WaitingForInput(self.run_state.key_wait_pc)));
+                }
+            }
+        }
+
+        // both bytes of the instruction need to be inside RAM
+        if self.pc + 1 >= RAM_SIZE {
+            return Err(Chip8Error::PcOutOfRange { pc: self.pc, last: self.run_state.last_instruction });
+        }
+
+        // get the instruction (2 bytes) out of RAM
+        let b1 = self.ram[self.pc] as u16;
+        let b2 = self.ram[self.pc + 1] as u16;
+        let instruction = (b1 * 256) + b2;
+        self.run_state.last_instruction = Some((self.pc, instruction));
+        
+        // flag to keep track of whether to move to next instruction
+        // or not; in most cases we will, but sometimes not
+        let mut next_instruction = true;
+        // set when the ROM ends itself
+        let mut exited = false;
+
+
+        if self.run_state.executing && !self.paused && self.breakpoints.contains(&self.pc) && self.resumed_at != Some(self.pc) {
+            log_info!("Breakpoint at {:03x}", self.pc);
+            self.paused = true;
+        }
+
+        // in slow motion, most iterations run nothing and only keep time
+        let idle = self.slow_motion > 1 && {
+            self.slow_motion_wait = (self.slow_motion_wait + 1) % self.slow_motion;
+            self.slow_motion_wait != 0
+        };
+
+        // and waiting for the delay timer, nothing needs running
+        let idle = idle || (self.run_state.executing && self.in_delay_loop());
+
+        // at under 8 instructions a frame, some updates run none at all
+        let idle = idle || self.run_state.instructions_left == 0;
+
+        if self.run_state.executing && !idle && !self.run_state.waiting_for_frame && (!self.paused || self.step_requested) {
+            span!("step");
+            let watch = if self.watch_steps { Some((self.pc, Watch::of(self))) } else { None };
+            self.step_requested = false;
+            self.resumed_at = None;
+            if self.config.log_steps {
+                log_trace!("{:03x}, {:04x}, {:04x}, {:02x?}", self.pc, instruction, self.i, self.v);
+            }
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back((self.pc, instruction));
+            self.counters.instructions += 1;
+            if self.config.opcode_stats {
+                *self.executed.entry((self.pc, instruction)).or_insert(0) += 1;
+            }
+            let decoded = match self.config.platform {
+                Platform::Chip8 => decode(instruction),
+                Platform::Chip8X => decode_chip8x(instruction),
+                Platform::MegaChip => decode_megachip(instruction)
+            };
+            match decoded {
+                Some(Clear) => {
+                    // clear display
+                    self.framebuffer.clear();
+                },
+                Some(Return) => {
+                    // return from subroutine
+                    if self.sp == 0 {
+                        return Err(Chip8Error::StackUnderflow { instruction, pc: self.pc, stack: Vec::new() });
+                    }
+                    self.sp -= 1;
+                    self.pc = self.stack[self.sp];
+                },
+                Some(MachineCall(_)) => {
+                    self.native_call(instruction)?;
+                },
+                Some(ScrollDown(n)) => {
+                    let n = self.scroll_distance(n);
+                    self.framebuffer.scroll_down(n);
+                },
+                Some(ScrollRight) => {
+                    let n = self.scroll_distance(4);
+                    self.framebuffer.scroll_right(n);
+                },
+                Some(ScrollLeft) => {
+                    let n = self.scroll_distance(4);
+                    self.framebuffer.scroll_left(n);
+                },
+                Some(Halt) => {
+                    // stay on it, so going on only ends the ROM again
+                    next_instruction = false;
+                    exited = true;
+                },
+                Some(LowRes) => {
+                    self.framebuffer.set_resolution(Resolution::Low);
+                },
+                Some(HighRes) => {
+                    self.framebuffer.set_resolution(Resolution::High);
+                },
+                Some(Jump(addr)) => {
+                    self.pc = addr;
+                    next_instruction = false;
+                },
+                Some(Call(addr)) => {
+                    // call a subroutine (that will eventually return)
+                    if self.sp == STACK_SIZE {
+                        return Err(Chip8Error::StackOverflow { instruction, pc: self.pc, stack: self.stack.to_vec() });
+                    }
+                    self.stack[self.sp] = self.pc;
+                    self.sp += 1;
+                    self.pc = addr;
+                    next_instruction = false;
+                },
+                Some(SkipEqByte(x, kk)) => {
+                    if self.v[x] == kk {
+                        self.skip_next();
+                    }
+                },
+                Some(SkipNeByte(x, kk)) => {
+                    if self.v[x] != kk {
+                        self.skip_next();
+                    }
+                },
+                Some(SkipEqReg(x, y)) => {
+                    if self.v[x] == self.v[y] {
+                        self.skip_next();
+                    }
+                },
+                Some(StoreRange(x, y)) => {
+                    // store Vx to Vy at I onwards, backwards if x > y,
+                    // leaving I alone
+                    for (j, reg) in register_range(x, y).into_iter().enumerate() {
+                        self.write_ram(self.i + j, self.v[reg])?;
+                    }
+                },
+                Some(LoadRange(x, y)) => {
+                    for (j, reg) in register_range(x, y).into_iter().enumerate() {
+                        self.v[reg] = self.read_ram(self.i + j)?;
+                    }
+                },
+                Some(LoadByte(x, kk)) => {
+                    self.v[x] = kk;
+                },
+                Some(AddByte(x, kk)) => {
+                    // we need to ignore overflows in adding in this case
+                    self.v[x] = self.v[x].overflowing_add(kk).0;
+                },
+                Some(LoadReg(x, y)) => {
+                    self.v[x] = self.v[y];
+                },
+                Some(Or(x, y)) => {
+                    self.v[x] |= self.v[y];
+                    self.logic_quirk();
+                },
+                Some(And(x, y)) => {
+                    self.v[x] &= self.v[y];
+                    self.logic_quirk();
+                },
+                Some(Xor(x, y)) => {
+                    self.v[x] ^= self.v[y];
+                    self.logic_quirk();
+                },
+                Some(AddReg(x, y)) => {
+                    // VF to 1 if overflow else 0
+                    let (res, over) = self.v[x].overflowing_add(self.v[y]);
+                    self.v[x] = res;
+                    self.v[0xf] = if over {1} else {0};
+                },
+                Some(SubReg(x, y)) => {
+                    // Vx = Vx - Vy, and VF to 0 if borrow else 1
+                    let (res, over) = self.v[x].overflowing_sub(self.v[y]);
+                    self.v[x] = res;
+                    self.v[0xf] = if over {0} else {1};
+                },
+                Some(ShiftRight(x, y)) => {
+                    // VF to the value of the bit lost
+                    let source = if self.config.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
+                    self.v[x] = source >> 1;
+                    self.v[0xf] = get_bit(&source, 0);
+                },
+                Some(SubN(x, y)) => {
+                    // Vx = Vy - Vx, and VF to 0 if borrow else 1
+                    let (res, over) = self.v[y].overflowing_sub(self.v[x]);
+                    self.v[x] = res;
+                    self.v[0xf] = if over {0} else {1};
+                },
+                Some(ShiftLeft(x, y)) => {
+                    // VF to the value of the bit lost
+                    let source = if self.config.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
+                    self.v[x] = source << 1;
+                    self.v[0xf] = get_bit(&source, 7);
+                },
+                Some(SkipNeReg(x, y)) => {
+                    if self.v[x] != self.v[y] {
+                        self.skip_next();
+                    }
+                },
+                Some(LoadI(addr)) => {
+                    self.i = addr;
+                },
+                Some(JumpV0(addr)) => {
+                    let offset = if self.config.quirks.jump_uses_vx { self.v[addr >> 8] } else { self.v[0] };
+                    self.pc = addr + offset as usize;
+                    next_instruction = false;
+                },
+                Some(Random(x, kk)) => {
+                    let rnd = self.rng.gen::<u8>();
+                    self.v[x] = rnd & kk;
+                },
+                Some(Draw(x, y, n)) if self.framebuffer.mega_screen().is_some() => {
+                    // in MegaChip mode, a byte a pixel at the size set with
+                    // 03nn and 04nn, except for the font's sprites, which
+                    // stay n rows of a bit a pixel
+                    span!("draw");
+                    let (init_x, init_y) = (self.v[x], self.v[y]);
+                    self.counters.draws += 1;
+                    if self.config.record_sprites {
+                        self.sprites_drawn.insert((self.i, n));
+                    }
+                    let (width, height) = self.framebuffer.mega_screen().map_or((0, 0), MegaScreen::sprite_size);
+                    let (width, sprite) = if self.i < PROGRAM_START {
+                        let rows = (0..n).map(|j| self.read_ram(self.i + j)).collect::<Result<Vec<u8>, _>>()?;
+                        (8, font_sprite(&rows))
+                    }
+                    else {
+                        (width, (0..width * height).map(|j| self.read_ram(self.i + j)).collect::<Result<Vec<u8>, _>>()?)
+                    };
+                    self.v[0xf] = self.framebuffer.draw_mega(&sprite, width, init_x, init_y);
+                },
+                Some(Draw(x, y, n)) => {
+                    // get n bytes and draw them starting at (Vx, Vy); with
+                    // n = 0, a 16x16 sprite from 32 bytes (SUPER-CHIP), and
+                    // as many again for each extra plane selected (XO-CHIP)
+                    span!("draw");
+                    let init_x = self.v[x];
+                    let init_y = self.v[y];
+                    let byte_count = sprite_len(n) * self.framebuffer.plane_count();
+                    self.counters.draws += 1;
+                    if self.config.record_sprites {
+                        self.sprites_drawn.insert((self.i, n));
+                    }
+                    // borrowed straight from RAM, unless the sprite runs
+                    // past the end of it
+                    let mut wrapped = [0u8; 64];
+                    let sprite = if self.i + byte_count <= RAM_SIZE {
+                        &self.ram[self.i..self.i + byte_count]
+                    }
+                    else {
+                        for (j, byte) in wrapped[..byte_count].iter_mut().enumerate() {
+                            *byte = self.read_ram(self.i + j)?;
+                        }
+                        &wrapped[..byte_count]
+                    };
+                    // collision byte -- 1 if any ON pixels were set to OFF, 0 otherwise
+                    let clip = self.config.quirks.clip_sprites;
+                    self.v[0xf] = if n == 0 {
+                        self.framebuffer.draw_large(sprite, init_x, init_y, clip)
+                    }
+                    else {
+                        self.framebuffer.draw_sprite(sprite, init_x, init_y, clip)
+                    };
+                    self.run_state.waiting_for_frame = self.config.quirks.display_wait;
+                },
+                Some(SkipKey(x)) => {
+                    if self.keypad.down[self.key_in(x)] {
+                        self.skip_next();
+                    }
+                },
+                Some(SkipNotKey(x)) => {
+                    if !self.keypad.down[self.key_in(x)] {
+                        self.skip_next();
+                    }
+                },
+                Some(SkipKey2(x)) => {
+                    if self.win.second_keypad()[self.key_in(x)] {
+                        self.skip_next();
+                    }
+                },
+                Some(SkipNotKey2(x)) => {
+                    if !self.win.second_keypad()[self.key_in(x)] {
+                        self.skip_next();
+                    }
+                },
+                Some(NextBackground) => {
+                    if let Some(zones) = self.framebuffer.colour_zones_mut() {
+                        zones.next_background();
+                    }
+                },
+                Some(AddNibbles(x, y)) => {
+                    self.v[x] = add_nibbles(self.v[x], self.v[y]);
+                },
+                Some(Colour(x, y, n)) => {
+                    let (vx, vy, colour) = (self.v[x], self.v[y], self.v[(x + 1) % REGISTER_COUNT]);
+                    if let Some(zones) = self.framebuffer.colour_zones_mut() {
+                        zones.colour(vx, vy, n, colour);
+                    }
+                },
+                Some(MegaOn) => {
+                    self.framebuffer.set_resolution(Resolution::Mega);
+                    self.framebuffer.set_mega_screen(Some(MegaScreen::new()));
+                },
+                Some(MegaOff) => {
+                    self.framebuffer.set_mega_screen(None);
+                    self.framebuffer.set_resolution(Resolution::Low);
+                },
+                Some(ScrollUp(n)) => {
+                    self.framebuffer.scroll_up(n);
+                },
+                Some(LoadIHigh(high)) => {
+                    // the low 16 bits are in the next two bytes, stepped
+                    // over too
+                    let low = u16::from_be_bytes([self.read_ram(self.pc + 2)?, self.read_ram(self.pc + 3)?]);
+                    self.i = ((high as usize) << 16 | low as usize) % self.memory_size();
+                    self.pc += 2;
+                },
+                Some(LoadPalette(count)) => {
+                    let colours = (0..count * 4).map(|j| self.read_ram(self.i + j)).collect::<Result<Vec<u8>, _>>()?;
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.load_palette(&colours);
+                    }
+                },
+                Some(SpriteWidth(width)) => {
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.set_sprite_width(width);
+                    }
+                },
+                Some(SpriteHeight(height)) => {
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.set_sprite_height(height);
+                    }
+                },
+                Some(Alpha(alpha)) => {
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.set_alpha(alpha);
+                    }
+                },
+                Some(PlaySample(n)) => {
+                    let mut header = [0; SAMPLE_HEADER_LEN];
+                    for (j, byte) in header.iter_mut().enumerate() {
+                        *byte = self.read_ram(self.i + j)?;
+                    }
+                    let (rate, len) = sample_header(header);
+                    let start = self.i + SAMPLE_HEADER_LEN;
+                    let samples = (start..start + len).map(|addr| self.read_ram(addr)).collect::<Result<Vec<u8>, _>>()?;
+                    self.audio.play_sample(&samples, rate, n == 0);
+                },
+                Some(StopSample) => {
+                    self.audio.stop_sample();
+                },
+                Some(BlendMode(n)) => {
+                    let blend = Blend::from_number(n).unwrap_or_else(|| {
+                        log_warn!("blend mode {} at {:03x} doesn't exist, using 0", n, self.pc);
+                        Blend::Normal
+                    });
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.set_blend(blend);
+                    }
+                },
+                Some(CollisionColour(index)) => {
+                    if let Some(mega) = self.framebuffer.mega_screen_mut() {
+                        mega.set_collision_colour(index);
+                    }
+                },
+                Some(GetDelay(x)) => {
+                    self.v[x] = self.dt;
+                },
+                Some(WaitKey(x)) => {
+                    // stop execution until keypress
+                    self.run_state.executing = false;
+                    self.run_state.waiting_for_keypress = true;
+                    self.run_state.store_keypress_in = x;
+                    self.run_state.key_wait_pc = self.pc;
+                    self.run_state.key_wait_started = self.win.elapsed();
+                },
+                Some(Plane(n)) => {
+                    self.framebuffer.select_planes(n as u8);
+                },
+                Some(AudioPattern) => {
+                    let mut pattern = [0; PATTERN_LEN];
+                    for (j, byte) in pattern.iter_mut().enumerate() {
+                        *byte = self.read_ram(self.i + j)?;
+                    }
+                    self.audio.set_pattern(pattern);
+                },
+                Some(LongLoadI) => {
+                    // the address is in the next two bytes, stepped over
+                    // too; past the end of memory (4K unless MegaChip) it wraps
+                    let addr = u16::from_be_bytes([self.read_ram(self.pc + 2)?, self.read_ram(self.pc + 3)?]);
+                    self.i = addr as usize % self.memory_size();
+                    self.pc += 2;
+                },
+                Some(Pitch(x)) => {
+                    self.audio.set_pitch(self.v[x]);
+                },
+                Some(SetDelay(x)) => {
+                    self.dt = self.v[x];
+                },
+                Some(SetSound(x)) => {
+                    self.st = self.v[x];
+                },
+                Some(AddI(x)) => {
+                    // wrapping around at the end of RAM
+                    let sum = self.i + self.v[x] as usize;
+                    self.i = sum % self.memory_size();
+                    if self.config.quirks.i_overflow_sets_vf {
+                        self.v[0xf] = if sum >= self.memory_size() {1} else {0};
+                    }
+                },
+                Some(Font(x)) => {
+                    // the sprite for digit Vx (low nibble only)
+                    self.i = self.config.font_layout.stride() * (self.v[x] & 0xf) as usize;
+                },
+                Some(BigFont(x)) => {
+                    // the big sprite for digit Vx (low nibble only)
+                    self.i = self.config.font_layout.big_font_start() + RAM_BIG_DIGITS[0].len() * (self.v[x] & 0xf) as usize;
+                },
+                Some(Bcd(x)) => {
+                    // store digits of Vx in memory locations
+                    // i (hundreds), i+1 (tens), i+2 (ones)
+                    self.write_ram(self.i, self.v[x] / 100)?;
+                    self.write_ram(self.i+1, (self.v[x] % 100) / 10)?;
+                    self.write_ram(self.i+2, self.v[x] % 10)?;
+                },
+                Some(StoreRegs(x)) => {
+                    // store [V0, Vx] in memory locations [i, i+x]
+                    for j in 0..=x {
+                        self.write_ram(self.i+j, self.v[j])?;
+                    }
+                    if self.config.quirks.load_store_increments_i {
+                        self.i = (self.i + x + 1) % self.memory_size();
+                    }
+                },
+                Some(LoadRegs(x)) => {
+                    // load [V0, Vx] from memory locations [i, i+x]
+                    for j in 0..=x {
+                        self.v[j] = self.read_ram(self.i+j)?;
+                    }
+                    if self.config.quirks.load_store_increments_i {
+                        self.i = (self.i + x + 1) % self.memory_size();
+                    }
+                },
+                Some(SaveFlags(x)) => {
+                    // store [V0, Vx] in the flags, as many as there are
+                    let count = (x + 1).min(FLAG_COUNT);
+                    self.flags[..count].copy_from_slice(&self.v[..count]);
+                },
+                Some(LoadFlags(x)) => {
+                    let count = (x + 1).min(FLAG_COUNT);
+                    self.v[..count].copy_from_slice(&self.flags[..count]);
+                },
+                None => {
+                    self.unknown_instruction(instruction)?;
+                }
+            };
+
+            // update program counter if necessary
+            if next_instruction {
+                self.pc += 2;
+            }
+
+            if self.config.check_invariants {
+                if let Err(err) = self.check_invariants() {
+                    return Err(Chip8Error::InvariantViolated { instruction, why: err, state: self.state_dump() });
+                }
+            }
+
+            if let Some((pc, before)) = watch {
+                self.last_step = Some(ExecutedOp::new(pc, instruction, before.effects(&Watch::of(self))));
+            }
+            self.run_hook(|hooks, cpu| hooks.on_step(cpu))?;
+            if exited {
+                self.audio.pause();
+                return Ok(Some(Exit::RomExited));
+            }
+        }
+
+        let ticks_due = (self.win.elapsed() - self.run_state.timers_started).as_micros() * TIMER_HZ / 1_000_000;
+        // time spent paused doesn't count
+        if self.paused {
+            self.run_state.timer_ticks = ticks_due;
+        }
+        if self.run_state.timer_ticks < ticks_due {
+            while self.run_state.timer_ticks < ticks_due {
+                if self.dt > 0 { self.dt -= 1; }
+                if self.st > 0 { self.st -= 1; }
+                self.run_state.timer_ticks += 1;
+            }
+
+            if self.st > 0 {
+                self.audio.play();
+            }
+            else {
+                self.audio.pause();
+            }
+        }
+
+        self.run_state.instructions_left = self.run_state.instructions_left.saturating_sub(1);
+        if self.run_state.instructions_left == 0 {
+            span!("frame");
+            self.win.refresh(&self.framebuffer).map_err(Chip8Error::Frontend)?;
+            self.framebuffer.mark_clean();
+            self.counters.frames += 1;
+            self.cheats.apply(&mut self.ram);
+            self.run_state.sample_keys = true;
+            self.run_state.waiting_for_frame = false;
+            for key in self.win.hotkeys() {
+                self.hotkey(key);
+            }
+            if self.config.pause_on_focus_loss {
+                self.follow_focus();
+            }
+            // a sound still playing has to stop on time
+            let idle = (self.paused || self.run_state.waiting_for_keypress || self.in_delay_loop()) && self.st == 0;
+            self.win.set_status(Status { paused: self.paused, idle, speed: self.config.speed, ..Status::default() });
+            self.run_hook(|hooks, cpu| hooks.on_frame(cpu))?;
+            if self.restarted {
+                self.restarted = false;
+                self.run_state.waiting_for_keypress = false;
+                self.run_state.wait_key = None;
+                self.run_state.executing = true;
+            }
+
+            self.run_state.next_update(self.config.speed);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::chip8x::COLOURS;
+    use crate::config::{
+        FontLayout,
+        DEFAULT_SPEED
+    };
+    use crate::frontend::{
+        Display,
+        Input,
+        REFRESH_INTERVAL
+    };
+    use crate::headless::{
+        Headless,
+        KeyEvent,
+        Silence
+    };
+    use crate::quirks::Quirks;
+    use crate::testsuite::run_headless;
+
+    // a frontend that lets the CPU run for a fixed number of loop
+    // iterations (one instruction each) with a fixed set of keys held;
+    // each iteration takes one refresh interval
+    struct MockFrontend {
+        steps_left: Cell<usize>,
+        steps_taken: Cell<u32>,
+        keys: [bool; 16],
+        focused: bool
+    }
+
+    impl Display for MockFrontend {
+        fn refresh(&mut self, _framebuffer: &Framebuffer) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn elapsed(&self) -> Duration {
+            REFRESH_INTERVAL * self.steps_taken.get()
+        }
+    }
+
+    impl Input for MockFrontend {
+        fn is_open(&self) -> bool {
+            let steps = self.steps_left.get();
+            self.steps_left.set(steps.saturating_sub(1));
+            self.steps_taken.set(self.steps_taken.get() + 1);
+            steps > 0
+        }
+
+        fn handle_key_events(&self) -> [bool; 16] {
+            self.keys
+        }
+
+        fn focused(&self) -> bool {
+            self.focused
+        }
+    }
+
+    // a buzzer that remembers whether it is currently playing, and what
+    struct MockBuzzer {
+        playing: Rc<Cell<bool>>,
+        pattern: Rc<Cell<Option<[u8; PATTERN_LEN]>>>
+    }
+
+    impl Buzzer for MockBuzzer {
+        fn play(&self) { self.playing.set(true); }
+        fn pause(&self) { self.playing.set(false); }
+        fn set_pattern(&self, pattern: [u8; PATTERN_LEN]) { self.pattern.set(Some(pattern)); }
+    }
+
+    fn machine(program: &[u16], steps: usize, keys: [bool; 16]) -> (CPU, Rc<Cell<bool>>) {
+        machine_with_config(program, steps, keys, Config::default())
+    }
+
+    fn machine_with_config(program: &[u16], steps: usize, keys: [bool; 16], config: Config) -> (CPU, Rc<Cell<bool>>) {
+        let playing = Rc::new(Cell::new(false));
+        let win = MockFrontend { steps_left: Cell::new(steps), steps_taken: Cell::new(0), keys, focused: true };
+        let audio = MockBuzzer { playing: playing.clone(), pattern: Rc::default() };
+        let mut cpu = CPU::new(Box::new(win), Box::new(audio), config);
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        cpu.load_rom(&rom).unwrap();
+        (cpu, playing)
+    }
+
+    // run the first `steps` instructions of program
+    fn run(program: &[u16], steps: usize) -> CPU {
+        run_with_keys(program, steps, [false; 16])
+    }
+
+    fn run_with_keys(program: &[u16], steps: usize, keys: [bool; 16]) -> CPU {
+        let (mut cpu, _) = machine(program, steps, keys);
+        cpu.run_loop().unwrap();
+        cpu
+    }
+
+    fn run_err(program: &[u16], steps: usize) -> Chip8Error {
+        let (mut cpu, _) = machine(program, steps, [false; 16]);
+        cpu.run_loop().unwrap_err()
+    }
+
+    fn run_with_config(program: &[u16], steps: usize, config: Config) -> Result<CPU, Chip8Error> {
+        let (mut cpu, _) = machine_with_config(program, steps, [false; 16], config);
+        cpu.run_loop().map(|_| cpu)
+    }
+
+    #[test]
+    fn cls_clears_screen() {
+        let cpu = run(&[0xf029, 0xd005, 0x00e0], 3);
+        assert!(cpu.framebuffer.pixels().iter().all(|px| !px));
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn call_and_return() {
+        // 200: call 206, 202: V0 = 1, 206: ret
+        let cpu = run(&[0x2206, 0x6001, 0x0000, 0x00ee], 2);
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.sp, 0);
+        let cpu = run(&[0x2206, 0x6001, 0x0000, 0x00ee], 1);
+        assert_eq!(cpu.pc, 0x206);
+        assert_eq!(cpu.sp, 1);
+        assert_eq!(cpu.stack[0], 0x200);
+    }
+
+    #[test]
+    fn return_with_empty_stack_fails() {
+        assert_eq!(run_err(&[0x00ee], 1), Chip8Error::StackUnderflow { instruction: 0x00ee, pc: 0x200, stack: Vec::new() });
+    }
+
+    #[test]
+    fn call_with_full_stack_fails() {
+        // calls itself until the stack runs out
+        let err = run_err(&[0x2200], STACK_SIZE + 1);
+        assert_eq!(err, Chip8Error::StackOverflow { instruction: 0x2200, pc: 0x200, stack: vec![0x200; STACK_SIZE] });
+        assert!(err.to_string().starts_with("Stack full, cannot call subroutine (instruction 2200 at 200, call stack: [200, 200"));
+    }
+
+    #[test]
+    fn jump() {
+        assert_eq!(run(&[0x1abc], 1).pc, 0xabc);
+    }
+
+    #[test]
+    fn jump_with_offset() {
+        let cpu = run(&[0x6010, 0xb300], 2);
+        assert_eq!(cpu.pc, 0x310);
+    }
+
+    #[test]
+    fn skip_if_equal_immediate() {
+        assert_eq!(run(&[0x6a12, 0x3a12], 2).pc, 0x206);
+        assert_eq!(run(&[0x6a12, 0x3a13], 2).pc, 0x204);
+    }
+
+    #[test]
+    fn skip_if_not_equal_immediate() {
+        assert_eq!(run(&[0x6a12, 0x4a12], 2).pc, 0x204);
+        assert_eq!(run(&[0x6a12, 0x4a13], 2).pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_equal() {
+        assert_eq!(run(&[0x6105, 0x6205, 0x5120], 3).pc, 0x208);
+        assert_eq!(run(&[0x6105, 0x6206, 0x5120], 3).pc, 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_not_equal() {
+        assert_eq!(run(&[0x6105, 0x6205, 0x9120], 3).pc, 0x206);
+        assert_eq!(run(&[0x6105, 0x6206, 0x9120], 3).pc, 0x208);
+    }
+
+    #[test]
+    fn load_immediate() {
+        assert_eq!(run(&[0x6c42], 1).v[0xc], 0x42);
+    }
+
+    #[test]
+    fn add_immediate_wraps_without_flag() {
+        let cpu = run(&[0x63ff, 0x6f07, 0x7302], 3);
+        assert_eq!(cpu.v[3], 0x01);
+        assert_eq!(cpu.v[0xf], 0x07);
+    }
+
+    #[test]
+    fn register_moves_and_logic() {
+        let cpu = run(&[0x61f0, 0x623c, 0x8120], 3);
+        assert_eq!(cpu.v[1], 0x3c);
+        assert_eq!(run(&[0x61f0, 0x623c, 0x8121], 3).v[1], 0xfc);
+        assert_eq!(run(&[0x61f0, 0x623c, 0x8122], 3).v[1], 0x30);
+        assert_eq!(run(&[0x61f0, 0x623c, 0x8123], 3).v[1], 0xcc);
+    }
+
+    #[test]
+    fn add_registers_sets_carry() {
+        let cpu = run(&[0x61f0, 0x6220, 0x8124], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x10, 1));
+        let cpu = run(&[0x6110, 0x6220, 0x8124], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x30, 0));
+    }
+
+    #[test]
+    fn sub_registers_sets_not_borrow() {
+        let cpu = run(&[0x6130, 0x6220, 0x8125], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x10, 1));
+        let cpu = run(&[0x6120, 0x6230, 0x8125], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0xf0, 0));
+    }
+
+    #[test]
+    fn reverse_sub_registers_sets_not_borrow() {
+        let cpu = run(&[0x6120, 0x6230, 0x8127], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x10, 1));
+        let cpu = run(&[0x6130, 0x6220, 0x8127], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0xf0, 0));
+    }
+
+    #[test]
+    fn shifts_use_vx_and_set_lost_bit() {
+        let cpu = run(&[0x6105, 0x62ff, 0x8126], 3);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x02, 1));
+        let cpu = run(&[0x6181, 0x8126], 2);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x40, 1));
+        let cpu = run(&[0x6181, 0x812e], 2);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x02, 1));
+        let cpu = run(&[0x6141, 0x812e], 2);
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0x82, 0));
+    }
+
+    #[test]
+    fn load_i() {
+        assert_eq!(run(&[0xa123], 1).i, 0x123);
+    }
+
+    #[test]
+    fn random_is_masked() {
+        let cpu = run(&[0x65ff, 0xc500], 2);
+        assert_eq!(cpu.v[5], 0);
+        let cpu = run(&[0xc50f], 1);
+        assert_eq!(cpu.v[5] & 0xf0, 0);
+    }
+
+    #[test]
+    fn random_seed_repeats() {
+        // the seed is only read when the CPU is created
+        let rom = [0xc0, 0xff, 0xc1, 0xff, 0xc2, 0xff, 0xc3, 0xff, 0x12, 0x08];
+        let config = Config { random_seed: Some(42), ..Config::default() };
+        let first = run_headless(&rom, 1, config);
+        let second = run_headless(&rom, 1, config);
+        assert_eq!(first.v[..4], second.v[..4]);
+    }
+
+    #[test]
+    fn draw_sets_collision_flag() {
+        // draw the "0" glyph at (0, 0) once, then again on top of itself
+        let cpu = run(&[0xd005], 1);
+        assert_eq!(cpu.v[0xf], 0);
+        assert_eq!(&cpu.framebuffer.pixels()[..4], &[true; 4]);
+        let cpu = run(&[0xd005, 0xd005], 2);
+        assert_eq!(cpu.v[0xf], 1);
+        assert!(cpu.framebuffer.pixels().iter().all(|px| !px));
+    }
+
+    #[test]
+    fn draw_at_top_of_memory() {
+        // rows past 0xfff come from the start of RAM, ie. the "0" glyph
+        let cpu = run(&[0xaffe, 0xd004], 2);
+        let rows: Vec<&[bool]> = cpu.framebuffer.pixels().chunks(64).take(4).map(|r| &r[..8]).collect();
+        assert_eq!(rows[2], &[true, true, true, true, false, false, false, false]);
+        assert_eq!(rows[3], &[true, false, false, true, false, false, false, false]);
+        let config = Config { memory: MemoryPolicy::Error, ..Config::default() };
+        let err = run_with_config(&[0xaffe, 0xd004], 2, config).err().unwrap();
+        assert_eq!(err, Chip8Error::OutOfBounds { addr: 0x1000, pc: 0x202, write: false });
+    }
+
+    #[test]
+    fn registers_at_top_of_memory() {
+        // Fx55, Fx33 and Fx65 past 0xfff carry on from address 0
+        let cpu = run(&[0xafff, 0x6007, 0x6108, 0xf155, 0x62ff, 0xf233, 0x6000, 0xf165], 8);
+        assert_eq!((cpu.ram[0xfff], cpu.ram[0], cpu.ram[1]), (2, 5, 5));
+        assert_eq!(&cpu.v[..2], &[2, 5]);
+        let config = Config { memory: MemoryPolicy::Error, ..Config::default() };
+        let err = run_with_config(&[0xafff, 0xf155], 2, config).err().unwrap();
+        assert_eq!(err, Chip8Error::OutOfBounds { addr: 0x1000, pc: 0x202, write: true });
+        let err = run_with_config(&[0xafff, 0xf165], 2, config).err().unwrap();
+        assert_eq!(err, Chip8Error::OutOfBounds { addr: 0x1000, pc: 0x202, write: false });
+    }
+
+    #[test]
+    fn skip_on_key() {
+        let mut keys = [false; 16];
+        keys[0xa] = true;
+        assert_eq!(run_with_keys(&[0x630a, 0xe39e], 2, keys).pc, 0x206);
+        assert_eq!(run_with_keys(&[0x630b, 0xe39e], 2, keys).pc, 0x204);
+        assert_eq!(run_with_keys(&[0x630a, 0xe3a1], 2, keys).pc, 0x204);
+        assert_eq!(run_with_keys(&[0x630b, 0xe3a1], 2, keys).pc, 0x206);
+    }
+
+    #[test]
+    fn skip_on_key_uses_low_nibble() {
+        let mut keys = [false; 16];
+        keys[0xa] = true;
+        assert_eq!(run_with_keys(&[0x63fa, 0xe39e], 2, keys).pc, 0x206);
+        assert_eq!(run_with_keys(&[0x631a, 0xe3a1], 2, keys).pc, 0x204);
+        assert_eq!(run_with_keys(&[0x63f0, 0xe39e], 2, keys).pc, 0x204);
+    }
+
+    #[test]
+    fn wait_for_key() {
+        // without a key the CPU stays put after Fx0A
+        let cpu = run(&[0xf40a, 0x6001], 10);
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.v[0], 0);
+        // nor while key 7 is held
+        let mut keys = [false; 16];
+        keys[7] = true;
+        let cpu = run_with_keys(&[0xf40a, 0x6001], 100, keys);
+        assert_eq!((cpu.pc, cpu.v[0]), (0x202, 0));
+        // once it is let go, it is stored in V4 and execution carries on
+        let script = vec![
+            KeyEvent { frame: 1, key: 7, down: true },
+            KeyEvent { frame: 3, key: 7, down: false }
+        ];
+        let mut cpu = CPU::new(Box::new(Headless::with_script(5, script)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0xf4, 0x0a, 0x60, 0x01, 0x12, 0x04]).unwrap();
+        cpu.run_loop().unwrap();
+        assert_eq!((cpu.v[4], cpu.v[0]), (7, 1));
+    }
+
+    #[test]
+    fn step_runs_one_instruction() {
+        let (mut cpu, _) = machine(&[0x6001, 0x7102, 0xf40a], 100, [false; 16]);
+        assert_eq!(cpu.step(), Ok(Step::Ran));
+        assert_eq!((cpu.pc(), cpu.v[0], cpu.v[1]), (0x202, 1, 0));
+        assert_eq!(cpu.step(), Ok(Step::Ran));
+        assert_eq!((cpu.pc(), cpu.v[1]), (0x204, 2));
+        assert_eq!(cpu.step(), Ok(Step::Ran));
+        assert_eq!(cpu.step(), Ok(Step::WaitingForKey));
+        assert_eq!(cpu.counters().instructions, 3);
+        while cpu.step() == Ok(Step::WaitingForKey) {}
+        assert_eq!(cpu.step(), Ok(Step::Halted(Exit::Closed)));
+    }
+
+    #[test]
+    fn key_wait_timeout() {
+        let (mut cpu, _) = machine(&[0x6001, 0xf40a], 1000, [false; 16]);
+        cpu.config.key_wait_timeout = Some(Duration::from_millis(100));
+        assert_eq!(cpu.run_loop(), Ok(Exit::WaitingForInput(0x202)));
+        let (mut cpu, _) = machine(&[0x6001, 0xf40a], 1000, [false; 16]);
+        assert_eq!(cpu.run_loop(), Ok(Exit::Closed));
+    }
+
+    #[test]
+    fn delay_timer() {
+        let cpu = run(&[0x6233, 0xf215, 0xf307], 3);
+        assert_eq!(cpu.dt, 0x33);
+        assert_eq!(cpu.v[3], 0x33);
+    }
+
+    #[test]
+    fn sound_timer_drives_buzzer() {
+        // eight steps of 1/480 s make one 60 Hz tick
+        let (mut cpu, playing) = machine(&[0x6220, 0xf218, 0x1204], 9, [false; 16]);
+        cpu.run_loop().unwrap();
+        assert!(playing.get());
+        assert_eq!(cpu.st, 0x1f);
+    }
+
+    #[test]
+    fn audio_patterns_go_to_the_buzzer() {
+        let pattern = Rc::new(Cell::new(None));
+        let win = MockFrontend { steps_left: Cell::new(2), steps_taken: Cell::new(0), keys: [false; 16], focused: true };
+        let audio = MockBuzzer { playing: Rc::default(), pattern: pattern.clone() };
+        let mut cpu = CPU::new(Box::new(win), Box::new(audio), Config::default());
+        // the 16 bytes from 0x0fff, wrapping around to the font
+        cpu.load_rom(&[0xaf, 0xff, 0xf0, 0x02]).unwrap();
+        cpu.ram[0xfff] = 0xaa;
+        cpu.run_loop().unwrap();
+        let played = pattern.get().unwrap();
+        assert_eq!(played[0], 0xaa);
+        assert_eq!(&played[1..6], &RAM_DIGITS[0]);
+    }
+
+    #[test]
+    fn pauses_in_the_background() {
+        let program: [u16; 4] = [0x6220, 0xf218, 0x7001, 0x1204];
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        let run_unfocused = |config: Config| {
+            let playing = Rc::new(Cell::new(false));
+            let win = MockFrontend { steps_left: Cell::new(100), steps_taken: Cell::new(0), keys: [false; 16], focused: false };
+            let mut cpu = CPU::new(Box::new(win), Box::new(MockBuzzer { playing: playing.clone(), pattern: Rc::default() }), config);
+            cpu.load_rom(&rom).unwrap();
+            cpu.run_loop().unwrap();
+            (cpu, playing.get())
+        };
+        // the first frame is shown after 9 steps, and nothing runs after it
+        let (cpu, playing) = run_unfocused(Config::default());
+        assert!(cpu.paused());
+        assert!(!playing);
+        assert!(cpu.v[0] < 9);
+        let (cpu, playing) = run_unfocused(Config { pause_on_focus_loss: false, ..Config::default() });
+        assert!(!cpu.paused());
+        assert!(playing);
+    }
+
+    #[test]
+    fn pause_menu() {
+        // count up in v0
+        let mut cpu = run(&[0x7001, 0x1200], 10);
+        let counted = cpu.v[0];
+        assert!(counted > 0);
+        cpu.hotkey(Hotkey::Menu);
+        assert!(cpu.paused());
+        assert_eq!(cpu.overlay[0], "> Resume");
+
+        // save state, count some more, then load it back
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuSelect);
+        assert!(cpu.menu.is_none() && !cpu.paused());
+        cpu.v[0] = 0xff;
+        cpu.hotkey(Hotkey::Menu);
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuSelect);
+        assert_eq!(cpu.v[0], counted);
+        assert!(cpu.overlay.is_empty());
+
+        // reset starts the ROM over
+        cpu.hotkey(Hotkey::Menu);
+        cpu.hotkey(Hotkey::MenuDown);
+        cpu.hotkey(Hotkey::MenuSelect);
+        assert_eq!((cpu.v[0], cpu.pc), (0, PROGRAM_START));
+        assert_eq!(cpu.ram[PROGRAM_START..PROGRAM_START + 4], [0x70, 0x01, 0x12, 0x00]);
+
+        cpu.hotkey(Hotkey::Menu);
+        cpu.hotkey(Hotkey::MenuUp);
+        cpu.hotkey(Hotkey::MenuSelect);
+        assert!(cpu.quit);
+    }
+
+    #[test]
+    fn save_slots() {
+        let (mut cpu, _) = machine(&[0x7001, 0x1200], 10, [false; 16]);
+        cpu.run_loop().unwrap();
+        let counted = cpu.v[0];
+        cpu.hotkey(Hotkey::SaveSlot(3));
+        assert_eq!(cpu.overlay, ["Could not use slot 3: there is nowhere to keep save slots"]);
+
+        // a directory of this test's own, so parallel runs don't share it
+        let dir = std::env::temp_dir().join(format!("chip8-states-{}-save_slots", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        cpu.set_state_dir(dir.clone());
+        cpu.hotkey(Hotkey::SaveSlot(3));
+        assert_eq!(cpu.overlay, ["Saved to slot 3"]);
+        assert!(dir.join("3.state").exists());
+        cpu.v[0] = 0xff;
+        cpu.hotkey(Hotkey::LoadSlot(3));
+        assert_eq!(cpu.overlay, ["Loaded slot 3"]);
+        assert_eq!(cpu.v[0], counted);
+        cpu.hotkey(Hotkey::LoadSlot(4));
+        assert_eq!(cpu.overlay, ["Slot 4 is empty"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_palette() {
+        let mut cpu = run(&[0x7001, 0x1200], 10);
+        let type_in = |cpu: &mut CPU, text: &str| {
+            text.chars().for_each(|c| cpu.hotkey(Hotkey::Type(c)));
+            cpu.hotkey(Hotkey::MenuSelect);
+        };
+        cpu.hotkey(Hotkey::Palette);
+        assert!(cpu.paused());
+        assert_eq!(cpu.overlay[0], ":_");
+        type_in(&mut cpu, "ld st");
+        assert_eq!(cpu.overlay, [":_", "No state has", "been saved yet"]);
+        type_in(&mut cpu, "qu i-o");
+        assert!(cpu.config.quirks.i_overflow_sets_vf);
+        assert_eq!(cpu.overlay[1], "OK");
+        // pausing from the palette keeps the CPU paused once it's closed
+        type_in(&mut cpu, "pau");
+        cpu.hotkey(Hotkey::Palette);
+        assert!(cpu.paused() && cpu.overlay.is_empty());
+        cpu.hotkey(Hotkey::Palette);
+        type_in(&mut cpu, "res");
+        cpu.hotkey(Hotkey::Palette);
+        assert!(!cpu.paused());
+    }
+
+    #[test]
+    fn slow_motion() {
+        // a second's worth of loop iterations, at 20 instructions a second
+        let (mut cpu, _) = machine(&[0x7001, 0x1200], 9 * 480, [false; 16]);
+        cpu.set_slow_motion(Some(20));
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.v[0], 10);
+        assert_eq!(cpu.counters().frames, 480);
+    }
+
+    #[test]
+    fn speed_is_instructions_a_frame() {
+        // a second is 480 screen updates; at under 8 instructions a frame
+        // some of them run nothing
+        for speed in [4, 15, DEFAULT_SPEED, 1000] {
+            let config = Config { speed, log_steps: false, ..Config::default() };
+            let cpu = run_headless(&[0x70, 0x01, 0x12, 0x00], 480, config);
+            assert_eq!(cpu.counters().instructions, speed as u64 * 60, "at {}", speed);
+        }
+        let (mut cpu, _) = machine(&[0x1200], 1, [false; 16]);
+        cpu.hotkey(Hotkey::Slower);
+        assert_eq!(cpu.speed(), 50);
+        cpu.hotkey(Hotkey::Faster);
+        cpu.hotkey(Hotkey::Faster);
+        assert_eq!(cpu.speed(), 100);
+        cpu.set_speed(10000);
+        cpu.hotkey(Hotkey::Faster);
+        assert_eq!(cpu.speed(), 10000);
+    }
+
+    #[test]
+    fn delay_loops_are_skipped() {
+        // wait for 10 ticks of the delay timer, then count once
+        let rom = [0x600a, 0xf015, 0xf107, 0x3100, 0x1204, 0x7201, 0x120c];
+        let run = |skip, steps| {
+            let (mut cpu, _) = machine(&rom, steps, [false; 16]);
+            cpu.config.skip_delay_loops = skip;
+            cpu.run_loop().unwrap();
+            cpu
+        };
+        // a step is a refresh interval, so the wait takes 80; halfway
+        // through, only the setup has run
+        assert_eq!(run(true, 40).counters().instructions, 2);
+        let (ran, skipped) = (run(false, 200), run(true, 200));
+        assert_eq!((skipped.v, skipped.pc), (ran.v, ran.pc));
+        assert_eq!((skipped.v[2], skipped.pc), (1, 0x20c));
+    }
+
+    #[test]
+    fn timers_tick_at_60hz() {
+        // 480 steps at 480 Hz is one second
+        let (mut cpu, playing) = machine(&[0x6250, 0xf215, 0xf218, 0x1206], 482, [false; 16]);
+        cpu.run_loop().unwrap();
+        assert_eq!((cpu.dt, cpu.st), (0x50 - 60, 0x50 - 60));
+        assert!(playing.get());
+        // half a second is 30 ticks however many instructions run in it
+        for speed in [7, 10000] {
+            let config = Config { speed, log_steps: false, ..Config::default() };
+            let cpu = run_headless(&[0x60, 0x50, 0xf0, 0x15, 0x12, 0x04], 244, config);
+            assert_eq!(cpu.dt, 0x50 - 30, "at {}", speed);
+        }
+    }
+
+    #[test]
+    fn add_to_i() {
+        let cpu = run(&[0xa100, 0x6f05, 0x6010, 0xf01e], 4);
+        assert_eq!(cpu.i, 0x110);
+        assert_eq!(cpu.v[0xf], 5);
+    }
+
+    #[test]
+    fn add_to_i_wraps_and_sets_flag_with_quirk() {
+        let cpu = run(&[0xafff, 0x6002, 0xf01e], 3);
+        assert_eq!((cpu.i, cpu.v[0xf]), (0x001, 0));
+        let mut config = Config::default();
+        config.quirks.i_overflow_sets_vf = true;
+        let cpu = run_with_config(&[0xafff, 0x6002, 0xf01e], 3, config).unwrap();
+        assert_eq!((cpu.i, cpu.v[0xf]), (0x001, 1));
+    }
+
+    #[test]
+    fn quirks_follow_the_preset() {
+        let vip = Config { quirks: Quirks::preset("vip").unwrap(), ..Config::default() };
+        let schip = Config { quirks: Quirks::preset("schip").unwrap(), ..Config::default() };
+        // shifting Vy into Vx
+        let shift = [0x6081, 0x6103, 0x8016];
+        assert_eq!(run(&shift, 3).v[0], 0x40);
+        assert_eq!(run_with_config(&shift, 3, vip).unwrap().v[0], 0x01);
+        // VF reset by OR
+        let or = [0x6f05, 0x6001, 0x8011];
+        assert_eq!(run(&or, 3).v[0xf], 5);
+        assert_eq!(run_with_config(&or, 3, vip).unwrap().v[0xf], 0);
+        // I left past the registers stored
+        let store = [0xa300, 0x6001, 0xf155];
+        assert_eq!(run(&store, 3).i, 0x300);
+        assert_eq!(run_with_config(&store, 3, vip).unwrap().i, 0x302);
+        // Bnnn using Vx
+        let jump = [0x6105, 0x6000, 0xb100];
+        assert_eq!(run(&jump, 3).pc, 0x100);
+        assert_eq!(run_with_config(&jump, 3, schip).unwrap().pc, 0x105);
+        // nothing more until the next frame after a draw
+        let draw = [0xa000, 0xd015, 0x7101, 0x7101];
+        assert_eq!(run(&draw, 4).v[1], 2);
+        assert_eq!(run_with_config(&draw, 4, vip).unwrap().v[1], 0);
+    }
+
+    #[test]
+    fn font_sprite_address() {
+        assert_eq!(run(&[0x6a0b, 0xfa29], 2).i, 0xb0);
+    }
+
+    #[test]
+    fn big_font_sprite_address() {
+        let cpu = run(&[0x6a09, 0xfa30], 2);
+        assert_eq!(cpu.i, 0x15a);
+        assert_eq!(&cpu.ram[cpu.i..cpu.i + 10], &RAM_BIG_DIGITS[9]);
+        let (mut cpu, _) = machine(&[0x6a09, 0xfa30], 2, [false; 16]);
+        cpu.config.font_layout = FontLayout::Standard;
+        cpu.reset();
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.i, 0x50 + 90);
+        assert_eq!(&cpu.ram[cpu.i..cpu.i + 10], &RAM_BIG_DIGITS[9]);
+        assert_eq!(&cpu.ram[0x4b..0x50], &RAM_DIGITS[0xf]);
+    }
+
+    #[test]
+    fn exit_ends_the_run() {
+        let (mut cpu, playing) = machine(&[0x60ff, 0xf018, 0x00fd, 0x6001], 100, [false; 16]);
+        assert_eq!(cpu.run_loop(), Ok(Exit::RomExited));
+        assert_eq!((cpu.pc, cpu.v[0]), (0x204, 0xff));
+        assert!(!playing.get());
+        assert_eq!(cpu.step(), Ok(Step::Halted(Exit::RomExited)));
+    }
+
+    #[test]
+    fn superchip_screen() {
+        // high resolution, then the big 0 at (100, 50)
+        let cpu = run(&[0x00ff, 0x6064, 0x6132, 0xf230, 0xd01a], 5);
+        let fb = cpu.framebuffer();
+        assert_eq!((fb.width(), fb.height()), (128, 64));
+        assert_eq!(fb.pixels().iter().filter(|px| **px).count(), 56);
+        assert!(fb.pixels()[50 * 128 + 100]);
+        // a 16x16 sprite of the small font's bytes, scrolled down and right
+        let cpu = run(&[0xa000, 0xd000, 0x00c2, 0x00fb], 4);
+        let fb = cpu.framebuffer();
+        assert_eq!((fb.width(), fb.height()), (64, 32));
+        assert!(!fb.pixels()[0]);
+        assert!(fb.pixels()[2 * 64 + 4]);
+        // back to low resolution clears the screen
+        let cpu = run(&[0x00ff, 0xa000, 0xd015, 0x00fe], 4);
+        assert!(cpu.framebuffer().pixels().iter().all(|px| !px));
+    }
+
+    #[test]
+    fn long_loads_are_skipped_whole() {
+        // skips over an F000 and its address take 4 bytes
+        let cpu = run(&[0xf000, 0x0abc, 0x3000, 0xf000, 0x0123, 0x6101], 3);
+        assert_eq!((cpu.i, cpu.v[1]), (0xabc, 1));
+        let cpu = run(&[0x3001, 0xf000, 0x0123, 0x6101], 3);
+        assert_eq!((cpu.i, cpu.v[1]), (0x123, 1));
+    }
+
+    #[test]
+    fn chip8x() {
+        let program: [u16; 11] = [
+            0x6104, 0x6011, 0x6210, 0xb020, // strips 1-2 of zones 0-1 green
+            0x02a0,                         // black background
+            0x6335, 0x6446, 0x5341,         // V3 = 0x73
+            0xe5f5, 0x6601, 0x6701          // key 0 isn't down on keypad 2
+        ];
+        let config = Config { platform: Platform::Chip8X, ..Config::default() };
+        let (mut cpu, _) = machine_with_config(&program, 10, [false; 16], config);
+        assert_eq!(cpu.pc, 0x300);
+        cpu.run_loop().unwrap();
+        assert_eq!((cpu.v[3], cpu.v[6], cpu.v[7]), (0x73, 0, 1));
+        let zones = cpu.framebuffer().colour_zones().unwrap();
+        assert_eq!((zones.rgb(8, 7, true), zones.rgb(0, 0, true)), (COLOURS[4], COLOURS[1]));
+        assert_eq!(zones.rgb(0, 0, false), COLOURS[0]);
+        // and on the usual platform, Bnnn is still a jump
+        assert!(run(&[0x6104, 0xb020], 2).framebuffer().colour_zones().is_none());
+    }
+
+    #[test]
+    fn megachip_roms_stop() {
+        assert_eq!(run_err(&[0x6001, 0x0011], 2), Chip8Error::MegaChip { pc: 0x202 });
+    }
+
+    #[test]
+    fn megachip() {
+        let mut program = vec![
+            0x0011,         // MegaChip mode
+            0x0100, 0x1000, // I = 1000, past the first 4K
+            0x0201,         // palette colour 1 from there
+            0x0302, 0x0402, // 2x2 sprites
+            0x0100, 0x1004, // I = 1004
+            0x6003, 0xd001, // drawn at (3, 3)
+            0x00e0          // and shown
+        ];
+        // the palette and sprite, where the ROM goes on past 4K
+        program.resize((0x1000 - PROGRAM_START) / 2, 0);
+        program.extend([0xffff, 0x0000, 0x0101, 0x0001]);
+        let config = Config { platform: Platform::MegaChip, ..Config::default() };
+        let mut cpu = run_with_config(&program, 9, config).unwrap();
+        let fb = cpu.framebuffer();
+        assert_eq!((fb.resolution(), cpu.i), (Resolution::Mega, 0x1004));
+        let at = |x: usize, y: usize| y * 256 + x;
+        assert_eq!([fb.colours()[at(3, 3)], fb.colours()[at(4, 3)], fb.colours()[at(3, 4)], fb.colours()[at(4, 4)]], [1, 1, 0, 1]);
+        assert_eq!(fb.mega_screen().unwrap().shown()[at(4, 4)], 0xff0000);
+        // addresses set from outside wrap at 16MB rather than 4K
+        cpu.set_i(0x12345);
+        cpu.set_pc(0x1000003);
+        assert_eq!((cpu.i, cpu.pc), (0x12345, 3));
+        // too big for the other platforms
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        assert!(matches!(machine(&[], 0, [false; 16]).0.load_rom(&rom), Err(Chip8Error::RomTooLarge { .. })));
+    }
+
+    #[test]
+    fn register_ranges() {
+        // V1-V3 saved at 0x300, then loaded back the other way round into
+        // V6-V4, with I left where it was
+        let cpu = run(&[0x6101, 0x6202, 0x6303, 0xa300, 0x5132, 0x5643], 6);
+        assert_eq!(&cpu.ram[0x300..0x303], &[1, 2, 3]);
+        assert_eq!(&cpu.v[4..7], &[3, 2, 1]);
+        assert_eq!(cpu.i, 0x300);
+    }
+
+    #[test]
+    fn xochip_planes() {
+        // a row on each of planes 1 and 2 at once, then plane 2 cleared
+        let program = [0xf301, 0xa20a, 0xd001, 0xf201, 0x00e0, 0xc060];
+        assert_eq!(&run(&program, 3).framebuffer().colours()[..4], &[1, 3, 2, 0]);
+        assert_eq!(&run(&program, 5).framebuffer().colours()[..4], &[1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn scrolls_move_half_as_far_in_low_resolution_on_superchip() {
+        let schip = Config { quirks: Quirks::preset("schip").unwrap(), ..Config::default() };
+        let scroll = [0xa000, 0xd001, 0x00c4, 0x00fb];
+        // the font's 0 is 4 pixels wide
+        let lit = |cpu: &CPU| cpu.framebuffer().pixels().iter().position(|px| *px);
+        assert_eq!(lit(&run(&scroll, 4)), Some(4 * 64 + 4));
+        assert_eq!(lit(&run_with_config(&scroll, 4, schip).unwrap()), Some(2 * 64 + 2));
+        let hires = [0x00ff, 0xa000, 0xd001, 0x00c4, 0x00fb];
+        assert_eq!(lit(&run_with_config(&hires, 5, schip).unwrap()), Some(4 * 128 + 4));
+    }
+
+    #[test]
+    fn two_page_roms_get_a_64x64_screen() {
+        // the jump over the interpreter, its 0230 clear, then a 0 drawn
+        // below where a 64x32 screen ends
+        let mut program = vec![0x1260; (TWO_PAGE_START - PROGRAM_START) / 2];
+        program.extend_from_slice(&[0x0230, 0x6128, 0xa000, 0xd015]);
+        let cpu = run(&program, 4);
+        let fb = cpu.framebuffer();
+        assert_eq!((fb.width(), fb.height()), (64, 64));
+        assert!(fb.pixels()[40 * 64]);
+        assert_eq!(cpu.pc, TWO_PAGE_START + 8);
+    }
+
+    #[test]
+    fn flags_are_kept_apart_from_ram() {
+        let cpu = run(&[0x6007, 0x6109, 0xf175, 0x6000, 0x6100, 0xf185], 6);
+        assert_eq!(&cpu.v[..2], &[7, 9]);
+        assert!(cpu.ram[PROGRAM_START + 12..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn standard_font_layout() {
+        let (mut cpu, _) = machine(&[0x6a0b, 0xfa29], 2, [false; 16]);
+        cpu.config.font_layout = FontLayout::Standard;
+        cpu.preload_ram();
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.i, 55);
+        assert_eq!(&cpu.ram[55..60], &RAM_DIGITS[0xb]);
+    }
+
+    #[test]
+    fn bcd() {
+        let cpu = run(&[0x69fe, 0xa300, 0xf933], 3);
+        assert_eq!(&cpu.ram[0x300..0x303], &[2, 5, 4]);
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let cpu = run(&[0x6011, 0x6122, 0x6233, 0xa300, 0xf155], 5);
+        assert_eq!(&cpu.ram[0x300..0x303], &[0x11, 0x22, 0x00]);
+        assert_eq!(cpu.i, 0x300);
+        let cpu = run(&[0xa20a, 0xf165, 0x0000, 0x0000, 0x0000, 0xabcd], 2);
+        assert_eq!(&cpu.v[..3], &[0xab, 0xcd, 0x00]);
+    }
+
+    #[test]
+    fn rom_size_limits() {
+        let (mut cpu, _) = machine(&[], 0, [false; 16]);
+        assert!(cpu.load_rom(&[0xaa; RAM_SIZE - PROGRAM_START]).is_ok());
+        assert_eq!(cpu.ram[RAM_SIZE - 1], 0xaa);
+        let err = cpu.load_rom(&[0xbb; RAM_SIZE - PROGRAM_START + 1]).unwrap_err();
+        assert_eq!(err, Chip8Error::RomTooLarge { size: 3585, available: 3584, start: 0x200 });
+        cpu.config.truncate_rom = true;
+        assert!(cpu.load_rom(&[0xbb; RAM_SIZE - PROGRAM_START + 1]).is_ok());
+        assert_eq!(cpu.ram[RAM_SIZE - 1], 0xbb);
+    }
+
+    #[test]
+    fn pc_leaving_ram_fails() {
+        let err = run_err(&[0x1ffe], 3);
+        assert!(matches!(err, Chip8Error::PcOutOfRange { pc: 0x1000, .. }));
+        assert!(err.to_string().starts_with("Program counter out of range: 1000"));
+    }
+
+    #[test]
+    fn unknown_opcode_policy() {
+        assert_eq!(run(&[0x0123, 0x6001], 2).v[0], 1);
+        let config = Config { unknown_opcode: UnknownOpcodePolicy::Halt, ..Config::default() };
+        assert!(run_with_config(&[0x0123, 0x6001], 2, config).is_err());
+    }
+
+    #[test]
+    fn invariant_checker_catches_misaligned_pc() {
+        let config = Config { check_invariants: true, ..Config::default() };
+        let err = run_with_config(&[0x1201], 1, config).err().unwrap().to_string();
+        assert!(err.starts_with("Invariant violated after instruction 1201: PC 201 is not aligned"));
+        assert!(err.contains("PC=201"));
+    }
+
+    #[test]
+    fn unknown_opcodes_are_counted() {
+        // 200: unknown, 202: V0 += 1, 204: skip unless V0 == 3, 206: back to 200
+        let cpu = run(&[0xe0a0, 0x7001, 0x3003, 0x1200, 0xf0ff], 12);
+        let seen: Vec<_> = cpu.unknown_opcodes().iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(seen, [((0x200, 0xe0a0), 3), ((0x208, 0xf0ff), 1)]);
+    }
+
+    #[test]
+    fn native_calls_are_noted_apart() {
+        // draw, clear with the hi-res routine, call one we don't know
+        let program = [0xd005, 0x0230, 0x02f0, 0x120a];
+        let cpu = run(&program, 4);
+        let seen: Vec<_> = cpu.native_calls().iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(seen, [((0x202, 0x0230), 1), ((0x204, 0x02f0), 1)]);
+        assert!(cpu.unknown_opcodes().is_empty());
+        assert!(cpu.framebuffer().pixels().iter().any(|&on| on));
+        let config = Config { stub_native_calls: true, ..Config::default() };
+        let cpu = run_with_config(&program, 4, config).unwrap();
+        assert!(cpu.framebuffer().pixels().iter().all(|&on| !on));
+    }
+
+    #[test]
+    fn counters_keep_running_totals() {
+        // draw, then an unknown instruction, then loop
+        let counters = run(&[0xd005, 0x0000, 0x1200], 9).counters();
+        assert_eq!(counters, Counters {
+            instructions: 9,
+            frames: 1,
+            draws: 3,
+            unknown_opcodes: 3,
+            audio_underruns: None
+        });
+    }
+
+    #[test]
+    fn executed_instructions_are_counted() {
+        let program = [0x0123, 0x7001, 0x3003, 0x1200, 0x0456];
+        assert!(run(&program, 12).executed().is_empty());
+        let config = Config { opcode_stats: true, ..Config::default() };
+        let cpu = run_with_config(&program, 12, config).unwrap();
+        let counts: Vec<_> = cpu.executed().iter().map(|((pc, _), n)| (*pc, *n)).collect();
+        assert_eq!(counts, [(0x200, 3), (0x202, 3), (0x204, 3), (0x206, 2), (0x208, 1)]);
+    }
+
+    #[test]
+    fn drawn_sprites_are_recorded() {
+        let program = [0xa20a, 0xd015, 0xf029, 0xd013, 0xd010];
+        assert!(run(&program, 5).sprites_drawn().is_empty());
+        let config = Config { record_sprites: true, ..Config::default() };
+        let cpu = run_with_config(&program, 5, config).unwrap();
+        assert_eq!(cpu.sprites_drawn().iter().copied().collect::<Vec<_>>(), [(0x000, 0), (0x000, 3), (0x20a, 5)]);
+    }
+
+    #[test]
+    fn registers_as_json() {
+        let cpu = run(&[0x6a05, 0xa123, 0x2206, 0x0000], 3);
+        assert_eq!(cpu.registers_json(), concat!(
+            "{\n  \"pc\": 518,\n  \"i\": 291,\n",
+            "  \"v\": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0],\n",
+            "  \"sp\": 1,\n  \"stack\": [516],\n  \"dt\": 0,\n  \"st\": 0\n}\n"
+        ));
+    }
+
+    #[test]
+    fn frozen_addresses_are_reset_every_frame() {
+        // 200: I = 300, 202: V0 = 5, 204: save V0, 206: back to 204
+        let (mut cpu, _) = machine(&[0xa300, 0x6005, 0xf055, 0x1204], 9, [false; 16]);
+        cpu.cheats_mut().freeze(0x300, 9);
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.ram[0x300], 9);
+        assert_eq!(cpu.search_ram(Filter::Equal(9)), 1);
+    }
+
+    #[test]
+    fn breakpoints_pause_and_step() {
+        // 200: V0 += 1, 202: V1 += 1, 204: jump back
+        let (mut cpu, _) = machine(&[0x7001, 0x7101, 0x1200], 4, [false; 16]);
+        cpu.add_breakpoint(0x202);
+        cpu.run_loop().unwrap();
+        assert!(cpu.paused());
+        assert_eq!((cpu.pc(), cpu.v()[0], cpu.v()[1]), (0x202, 1, 0));
+
+        cpu.request_step();
+        cpu.win = Box::new(MockFrontend { steps_left: Cell::new(2), steps_taken: Cell::new(0), keys: [false; 16], focused: true });
+        cpu.run_loop().unwrap();
+        assert_eq!((cpu.pc(), cpu.v()[1]), (0x204, 1));
+
+        // resuming at a breakpoint doesn't stop there again straight away
+        cpu.set_pc(0x202);
+        cpu.set_paused(false);
+        cpu.win = Box::new(MockFrontend { steps_left: Cell::new(4), steps_taken: Cell::new(0), keys: [false; 16], focused: true });
+        cpu.run_loop().unwrap();
+        assert_eq!((cpu.pc(), cpu.v()[0], cpu.v()[1]), (0x202, 2, 2));
+    }
+
+    #[test]
+    fn font_write_protection() {
+        let program = [0xa000, 0xf033];
+        // BCD of V0 = 0 overwrites the top row of the "0" glyph
+        assert_eq!(run(&program, 2).ram[0], 0);
+        let config = Config { font_protection: WriteProtection::Reject, ..Config::default() };
+        assert!(run_with_config(&program, 2, config).is_err());
+    }
+
+    // what 8xyN should leave in Vx and VF, worked out independently of the CPU
+    fn reference_alu(n: u16, vx: u8, vy: u8) -> (u8, u8) {
+        match n {
+            0x4 => {
+                let sum = vx as u16 + vy as u16;
+                (sum as u8, (sum > 0xff) as u8)
+            },
+            0x5 => (vx.wrapping_sub(vy), (vx >= vy) as u8),
+            0x7 => (vy.wrapping_sub(vx), (vy >= vx) as u8),
+            0x6 => (vx >> 1, vx & 1),
+            0xe => (vx << 1, vx >> 7),
+            _ => unreachable!()
+        }
+    }
+
+    proptest! {
+        // any registers, VF included, and any values: the result lands in Vx
+        // first and the flag in VF last, so the flag wins when x is F
+        #[test]
+        fn alu_matches_reference(
+            n in prop::sample::select(vec![0x4u16, 0x5, 0x6, 0x7, 0xe]),
+            x in 0..16u16,
+            y in 0..16u16,
+            a: u8,
+            b: u8
+        ) {
+            let program = [0x6000 | x << 8 | a as u16, 0x6000 | y << 8 | b as u16, 0x8000 | x << 8 | y << 4 | n];
+            let before = run(&program[..2], 2);
+            let (vx, vy) = (before.v[x as usize], before.v[y as usize]);
+            let (result, flag) = reference_alu(n, vx, vy);
+            let mut expected = before.v;
+            expected[x as usize] = result;
+            expected[0xf] = flag;
+            prop_assert_eq!(run(&program, 3).v, expected);
+        }
+    }
 }
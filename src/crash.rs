@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+    time::{
+        SystemTime,
+        UNIX_EPOCH
+    }
+};
+
+use crate::cpu::CPU;
+use crate::util::fnv1a;
+
+// how many bytes of RAM to show before and after an address of interest
+const HEXDUMP_CONTEXT: usize = 32;
+
+// write everything we know about the machine to a crash file in the
+// current directory, returning its path, so bug reports can include it
+pub fn write_crash_file(reason: &str, cpu: &CPU, rom: &[u8]) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = PathBuf::from(format!("chip8-crash-{}.txt", timestamp));
+    fs::write(&path, crash_report(reason, cpu, rom))?;
+    Ok(path)
+}
+
+// the text of a crash file: the ROM, the machine state, memory around PC
+// and I, the last instructions run, and the configuration
+pub fn crash_report(reason: &str, cpu: &CPU, rom: &[u8]) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("chip8-rust {} crash report\n\n", env!("CARGO_PKG_VERSION")));
+    report.push_str(&format!("reason: {}\n", reason));
+    report.push_str(&format!("ROM: {} bytes, hash {:016x}\n", rom.len(), fnv1a(rom.iter().copied())));
+
+    report.push_str("\n== machine state ==\n");
+    report.push_str(&format!("{}\n", cpu.state_dump()));
+
+    report.push_str(&format!("\n== RAM around PC ({:03x}) ==\n", cpu.pc()));
+    report.push_str(&hexdump(cpu.ram(), cpu.pc()));
+    report.push_str(&format!("\n== RAM around I ({:03x}) ==\n", cpu.i()));
+    report.push_str(&hexdump(cpu.ram(), cpu.i()));
+
+    report.push_str("\n== recent instructions (oldest first) ==\n");
+    for (pc, instruction) in cpu.history() {
+        report.push_str(&format!("{:03x}: {:04x}\n", pc, instruction));
+    }
+
+    report.push_str("\n== configuration ==\n");
+    report.push_str(&format!("{:#?}\n", cpu.config()));
+    report
+}
+
+// 16 bytes per line around addr, with addr itself marked by a >
+fn hexdump(ram: &[u8], addr: usize) -> String {
+    // an address past the end of RAM, eg. a program counter that ran off
+    // it, shows whatever of its surroundings is in RAM
+    let end = (addr + HEXDUMP_CONTEXT).min(ram.len());
+    let start = (addr.saturating_sub(HEXDUMP_CONTEXT) & !0xf).min(end);
+    let mut out = String::new();
+    for (line, bytes) in ram[start..end].chunks(16).enumerate() {
+        let line_start = start + line * 16;
+        out.push_str(&format!("{:03x}:", line_start));
+        for (j, b) in bytes.iter().enumerate() {
+            let marker = if line_start + j == addr { '>' } else { ' ' };
+            out.push_str(&format!("{}{:02x}", marker, b));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hexdump_marks_the_address() {
+        let ram: Vec<u8> = (0..=255).collect();
+        let dump = hexdump(&ram, 0x42);
+        assert!(dump.starts_with("020: 20 21"));
+        assert!(dump.contains(" 41>42 43"));
+        assert_eq!(dump.lines().count(), 5);
+        assert_eq!(hexdump(&ram, 0x1000), "");
+    }
+}
@@ -0,0 +1,116 @@
+// finding the parts of a ROM that never run: bytes no path from the entry
+// point reaches (as far as static analysis can tell), and reachable code a
+// recorded run never got to
+
+use std::collections::BTreeSet;
+
+use crate::disasm::{
+    analyze,
+    fetch,
+    ENTRY_POINT
+};
+
+// address ranges [start, end) of the parts of a ROM that never run
+pub struct DeadCode {
+    // not part of any instruction reachable from the entry point, nor
+    // executed; may be data the code reads with I
+    pub unreachable: Vec<(usize, usize)>,
+    // reachable instructions the run didn't execute
+    pub unexecuted: Vec<(usize, usize)>,
+    // the ROM has computed jumps (Bnnn), whose targets static analysis
+    // can't follow, so some of the unreachable code may run after all
+    pub computed_jumps: bool
+}
+
+// runs of addresses from ENTRY_POINT for which flag is true
+fn ranges(flags: &[bool]) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (j, &flag) in flags.iter().chain(std::iter::once(&false)).enumerate() {
+        match (flag, start) {
+            (true, None) => start = Some(j),
+            (false, Some(s)) => {
+                out.push((ENTRY_POINT + s, ENTRY_POINT + j));
+                start = None;
+            },
+            _ => ()
+        }
+    }
+    out
+}
+
+// compare what the code can reach with the addresses of the instructions a
+// run executed (empty if the ROM wasn't run)
+pub fn dead_code(rom: &[u8], executed: &BTreeSet<usize>) -> DeadCode {
+    let analysis = analyze(rom);
+    let mut reachable = vec![false; rom.len()];
+    let mut ran = vec![false; rom.len()];
+    for (addrs, covered) in [(&analysis.code, &mut reachable), (executed, &mut ran)] {
+        for &pc in addrs.iter() {
+            for addr in pc..pc + 2 {
+                if let Some(flag) = addr.checked_sub(ENTRY_POINT).and_then(|j| covered.get_mut(j)) {
+                    *flag = true;
+                }
+            }
+        }
+    }
+
+    let unreachable: Vec<bool> = reachable.iter().zip(&ran).map(|(&r, &e)| !r && !e).collect();
+    let unexecuted: Vec<bool> = reachable.iter().zip(&ran).map(|(&r, &e)| r && !e && !executed.is_empty()).collect();
+    let computed_jumps = analysis.code.iter().any(|&pc| fetch(rom, pc).unwrap() >> 12 == 0xb);
+    DeadCode { unreachable: ranges(&unreachable), unexecuted: ranges(&unexecuted), computed_jumps }
+}
+
+fn range_lines(out: &mut String, ranges: &[(usize, usize)]) {
+    for (start, end) in ranges {
+        out.push_str(&format!("  {:03x}-{:03x}  {:>4} bytes\n", start, end - 1, end - start));
+    }
+}
+
+impl DeadCode {
+    pub fn report(&self, rom_len: usize, ran: bool) -> String {
+        let total = |ranges: &[(usize, usize)]| ranges.iter().map(|(s, e)| e - s).sum::<usize>();
+        let mut out = format!(
+            "Never reached from {:03x} ({} of {} bytes; data the code loads into I lives here too):\n",
+            ENTRY_POINT, total(&self.unreachable), rom_len
+        );
+        range_lines(&mut out, &self.unreachable);
+        if self.computed_jumps {
+            out.push_str("  (the ROM uses computed jumps (Bnnn), so some of this may run after all)\n");
+        }
+        if ran {
+            out.push_str(&format!("Reachable but not executed in this run ({} bytes):\n", total(&self.unexecuted)));
+            range_lines(&mut out, &self.unexecuted);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 12] = [
+        0x30, 0x00, // 200: SE V0, 0
+        0x12, 0x08, // 202: JP 208, skipped while V0 is 0
+        0x12, 0x04, // 204: JP 204
+        0xff, 0xff, // 206: junk
+        0x60, 0x01, // 208: LD V0, 1
+        0x00, 0x00  // 20a: padding
+    ];
+
+    #[test]
+    fn static_only() {
+        let dead = dead_code(&ROM, &BTreeSet::new());
+        assert_eq!(dead.unreachable, [(0x206, 0x208), (0x20a, 0x20c)]);
+        assert!(dead.unexecuted.is_empty());
+        assert!(!dead.computed_jumps);
+    }
+
+    #[test]
+    fn with_coverage() {
+        let executed: BTreeSet<usize> = [0x200, 0x204].iter().copied().collect();
+        let dead = dead_code(&ROM, &executed);
+        assert_eq!(dead.unexecuted, [(0x202, 0x204), (0x208, 0x20a)]);
+    }
+}
@@ -0,0 +1,321 @@
+// turns a 16-bit opcode into the instruction it stands for, so the CPU
+// and the disassembler agree on what every opcode means
+
+use crate::native::is_native_call;
+
+// the fields of an opcode, named as in Cowgod's reference
+pub(crate) fn x(op: u16) -> u16 { (op >> 8) & 0xf }
+pub(crate) fn y(op: u16) -> u16 { (op >> 4) & 0xf }
+pub(crate) fn n(op: u16) -> u16 { op & 0xf }
+pub(crate) fn kk(op: u16) -> u16 { op & 0xff }
+pub(crate) fn nnn(op: u16) -> u16 { op & 0xfff }
+
+// x and y are register numbers, kk a byte, n a nibble and addr a 12-bit
+// address, as in Cowgod's reference
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 00e0
+    Clear,
+    // 00ee
+    Return,
+    // 0nnn: a call into the host's machine code (see native)
+    MachineCall(usize),
+    // 00cn (SUPER-CHIP)
+    ScrollDown(usize),
+    // 00fb (SUPER-CHIP)
+    ScrollRight,
+    // 00fc (SUPER-CHIP)
+    ScrollLeft,
+    // 00fd (SUPER-CHIP): the program is over
+    Halt,
+    // 00fe (SUPER-CHIP)
+    LowRes,
+    // 00ff (SUPER-CHIP)
+    HighRes,
+    // 1nnn
+    Jump(usize),
+    // 2nnn
+    Call(usize),
+    // 3xkk
+    SkipEqByte(usize, u8),
+    // 4xkk
+    SkipNeByte(usize, u8),
+    // 5xy0
+    SkipEqReg(usize, usize),
+    // 5xy2 (XO-CHIP): store Vx to Vy, either way round, at I
+    StoreRange(usize, usize),
+    // 5xy3 (XO-CHIP): load them back
+    LoadRange(usize, usize),
+    // 6xkk
+    LoadByte(usize, u8),
+    // 7xkk
+    AddByte(usize, u8),
+    // 8xy0
+    LoadReg(usize, usize),
+    // 8xy1
+    Or(usize, usize),
+    // 8xy2
+    And(usize, usize),
+    // 8xy3
+    Xor(usize, usize),
+    // 8xy4
+    AddReg(usize, usize),
+    // 8xy5
+    SubReg(usize, usize),
+    // 8xy6
+    ShiftRight(usize, usize),
+    // 8xy7
+    SubN(usize, usize),
+    // 8xye
+    ShiftLeft(usize, usize),
+    // 9xy0
+    SkipNeReg(usize, usize),
+    // annn
+    LoadI(usize),
+    // bnnn
+    JumpV0(usize),
+    // cxkk
+    Random(usize, u8),
+    // dxyn, and SUPER-CHIP's 16x16 dxy0
+    Draw(usize, usize, usize),
+    // ex9e
+    SkipKey(usize),
+    // exa1
+    SkipNotKey(usize),
+    // fx07
+    GetDelay(usize),
+    // fx0a
+    WaitKey(usize),
+    // fn01 (XO-CHIP): draw to the planes in the bits of n
+    Plane(usize),
+    // f002 (XO-CHIP): the buzzer plays the 16 bytes at I
+    AudioPattern,
+    // fx15
+    SetDelay(usize),
+    // fx18
+    SetSound(usize),
+    // fx1e
+    AddI(usize),
+    // fx29
+    Font(usize),
+    // f000 nnnn (XO-CHIP): load I with the 16-bit address in the next
+    // two bytes
+    LongLoadI,
+    // fx3a (XO-CHIP): the buzzer's pitch
+    Pitch(usize),
+    // fx30 (SUPER-CHIP)
+    BigFont(usize),
+    // fx33
+    Bcd(usize),
+    // fx55
+    StoreRegs(usize),
+    // fx65
+    LoadRegs(usize),
+    // fx75 (SUPER-CHIP)
+    SaveFlags(usize),
+    // fx85 (SUPER-CHIP)
+    LoadFlags(usize),
+    // CHIP-8X's, which only decode_chip8x gives:
+    // 02a0: the next background colour
+    NextBackground,
+    // 5xy1: add the nibbles of Vy to Vx's apart (see chip8x)
+    AddNibbles(usize, usize),
+    // bxyn: colour the screen from V(x + 1)
+    Colour(usize, usize, usize),
+    // exf2 and exf5: the same as ex9e and exa1 on the second keypad
+    SkipKey2(usize),
+    SkipNotKey2(usize),
+    // MegaChip's, which only decode_megachip gives:
+    // 0010: back to the usual screen
+    MegaOff,
+    // 0011: the 256x192 colour screen (see megachip)
+    MegaOn,
+    // 00bn: scroll up n rows
+    ScrollUp(usize),
+    // 01nn nnnn: load I with the 24-bit address nn and the next two bytes
+    LoadIHigh(u8),
+    // 02nn: the first nn colours of the palette, from I
+    LoadPalette(usize),
+    // 03nn and 04nn: the size sprites are drawn at, 0 meaning 256
+    SpriteWidth(u8),
+    SpriteHeight(u8),
+    // 05nn: the screen's alpha
+    Alpha(u8),
+    // 060n: play the digitised sound at I, once, or over and over if n is 0
+    PlaySample(usize),
+    // 0700: stop it
+    StopSample,
+    // 080n: how sprites are mixed with what's under them
+    BlendMode(usize),
+    // 09nn: the palette index sprites collide with
+    CollisionColour(u8)
+}
+
+// the instruction op stands for, or None if it isn't one
+pub fn decode(op: u16) -> Option<Instruction> {
+    use Instruction::*;
+    let (x, y, n, kk, nnn) = (x(op) as usize, y(op) as usize, n(op) as usize, kk(op) as u8, nnn(op) as usize);
+    let instruction = match op >> 12 {
+        0x0 => match op {
+            0x00e0 => Clear,
+            0x00ee => Return,
+            0x00c0..=0x00cf => ScrollDown(n),
+            0x00fb => ScrollRight,
+            0x00fc => ScrollLeft,
+            0x00fd => Halt,
+            0x00fe => LowRes,
+            0x00ff => HighRes,
+            _ if is_native_call(op) => MachineCall(nnn),
+            _ => return None
+        },
+        0x1 => Jump(nnn),
+        0x2 => Call(nnn),
+        0x3 => SkipEqByte(x, kk),
+        0x4 => SkipNeByte(x, kk),
+        0x5 => match n {
+            0x0 => SkipEqReg(x, y),
+            0x2 => StoreRange(x, y),
+            0x3 => LoadRange(x, y),
+            _ => return None
+        },
+        0x6 => LoadByte(x, kk),
+        0x7 => AddByte(x, kk),
+        0x8 => match n {
+            0x0 => LoadReg(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => AddReg(x, y),
+            0x5 => SubReg(x, y),
+            0x6 => ShiftRight(x, y),
+            0x7 => SubN(x, y),
+            0xe => ShiftLeft(x, y),
+            _ => return None
+        },
+        0x9 if n == 0 => SkipNeReg(x, y),
+        0xa => LoadI(nnn),
+        0xb => JumpV0(nnn),
+        0xc => Random(x, kk),
+        0xd => Draw(x, y, n),
+        0xe => match kk {
+            0x9e => SkipKey(x),
+            0xa1 => SkipNotKey(x),
+            _ => return None
+        },
+        0xf => match kk {
+            0x07 => GetDelay(x),
+            0x00 if x == 0 => LongLoadI,
+            0x01 => Plane(x),
+            0x02 if x == 0 => AudioPattern,
+            0x0a => WaitKey(x),
+            0x15 => SetDelay(x),
+            0x18 => SetSound(x),
+            0x1e => AddI(x),
+            0x29 => Font(x),
+            0x30 => BigFont(x),
+            0x3a => Pitch(x),
+            0x33 => Bcd(x),
+            0x55 => StoreRegs(x),
+            0x65 => LoadRegs(x),
+            0x75 => SaveFlags(x),
+            0x85 => LoadFlags(x),
+            _ => return None
+        },
+        _ => return None
+    };
+    Some(instruction)
+}
+
+// the same for a CHIP-8X ROM, whose interpreter gave a few opcodes new
+// meanings (Bnnn among them) and added others
+pub fn decode_chip8x(op: u16) -> Option<Instruction> {
+    use Instruction::*;
+    let (x, y, n, kk) = (x(op) as usize, y(op) as usize, n(op) as usize, kk(op) as u8);
+    let instruction = match op >> 12 {
+        _ if op == 0x02a0 => NextBackground,
+        0x5 if n == 1 => AddNibbles(x, y),
+        0xb => Colour(x, y, n),
+        0xe if kk == 0xf2 => SkipKey2(x),
+        0xe if kk == 0xf5 => SkipNotKey2(x),
+        _ => return decode(op)
+    };
+    Some(instruction)
+}
+
+// the same for a MegaChip ROM, which uses some of what would be calls
+// into machine code; the rest only mean something in MegaChip mode, but
+// the CPU knows which mode it's in
+pub fn decode_megachip(op: u16) -> Option<Instruction> {
+    use Instruction::*;
+    let (n, kk) = (n(op) as usize, kk(op) as u8);
+    let instruction = match op >> 8 {
+        _ if op == 0x0010 => MegaOff,
+        _ if op == 0x0011 => MegaOn,
+        0x00 if op & 0xf0 == 0xb0 => ScrollUp(n),
+        0x01 => LoadIHigh(kk),
+        0x02 => LoadPalette(kk as usize),
+        0x03 => SpriteWidth(kk),
+        0x04 => SpriteHeight(kk),
+        0x05 => Alpha(kk),
+        0x06 if kk >> 4 == 0 => PlaySample(n),
+        0x07 if kk == 0 => StopSample,
+        0x08 if kk >> 4 == 0 => BlendMode(n),
+        0x09 => CollisionColour(kk),
+        _ => return decode(op)
+    };
+    Some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Instruction::*;
+
+    #[test]
+    fn operands_are_split_out() {
+        assert_eq!(decode(0x00e0), Some(Clear));
+        assert_eq!(decode(0x0230), Some(MachineCall(0x230)));
+        assert_eq!(decode(0x1abc), Some(Jump(0xabc)));
+        assert_eq!(decode(0x3a42), Some(SkipEqByte(0xa, 0x42)));
+        assert_eq!(decode(0x8ab4), Some(AddReg(0xa, 0xb)));
+        assert_eq!(decode(0xd12f), Some(Draw(1, 2, 0xf)));
+        assert_eq!(decode(0xf565), Some(LoadRegs(5)));
+        assert_eq!(decode(0x00c4), Some(ScrollDown(4)));
+        assert_eq!(decode(0x00ff), Some(HighRes));
+        assert_eq!(decode(0xf301), Some(Plane(3)));
+        assert_eq!(decode(0xf002), Some(AudioPattern));
+        assert_eq!(decode(0xf53a), Some(Pitch(5)));
+        assert_eq!(decode(0xf000), Some(LongLoadI));
+        assert_eq!(decode(0x5a32), Some(StoreRange(0xa, 3)));
+    }
+
+    #[test]
+    fn chip8x_opcodes() {
+        assert_eq!(decode(0xb123), Some(JumpV0(0x123)));
+        assert_eq!(decode_chip8x(0xb123), Some(Colour(1, 2, 3)));
+        assert_eq!(decode_chip8x(0x02a0), Some(NextBackground));
+        assert_eq!(decode_chip8x(0x5121), Some(AddNibbles(1, 2)));
+        assert_eq!(decode_chip8x(0xe3f5), Some(SkipNotKey2(3)));
+        assert_eq!(decode_chip8x(0x00e0), Some(Clear));
+    }
+
+    #[test]
+    fn megachip_opcodes() {
+        assert_eq!(decode(0x0011), Some(MachineCall(0x011)));
+        assert_eq!(decode_megachip(0x0011), Some(MegaOn));
+        assert_eq!(decode_megachip(0x00b4), Some(ScrollUp(4)));
+        assert_eq!(decode_megachip(0x0112), Some(LoadIHigh(0x12)));
+        assert_eq!(decode_megachip(0x0300), Some(SpriteWidth(0)));
+        assert_eq!(decode_megachip(0x0601), Some(PlaySample(1)));
+        assert_eq!(decode_megachip(0x0805), Some(BlendMode(5)));
+        assert_eq!(decode_megachip(0x00e0), Some(Clear));
+        assert_eq!(decode_megachip(0x0230), Some(LoadPalette(0x30)));
+    }
+
+    #[test]
+    fn unknown_opcodes_decode_to_none() {
+        for op in [0x0000, 0x5121, 0x8ab8, 0x9ab1, 0xe0a0, 0xf0ff, 0xf102, 0xf100].iter().copied() {
+            assert_eq!(decode(op), None, "{:04x}", op);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// turns ROM bytes into Octo-style source, for reading how a game works;
+// uses the same control-flow analysis as the disassembler, and the output
+// assembles back into the same ROM with asm::assemble_octo
+
+use crate::decode::{
+    kk,
+    n,
+    nnn,
+    x,
+    y
+};
+use crate::disasm::{
+    analyze,
+    fetch,
+    instruction_len,
+    long_operand,
+    Analysis,
+    ENTRY_POINT
+};
+
+// Octo starts running at `main`, so that's what the entry point is called
+fn label(analysis: &Analysis, addr: usize) -> Option<String> {
+    if addr == ENTRY_POINT {
+        return Some(String::from("main"));
+    }
+    analysis.label(addr)
+}
+
+// a skip instruction as the `if ... then` that compiles to it, remembering
+// that Octo's condition is the one under which the next line runs
+fn condition(op: u16) -> Option<String> {
+    let (x, y, kk) = (x(op), y(op), kk(op));
+    let text = match op >> 12 {
+        0x3 => format!("if v{:x} != {} then", x, kk),
+        0x4 => format!("if v{:x} == {} then", x, kk),
+        0x5 if n(op) == 0 => format!("if v{:x} != v{:x} then", x, y),
+        0x9 => format!("if v{:x} == v{:x} then", x, y),
+        0xe if kk == 0x9e => format!("if v{:x} -key then", x),
+        0xe if kk == 0xa1 => format!("if v{:x} key then", x),
+        _ => return None
+    };
+    Some(text)
+}
+
+// any other known instruction as an Octo statement
+fn statement(op: u16, addr: &dyn Fn(u16) -> String) -> Option<String> {
+    let (x, y, n, kk) = (x(op), y(op), n(op), kk(op));
+    let text = match op >> 12 {
+        0x0 => match op {
+            0x00e0 => String::from("clear"),
+            0x00ee => String::from("return"),
+            0x00c0..=0x00cf => format!("scroll-down {}", n),
+            0x00fb => String::from("scroll-right"),
+            0x00fc => String::from("scroll-left"),
+            0x00fd => String::from("exit"),
+            0x00fe => String::from("lores"),
+            0x00ff => String::from("hires"),
+            _ => return None
+        },
+        0x1 => format!("jump {}", addr(nnn(op))),
+        // calling is just naming the subroutine
+        0x2 => addr(nnn(op)),
+        0x5 if n == 2 => format!("save v{:x} - v{:x}", x, y),
+        0x5 if n == 3 => format!("load v{:x} - v{:x}", x, y),
+        0x6 => format!("v{:x} := {}", x, kk),
+        0x7 => format!("v{:x} += {}", x, kk),
+        0x8 => {
+            let operator = match n {
+                0x0 => ":=",
+                0x1 => "|=",
+                0x2 => "&=",
+                0x3 => "^=",
+                0x4 => "+=",
+                0x5 => "-=",
+                0x6 => ">>=",
+                0x7 => "=-",
+                0xe => "<<=",
+                _ => return None
+            };
+            format!("v{:x} {} v{:x}", x, operator, y)
+        },
+        0xa => format!("i := {}", addr(nnn(op))),
+        0xb => format!("jump0 {}", addr(nnn(op))),
+        0xc => format!("v{:x} := random {}", x, kk),
+        0xd => format!("sprite v{:x} v{:x} {}", x, y, n),
+        0xf => match kk {
+            0x07 => format!("v{:x} := delay", x),
+            0x01 => format!("plane {}", x),
+            0x02 if x == 0 => String::from("audio"),
+            0x0a => format!("v{:x} := key", x),
+            0x15 => format!("delay := v{:x}", x),
+            0x18 => format!("buzzer := v{:x}", x),
+            0x1e => format!("i += v{:x}", x),
+            0x29 => format!("i := hex v{:x}", x),
+            0x30 => format!("i := bighex v{:x}", x),
+            0x3a => format!("pitch := v{:x}", x),
+            0x33 => format!("bcd v{:x}", x),
+            0x55 => format!("save v{:x}", x),
+            0x65 => format!("load v{:x}", x),
+            0x75 => format!("saveflags v{:x}", x),
+            0x85 => format!("loadflags v{:x}", x),
+            _ => return None
+        },
+        _ => return None
+    };
+    Some(text)
+}
+
+// Octo-style source for the whole ROM: reachable code as statements, with
+// a skip and the instruction it guards joined into one `if ... then` line,
+// and everything else as raw bytes
+pub fn decompile(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let end = ENTRY_POINT + rom.len();
+    let addr_text = |a: u16| {
+        let a = a as usize;
+        match label(&analysis, a) {
+            Some(label) if (ENTRY_POINT..end).contains(&a) => label,
+            _ => format!("{:#05x}", a)
+        }
+    };
+    // the text for the instruction at addr, which must be code
+    let line = |addr: usize| {
+        if let Some(target) = long_operand(rom, addr) {
+            return format!("i := long {}", addr_text(target));
+        }
+        let op = fetch(rom, addr).unwrap();
+        condition(op).or_else(|| statement(op, &addr_text)).unwrap()
+    };
+
+    let mut out = String::new();
+    let mut addr = ENTRY_POINT;
+    while addr < end {
+        if let Some(label) = label(&analysis, addr) {
+            if analysis.is_subroutine(addr) {
+                out.push('\n');
+            }
+            out.push_str(&format!(": {}\n", label));
+        }
+
+        if analysis.code.contains(&addr) {
+            let op = fetch(rom, addr).unwrap();
+            let mut text = line(addr);
+            let mut size = instruction_len(rom, addr);
+            // put the guarded instruction on the same line, unless
+            // something jumps straight to it
+            let guarded = addr + 2;
+            if condition(op).is_some() && analysis.code.contains(&guarded) && label(&analysis, guarded).is_none()
+                && condition(fetch(rom, guarded).unwrap()).is_none() {
+                text = format!("{} {}", text, line(guarded));
+                size = 2 + instruction_len(rom, guarded);
+            }
+            out.push_str(&format!("    {:<32}# {:03x}\n", text, addr));
+            addr += size;
+            continue;
+        }
+
+        // a run of data, up to 8 bytes, stopping at code or a label
+        let mut bytes = Vec::new();
+        while addr < end && bytes.len() < 8 && !analysis.code.contains(&addr)
+            && (bytes.is_empty() || label(&analysis, addr).is_none()) {
+            bytes.push(format!("{:#04x}", rom[addr - ENTRY_POINT]));
+            addr += 1;
+        }
+        let start = addr - bytes.len();
+        out.push_str(&format!("    {:<32}# {:03x}\n", bytes.join(" "), start));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble_octo;
+
+    #[test]
+    fn readable_source() {
+        let rom = [
+            0x22, 0x08, // 200: call sub_208
+            0xa2, 0x0e, // 202: i := data_20e
+            0x12, 0x02, // 204: jump L_202
+            0xff, 0xff, // 206: unreachable
+            0x30, 0x01, // 208: if v0 != 1 then
+            0x61, 0x02, // 20a:   v1 := 2
+            0x00, 0xee, // 20c: return
+            0xf0, 0x90  // 20e: sprite data
+        ];
+        let source = decompile(&rom);
+        let lines: Vec<&str> = source.lines().map(|l| l.split('#').next().unwrap().trim_end()).collect();
+        assert_eq!(lines, [
+            ": main",
+            "    sub_208",
+            ": L_202",
+            "    i := data_20e",
+            "    jump L_202",
+            "    0xff 0xff",
+            "",
+            ": sub_208",
+            "    if v0 != 1 then v1 := 2",
+            "    return",
+            ": data_20e",
+            "    0xf0 0x90"
+        ]);
+    }
+
+    #[test]
+    fn reassembles_to_the_same_rom() {
+        let rom = [
+            0x60, 0x05, 0xe0, 0xa1, 0x12, 0x00, 0xf0, 0x29, 0x8a, 0xb7,
+            0xc3, 0x0f, 0x90, 0x10, 0xf5, 0x65, 0x00, 0xff, 0x00, 0xc3,
+            0xf2, 0x30, 0xf3, 0x75, 0xf3, 0x01, 0xf0, 0x02, 0xf4, 0x3a, 0x30, 0x01, 0xf0, 0x00, 0x02, 0x04, 0x5a, 0x32, 0x51, 0x03, 0xb2, 0x00
+        ];
+        assert_eq!(assemble_octo(&decompile(&rom)).unwrap(), rom);
+    }
+}
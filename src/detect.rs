@@ -0,0 +1,233 @@
+// guessing which interpreter a ROM was written for, and so which quirks it
+// needs, when it's run without saying: first by reading its code, then by
+// running it for a few seconds and watching what it does. the guess is
+// kept next to the ROM (pong.ch8.quirks) and used from then on, so it can
+// be corrected by hand:
+//
+//   # Fx1E at 2a4 is followed by a test of VF
+//   variant chip-8
+//   quirk-i-overflow on
+//
+// the variant is which interpreter it looks like; for SUPER-CHIP the
+// quirks start from its preset. it isn't a --platform, as the emulator
+// always understands SUPER-CHIP's instructions
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::config::{
+    Config,
+    UnknownOpcodePolicy
+};
+use crate::cpu::CPU;
+use crate::disasm::{
+    analyze,
+    fetch
+};
+use crate::headless::{
+    Headless,
+    Silence
+};
+use crate::hooks::Hooks;
+use crate::quirks::{
+    Quirk,
+    Quirks,
+    QUIRKS
+};
+use crate::replay::{
+    on_off,
+    parse_on_off
+};
+
+// how long to watch the ROM run for, in frames (5 seconds)
+const WATCH_FRAMES: usize = 5 * 480;
+
+// what's added to a ROM's path for the file its guess is kept in
+pub const GUESS_EXTENSION: &str = "quirks";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Chip8,
+    SuperChip
+}
+
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "chip-8" => Some(Variant::Chip8),
+            "schip" => Some(Variant::SuperChip),
+            _ => None
+        }
+    }
+
+    // the name from_name takes, for SUPER-CHIP also its quirks preset's
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip-8",
+            Variant::SuperChip => "schip"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Guess {
+    pub variant: Variant,
+    pub quirks: Quirks,
+    // what gave it away, a line each
+    pub reasons: Vec<String>
+}
+
+impl Guess {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for reason in &self.reasons {
+            text += &format!("# {}\n", reason);
+        }
+        text += &format!("variant {}\n", self.variant.name());
+        for quirk in QUIRKS.iter() {
+            text += &format!("quirk-{} {}\n", quirk.name(), on_off(self.quirks.get(*quirk)));
+        }
+        text
+    }
+
+    // a guess as saved by to_text, maybe edited since; # starts a comment
+    pub fn parse(text: &str) -> Result<Guess, String> {
+        let mut guess = Guess { variant: Variant::Chip8, quirks: Quirks::default(), reasons: Vec::new() };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            let ok = match name {
+                // files from before were saying platform
+                "variant" | "platform" => Variant::from_name(value).map(|v| guess.variant = v).is_some(),
+                _ if name.starts_with("quirk-") => match (Quirk::from_name(&name["quirk-".len()..]), parse_on_off(value)) {
+                    (Some(quirk), Some(on)) => {
+                        guess.quirks.set(quirk, on);
+                        true
+                    },
+                    _ => false
+                },
+                _ => false
+            };
+            if !ok {
+                return Err(format!("line {}: unexpected `{}`", n + 1, line));
+            }
+        }
+        Ok(guess)
+    }
+
+    // the names of the quirks turned on, eg. ["i-overflow"]
+    pub fn quirks_on(&self) -> Vec<&'static str> {
+        self.quirks.names_on()
+    }
+}
+
+// instructions only SUPER-CHIP has: scrolling, exit, the screen modes and
+// the big font, the flag registers
+fn is_superchip(op: u16) -> bool {
+    matches!(op, 0x00c0..=0x00cf | 0x00fb..=0x00ff)
+        || matches!(op & 0xf0ff, 0xf030 | 0xf075 | 0xf085)
+}
+
+// notes where an Fx1E took I past the end of memory, which only makes
+// sense for a ROM expecting VF to say so
+struct OverflowWatch {
+    i: usize,
+    overflowed_at: Rc<Cell<Option<usize>>>
+}
+
+impl Hooks for OverflowWatch {
+    fn on_step(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if let Some(&(addr, op)) = cpu.history().last() {
+            if op & 0xf0ff == 0xf01e && cpu.i() < self.i && self.overflowed_at.get().is_none() {
+                self.overflowed_at.set(Some(addr));
+            }
+        }
+        self.i = cpu.i();
+        Ok(())
+    }
+}
+
+pub fn guess(rom: &[u8]) -> Guess {
+    let mut guess = Guess { variant: Variant::Chip8, quirks: Quirks::default(), reasons: Vec::new() };
+    let analysis = analyze(rom);
+
+    if let Some((addr, op)) = analysis.code.iter().chain(analysis.unknown.iter())
+        .filter_map(|&addr| Some((addr, fetch(rom, addr)?)))
+        .find(|&(_, op)| is_superchip(op)) {
+        guess.variant = Variant::SuperChip;
+        guess.quirks = Quirks::preset(Variant::SuperChip.name()).unwrap_or_default();
+        guess.reasons.push(format!("{:04x} at {:03x} is a SUPER-CHIP instruction", op, addr));
+    }
+
+    // Fx1E then straight away SE or SNE VF, kk
+    let tests_vf = |op: u16| matches!(op & 0xff00, 0x3f00 | 0x4f00);
+    if let Some(&addr) = analysis.code.iter().find(|&&addr| {
+        fetch(rom, addr).is_some_and(|op| op & 0xf0ff == 0xf01e)
+            && analysis.code.contains(&(addr + 2))
+            && fetch(rom, addr + 2).is_some_and(tests_vf)
+    }) {
+        guess.quirks.i_overflow_sets_vf = true;
+        guess.reasons.push(format!("Fx1E at {:03x} is followed by a test of VF", addr));
+    }
+
+    let overflowed_at = Rc::new(Cell::new(None));
+    let config = Config {
+        unknown_opcode: UnknownOpcodePolicy::Ignore,
+        key_wait_timeout: Some(Duration::from_secs(1)),
+        random_seed: Some(0),
+        ..Config::default()
+    };
+    let mut cpu = CPU::new(Box::new(Headless::new(WATCH_FRAMES)), Box::new(Silence), config);
+    if cpu.load_rom(rom).is_ok() {
+        cpu.add_hooks(Box::new(OverflowWatch { i: 0, overflowed_at: overflowed_at.clone() }));
+        // whatever stops it, we've seen what we could
+        let _ = cpu.run_loop();
+    }
+    if let Some(addr) = overflowed_at.get() {
+        guess.quirks.i_overflow_sets_vf = true;
+        guess.reasons.push(format!("Fx1E at {:03x} took I past fff while running", addr));
+    }
+    guess
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(program: &[u16]) -> Vec<u8> {
+        program.iter().flat_map(|op| op.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn guesses_from_the_code() {
+        let plain = guess(&rom(&[0x6001, 0x1202]));
+        assert_eq!((plain.variant, plain.quirks.i_overflow_sets_vf), (Variant::Chip8, false));
+        assert!(plain.reasons.is_empty());
+        // I never goes past fff, but the ROM expects it might
+        let tests_vf = guess(&rom(&[0x1202, 0xf01e, 0x3f01, 0x1206, 0x1206]));
+        assert_eq!(tests_vf.quirks_on(), ["i-overflow"]);
+        assert_eq!(tests_vf.reasons, ["Fx1E at 202 is followed by a test of VF"]);
+        let schip = guess(&rom(&[0x00ff, 0x1202]));
+        assert_eq!((schip.variant, schip.quirks), (Variant::SuperChip, Quirks::preset("schip").unwrap()));
+    }
+
+    #[test]
+    fn guesses_from_running() {
+        let overflowing = guess(&rom(&[0xaff0, 0x6020, 0xf01e, 0x1206]));
+        assert_eq!(overflowing.reasons, ["Fx1E at 204 took I past fff while running"]);
+        assert!(overflowing.quirks.i_overflow_sets_vf);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let guess = guess(&rom(&[0x00ff, 0xaff0, 0x6020, 0xf01e, 0x1208]));
+        assert_eq!(Guess::parse(&guess.to_text()).unwrap(), Guess { reasons: Vec::new(), ..guess });
+        assert!(Guess::parse("variant gameboy").is_err());
+        assert_eq!(Guess::parse("platform schip").unwrap().variant, Variant::SuperChip);
+    }
+}
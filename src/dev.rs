@@ -0,0 +1,132 @@
+// a quick edit-and-see loop for writing ROMs: the source file is checked
+// for changes every so often while the ROM runs, and rebuilt and swapped in
+// when it changes, either starting over or carrying on from where the
+// machine was with just the program replaced
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::cpu::CPU;
+use crate::hooks::Hooks;
+
+// frames between looks at the source (a quarter of a second)
+const CHECK_FRAMES: u32 = 120;
+// how long a build error stays over the screen, in frames
+const ERROR_FRAMES: u32 = 5 * 480;
+
+pub struct SourceWatcher {
+    path: PathBuf,
+    // turns the source into a ROM, eg. the assembler
+    build: Box<dyn FnMut() -> Result<Vec<u8>, String>>,
+    // carry on from the machine's state rather than starting over
+    keep_state: bool,
+    modified: Option<SystemTime>,
+    frames: u32,
+    // frames left showing a build error
+    error_left: u32
+}
+
+impl SourceWatcher {
+    pub fn new(path: PathBuf, keep_state: bool, build: Box<dyn FnMut() -> Result<Vec<u8>, String>>) -> SourceWatcher {
+        let modified = SourceWatcher::modified(&path);
+        SourceWatcher { path, build, keep_state, modified, frames: 0, error_left: 0 }
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn rebuild(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let rom = (self.build)()?;
+        if self.keep_state {
+            cpu.patch_rom(&rom).map_err(|err| err.to_string())?;
+        }
+        else {
+            cpu.swap_rom(&rom).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Hooks for SourceWatcher {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if self.error_left > 0 {
+            self.error_left -= 1;
+            if self.error_left == 0 {
+                cpu.set_overlay(Vec::new());
+            }
+        }
+        self.frames += 1;
+        if self.frames < CHECK_FRAMES {
+            return Ok(());
+        }
+        self.frames = 0;
+        let modified = SourceWatcher::modified(&self.path);
+        if modified == self.modified {
+            return Ok(());
+        }
+        self.modified = modified;
+        // a broken build leaves the old ROM running
+        match self.rebuild(cpu) {
+            Ok(()) => {
+                println!("Rebuilt {}", self.path.display());
+                if self.error_left > 0 {
+                    self.error_left = 0;
+                    cpu.set_overlay(Vec::new());
+                }
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                cpu.set_overlay(vec![String::from("BUILD FAILED"), String::from("see the terminal")]);
+                self.error_left = ERROR_FRAMES;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn rebuilds_when_the_source_changes() {
+        let path = env::temp_dir().join(format!("chip8-dev-test-{}.txt", std::process::id()));
+        fs::write(&path, "1").unwrap();
+        let run = |keep_state| {
+            // V0 += 1, forever; the rebuilt ROM starts by setting V1
+            let source = path.clone();
+            let build = Box::new(move || match fs::read_to_string(&source).unwrap().as_str() {
+                "1" => Ok(vec![0x70, 0x01, 0x12, 0x00]),
+                _ => Ok(vec![0x61, 0x07, 0x70, 0x01, 0x12, 0x02])
+            });
+            let mut cpu = CPU::new(Box::new(Headless::new(CHECK_FRAMES as usize)), Box::new(Silence), Config::default());
+            cpu.load_rom(&build().unwrap()).unwrap();
+            let mut watcher = SourceWatcher::new(path.clone(), keep_state, build);
+            // as if it had been changed since
+            watcher.modified = Some(SystemTime::now() - Duration::from_secs(60));
+            cpu.add_hooks(Box::new(watcher));
+            cpu.run_loop().unwrap();
+            cpu
+        };
+        fs::write(&path, "2").unwrap();
+        // started over from the top
+        let cpu = run(false);
+        assert_eq!((cpu.v()[0], cpu.v()[1], cpu.pc()), (0, 0, 0x200));
+        // or kept V0 as it was (every other instruction adds to it), with
+        // the new program in place
+        let cpu = run(true);
+        assert_eq!(cpu.v()[0], (CHECK_FRAMES * 9 / 2 % 256) as u8);
+        assert_eq!(cpu.rom(), [0x61, 0x07, 0x70, 0x01, 0x12, 0x02]);
+        fs::remove_file(&path).unwrap();
+    }
+}
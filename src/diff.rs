@@ -0,0 +1,148 @@
+// instruction-level differences between two ROMs, for seeing exactly what
+// a patch changed: both are listed like the disassembler does (without
+// labels, whose names would change along with the addresses), and the
+// listings compared line by line, so code that only moved isn't reported
+
+use crate::disasm::{
+    analyze,
+    fetch,
+    mnemonic,
+    ENTRY_POINT
+};
+
+// unchanged lines shown around each change
+const CONTEXT: usize = 2;
+
+// (address, text) for every instruction, and every byte of data, in the ROM
+fn listing(rom: &[u8]) -> Vec<(usize, String)> {
+    let analysis = analyze(rom);
+    let end = ENTRY_POINT + rom.len();
+    let mut lines = Vec::new();
+    let mut addr = ENTRY_POINT;
+    while addr < end {
+        if analysis.code.contains(&addr) {
+            let op = fetch(rom, addr).unwrap();
+            lines.push((addr, mnemonic(op).unwrap()));
+            addr += 2;
+        }
+        else {
+            lines.push((addr, format!("DB {:#04x}", rom[addr - ENTRY_POINT])));
+            addr += 1;
+        }
+    }
+    lines
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    // the ith line of a and the jth of b are the same
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize)
+}
+
+// the shortest way from a to b, through their longest common subsequence
+fn edits(a: &[(usize, String)], b: &[(usize, String)]) -> Vec<Edit> {
+    // common[i][j] is the length of the LCS of a[i..] and b[j..]
+    let mut common = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i].1 == b[j].1 {
+                common[i + 1][j + 1] + 1
+            }
+            else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].1 == b[j].1 {
+            edits.push(Edit::Same(i, j));
+            i += 1;
+            j += 1;
+        }
+        else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(Edit::Removed(i));
+            i += 1;
+        }
+        else {
+            edits.push(Edit::Added(j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+// the changes from ROM a to ROM b: removed lines with `-` and their address
+// in a, added ones with `+` and their address in b, and a little unchanged
+// code around them; empty if the ROMs are the same
+pub fn diff(a: &[u8], b: &[u8]) -> String {
+    let (a, b) = (listing(a), listing(b));
+    let edits = edits(&a, &b);
+    let changed: Vec<usize> = edits.iter().enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Same(..)))
+        .map(|(n, _)| n)
+        .collect();
+
+    let mut out = String::new();
+    let mut last_shown: Option<usize> = None;
+    for (n, edit) in edits.iter().enumerate() {
+        let near_change = changed.iter().any(|&c| c.abs_diff(n) <= CONTEXT);
+        if !near_change {
+            continue;
+        }
+        if last_shown.is_some_and(|last| last + 1 != n) {
+            out.push_str("...\n");
+        }
+        last_shown = Some(n);
+        let line = match *edit {
+            Edit::Same(i, j) => format!("  {:03x} {:03x}  {}", a[i].0, b[j].0, a[i].1),
+            Edit::Removed(i) => format!("- {:03x}      {}", a[i].0, a[i].1),
+            Edit::Added(j) => format!("+     {:03x}  {}", b[j].0, b[j].1)
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_roms_have_no_diff() {
+        assert_eq!(diff(&[0x60, 0x01, 0x12, 0x00], &[0x60, 0x01, 0x12, 0x00]), "");
+    }
+
+    #[test]
+    fn shows_changes_with_context() {
+        let a = [
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04, 0x64, 0x05,
+            0x65, 0x06, 0x66, 0x07, 0x67, 0x08, 0x12, 0x10
+        ];
+        // 202 changed, a CLS inserted after 20c, and the jump moved with it
+        let b = [
+            0x60, 0x01, 0x61, 0x09, 0x62, 0x03, 0x63, 0x04, 0x64, 0x05,
+            0x65, 0x06, 0x66, 0x07, 0x00, 0xe0, 0x67, 0x08, 0x12, 0x12
+        ];
+        let lines: Vec<String> = diff(&a, &b).lines().map(String::from).collect();
+        assert_eq!(lines, [
+            "  200 200  LD V0, 0x01",
+            "- 202      LD V1, 0x02",
+            "+     202  LD V1, 0x09",
+            "  204 204  LD V2, 0x03",
+            "  206 206  LD V3, 0x04",
+            "...",
+            "  20a 20a  LD V5, 0x06",
+            "  20c 20c  LD V6, 0x07",
+            "+     20e  CLS",
+            "  20e 210  LD V7, 0x08",
+            "- 210      JP 0x210",
+            "+     212  JP 0x212"
+        ]);
+    }
+}
@@ -0,0 +1,408 @@
+// turns ROM bytes back into Cowgod-style assembly (see the references
+// in the README), following the control flow from the entry point so
+// code and data can be told apart
+
+use std::collections::{
+    BTreeMap,
+    BTreeSet
+};
+
+use crate::decode::{
+    decode,
+    nnn,
+    Instruction::*
+};
+
+pub const ENTRY_POINT: usize = 0x200;
+
+// the assembly for a single instruction, with addresses formatted by
+// addr (so callers can substitute labels), or None for unknown opcodes
+pub fn mnemonic_with(op: u16, addr: &dyn Fn(u16) -> String) -> Option<String> {
+    let addr = |a: usize| addr(a as u16);
+    let text = match decode(op)? {
+        Clear => String::from("CLS"),
+        Return => String::from("RET"),
+        // not CHIP-8 code, so left for the caller to show as data
+        MachineCall(_) => return None,
+        ScrollDown(n) => format!("SCD {}", n),
+        ScrollRight => String::from("SCR"),
+        ScrollLeft => String::from("SCL"),
+        Halt => String::from("EXIT"),
+        LowRes => String::from("LOW"),
+        HighRes => String::from("HIGH"),
+        Jump(a) => format!("JP {}", addr(a)),
+        Call(a) => format!("CALL {}", addr(a)),
+        SkipEqByte(x, kk) => format!("SE V{:X}, {:#04x}", x, kk),
+        SkipNeByte(x, kk) => format!("SNE V{:X}, {:#04x}", x, kk),
+        SkipEqReg(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        StoreRange(x, y) => format!("LD [I], V{:X} - V{:X}", x, y),
+        LoadRange(x, y) => format!("LD V{:X} - V{:X}, [I]", x, y),
+        LoadByte(x, kk) => format!("LD V{:X}, {:#04x}", x, kk),
+        AddByte(x, kk) => format!("ADD V{:X}, {:#04x}", x, kk),
+        LoadReg(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        AddReg(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        SubReg(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        SubN(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        SkipNeReg(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        LoadI(a) => format!("LD I, {}", addr(a)),
+        JumpV0(a) => format!("JP V0, {}", addr(a)),
+        Random(x, kk) => format!("RND V{:X}, {:#04x}", x, kk),
+        Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        SkipKey(x) => format!("SKP V{:X}", x),
+        SkipNotKey(x) => format!("SKNP V{:X}", x),
+        GetDelay(x) => format!("LD V{:X}, DT", x),
+        WaitKey(x) => format!("LD V{:X}, K", x),
+        Plane(n) => format!("PLANE {}", n),
+        AudioPattern => String::from("AUDIO"),
+        SetDelay(x) => format!("LD DT, V{:X}", x),
+        SetSound(x) => format!("LD ST, V{:X}", x),
+        AddI(x) => format!("ADD I, V{:X}", x),
+        Font(x) => format!("LD F, V{:X}", x),
+        BigFont(x) => format!("LD HF, V{:X}", x),
+        // the address is in the next two bytes, which the caller adds
+        LongLoadI => String::from("LD I, LONG"),
+        Pitch(x) => format!("LD PITCH, V{:X}", x),
+        Bcd(x) => format!("LD B, V{:X}", x),
+        StoreRegs(x) => format!("LD [I], V{:X}", x),
+        LoadRegs(x) => format!("LD V{:X}, [I]", x),
+        SaveFlags(x) => format!("LD R, V{:X}", x),
+        LoadFlags(x) => format!("LD V{:X}, R", x),
+        // CHIP-8X's and MegaChip's, which decode never gives
+        NextBackground | AddNibbles(..) | Colour(..) | SkipKey2(_) | SkipNotKey2(_) => return None,
+        MegaOff | MegaOn | ScrollUp(_) | LoadIHigh(_) | LoadPalette(_) | SpriteWidth(_) | SpriteHeight(_) | Alpha(_)
+            | PlaySample(_) | StopSample | BlendMode(_) | CollisionColour(_) => return None
+    };
+    Some(text)
+}
+
+// the assembly for a single instruction with plain hex addresses
+pub fn mnemonic(op: u16) -> Option<String> {
+    mnemonic_with(op, &|a| format!("{:#05x}", a))
+}
+
+// how many bytes the instruction at addr takes: 4 for XO-CHIP's F000,
+// which is followed by the address it loads, and otherwise 2
+pub(crate) fn instruction_len(rom: &[u8], addr: usize) -> usize {
+    if fetch(rom, addr) == Some(0xf000) { 4 } else { 2 }
+}
+
+// the address an F000 at addr loads into I, if it's there
+pub(crate) fn long_operand(rom: &[u8], addr: usize) -> Option<u16> {
+    fetch(rom, addr).filter(|op| *op == 0xf000).and(fetch(rom, addr + 2))
+}
+
+// whether op skips the instruction after it if its condition holds
+pub(crate) fn is_skip(op: u16) -> bool {
+    matches!(decode(op), Some(SkipEqByte(..) | SkipNeByte(..) | SkipEqReg(..) | SkipNeReg(..) | SkipKey(_) | SkipNotKey(_)))
+}
+
+// where execution can go after the instruction op at pc
+fn successors(rom: &[u8], op: u16, pc: usize) -> Vec<usize> {
+    let next = pc + instruction_len(rom, pc);
+    match op >> 12 {
+        // jump: only the target
+        0x1 => vec![nnn(op) as usize],
+        // call: the subroutine, and back here once it returns
+        0x2 => vec![nnn(op) as usize, next],
+        // skips: the next instruction or the one after, however long
+        _ if is_skip(op) => vec![next, next + instruction_len(rom, next)],
+        // computed jump: the target depends on V0 at runtime
+        0xb => vec![],
+        // return, and SUPER-CHIP's exit
+        _ if op == 0x00ee || op == 0x00fd => vec![],
+        _ => vec![next]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Label {
+    Code,
+    Subroutine,
+    Data
+}
+
+// the result of following the control flow through a ROM loaded at ENTRY_POINT
+pub struct Analysis {
+    // addresses of instructions reachable from the entry point
+    pub code: BTreeSet<usize>,
+    // addresses where a path ran into an instruction we don't know
+    pub unknown: BTreeSet<usize>,
+    // addresses that are jump/call targets or I loads, by kind
+    labels: BTreeMap<usize, Label>
+}
+
+impl Analysis {
+    pub fn is_subroutine(&self, addr: usize) -> bool {
+        self.labels.get(&addr) == Some(&Label::Subroutine)
+    }
+
+    // the label for addr, if anything refers to it
+    pub fn label(&self, addr: usize) -> Option<String> {
+        self.labels.get(&addr).map(|kind| match kind {
+            Label::Code => format!("L_{:03x}", addr),
+            Label::Subroutine => format!("sub_{:03x}", addr),
+            Label::Data => format!("data_{:03x}", addr)
+        })
+    }
+}
+
+pub(crate) fn fetch(rom: &[u8], addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(ENTRY_POINT)?;
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+    Some(((rom[offset] as u16) << 8) | rom[offset + 1] as u16)
+}
+
+// walk every path from the entry point, marking instructions as code
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let mut code = BTreeSet::new();
+    let mut unknown = BTreeSet::new();
+    let mut labels = BTreeMap::new();
+    let mut pending = vec![ENTRY_POINT];
+
+    while let Some(pc) = pending.pop() {
+        if code.contains(&pc) {
+            continue;
+        }
+        // unknown opcodes and addresses outside the ROM end the path, and
+        // so does an F000 with its address cut off
+        let op = match fetch(rom, pc) {
+            Some(0xf000) if long_operand(rom, pc).is_none() => {
+                unknown.insert(pc);
+                continue;
+            },
+            Some(op) if mnemonic(op).is_some() => op,
+            Some(_) => {
+                unknown.insert(pc);
+                continue;
+            },
+            None => continue
+        };
+        code.insert(pc);
+
+        let target = nnn(op) as usize;
+        match op >> 12 {
+            0x1 | 0xb => { labels.entry(target).or_insert(Label::Code); },
+            0x2 => { labels.insert(target, Label::Subroutine); },
+            0xa => { labels.entry(target).or_insert(Label::Data); },
+            0xf => if let Some(target) = long_operand(rom, pc) {
+                labels.entry(target as usize).or_insert(Label::Data);
+            },
+            _ => ()
+        }
+        pending.extend(successors(rom, op, pc));
+    }
+
+    // something jumped into or called turned out to be code after all
+    for (addr, kind) in labels.iter_mut() {
+        if *kind == Label::Data && code.contains(addr) {
+            *kind = Label::Code;
+        }
+    }
+    Analysis { code, unknown, labels }
+}
+
+// full listing of the ROM: reachable instructions as assembly, everything
+// else as DB data, with labels for every referenced address inside the ROM
+pub fn disassemble(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let end = ENTRY_POINT + rom.len();
+    // only refer to labels we are going to emit
+    let addr_text = |a: u16| {
+        let a = a as usize;
+        match analysis.label(a) {
+            Some(label) if (ENTRY_POINT..end).contains(&a) => label,
+            _ => format!("{:#05x}", a)
+        }
+    };
+
+    let mut out = String::new();
+    let mut addr = ENTRY_POINT;
+    while addr < end {
+        if let Some(label) = analysis.label(addr) {
+            if analysis.is_subroutine(addr) {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}:\n", label));
+        }
+
+        if analysis.code.contains(&addr) {
+            let op = fetch(rom, addr).unwrap();
+            let text = mnemonic_with(op, &addr_text).unwrap();
+            match long_operand(rom, addr) {
+                Some(target) => {
+                    let text = format!("{} {}", text, addr_text(target));
+                    out.push_str(&format!("    {:<24}; {:03x}: {:04x} {:04x}\n", text, addr, op, target));
+                },
+                None => out.push_str(&format!("    {:<24}; {:03x}: {:04x}\n", text, addr, op))
+            }
+            addr += instruction_len(rom, addr);
+            continue;
+        }
+
+        // a run of data, up to 8 bytes, stopping at code or a label
+        let mut bytes = Vec::new();
+        while addr < end && bytes.len() < 8 && !analysis.code.contains(&addr)
+            && (bytes.is_empty() || analysis.label(addr).is_none()) {
+            bytes.push(format!("{:#04x}", rom[addr - ENTRY_POINT]));
+            addr += 1;
+        }
+        let start = addr - bytes.len();
+        out.push_str(&format!("    {:<24}; {:03x}\n", format!("DB {}", bytes.join(", ")), start));
+    }
+    out
+}
+
+// the control flow as a Graphviz DOT graph: one node per basic block of
+// reachable code, with edges for jumps, skips, calls and returns
+pub fn control_flow_graph(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let end = ENTRY_POINT + rom.len();
+    let addr_text = |a: u16| {
+        let a = a as usize;
+        match analysis.label(a) {
+            Some(label) if (ENTRY_POINT..end).contains(&a) => label,
+            _ => format!("{:#05x}", a)
+        }
+    };
+
+    // blocks start at the entry point and wherever control can arrive
+    // other than by falling through from the previous instruction
+    let mut leaders = BTreeSet::new();
+    leaders.insert(ENTRY_POINT);
+    for &pc in &analysis.code {
+        let op = fetch(rom, pc).unwrap();
+        let next = successors(rom, op, pc);
+        if next != [pc + instruction_len(rom, pc)] {
+            leaders.extend(next);
+        }
+        if op >> 12 == 0xb {
+            leaders.insert(nnn(op) as usize);
+        }
+    }
+    leaders.retain(|a| analysis.code.contains(a));
+
+    let mut out = String::from("digraph chip8 {\n    node [shape=box, fontname=monospace];\n");
+    for &start in &leaders {
+        let mut text = match analysis.label(start) {
+            Some(label) => format!("{}:\\l", label),
+            None => String::new()
+        };
+        let mut pc = start;
+        loop {
+            let op = fetch(rom, pc).unwrap();
+            text.push_str(&format!("{:03x}  {}\\l", pc, mnemonic_with(op, &addr_text).unwrap()));
+            let next = pc + instruction_len(rom, pc);
+            if successors(rom, op, pc) != [next] || !analysis.code.contains(&next) || leaders.contains(&next) {
+                break;
+            }
+            pc = next;
+        }
+        out.push_str(&format!("    b{:03x} [label=\"{}\"];\n", start, text));
+
+        // edges out of the block's last instruction, to code we know about
+        let op = fetch(rom, pc).unwrap();
+        let target = nnn(op) as usize;
+        let next = pc + instruction_len(rom, pc);
+        let edges: Vec<(usize, &str)> = match op >> 12 {
+            0x1 => vec![(target, "")],
+            0x2 => vec![(target, " [style=dashed, label=\"call\"]"), (next, " [label=\"return\"]")],
+            _ if is_skip(op) => vec![(next, ""), (next + instruction_len(rom, next), " [label=\"skip\"]")],
+            0xb => vec![(target, " [style=dotted, label=\"+V0\"]")],
+            _ if op == 0x00ee => vec![],
+            _ => vec![(next, "")]
+        };
+        for (to, attrs) in edges {
+            if leaders.contains(&to) {
+                out.push_str(&format!("    b{:03x} -> b{:03x}{};\n", start, to, attrs));
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(mnemonic(0x00e0).unwrap(), "CLS");
+        assert_eq!(mnemonic(0x8ab6).unwrap(), "SHR VA, VB");
+        assert_eq!(mnemonic(0xd125).unwrap(), "DRW V1, V2, 5");
+        assert_eq!(mnemonic(0xf355).unwrap(), "LD [I], V3");
+        assert_eq!(mnemonic(0x00c3).unwrap(), "SCD 3");
+        assert_eq!(mnemonic(0xf201).unwrap(), "PLANE 2");
+        assert_eq!(mnemonic(0xf002).unwrap(), "AUDIO");
+        assert_eq!(mnemonic(0xf53a).unwrap(), "LD PITCH, V5");
+        assert_eq!(mnemonic(0x5a32).unwrap(), "LD [I], VA - V3");
+        assert_eq!(mnemonic(0x5133).unwrap(), "LD V1 - V3, [I]");
+        assert_eq!(mnemonic(0x00fd).unwrap(), "EXIT");
+        assert_eq!(mnemonic(0xf230).unwrap(), "LD HF, V2");
+        assert_eq!(mnemonic(0xf385).unwrap(), "LD V3, R");
+        assert_eq!(mnemonic(0x0123), None);
+        assert_eq!(mnemonic(0x5121), None);
+    }
+
+    #[test]
+    fn follows_control_flow() {
+        let rom = [
+            0x22, 0x08, // 200: CALL sub_208
+            0xa2, 0x0c, // 202: LD I, data_20c
+            0x12, 0x02, // 204: JP L_202
+            0xff, 0xff, // 206: unreachable
+            0x60, 0x01, // 208: LD V0, 1
+            0x00, 0xee, // 20a: RET
+            0xf0, 0x90  // 20c: sprite data
+        ];
+        let listing = disassemble(&rom);
+        let lines: Vec<&str> = listing.lines().map(|l| l.split(';').next().unwrap().trim_end()).collect();
+        assert_eq!(lines, [
+            "    CALL sub_208",
+            "L_202:",
+            "    LD I, data_20c",
+            "    JP L_202",
+            "    DB 0xff, 0xff",
+            "",
+            "sub_208:",
+            "    LD V0, 0x01",
+            "    RET",
+            "data_20c:",
+            "    DB 0xf0, 0x90"
+        ]);
+    }
+
+    #[test]
+    fn control_flow_graph_blocks() {
+        let rom = [
+            0x22, 0x08, // 200: CALL sub_208
+            0x30, 0x01, // 202: SE V0, 1
+            0x12, 0x00, // 204: JP 200
+            0x12, 0x06, // 206: JP 206
+            0x60, 0x01, // 208: LD V0, 1
+            0x00, 0xee  // 20a: RET
+        ];
+        let dot = control_flow_graph(&rom);
+        assert!(dot.starts_with("digraph chip8 {"));
+        for edge in [
+            "b200 -> b208 [style=dashed",
+            "b200 -> b202 [label=\"return\"]",
+            "b202 -> b204;",
+            "b202 -> b206 [label=\"skip\"]",
+            "b204 -> b200;",
+            "b206 -> b206;"
+        ].iter() {
+            assert!(dot.contains(edge), "missing {} in\n{}", edge, dot);
+        }
+        // the subroutine body is one block ending in RET, with no edges out
+        assert!(dot.contains("sub_208:\\l208  LD V0, 0x01\\l20a  RET\\l"));
+        assert!(!dot.contains("b208 ->"));
+    }
+}
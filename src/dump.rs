@@ -0,0 +1,27 @@
+// saving the machine's memory for a closer look in a hex editor: the 4K of
+// RAM as a raw file, with the registers next to it as JSON
+
+use std::{
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf
+    },
+    time::{
+        SystemTime,
+        UNIX_EPOCH
+    }
+};
+
+use crate::cpu::CPU;
+
+// write chip8-dump-<milliseconds>.bin and .json into dir, returning the
+// path of the RAM file
+pub fn write_memory_dump(cpu: &CPU, dir: &Path) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let ram_path = dir.join(format!("chip8-dump-{}.bin", timestamp));
+    fs::write(&ram_path, cpu.ram())?;
+    fs::write(ram_path.with_extension("json"), cpu.registers_json())?;
+    Ok(ram_path)
+}
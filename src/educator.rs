@@ -0,0 +1,218 @@
+// a teaching mode: the CPU stays paused and goes through each instruction
+// a stage at a time, shown over the screen, slowly enough to talk about:
+//
+//   fetch     the two bytes at PC, with their neighbours in memory
+//   decode    the instruction as assembly, and what it is going to change
+//   execute   what did change, old value > new value
+
+use crate::cpu::CPU;
+use crate::decode::{
+    n,
+    x
+};
+use crate::disasm::{
+    mnemonic,
+    ENTRY_POINT
+};
+use crate::hooks::Hooks;
+
+// changes listed in the execute stage, below its heading
+const CHANGES_SHOWN: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    Fetch,
+    Decode,
+    Execute
+}
+
+// what an instruction can change, to compare before and after
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Registers {
+    v: [u8; 16],
+    i: usize,
+    dt: u8,
+    st: u8,
+    pc: usize
+}
+
+impl Registers {
+    fn of(cpu: &CPU) -> Registers {
+        let mut v = [0; 16];
+        v.copy_from_slice(cpu.v());
+        Registers { v, i: cpu.i(), dt: cpu.delay_timer(), st: cpu.sound_timer(), pc: cpu.pc() }
+    }
+
+    // eg. "V3 05 > 06", one per register that changed, PC last
+    fn changes(&self, after: &Registers) -> Vec<String> {
+        let mut changes: Vec<String> = (0..16)
+            .filter(|&r| self.v[r] != after.v[r])
+            .map(|r| format!("V{:X} {:02X} > {:02X}", r, self.v[r], after.v[r]))
+            .collect();
+        if self.i != after.i {
+            changes.push(format!("I {:03X} > {:03X}", self.i, after.i));
+        }
+        if self.dt != after.dt {
+            changes.push(format!("DT {:02X} > {:02X}", self.dt, after.dt));
+        }
+        if self.st != after.st {
+            changes.push(format!("ST {:02X} > {:02X}", self.st, after.st));
+        }
+        changes.push(format!("PC {:03X} > {:03X}", self.pc, after.pc));
+        changes
+    }
+}
+
+// what the instruction op writes to, besides moving PC along
+fn writes(op: u16) -> Vec<String> {
+    let vx = format!("V{:X}", x(op));
+    match (op >> 12, op & 0xff) {
+        (0x0, 0xe0) => vec![String::from("SCREEN")],
+        (0x0, 0xee) | (0x2, _) => vec![String::from("SP")],
+        (0x6, _) | (0x7, _) | (0xc, _) => vec![vx],
+        (0x8, _) => match n(op) {
+            0x4..=0x7 | 0xe => vec![vx, String::from("VF")],
+            _ => vec![vx]
+        },
+        (0xa, _) => vec![String::from("I")],
+        (0xd, _) => vec![String::from("SCREEN"), String::from("VF")],
+        (0xf, 0x07) | (0xf, 0x0a) => vec![vx],
+        (0xf, 0x15) => vec![String::from("DT")],
+        (0xf, 0x18) => vec![String::from("ST")],
+        (0xf, 0x1e) | (0xf, 0x29) => vec![String::from("I")],
+        (0xf, 0x33) | (0xf, 0x55) => vec![String::from("RAM")],
+        (0xf, 0x65) if x(op) == 0 => vec![String::from("V0")],
+        (0xf, 0x65) => vec![format!("V0-{}", vx)],
+        _ => Vec::new()
+    }
+}
+
+// runs the CPU, which should start out paused so the educator gets to
+// show its first instruction
+pub struct Educator {
+    // how long each stage stays up
+    frames_per_stage: u32,
+    frames_left: u32,
+    // None until the first frame, when we take over
+    stage: Option<Stage>,
+    // the registers before the instruction being executed
+    before: Option<Registers>
+}
+
+impl Educator {
+    pub fn new(frames_per_stage: u32) -> Educator {
+        Educator { frames_per_stage: frames_per_stage.max(1), frames_left: 0, stage: None, before: None }
+    }
+
+    fn instruction(cpu: &CPU, addr: usize) -> Option<u16> {
+        let ram = cpu.ram();
+        Some(u16::from_be_bytes([*ram.get(addr)?, *ram.get(addr + 1)?]))
+    }
+
+    fn show(&mut self, cpu: &mut CPU, stage: Stage) {
+        let pc = cpu.pc();
+        let op = Educator::instruction(cpu, pc).unwrap_or(0);
+        let lines = match stage {
+            Stage::Fetch => {
+                let mut lines = vec![String::from("FETCH")];
+                // the program's memory, not the interpreter's
+                for addr in [pc.wrapping_sub(2), pc, pc + 2].iter().copied().filter(|a| *a >= ENTRY_POINT) {
+                    if let Some(op) = Educator::instruction(cpu, addr) {
+                        let marker = if addr == pc { '>' } else { ' ' };
+                        lines.push(format!("{}{:03X} {:04X}", marker, addr, op));
+                    }
+                }
+                lines
+            },
+            Stage::Decode => vec![
+                format!("DECODE {:04X}", op),
+                mnemonic(op).unwrap_or_else(|| String::from("UNKNOWN")),
+                format!("WRITES {}", writes(op).join(" "))
+            ],
+            Stage::Execute => {
+                self.before = Some(Registers::of(cpu));
+                cpu.request_step();
+                vec![String::from("EXECUTE")]
+            }
+        };
+        cpu.set_overlay(lines);
+        self.stage = Some(stage);
+        self.frames_left = self.frames_per_stage;
+    }
+}
+
+impl Hooks for Educator {
+    fn on_step(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if let Some(before) = self.before.take() {
+            let mut lines = vec![String::from("EXECUTE")];
+            lines.extend(before.changes(&Registers::of(cpu)).into_iter().take(CHANGES_SHOWN));
+            cpu.set_overlay(lines);
+        }
+        Ok(())
+    }
+
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        // we run the show, even after eg. the pause menu resumed
+        cpu.set_paused(true);
+        let stage = match self.stage {
+            None => {
+                self.show(cpu, Stage::Fetch);
+                return Ok(());
+            },
+            Some(stage) => stage
+        };
+        self.frames_left -= 1;
+        if self.frames_left > 0 {
+            return Ok(());
+        }
+        let next = match stage {
+            Stage::Fetch => Stage::Decode,
+            Stage::Decode => Stage::Execute,
+            Stage::Execute => Stage::Fetch
+        };
+        self.show(cpu, next);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn what_instructions_write() {
+        assert_eq!(writes(0x8124), ["V1", "VF"]);
+        assert_eq!(writes(0xf365), ["V0-V3"]);
+        assert_eq!(writes(0xd015), ["SCREEN", "VF"]);
+        assert!(writes(0x1200).is_empty());
+    }
+
+    #[test]
+    fn goes_through_the_stages() {
+        let run = |frames| {
+            let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), Config::default());
+            cpu.load_rom(&[0x60, 0x05, 0x70, 0x01]).unwrap();
+            cpu.set_paused(true);
+            cpu.add_hooks(Box::new(Educator::new(2)));
+            cpu.run_loop().unwrap();
+            cpu
+        };
+        let cpu = run(1);
+        assert_eq!(cpu.overlay(), ["FETCH", ">200 6005", " 202 7001"]);
+        let cpu = run(3);
+        assert_eq!(cpu.overlay(), ["DECODE 6005", "LD V0, 0x05", "WRITES V0"]);
+        let cpu = run(5);
+        assert_eq!(cpu.overlay(), ["EXECUTE"]);
+        assert_eq!(cpu.pc(), 0x200);
+        let cpu = run(6);
+        assert_eq!(cpu.overlay(), ["EXECUTE", "V0 00 > 05", "PC 200 > 202"]);
+        // one instruction every three stages
+        let cpu = run(12);
+        assert_eq!((cpu.v()[0], cpu.pc()), (6, 0x204));
+    }
+}
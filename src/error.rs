@@ -0,0 +1,143 @@
+// what stops the CPU: a ROM doing something the machine can't carry on
+// from, or a frontend or hook giving up; and what keeps a save state from
+// being saved or loaded
+
+use std::{
+    error::Error,
+    fmt
+};
+
+use crate::i18n::{
+    Language,
+    Text
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chip8Error {
+    // a call with the stack full, or a return with it empty; the
+    // instruction, where it was, and the return addresses on the stack
+    StackOverflow { instruction: u16, pc: usize, stack: Vec<usize> },
+    StackUnderflow { instruction: u16, pc: usize, stack: Vec<usize> },
+    // a read or write past the end of RAM, with --memory=error
+    OutOfBounds { addr: usize, pc: usize, write: bool },
+    // the program counter left RAM; the last instruction fetched, if any,
+    // as (address, instruction)
+    PcOutOfRange { pc: usize, last: Option<(usize, u16)> },
+    // a write below 0x200, with --protect-font=reject
+    ProtectedWrite { addr: usize, value: u8, pc: usize },
+    // with --unknown-opcode=halt, an instruction we don't know, or a call
+    // to machine code we can't run
+    UnknownInstruction { instruction: u16, pc: usize },
+    MachineCode { addr: usize, pc: usize },
+    // 0011, which switches to MegaChip mode, on another platform
+    MegaChip { pc: usize },
+    // a ROM bigger than the RAM it's loaded into, without --truncate-rom
+    RomTooLarge { size: usize, available: usize, start: usize },
+    // with --check-invariants; what was wrong, and the machine's state
+    InvariantViolated { instruction: u16, why: String, state: String },
+    // an Fx0A at pc waited longer than the configured timeout, for
+    // CPU::iter_steps, which has no Exit to say so with
+    KeyWaitTimedOut { pc: usize },
+    // CPU::load_state with nothing saved
+    NoSavedState,
+    // a numbered save slot, with nowhere set to keep them
+    NoStateDir { slot: usize },
+    // loading a slot nothing was saved to
+    SlotEmpty { slot: usize },
+    // the slot's file couldn't be written or read, or isn't a save state
+    // of this ROM
+    StateIo { slot: usize, why: String },
+    BadState { slot: usize, why: String },
+    Frontend(String),
+    Hook(String)
+}
+
+impl Chip8Error {
+    // the option that would have let the ROM carry on, if there is one
+    pub fn option(&self) -> Option<&'static str> {
+        match self {
+            Chip8Error::OutOfBounds { .. } => Some("--memory=wrap"),
+            Chip8Error::ProtectedWrite { .. } => Some("--protect-font=log"),
+            Chip8Error::UnknownInstruction { .. } | Chip8Error::MachineCode { .. } => Some("--unknown-opcode=warn"),
+            Chip8Error::RomTooLarge { .. } => Some("--truncate-rom"),
+            Chip8Error::MegaChip { .. } => Some("--platform=megachip"),
+            _ => None
+        }
+    }
+
+    // the message in lang, for the errors players see over the screen;
+    // the rest are only in English
+    pub fn localized(&self, lang: Language) -> String {
+        match self {
+            Chip8Error::KeyWaitTimedOut { pc } => lang.fill(Text::WaitingForInput, &[&format!("{:03x}", pc)]),
+            Chip8Error::NoSavedState => String::from(lang.text(Text::NoSavedState)),
+            Chip8Error::NoStateDir { slot } => lang.fill(Text::SlotFailed, &[slot, &lang.text(Text::NoStateDir)]),
+            Chip8Error::SlotEmpty { slot } => lang.fill(Text::SlotEmpty, &[slot]),
+            Chip8Error::StateIo { slot, why } | Chip8Error::BadState { slot, why } => lang.fill(Text::SlotFailed, &[slot, why]),
+            _ => self.to_string()
+        }
+    }
+}
+
+// eg. "3a0, 2f4"
+fn addresses(stack: &[usize]) -> String {
+    stack.iter().map(|addr| format!("{:03x}", addr)).collect::<Vec<String>>().join(", ")
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { instruction, pc, stack } => write!(
+                f, "Stack full, cannot call subroutine (instruction {:04x} at {:03x}, call stack: [{}])",
+                instruction, pc, addresses(stack)
+            ),
+            Chip8Error::StackUnderflow { instruction, pc, stack } => write!(
+                f, "Stack empty, cannot return from subroutine (instruction {:04x} at {:03x}, call stack: [{}])",
+                instruction, pc, addresses(stack)
+            ),
+            Chip8Error::OutOfBounds { addr, pc, write } => write!(
+                f, "{} past the end of RAM at {:04x} by instruction at {:03x}",
+                if *write { "Write" } else { "Read" }, addr, pc
+            ),
+            Chip8Error::PcOutOfRange { pc, last: Some((at, instruction)) } => write!(
+                f, "Program counter out of range: {:03x} (last valid instruction was {:04x} at {:03x})",
+                pc, instruction, at
+            ),
+            Chip8Error::PcOutOfRange { pc, last: None } => write!(f, "Program counter out of range: {:03x}", pc),
+            Chip8Error::ProtectedWrite { addr, value, pc } => write!(
+                f, "Write of {:02x} to protected address {:03x} by instruction at {:03x}", value, addr, pc
+            ),
+            Chip8Error::UnknownInstruction { instruction, pc } => write!(f, "Unrecognized instruction {:04x} at {:03x}", instruction, pc),
+            Chip8Error::MachineCode { addr, pc } => write!(f, "Call to machine code at {:03x} from {:03x}, which can't be run", addr, pc),
+            Chip8Error::MegaChip { pc } => write!(f, "0011 at {:03x} switches to MegaChip mode, which this platform doesn't have", pc),
+            Chip8Error::RomTooLarge { size, available, start } => write!(
+                f, "Out of memory: program is {} bytes but only {} bytes are available from {:03x}", size, available, start
+            ),
+            Chip8Error::InvariantViolated { instruction, why, state } => write!(
+                f, "Invariant violated after instruction {:04x}: {}\n{}", instruction, why, state
+            ),
+            Chip8Error::Frontend(why) | Chip8Error::Hook(why) => write!(f, "{}", why),
+            _ => write!(f, "{}", self.localized(Language::English))
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let err = Chip8Error::StackUnderflow { instruction: 0x00ee, pc: 0x204, stack: Vec::new() };
+        assert_eq!(err.to_string(), "Stack empty, cannot return from subroutine (instruction 00ee at 204, call stack: [])");
+        let err = Chip8Error::OutOfBounds { addr: 0x1000, pc: 0x202, write: true };
+        assert_eq!(err.to_string(), "Write past the end of RAM at 1000 by instruction at 202");
+        assert_eq!(err.option(), Some("--memory=wrap"));
+        assert_eq!(Chip8Error::MegaChip { pc: 0x200 }.option(), Some("--platform=megachip"));
+        assert_eq!(Chip8Error::Hook(String::from("gone")).option(), None);
+        assert_eq!(Chip8Error::SlotEmpty { slot: 4 }.to_string(), "Slot 4 is empty");
+        assert_eq!(Chip8Error::NoStateDir { slot: 3 }.localized(Language::Spanish), "No se pudo usar la ranura 3: no hay dónde guardar las ranuras");
+    }
+}
@@ -0,0 +1,93 @@
+// SUPER-CHIP's flag registers (Fx75 and Fx85) kept in a file next to the
+// ROM, eg. game.ch8.flags, as the HP48 kept them between runs, so the high
+// scores games save there survive closing the emulator. the file is one
+// line of hex bytes, V0 first: "1a 00 00 00 00 00 00 00"
+
+use std::{
+    fs,
+    path::PathBuf
+};
+
+use crate::cpu::{
+    CPU,
+    FLAG_COUNT
+};
+use crate::hooks::Hooks;
+
+pub const FLAGS_EXTENSION: &str = "flags";
+
+pub fn parse_flags(text: &str) -> Result<[u8; FLAG_COUNT], String> {
+    let mut flags = [0; FLAG_COUNT];
+    let bytes: Vec<&str> = text.split_whitespace().collect();
+    if bytes.len() > FLAG_COUNT {
+        return Err(format!("expected at most {} bytes, got {}", FLAG_COUNT, bytes.len()));
+    }
+    for (flag, byte) in flags.iter_mut().zip(bytes) {
+        *flag = u8::from_str_radix(byte, 16).map_err(|_| format!("`{}` is not a hex byte", byte))?;
+    }
+    Ok(flags)
+}
+
+pub fn flags_text(flags: &[u8; FLAG_COUNT]) -> String {
+    let bytes: Vec<String> = flags.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}\n", bytes.join(" "))
+}
+
+// hooks that write the flags to path whenever the ROM changes them
+pub struct FlagsFile {
+    path: PathBuf,
+    saved: [u8; FLAG_COUNT]
+}
+
+impl FlagsFile {
+    // saved being what the file holds already
+    pub fn new(path: PathBuf, saved: [u8; FLAG_COUNT]) -> FlagsFile {
+        FlagsFile { path, saved }
+    }
+}
+
+impl Hooks for FlagsFile {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let flags = cpu.flags();
+        if flags == self.saved {
+            return Ok(());
+        }
+        fs::write(&self.path, flags_text(&flags))
+            .map_err(|why| format!("Could not save the flags to {}: {}", self.path.display(), why))?;
+        self.saved = flags;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::testsuite::run_headless;
+
+    #[test]
+    fn round_trips_through_text() {
+        let flags = [0x1a, 0, 0, 0, 0, 0, 0, 0xff];
+        assert_eq!(flags_text(&flags), "1a 00 00 00 00 00 00 ff\n");
+        assert_eq!(parse_flags(&flags_text(&flags)), Ok(flags));
+        assert_eq!(parse_flags("07"), Ok([7, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(parse_flags("zz").is_err());
+        assert!(parse_flags("0 1 2 3 4 5 6 7 8").is_err());
+    }
+
+    #[test]
+    fn saves_changed_flags() {
+        let path = env::temp_dir().join(format!("chip8-flags-test-{}", std::process::id()));
+        let mut file = FlagsFile::new(path.clone(), [0; FLAG_COUNT]);
+        // V0 = 9, saved to the flags
+        let mut cpu = run_headless(&[0x60, 0x09, 0xf0, 0x75, 0x12, 0x04], 1, Config::default());
+        file.on_frame(&mut cpu).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "09 00 00 00 00 00 00 00\n");
+        fs::remove_file(&path).unwrap();
+        // unchanged since, so not written again
+        file.on_frame(&mut cpu).unwrap();
+        assert!(!path.exists());
+    }
+}
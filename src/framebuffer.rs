@@ -0,0 +1,468 @@
+use std::fmt;
+
+use crate::chip8x::ColourZones;
+use crate::megachip::MegaScreen;
+use crate::util::fnv1a;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+// the size of SUPER-CHIP's high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+// and of MegaChip's
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+
+// the screen sizes a program can ask for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    // 64x32, the usual
+    Low,
+    // 64x64, from the COSMAC VIP's two-page hi-res interpreter
+    TwoPage,
+    // 128x64, SUPER-CHIP's 00FF
+    High,
+    // 256x192, MegaChip's 0011 (see megachip)
+    Mega
+}
+
+impl Resolution {
+    pub fn from_size(width: usize, height: usize) -> Option<Resolution> {
+        [Resolution::Low, Resolution::TwoPage, Resolution::High, Resolution::Mega].iter().copied()
+            .find(|resolution| resolution.size() == (width, height))
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        match self {
+            Resolution::Low => (WIDTH, HEIGHT),
+            Resolution::TwoPage => (WIDTH, WIDTH),
+            Resolution::High => (HIRES_WIDTH, HIRES_HEIGHT),
+            Resolution::Mega => (MEGA_WIDTH, MEGA_HEIGHT)
+        }
+    }
+}
+
+// XO-CHIP's two bitplanes, as the bits of a pixel's colour
+pub const PLANES: u8 = 0b11;
+
+// the logical CHIP-8 screen, one bool per pixel (true = on),
+// independent of how a frontend ends up showing it; WIDTH x HEIGHT,
+// unless the program switched to another Resolution
+#[derive(Clone)]
+pub struct Framebuffer {
+    // row by row, width to a row; room for the biggest mode
+    pixels: Vec<bool>,
+    // the same pixels as colours 0-3, one bit per plane; a pixel is on
+    // if it's on in either plane. in MegaChip mode, palette indices instead
+    colours: Vec<u8>,
+    // the planes drawing, clearing and scrolling act on (XO-CHIP's Fn01)
+    selected: u8,
+    // CHIP-8X's colours, for its ROMs
+    zones: Option<ColourZones>,
+    // MegaChip's colours and the frame being drawn, in MegaChip mode
+    mega: Option<Box<MegaScreen>>,
+    resolution: Resolution,
+    width: usize,
+    height: usize,
+    // whether any pixel changed since the screen was last shown
+    dirty: bool
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            pixels: vec![false; MEGA_WIDTH * MEGA_HEIGHT],
+            colours: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            selected: 1,
+            zones: None,
+            mega: None,
+            resolution: Resolution::Low,
+            width: WIDTH,
+            height: HEIGHT,
+            dirty: true
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    // switch to another screen size, eg. for 00FE and 00FF, clearing it
+    // in every plane
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        let (width, height) = resolution.size();
+        self.resolution = resolution;
+        self.width = width;
+        self.height = height;
+        self.pixels.fill(false);
+        self.colours.fill(0);
+        self.dirty = true;
+    }
+
+    // the planes selected with Fn01, 1 unless an XO-CHIP program changed it
+    pub fn planes(&self) -> u8 {
+        self.selected
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected = planes & PLANES;
+    }
+
+    pub fn colour_zones(&self) -> Option<&ColourZones> {
+        self.zones.as_ref()
+    }
+
+    // start a CHIP-8X ROM with the default colours, or go back to none
+    pub fn set_colour_zones(&mut self, zones: Option<ColourZones>) {
+        self.zones = zones;
+        self.dirty = true;
+    }
+
+    // for changing them, eg. with Bxyn
+    pub fn colour_zones_mut(&mut self) -> Option<&mut ColourZones> {
+        self.dirty = true;
+        self.zones.as_mut()
+    }
+
+    pub fn mega_screen(&self) -> Option<&MegaScreen> {
+        self.mega.as_deref()
+    }
+
+    // start MegaChip mode, at Resolution::Mega, or go back to none
+    pub fn set_mega_screen(&mut self, mega: Option<MegaScreen>) {
+        self.mega = mega.map(Box::new);
+        self.dirty = true;
+    }
+
+    // for its settings, eg. the palette; changes nothing shown until the
+    // next clear
+    pub fn mega_screen_mut(&mut self) -> Option<&mut MegaScreen> {
+        self.mega.as_deref_mut()
+    }
+
+    // how many planes a sprite is drawn to, each from its own run of bytes
+    pub fn plane_count(&self) -> usize {
+        self.selected.count_ones() as usize
+    }
+
+    // turn off the selected planes; in MegaChip mode, show the frame drawn
+    // since the last clear instead, and start on a new one
+    pub fn clear(&mut self) {
+        if let Some(mega) = &mut self.mega {
+            let indices = mega.present();
+            for ((px, colour), index) in self.pixels.iter_mut().zip(self.colours.iter_mut()).zip(indices) {
+                *colour = index;
+                *px = index != 0;
+            }
+            self.dirty = true;
+            return;
+        }
+        let mask = !self.selected;
+        for (px, colour) in self.pixels.iter_mut().zip(self.colours.iter_mut()) {
+            *colour &= mask;
+            *px = *colour != 0;
+        }
+        self.dirty = true;
+    }
+
+    // xor the given sprite rows onto the screen starting at (init_x, init_y),
+    // wrapping around the edges; returns 1 if any ON pixel was turned OFF
+    pub fn draw(&mut self, bytes: &[u8], init_x: u8, init_y: u8) -> u8 {
+        self.draw_sprite(bytes, init_x, init_y, false)
+    }
+
+    // the same, but if clip is set only the starting position wraps, and
+    // the parts of the sprite past the edges are left off; with both
+    // planes selected, the first half of bytes goes to plane 1 and the
+    // second half to plane 2
+    pub fn draw_sprite(&mut self, bytes: &[u8], init_x: u8, init_y: u8, clip: bool) -> u8 {
+        let mut collision = 0;
+        for (plane, bytes) in self.plane_bytes(bytes) {
+            collision |= self.xor_rows(plane, bytes.iter().map(|b| (*b as u16) << 8), 8, init_x, init_y, clip);
+        }
+        collision
+    }
+
+    // a 16x16 sprite from 32 bytes (per plane), two to a row (SUPER-CHIP's Dxy0)
+    pub fn draw_large(&mut self, bytes: &[u8], init_x: u8, init_y: u8, clip: bool) -> u8 {
+        let mut collision = 0;
+        for (plane, bytes) in self.plane_bytes(bytes) {
+            let rows = bytes.chunks(2).map(|row| u16::from_be_bytes([row[0], *row.get(1).unwrap_or(&0)]));
+            collision |= self.xor_rows(plane, rows, 16, init_x, init_y, clip);
+        }
+        collision
+    }
+
+    // a MegaChip sprite of palette indices, width to a row, onto the frame
+    // being drawn (see MegaScreen::draw); outside MegaChip mode nothing
+    pub fn draw_mega(&mut self, sprite: &[u8], width: usize, x: u8, y: u8) -> u8 {
+        match &mut self.mega {
+            Some(mega) => mega.draw(sprite, width, x as usize, y as usize),
+            None => 0
+        }
+    }
+
+    // bytes split evenly between the selected planes, lowest plane first
+    fn plane_bytes<'a>(&self, bytes: &'a [u8]) -> Vec<(u8, &'a [u8])> {
+        let count = self.plane_count();
+        if count == 0 {
+            return Vec::new();
+        }
+        let planes = [1, 2].iter().copied().filter(|plane| self.selected & plane != 0);
+        planes.zip(bytes.chunks(bytes.len().div_ceil(count).max(1))).collect()
+    }
+
+    // rows row_width (up to 16) pixels wide, leftmost pixel in the top bit,
+    // xored onto a single plane
+    fn xor_rows<I: Iterator<Item = u16>>(&mut self, plane: u8, rows: I, row_width: usize, init_x: u8, init_y: u8, clip: bool) -> u8 {
+        let (init_x, init_y) = (init_x as usize % self.width, init_y as usize % self.height);
+        let mut collision: u8 = 0;
+        for (k, row) in rows.enumerate() {
+            for j in 0..row_width {
+                if clip && (init_x + j >= self.width || init_y + k >= self.height) {
+                    continue;
+                }
+                let x = (init_x + j) % self.width;
+                let y = (init_y + k) % self.height;
+                let coord = (y * self.width) + x;
+                // xor pixels bits only if they are set
+                // if existing bit erased then set collision bit to true
+                if row & (0x8000 >> j) != 0 {
+                    if self.colours[coord] & plane != 0 { collision = 1; }
+                    self.colours[coord] ^= plane;
+                    self.pixels[coord] = self.colours[coord] != 0;
+                    self.dirty = true;
+                }
+            }
+        }
+        collision
+    }
+
+    // move the selected planes down by n rows (00Cn), blank rows coming in
+    // at the top
+    pub fn scroll_down(&mut self, n: usize) {
+        if let Some(mega) = &mut self.mega {
+            mega.scroll(0, n as isize);
+            return;
+        }
+        let n = n.min(self.height);
+        self.scroll_by(|x, y| (Some(x), y.checked_sub(n)));
+    }
+
+    // move them right (00FB) or left (00FC) by n columns
+    pub fn scroll_right(&mut self, n: usize) {
+        if let Some(mega) = &mut self.mega {
+            mega.scroll(n as isize, 0);
+            return;
+        }
+        self.scroll_by(|x, y| (x.checked_sub(n), Some(y)));
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        if let Some(mega) = &mut self.mega {
+            mega.scroll(-(n as isize), 0);
+            return;
+        }
+        let width = self.width;
+        self.scroll_by(|x, y| (Some(x + n).filter(|x| *x < width), Some(y)));
+    }
+
+    // and up by n rows (MegaChip's 00Bn)
+    pub fn scroll_up(&mut self, n: usize) {
+        if let Some(mega) = &mut self.mega {
+            mega.scroll(0, -(n as isize));
+            return;
+        }
+        let height = self.height;
+        self.scroll_by(|x, y| (Some(x), Some(y + n).filter(|y| *y < height)));
+    }
+
+    // give every pixel of the selected planes the one at from(x, y), or
+    // blank if that's off the screen; the other planes stay put
+    fn scroll_by<F: Fn(usize, usize) -> (Option<usize>, Option<usize>)>(&mut self, from: F) {
+        let (width, height, mask) = (self.width, self.height, self.selected);
+        let before = self.colours.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let moved = match from(x, y) {
+                    (Some(from_x), Some(from_y)) => before[from_y * width + from_x] & mask,
+                    _ => 0
+                };
+                let coord = y * width + x;
+                self.colours[coord] = self.colours[coord] & !mask | moved;
+                self.pixels[coord] = self.colours[coord] != 0;
+            }
+        }
+        self.dirty = true;
+    }
+
+    // turn a single pixel on or off, eg. to show a screen received from
+    // elsewhere; on means colour 1
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        self.set_colour(x, y, on as u8);
+    }
+
+    // the same with any colour, as colours() has them
+    pub fn set_colour(&mut self, x: usize, y: usize, colour: u8) {
+        let coord = (y % self.height) * self.width + x % self.width;
+        let colour = if self.resolution == Resolution::Mega { colour } else { colour & PLANES };
+        self.dirty |= self.colours[coord] != colour;
+        self.pixels[coord] = colour != 0;
+        self.colours[coord] = colour;
+    }
+
+    // true if the screen changed since mark_clean was last called, so a
+    // frontend can skip redrawing it
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // called once the screen has been shown
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    // width() * height() of them, row by row
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels[..self.width * self.height]
+    }
+
+    // the same, as colours: 0 is off, 1 and 2 on in just plane 1 or 2, and
+    // 3 on in both; or in MegaChip mode, the palette indices shown
+    pub fn colours(&self) -> &[u8] {
+        &self.colours[..self.width * self.height]
+    }
+
+    // hash of the screen contents, for comparing frames cheaply
+    pub fn hash(&self) -> u64 {
+        match &self.mega {
+            Some(mega) => fnv1a(mega.shown().iter().flat_map(|colour| colour.to_be_bytes())),
+            None => fnv1a(self.colours().iter().copied())
+        }
+    }
+}
+
+// pixels, width to a row, blown up to to_width x to_height by repeating
+// each one, eg. to put a 64x32 picture over a 128x64 screen
+pub fn scale_pixels(pixels: &[bool], width: usize, to_width: usize, to_height: usize) -> Vec<bool> {
+    let height = pixels.len() / width;
+    let (scale_x, scale_y) = (to_width / width, to_height / height);
+    (0..to_width * to_height)
+        .map(|j| pixels[(j / to_width / scale_y) * width + j % to_width / scale_x])
+        .collect()
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+// render the screen as text, '#' for ON and '.' for OFF
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.pixels().chunks(self.width) {
+            let line: String = row.iter().map(|px| if *px { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolutions() {
+        let mut fb = Framebuffer::new();
+        fb.set_resolution(Resolution::High);
+        assert_eq!((fb.width(), fb.height(), fb.pixels().len()), (128, 64, 128 * 64));
+        assert_eq!(Resolution::from_size(64, 64), Some(Resolution::TwoPage));
+        assert_eq!(Resolution::from_size(64, 48), None);
+        let scaled = scale_pixels(&[true, false, false, true], 2, 4, 4);
+        assert_eq!(scaled, [
+            true, true, false, false,
+            true, true, false, false,
+            false, false, true, true,
+            false, false, true, true
+        ]);
+    }
+
+    #[test]
+    fn scrolls_bring_in_blank_pixels() {
+        let mut fb = Framebuffer::new();
+        fb.set(0, 0, true);
+        fb.set(63, 31, true);
+        fb.scroll_down(2);
+        assert!(fb.pixels()[2 * 64]);
+        assert_eq!(fb.pixels().iter().filter(|px| **px).count(), 1);
+        fb.scroll_right(4);
+        assert!(fb.pixels()[2 * 64 + 4]);
+        fb.scroll_left(8);
+        assert!(fb.pixels().iter().all(|px| !px));
+        fb.set(1, 1, true);
+        fb.scroll_down(40);
+        assert!(fb.pixels().iter().all(|px| !px));
+    }
+
+    #[test]
+    fn planes_draw_clear_and_scroll_apart() {
+        let mut fb = Framebuffer::new();
+        fb.draw(&[0xc0], 0, 0);
+        fb.select_planes(2);
+        fb.draw(&[0x60], 0, 0);
+        assert_eq!(&fb.colours()[..4], &[1, 3, 2, 0]);
+        // both planes take their own rows, plane 1's first
+        fb.select_planes(PLANES);
+        assert_eq!(fb.draw(&[0x80, 0x10], 0, 1), 0);
+        assert_eq!(&fb.colours()[64..68], &[1, 0, 0, 2]);
+        assert_eq!(fb.draw(&[0x40, 0x40], 0, 0), 1);
+        assert_eq!(&fb.colours()[..4], &[1, 0, 2, 0]);
+        fb.select_planes(2);
+        fb.scroll_right(1);
+        assert_eq!(&fb.colours()[..4], &[1, 0, 0, 2]);
+        fb.clear();
+        assert_eq!(&fb.colours()[..4], &[1, 0, 0, 0]);
+        assert_eq!(&fb.pixels()[..2], &[true, false]);
+        // nothing selected, nothing drawn
+        fb.select_planes(0);
+        assert_eq!(fb.draw(&[0xff], 0, 0), 0);
+        assert_eq!(&fb.colours()[..4], &[1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn dirty_until_shown() {
+        let mut fb = Framebuffer::new();
+        assert!(fb.is_dirty());
+        fb.mark_clean();
+        // a blank row changes nothing, and neither does setting a pixel to
+        // what it already is
+        fb.draw(&[0], 0, 0);
+        fb.set(1, 1, false);
+        assert!(!fb.is_dirty());
+        fb.draw(&[0x80], 0, 0);
+        assert!(fb.is_dirty());
+    }
+
+    #[test]
+    fn clipped_sprites_stop_at_the_edge() {
+        let mut wrapped = Framebuffer::new();
+        wrapped.draw_sprite(&[0xff, 0xff], 60, 31, false);
+        let mut clipped = Framebuffer::new();
+        clipped.draw_sprite(&[0xff, 0xff], 60, 31, true);
+        let lit = |fb: &Framebuffer| fb.pixels().iter().filter(|px| **px).count();
+        assert_eq!((lit(&wrapped), lit(&clipped)), (16, 4));
+        // but where it starts still wraps
+        clipped.draw_sprite(&[0x80], 64 + 1, 32, true);
+        assert!(clipped.pixels()[1]);
+    }
+}
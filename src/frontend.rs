@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::framebuffer::Framebuffer;
+use crate::tone::PATTERN_LEN;
+
+// how often frontends show a frame (480 Hz); the window waiting for the
+// next one (see pacing) is what paces the CPU
+pub const REFRESH_INTERVAL: Duration = Duration::from_micros(2083);
+
+// the sizes a window can be, in screen pixels to a CHIP-8 pixel
+pub const SCALES: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+// what a frontend can show about the emulator, eg. in its title bar
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Status {
+    pub paused: bool,
+    // nothing will happen until a key is pressed or the CPU is resumed, so
+    // the frontend can take it easy rather than keep exact time
+    pub idle: bool,
+    // a replay of the session is being recorded
+    pub recording: bool,
+    // instructions run a 60 Hz frame
+    pub speed: u32
+}
+
+// the ways the CPU talks to the outside world; the minifb window and
+// rodio audio implement these, as does the headless frontend
+
+pub trait Display {
+    // show the current contents of the framebuffer; an error stops the CPU
+    fn refresh(&mut self, framebuffer: &Framebuffer) -> Result<(), String>;
+
+    // time since the frontend started; the delay and sound timers
+    // count down at 60 Hz of this time
+    fn elapsed(&self) -> Duration;
+
+    // text to draw over the screen from now on, if the frontend can
+    fn set_overlay(&mut self, _lines: &[String]) {}
+
+    // the emulator's state, given every frame
+    fn set_status(&mut self, _status: Status) {}
+
+    // switch to the next set of colours, if the frontend has any
+    fn next_palette(&mut self) {}
+}
+
+// keys for the emulator itself rather than the ROM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    // save RAM and registers to files in the current directory
+    DumpMemory,
+    // run more or fewer instructions a frame (see config::step_speed)
+    Faster,
+    Slower,
+    // write the machine to a numbered save slot, or go back to one
+    SaveSlot(usize),
+    LoadSlot(usize),
+    // open or close the pause menu
+    Menu,
+    // move through the pause menu or the command palette's suggestions,
+    // and pick one
+    MenuUp,
+    MenuDown,
+    MenuSelect,
+    // open or close the command palette
+    Palette,
+    // typing in the command palette: a character, deleting one, and
+    // completing the command
+    Type(char),
+    Backspace,
+    Complete
+}
+
+pub trait Input {
+    // false once the emulator should stop running
+    fn is_open(&self) -> bool;
+
+    // the ith element is true if CHIP-8 key i is held down
+    fn handle_key_events(&self) -> [bool; 16];
+
+    // the same for CHIP-8X's second keypad, for frontends that have one
+    fn second_keypad(&self) -> [bool; 16] {
+        [false; 16]
+    }
+
+    // hotkeys pressed since the last frame
+    fn hotkeys(&self) -> Vec<Hotkey> {
+        Vec::new()
+    }
+
+    // false while the user is in another window
+    fn focused(&self) -> bool {
+        true
+    }
+}
+
+pub trait Buzzer {
+    fn play(&self);
+    fn pause(&self);
+
+    // play pattern (see tone) from now on, instead of the usual tone, if
+    // the backend can (XO-CHIP's F002)
+    fn set_pattern(&self, _pattern: [u8; PATTERN_LEN]) {}
+
+    // the rate the pattern plays at, as a pitch (see tone; Fx3A)
+    fn set_pitch(&self, _pitch: u8) {}
+
+    // play a digitised sound, samples unsigned with 128 silent and rate of
+    // them a second, over and over if looping, if the backend can
+    // (MegaChip's 060n); it plays whatever the sound timer does
+    fn play_sample(&self, _samples: &[u8], _rate: u32, _looping: bool) {}
+
+    // and stop it (0700)
+    fn stop_sample(&self) {}
+
+    // how often the audio output ran out of samples, if the backend knows
+    fn underruns(&self) -> Option<u64> {
+        None
+    }
+}
+
+// a display and input source in one, eg. a window
+pub trait Frontend: Display + Input {}
+
+impl<T: Display + Input> Frontend for T {}
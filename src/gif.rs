@@ -0,0 +1,197 @@
+// just enough GIF to save recordings of the screen as animations without
+// another dependency: two colours, white on black, looping forever
+
+use std::collections::HashMap;
+
+// GIF codes can't be longer than this
+const MAX_CODE_BITS: u8 = 12;
+
+// packs codes into bytes, least significant bit first
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u8
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+// the LZW stream for pixels, each below 1 << min_width
+fn lzw(min_width: u8, pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_width;
+    let end = clear + 1;
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = min_width + 1;
+    let mut writer = BitWriter { out: Vec::new(), buffer: 0, bits: 0 };
+    writer.write(clear, width);
+
+    let mut current: Option<u16> = None;
+    for &pixel in pixels {
+        let prefix = match current {
+            None => {
+                current = Some(pixel as u16);
+                continue;
+            },
+            Some(prefix) => prefix
+        };
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            current = Some(code);
+            continue;
+        }
+        writer.write(prefix, width);
+        if next < 1 << MAX_CODE_BITS {
+            table.insert((prefix, pixel), next);
+            next += 1;
+            // the decoder adds each entry a code later than we do, so it
+            // widens its codes one code later too
+            if next - 1 == 1 << width && width < MAX_CODE_BITS {
+                width += 1;
+            }
+        }
+        else {
+            writer.write(clear, width);
+            table.clear();
+            next = end + 1;
+            width = min_width + 1;
+        }
+        current = Some(pixel as u16);
+    }
+    if let Some(code) = current {
+        writer.write(code, width);
+    }
+    writer.write(end, width);
+    writer.finish()
+}
+
+// an animated GIF of width x height on/off frames, each pixel scale x scale
+// big, with how long to show each frame in hundredths of a second
+pub fn encode_animation(width: usize, height: usize, frames: &[(Vec<bool>, u16)], scale: usize) -> Vec<u8> {
+    let (w, h) = ((width * scale) as u16, (height * scale) as u16);
+    let mut out = Vec::from(&b"GIF89a"[..]);
+    out.extend_from_slice(&w.to_le_bytes());
+    out.extend_from_slice(&h.to_le_bytes());
+    // a global colour table of 2 entries, black and white
+    out.extend_from_slice(&[0x80, 0, 0]);
+    out.extend_from_slice(&[0, 0, 0, 0xff, 0xff, 0xff]);
+    // loop forever
+    out.extend_from_slice(&[0x21, 0xff, 11]);
+    out.extend_from_slice(b"NETSCAPE2.0");
+    out.extend_from_slice(&[3, 1, 0, 0, 0]);
+
+    for (bits, delay) in frames {
+        out.extend_from_slice(&[0x21, 0xf9, 4, 0]);
+        out.extend_from_slice(&delay.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+
+        out.push(0x2c);
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out.extend_from_slice(&w.to_le_bytes());
+        out.extend_from_slice(&h.to_le_bytes());
+        out.push(0);
+
+        let mut pixels = Vec::with_capacity(w as usize * h as usize);
+        for row in bits.chunks(width.max(1)).take(height) {
+            let line: Vec<u8> = row.iter().flat_map(|&on| std::iter::repeat_n(on as u8, scale)).collect();
+            for _ in 0..scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+        // 2 is the smallest code width GIF allows
+        out.push(2);
+        for block in lzw(2, &pixels).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+    }
+    out.push(0x3b);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the reverse of lzw, to check it against
+    fn unlzw(min_width: u8, data: &[u8]) -> Vec<u8> {
+        let clear = 1usize << min_width;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = min_width + 1;
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0u8, data.iter());
+        let mut previous: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        loop {
+            while bits < width {
+                buffer |= (*bytes.next().unwrap() as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << width) - 1)) as usize;
+            buffer >>= width;
+            bits -= width;
+            if code == clear {
+                table = (0..clear).map(|c| vec![c as u8]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                width = min_width + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => [&prev[..], &prev[..1]].concat(),
+                (None, None) => panic!("bad code {}", code)
+            };
+            if let Some(prev) = previous {
+                if table.len() < 1 << MAX_CODE_BITS {
+                    table.push([&prev[..], &entry[..1]].concat());
+                }
+                if table.len() == 1 << width && width < MAX_CODE_BITS {
+                    width += 1;
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        // long runs, and enough variety to fill the table more than once
+        let mut pixels = vec![0; 5000];
+        let mut x = 1u32;
+        for _ in 0..40000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            pixels.push((x >> 16) as u8 & 3);
+        }
+        assert_eq!(unlzw(2, &lzw(2, &pixels)), pixels);
+        assert_eq!(unlzw(2, &lzw(2, &[1])), [1]);
+    }
+
+    #[test]
+    fn animation_layout() {
+        let gif = encode_animation(2, 1, &[(vec![true, false], 5)], 2);
+        assert_eq!(&gif[..10], b"GIF89a\x04\x00\x02\x00");
+        // the graphic control extension holds the delay
+        assert_eq!(&gif[13 + 6 + 19..13 + 6 + 27], &[0x21, 0xf9, 4, 0, 5, 0, 0, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+    }
+}
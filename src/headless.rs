@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use crate::framebuffer::Framebuffer;
+use crate::frontend::{
+    Buzzer,
+    Display,
+    Input,
+    REFRESH_INTERVAL
+};
+
+// keys from this one up are on CHIP-8X's second keypad: key 10 is its 0
+pub const SECOND_KEYPAD: usize = 16;
+
+// a key going down or up once the given number of frames have been shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: usize,
+    pub down: bool
+}
+
+// parse an input script: one `<frame> <key> <down|up>` per line, with the
+// key in hex, eg. `120 a down`, and 10-1f for the keys of CHIP-8X's second
+// keypad; blank lines and lines starting with # are skipped
+pub fn parse_input_script(text: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let event = match fields.as_slice() {
+            [frame, key, action] => {
+                let frame = frame.parse().ok();
+                let key = usize::from_str_radix(key, 16).ok().filter(|k| *k < 2 * SECOND_KEYPAD);
+                let down = match *action {
+                    "down" => Some(true),
+                    "up" => Some(false),
+                    _ => None
+                };
+                match (frame, key, down) {
+                    (Some(frame), Some(key), Some(down)) => Some(KeyEvent { frame, key, down }),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        match event {
+            Some(e) => events.push(e),
+            None => {
+                return Err(format!("line {}: expected `<frame> <key 0-1f> <down|up>`, got `{}`", n + 1, line));
+            }
+        }
+    }
+    Ok(events)
+}
+
+// the keys of one keypad (the second when second is true) held down once
+// frame frames have been shown, going by events in order
+pub fn held_keys(events: &[KeyEvent], frame: u32, second: bool) -> [bool; 16] {
+    let first = if second { SECOND_KEYPAD } else { 0 };
+    let mut keys = [false; 16];
+    for e in events.iter().take_while(|e| e.frame <= frame) {
+        if (first..first + 16).contains(&e.key) {
+            keys[e.key - first] = e.down;
+        }
+    }
+    keys
+}
+
+// a frontend with no window: it shows nothing, presses keys only as
+// scripted, and stops the emulator after a fixed number of frames; time
+// passes by exactly one refresh interval per frame so runs are reproducible
+pub struct Headless {
+    frames_left: usize,
+    frames_shown: u32,
+    script: Vec<KeyEvent>
+}
+
+impl Headless {
+    pub fn new(frames: usize) -> Headless {
+        Headless::with_script(frames, Vec::new())
+    }
+
+    pub fn with_script(frames: usize, mut script: Vec<KeyEvent>) -> Headless {
+        script.sort_by_key(|e| e.frame);
+        Headless { frames_left: frames, frames_shown: 0, script }
+    }
+}
+
+impl Display for Headless {
+    fn refresh(&mut self, _framebuffer: &Framebuffer) -> Result<(), String> {
+        self.frames_left = self.frames_left.saturating_sub(1);
+        self.frames_shown += 1;
+        Ok(())
+    }
+
+    fn elapsed(&self) -> Duration {
+        REFRESH_INTERVAL * self.frames_shown
+    }
+}
+
+impl Input for Headless {
+    fn is_open(&self) -> bool {
+        self.frames_left > 0
+    }
+
+    fn handle_key_events(&self) -> [bool; 16] {
+        held_keys(&self.script, self.frames_shown, false)
+    }
+
+    fn second_keypad(&self) -> [bool; 16] {
+        held_keys(&self.script, self.frames_shown, true)
+    }
+}
+
+// a buzzer that makes no sound
+pub struct Silence;
+
+impl Buzzer for Silence {
+    fn play(&self) {}
+    fn pause(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted_keys() {
+        let script = parse_input_script("# press A for a frame\n2 a down\n\n3 a up\n").unwrap();
+        let mut headless = Headless::with_script(10, script);
+        let fb = Framebuffer::new();
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(headless.handle_key_events()[0xa]);
+            headless.refresh(&fb).unwrap();
+        }
+        assert_eq!(seen, [false, false, true, false]);
+        assert!(parse_input_script("2 g down").is_err());
+        assert!(parse_input_script("2 20 down").is_err());
+
+        let headless = Headless::with_script(10, parse_input_script("0 1a down").unwrap());
+        assert!(headless.second_keypad()[0xa]);
+        assert!(!headless.handle_key_events()[0xa]);
+    }
+}
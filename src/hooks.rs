@@ -0,0 +1,47 @@
+// code that runs alongside the ROM, eg. a Lua script: it gets the whole
+// machine after every instruction and every frame, and can change anything
+
+use crate::cpu::CPU;
+
+pub trait Hooks {
+    // after each instruction executed
+    fn on_step(&mut self, _cpu: &mut CPU) -> Result<(), String> {
+        Ok(())
+    }
+
+    // after each frame is shown, also while paused
+    fn on_frame(&mut self, _cpu: &mut CPU) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// lines shown over the screen for a number of frames, then taken down
+// unless something else has replaced them
+pub struct Notice {
+    lines: Vec<String>,
+    frames_left: u32
+}
+
+impl Notice {
+    pub fn new(lines: Vec<String>, frames: u32) -> Notice {
+        Notice { lines, frames_left: frames }
+    }
+}
+
+impl Hooks for Notice {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if self.frames_left == 0 {
+            return Ok(());
+        }
+        self.frames_left -= 1;
+        if self.frames_left == 0 {
+            if cpu.overlay() == self.lines.as_slice() {
+                cpu.set_overlay(Vec::new());
+            }
+        }
+        else if cpu.overlay().is_empty() {
+            cpu.set_overlay(self.lines.clone());
+        }
+        Ok(())
+    }
+}
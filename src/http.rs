@@ -0,0 +1,304 @@
+// a tiny read-only HTTP server for looking at a running emulator from
+// scripts and monitoring tools:
+//
+//   /state             registers, breakpoints and whether paused, as JSON
+//   /memory            all 4K of RAM, raw
+//   /memory?addr=2a0&len=16
+//                      part of RAM as JSON (hex address, len defaults to 16)
+//   /framebuffer.png   the screen, one pixel per CHIP-8 pixel
+//   /screenshot        the screen as the window shows it: 8x, with overlay text
+//   /metrics           running totals in Prometheus text format
+
+use std::{
+    io::{
+        BufRead,
+        BufReader,
+        Write
+    },
+    net::{
+        SocketAddr,
+        TcpListener,
+        TcpStream
+    },
+    sync::{
+        Arc,
+        Mutex
+    },
+    thread,
+    time::Duration
+};
+
+use crate::control::state_json;
+use crate::cpu::CPU;
+use crate::framebuffer::{
+    scale_pixels,
+    WIDTH
+};
+use crate::hooks::Hooks;
+use crate::overlay;
+use crate::png::{
+    encode_bits,
+    encode_grey
+};
+
+// frames (at 480 Hz) between snapshots for the server
+const FRAMES_PER_SNAPSHOT: u32 = 8;
+
+// how much bigger /screenshot is than the CHIP-8 screen
+const SCREENSHOT_SCALE: usize = 8;
+
+// how long a client may take to send its request before we hang up, so one
+// idle connection can't hold up everyone else
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+// what the server shows, copied from the CPU every few frames
+#[derive(Default)]
+pub struct Snapshot {
+    state: String,
+    ram: Vec<u8>,
+    pixels: Vec<bool>,
+    width: usize,
+    height: usize,
+    overlay: Vec<String>,
+    metrics: String
+}
+
+impl Snapshot {
+    pub fn of(cpu: &CPU) -> Snapshot {
+        Snapshot {
+            state: state_json(cpu),
+            ram: cpu.ram().to_vec(),
+            pixels: cpu.framebuffer().pixels().to_vec(),
+            width: cpu.framebuffer().width(),
+            height: cpu.framebuffer().height(),
+            overlay: cpu.overlay().to_vec(),
+            metrics: metrics(cpu)
+        }
+    }
+}
+
+// the CPU's counters as Prometheus metrics
+pub fn metrics(cpu: &CPU) -> String {
+    let counters = cpu.counters();
+    let mut metrics = vec![
+        ("chip8_instructions_executed_total", "Instructions executed.", counters.instructions),
+        ("chip8_frames_rendered_total", "Frames shown by the frontend.", counters.frames),
+        ("chip8_draw_calls_total", "Sprites drawn with Dxyn.", counters.draws),
+        ("chip8_unknown_opcodes_total", "Unrecognized instructions met.", counters.unknown_opcodes)
+    ];
+    if let Some(underruns) = counters.audio_underruns {
+        metrics.push(("chip8_audio_underruns_total", "Times the audio output ran out of samples.", underruns));
+    }
+    metrics.iter()
+        .map(|(name, help, value)| format!("# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value))
+        .collect()
+}
+
+pub struct HttpInspector {
+    addr: SocketAddr,
+    snapshot: Arc<Mutex<Snapshot>>,
+    frames: u32
+}
+
+impl HttpInspector {
+    // start serving on addr, eg. 127.0.0.1:8481
+    pub fn listen(addr: &str) -> Result<HttpInspector, String> {
+        let listener = TcpListener::bind(addr).map_err(|why| format!("Could not listen on {}: {}", addr, why))?;
+        let addr = listener.local_addr().map_err(|why| why.to_string())?;
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+        let served = snapshot.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // one client at a time is plenty for a debugging aid
+                let _ = handle(stream, &served);
+            }
+        });
+        Ok(HttpInspector { addr, snapshot, frames: 0 })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Hooks for HttpInspector {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        // the first frame, then every few after
+        if self.frames.is_multiple_of(FRAMES_PER_SNAPSHOT) {
+            if let Ok(mut snapshot) = self.snapshot.lock() {
+                *snapshot = Snapshot::of(cpu);
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>
+}
+
+fn text(status: &'static str, body: &str) -> Response {
+    Response { status, content_type: "text/plain", body: body.as_bytes().to_vec() }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == name { Some(value) } else { None }
+    })
+}
+
+// the answer to a GET for target (path and query string)
+fn respond(target: &str, snapshot: &Snapshot) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/state" => Response { status: "200 OK", content_type: "application/json", body: snapshot.state.clone().into_bytes() },
+        "/memory" if query.is_empty() => Response { status: "200 OK", content_type: "application/octet-stream", body: snapshot.ram.clone() },
+        "/memory" => {
+            let addr = query_param(query, "addr").and_then(|a| usize::from_str_radix(a.trim_start_matches("0x"), 16).ok());
+            let len = match query_param(query, "len") {
+                Some(l) => l.parse().ok(),
+                None => Some(16)
+            };
+            match (addr, len) {
+                (Some(addr), Some(len)) if addr < snapshot.ram.len() => {
+                    let end = addr.saturating_add(len).min(snapshot.ram.len());
+                    let bytes: Vec<String> = snapshot.ram[addr..end].iter().map(|b| b.to_string()).collect();
+                    let body = format!("{{\"addr\": {}, \"bytes\": [{}]}}", addr, bytes.join(", "));
+                    Response { status: "200 OK", content_type: "application/json", body: body.into_bytes() }
+                },
+                _ => text("400 Bad Request", "expected /memory?addr=<hex address inside RAM>&len=<bytes>\n")
+            }
+        },
+        "/metrics" => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body: snapshot.metrics.clone().into_bytes()
+        },
+        "/framebuffer.png" => Response {
+            status: "200 OK",
+            content_type: "image/png",
+            body: encode_bits(snapshot.width, snapshot.height, &snapshot.pixels, 1)
+        },
+        "/screenshot" => {
+            let (width, height) = (snapshot.width, snapshot.height);
+            let text_pixels = scale_pixels(&overlay::render(&snapshot.overlay), WIDTH, width, height);
+            let mut grey = Vec::with_capacity(width * height * SCREENSHOT_SCALE * SCREENSHOT_SCALE);
+            let shades: Vec<u8> = snapshot.pixels.iter().zip(&text_pixels)
+                .map(|(&on, &text)| if text { 0x80 } else if on { 0xff } else { 0 })
+                .collect();
+            for row in shades.chunks(width) {
+                let line: Vec<u8> = row.iter().flat_map(|&s| std::iter::repeat_n(s, SCREENSHOT_SCALE)).collect();
+                for _ in 0..SCREENSHOT_SCALE {
+                    grey.extend_from_slice(&line);
+                }
+            }
+            Response {
+                status: "200 OK",
+                content_type: "image/png",
+                body: encode_grey(width * SCREENSHOT_SCALE, height * SCREENSHOT_SCALE, &grey)
+            }
+        },
+        _ => text("404 Not Found", "try /state, /memory, /framebuffer.png, /screenshot or /metrics\n")
+    }
+}
+
+fn handle(stream: TcpStream, snapshot: &Mutex<Snapshot>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, we don't need any of them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let response = match request_line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["GET", target, _] => match snapshot.lock() {
+            Ok(snapshot) => respond(target, &snapshot),
+            Err(_) => text("500 Internal Server Error", "snapshot unavailable\n")
+        },
+        _ => text("405 Method Not Allowed", "only GET is supported\n")
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, response.body.len()
+    )?;
+    stream.write_all(&response.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+    use crate::testsuite::run_headless;
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0x12, 0x34, 0x56]).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn routes() {
+        let snapshot = Snapshot::of(&cpu());
+        assert_eq!(respond("/memory?addr=200&len=3", &snapshot).body, b"{\"addr\": 512, \"bytes\": [18, 52, 86]}");
+        assert_eq!(respond("/memory?addr=0xfff", &snapshot).body, b"{\"addr\": 4095, \"bytes\": [0]}");
+        assert_eq!(respond("/memory?addr=1000", &snapshot).status, "400 Bad Request");
+        // lengths past the end of RAM, however big, stop at the end
+        assert_eq!(respond("/memory?addr=ffe&len=9", &snapshot).body, b"{\"addr\": 4094, \"bytes\": [0, 0]}");
+        let huge = format!("/memory?addr=ffe&len={}", usize::MAX);
+        assert_eq!(respond(&huge, &snapshot).body, b"{\"addr\": 4094, \"bytes\": [0, 0]}");
+        assert_eq!(respond("/memory", &snapshot).body.len(), 4096);
+        assert!(respond("/state", &snapshot).body.starts_with(b"{\"type\": \"state\""));
+        assert_eq!(&respond("/framebuffer.png", &snapshot).body[..4], b"\x89PNG");
+        assert_eq!(respond("/nope", &snapshot).status, "404 Not Found");
+    }
+
+    #[test]
+    fn prometheus_metrics() {
+        let text = metrics(&run_headless(&[0xd0, 0x05, 0x12, 0x00], 2, Config::default()));
+        assert!(text.contains("# TYPE chip8_draw_calls_total counter\nchip8_draw_calls_total 9\n"));
+        assert!(text.contains("\nchip8_frames_rendered_total 2\n"));
+        assert!(text.contains("\nchip8_unknown_opcodes_total 0\n"));
+        // the headless buzzer can't know about underruns
+        assert!(!text.contains("underruns"));
+    }
+
+    #[test]
+    fn serves_over_http() {
+        let mut inspector = HttpInspector::listen("127.0.0.1:0").unwrap();
+        inspector.on_frame(&mut cpu()).unwrap();
+        let mut stream = TcpStream::connect(inspector.addr()).unwrap();
+        stream.write_all(b"GET /memory?addr=201&len=1 HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n{\"addr\": 513, \"bytes\": [52]}"));
+    }
+
+    #[test]
+    fn idle_clients_time_out() {
+        let mut inspector = HttpInspector::listen("127.0.0.1:0").unwrap();
+        inspector.on_frame(&mut cpu()).unwrap();
+        // connects and never says anything
+        let _idle = TcpStream::connect(inspector.addr()).unwrap();
+        let mut stream = TcpStream::connect(inspector.addr()).unwrap();
+        stream.write_all(b"GET /memory?addr=200&len=1 HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("{\"addr\": 512, \"bytes\": [18]}"));
+    }
+}
@@ -0,0 +1,374 @@
+// the messages people see: on the command line, over the screen and in
+// the pause menu, in each language we have them in; {} in a message is
+// filled in with fill, in order
+
+use std::env;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    English,
+    Spanish
+}
+
+pub const LANGUAGES: [Language; 2] = [Language::English, Language::Spanish];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Text {
+    // the pause menu
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    Palette,
+    Quit,
+    // over the screen
+    Achievement,
+    Done,
+    NoSavedState,
+    QuirksGuessed,
+    NoQuirks,
+    // the command line
+    Banner,
+    Usage,
+    UnknownOption,
+    InvalidValue,
+    NumberOfFrames,
+    NumberOfSeconds,
+    InstructionsPerSecond,
+    InstructionsPerFrame,
+    Warning,
+    CouldNotOpenFile,
+    CouldNotLoadRom,
+    CouldNotStartAudio,
+    CouldNotOpenWindow,
+    CouldNotStartCpu,
+    CouldNotOpenPath,
+    CouldNotStartTracing,
+    CouldNotAssemble,
+    CouldNotOpenPatch,
+    CouldNotApplyPatch,
+    CallsMachineCode,
+    InvalidQuirksFile,
+    CouldNotOpenInputScript,
+    InvalidInputScript,
+    OnlyWith,
+    NotBoth,
+    NotBuiltWith,
+    NeedsUnix,
+    CouldNotListen,
+    WaitingForPlayer,
+    CouldNotStartCoop,
+    InvalidFlagsFile,
+    CouldNotLoadScript,
+    RemoteListening,
+    CouldNotStartRemote,
+    CouldNotLoadCheats,
+    CouldNotLoadAchievements,
+    InspectionEndpoints,
+    CouldNotStartHttp,
+    TakingVotes,
+    CouldNotStartChatPlays,
+    StreamingTo,
+    CouldNotStartStreaming,
+    CouldNotOpenInputSocket,
+    CouldNotShareScreen,
+    CpuCrashed,
+    TryOption,
+    CrashReportSaved,
+    CouldNotWriteCrashReport,
+    EmulatorPanicked,
+    PanicNotSaved,
+    RecordingSaved,
+    CouldNotSaveRecording,
+    WaitingForInput,
+    MemoryDumped,
+    SpeedChanged,
+    SlotSaved,
+    SlotLoaded,
+    SlotEmpty,
+    SlotFailed,
+    NoStateDir,
+    QuirksSaved,
+    RomExited,
+    FramebufferHash,
+    UnknownOpcodesTable,
+    NativeCallsStubbed,
+    NativeCallsIgnored,
+    OpcodeFamilies,
+    BusiestAddresses,
+    TableHeader
+}
+
+pub const TEXTS: [Text; 80] = [
+    Text::Resume, Text::Reset, Text::SaveState, Text::LoadState, Text::Palette, Text::Quit, Text::Achievement,
+    Text::Done, Text::NoSavedState, Text::QuirksGuessed, Text::NoQuirks, Text::Banner, Text::Usage, Text::UnknownOption,
+    Text::InvalidValue, Text::NumberOfFrames, Text::NumberOfSeconds, Text::InstructionsPerSecond,
+    Text::InstructionsPerFrame, Text::Warning, Text::CouldNotOpenFile, Text::CouldNotLoadRom, Text::CouldNotStartAudio,
+    Text::CouldNotOpenWindow, Text::CouldNotStartCpu, Text::CouldNotOpenPath, Text::CouldNotStartTracing,
+    Text::CouldNotAssemble, Text::CouldNotOpenPatch, Text::CouldNotApplyPatch, Text::CallsMachineCode,
+    Text::InvalidQuirksFile, Text::CouldNotOpenInputScript, Text::InvalidInputScript, Text::OnlyWith, Text::NotBoth,
+    Text::NotBuiltWith, Text::NeedsUnix, Text::CouldNotListen, Text::WaitingForPlayer, Text::CouldNotStartCoop,
+    Text::InvalidFlagsFile, Text::CouldNotLoadScript, Text::RemoteListening, Text::CouldNotStartRemote,
+    Text::CouldNotLoadCheats, Text::CouldNotLoadAchievements, Text::InspectionEndpoints, Text::CouldNotStartHttp,
+    Text::TakingVotes, Text::CouldNotStartChatPlays, Text::StreamingTo, Text::CouldNotStartStreaming,
+    Text::CouldNotOpenInputSocket, Text::CouldNotShareScreen, Text::CpuCrashed, Text::TryOption, Text::CrashReportSaved,
+    Text::CouldNotWriteCrashReport, Text::EmulatorPanicked, Text::PanicNotSaved, Text::RecordingSaved,
+    Text::CouldNotSaveRecording, Text::WaitingForInput, Text::MemoryDumped, Text::SpeedChanged, Text::SlotSaved,
+    Text::SlotLoaded, Text::SlotEmpty, Text::SlotFailed, Text::NoStateDir, Text::QuirksSaved, Text::RomExited,
+    Text::FramebufferHash, Text::UnknownOpcodesTable, Text::NativeCallsStubbed, Text::NativeCallsIgnored,
+    Text::OpcodeFamilies, Text::BusiestAddresses, Text::TableHeader
+];
+
+impl Language {
+    // a language code as in --lang, eg. es
+    pub fn from_name(name: &str) -> Option<Language> {
+        LANGUAGES.iter().copied().find(|language| language.name() == name)
+    }
+
+    // the name from_name takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es"
+        }
+    }
+
+    // the system's language, going by the usual environment variables
+    // (eg. LANG=es_ES.UTF-8), or English if we don't have it
+    pub fn from_env() -> Language {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Language::from_name(value.split(['_', '.', '-']).next().unwrap_or_default()))
+            .unwrap_or(Language::English)
+    }
+
+    pub fn text(&self, text: Text) -> &'static str {
+        match self {
+            Language::English => english(text),
+            Language::Spanish => spanish(text)
+        }
+    }
+
+    // the message with each {} replaced by the next argument
+    pub fn fill(&self, text: Text, args: &[&dyn Display]) -> String {
+        let mut parts = self.text(text).split("{}");
+        let mut out = String::from(parts.next().unwrap_or_default());
+        for (part, arg) in parts.zip(args.iter().map(|a| a.to_string()).chain(std::iter::repeat(String::new()))) {
+            out.push_str(&arg);
+            out.push_str(part);
+        }
+        out
+    }
+
+    // choices listed for a message, eg. "off, log or reject"
+    pub fn one_of(&self, choices: &[&str]) -> String {
+        let or = match self {
+            Language::English => "or",
+            Language::Spanish => "o"
+        };
+        match choices.split_last() {
+            Some((last, [])) => String::from(*last),
+            Some((last, rest)) => format!("{} {} {}", rest.join(", "), or, last),
+            None => String::new()
+        }
+    }
+}
+
+fn english(text: Text) -> &'static str {
+    match text {
+        Text::Resume => "Resume",
+        Text::Reset => "Reset",
+        Text::SaveState => "Save state",
+        Text::LoadState => "Load state",
+        Text::Palette => "Palette",
+        Text::Quit => "Quit",
+        Text::Achievement => "ACHIEVEMENT!",
+        Text::Done => "OK",
+        Text::NoSavedState => "No state has been saved yet",
+        Text::QuirksGuessed => "Quirks guessed:",
+        Text::NoQuirks => "none",
+        Text::Banner => "chip8-rust: CHIP-8 emulator written in Rust",
+        Text::Usage => "Usage: {} [options] <rom-file-name>",
+        Text::UnknownOption => "Unknown option: {}",
+        Text::InvalidValue => "Invalid value for {}: expected {}",
+        Text::NumberOfFrames => "a number of frames",
+        Text::NumberOfSeconds => "a number of seconds",
+        Text::InstructionsPerSecond => "a number of instructions a second",
+        Text::InstructionsPerFrame => "a number of instructions a frame",
+        Text::Warning => "Warning: {}",
+        Text::CouldNotOpenFile => "Could not open file: {}",
+        Text::CouldNotLoadRom => "Could not load ROM: {}",
+        Text::CouldNotStartAudio => "Could not initialize audio device: {}",
+        Text::CouldNotOpenWindow => "Could not initialize window: {}",
+        Text::CouldNotStartCpu => "Could not initialize CPU: {}",
+        Text::CouldNotOpenPath => "Could not open {}: {}",
+        Text::CouldNotStartTracing => "Could not start tracing: {}",
+        Text::CouldNotAssemble => "Could not assemble: {}",
+        Text::CouldNotOpenPatch => "Could not open patch {}: {}",
+        Text::CouldNotApplyPatch => "Could not apply patch {}: {}",
+        Text::CallsMachineCode => "this ROM calls machine code, which can't be run here: {}",
+        Text::InvalidQuirksFile => "Invalid quirks file {}: {}",
+        Text::CouldNotOpenInputScript => "Could not open input script: {}",
+        Text::InvalidInputScript => "Invalid input script: {}",
+        Text::OnlyWith => "{} only works together with {}",
+        Text::NotBoth => "use either {} or {}, not both",
+        Text::NotBuiltWith => "this build has no {} support; rebuild with `--features {}`",
+        Text::NeedsUnix => "{} needs a Unix-like system",
+        Text::CouldNotListen => "Could not listen on {}: {}",
+        Text::WaitingForPlayer => "Waiting for the other player on {}",
+        Text::CouldNotStartCoop => "Could not start co-op: {}",
+        Text::InvalidFlagsFile => "Invalid flags file {}: {}",
+        Text::CouldNotLoadScript => "Could not load script: {}",
+        Text::RemoteListening => "Remote control listening on ws://{}",
+        Text::CouldNotStartRemote => "Could not start remote control: {}",
+        Text::CouldNotLoadCheats => "Could not load cheats from {}: {}",
+        Text::CouldNotLoadAchievements => "Could not load achievements from {}: {}",
+        Text::InspectionEndpoints => "Inspection endpoints at http://{}/state",
+        Text::CouldNotStartHttp => "Could not start HTTP server: {}",
+        Text::TakingVotes => "Taking key votes on {}",
+        Text::CouldNotStartChatPlays => "Could not start chat plays: {}",
+        Text::StreamingTo => "Streaming to viewers on {}",
+        Text::CouldNotStartStreaming => "Could not start streaming: {}",
+        Text::CouldNotOpenInputSocket => "Could not open input socket: {}",
+        Text::CouldNotShareScreen => "Could not share the screen: {}",
+        Text::CpuCrashed => "CPU crashed: {}",
+        Text::TryOption => "Running with {} would carry on past this",
+        Text::CrashReportSaved => "Crash report saved to {}",
+        Text::CouldNotWriteCrashReport => "Could not write crash report: {}",
+        Text::EmulatorPanicked => "Emulator panicked; machine state saved to {}",
+        Text::PanicNotSaved => "Emulator panicked and the crash file could not be written: {}",
+        Text::RecordingSaved => "Recording saved to {}",
+        Text::CouldNotSaveRecording => "Could not save recording to {}: {}",
+        Text::WaitingForInput => "ROM is waiting for input (Fx0A at {}) and none arrived",
+        Text::MemoryDumped => "Memory dumped to {}",
+        Text::SpeedChanged => "Running {} instructions a frame",
+        Text::SlotSaved => "Saved to slot {}",
+        Text::SlotLoaded => "Loaded slot {}",
+        Text::SlotEmpty => "Slot {} is empty",
+        Text::SlotFailed => "Could not use slot {}: {}",
+        Text::NoStateDir => "there is nowhere to keep save slots",
+        Text::QuirksSaved => "Guessed this ROM is for {} and needs the quirks: {}; edit {} if that's wrong",
+        Text::RomExited => "The ROM exited after {} instructions and {} frames ({} s)",
+        Text::FramebufferHash => "Framebuffer hash: {}",
+        Text::UnknownOpcodesTable => "Unrecognized instructions executed:",
+        Text::NativeCallsStubbed => "Calls into machine code (stubbed where known):",
+        Text::NativeCallsIgnored => "Calls into machine code (ignored):",
+        Text::OpcodeFamilies => "Executed instructions by family:",
+        Text::BusiestAddresses => "Busiest addresses:",
+        Text::TableHeader => "address  instruction  count"
+    }
+}
+
+fn spanish(text: Text) -> &'static str {
+    match text {
+        Text::Resume => "Continuar",
+        Text::Reset => "Reiniciar",
+        Text::SaveState => "Guardar estado",
+        Text::LoadState => "Cargar estado",
+        Text::Palette => "Colores",
+        Text::Quit => "Salir",
+        Text::Achievement => "¡LOGRO!",
+        Text::Done => "Listo",
+        Text::NoSavedState => "Aún no hay ningún estado guardado",
+        Text::QuirksGuessed => "Quirks supuestas",
+        Text::NoQuirks => "ninguna",
+        Text::Banner => "chip8-rust: emulador de CHIP-8 escrito en Rust",
+        Text::Usage => "Uso: {} [opciones] <archivo-rom>",
+        Text::UnknownOption => "Opción desconocida: {}",
+        Text::InvalidValue => "Valor no válido para {}: se esperaba {}",
+        Text::NumberOfFrames => "un número de fotogramas",
+        Text::NumberOfSeconds => "un número de segundos",
+        Text::InstructionsPerSecond => "un número de instrucciones por segundo",
+        Text::InstructionsPerFrame => "un número de instrucciones por fotograma",
+        Text::Warning => "Aviso: {}",
+        Text::CouldNotOpenFile => "No se pudo abrir el archivo: {}",
+        Text::CouldNotLoadRom => "No se pudo cargar la ROM: {}",
+        Text::CouldNotStartAudio => "No se pudo iniciar el dispositivo de audio: {}",
+        Text::CouldNotOpenWindow => "No se pudo abrir la ventana: {}",
+        Text::CouldNotStartCpu => "No se pudo iniciar la CPU: {}",
+        Text::CouldNotOpenPath => "No se pudo abrir {}: {}",
+        Text::CouldNotStartTracing => "No se pudo iniciar el trazado: {}",
+        Text::CouldNotAssemble => "No se pudo ensamblar: {}",
+        Text::CouldNotOpenPatch => "No se pudo abrir el parche {}: {}",
+        Text::CouldNotApplyPatch => "No se pudo aplicar el parche {}: {}",
+        Text::CallsMachineCode => "esta ROM llama a código máquina, que aquí no se puede ejecutar: {}",
+        Text::InvalidQuirksFile => "Archivo de quirks no válido {}: {}",
+        Text::CouldNotOpenInputScript => "No se pudo abrir el guion de entrada: {}",
+        Text::InvalidInputScript => "Guion de entrada no válido: {}",
+        Text::OnlyWith => "{} solo funciona junto con {}",
+        Text::NotBoth => "usa {} o {}, no ambos",
+        Text::NotBuiltWith => "esta compilación no admite {}; vuelve a compilar con `--features {}`",
+        Text::NeedsUnix => "{} necesita un sistema tipo Unix",
+        Text::CouldNotListen => "No se pudo escuchar en {}: {}",
+        Text::WaitingForPlayer => "Esperando al otro jugador en {}",
+        Text::CouldNotStartCoop => "No se pudo iniciar el modo cooperativo: {}",
+        Text::InvalidFlagsFile => "Archivo de flags no válido {}: {}",
+        Text::CouldNotLoadScript => "No se pudo cargar el script: {}",
+        Text::RemoteListening => "Control remoto escuchando en ws://{}",
+        Text::CouldNotStartRemote => "No se pudo iniciar el control remoto: {}",
+        Text::CouldNotLoadCheats => "No se pudieron cargar los trucos de {}: {}",
+        Text::CouldNotLoadAchievements => "No se pudieron cargar los logros de {}: {}",
+        Text::InspectionEndpoints => "Puntos de inspección en http://{}/state",
+        Text::CouldNotStartHttp => "No se pudo iniciar el servidor HTTP: {}",
+        Text::TakingVotes => "Recibiendo votos de teclas en {}",
+        Text::CouldNotStartChatPlays => "No se pudieron iniciar las votaciones del chat: {}",
+        Text::StreamingTo => "Transmitiendo a los espectadores en {}",
+        Text::CouldNotStartStreaming => "No se pudo iniciar la transmisión: {}",
+        Text::CouldNotOpenInputSocket => "No se pudo abrir el socket de entrada: {}",
+        Text::CouldNotShareScreen => "No se pudo compartir la pantalla: {}",
+        Text::CpuCrashed => "La CPU se detuvo por un error: {}",
+        Text::TryOption => "Con {} seguiría adelante",
+        Text::CrashReportSaved => "Informe del error guardado en {}",
+        Text::CouldNotWriteCrashReport => "No se pudo escribir el informe del error: {}",
+        Text::EmulatorPanicked => "El emulador falló; estado de la máquina guardado en {}",
+        Text::PanicNotSaved => "El emulador falló y no se pudo escribir el archivo del error: {}",
+        Text::RecordingSaved => "Grabación guardada en {}",
+        Text::CouldNotSaveRecording => "No se pudo guardar la grabación en {}: {}",
+        Text::WaitingForInput => "La ROM espera una tecla (Fx0A en {}) y no llegó ninguna",
+        Text::MemoryDumped => "Memoria volcada en {}",
+        Text::SpeedChanged => "Ejecutando {} instrucciones por fotograma",
+        Text::SlotSaved => "Guardado en la ranura {}",
+        Text::SlotLoaded => "Cargada la ranura {}",
+        Text::SlotEmpty => "La ranura {} está vacía",
+        Text::SlotFailed => "No se pudo usar la ranura {}: {}",
+        Text::NoStateDir => "no hay dónde guardar las ranuras",
+        Text::QuirksSaved => "Parece que esta ROM es para {} y necesita las quirks: {}; edita {} si no es así",
+        Text::RomExited => "La ROM terminó tras {} instrucciones y {} fotogramas ({} s)",
+        Text::FramebufferHash => "Hash de la pantalla: {}",
+        Text::UnknownOpcodesTable => "Instrucciones no reconocidas ejecutadas:",
+        Text::NativeCallsStubbed => "Llamadas a código máquina (simuladas si se conocen):",
+        Text::NativeCallsIgnored => "Llamadas a código máquina (ignoradas):",
+        Text::OpcodeFamilies => "Instrucciones ejecutadas por familia:",
+        Text::BusiestAddresses => "Direcciones más usadas:",
+        Text::TableHeader => "dir.     instrucción  veces"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translations_take_the_same_arguments() {
+        for text in TEXTS {
+            for language in LANGUAGES {
+                assert_eq!(
+                    language.text(text).matches("{}").count(),
+                    Language::English.text(text).matches("{}").count(),
+                    "{:?} in {:?}", text, language
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fills_in_messages() {
+        let es = Language::from_name("es").unwrap();
+        assert_eq!(es.fill(Text::InvalidValue, &[&"--memory", &es.one_of(&["wrap", "error"])]),
+            "Valor no válido para --memory: se esperaba wrap o error");
+        assert_eq!(Language::English.one_of(&["off", "log", "reject"]), "off, log or reject");
+        assert_eq!(Language::English.fill(Text::Warning, &[]), "Warning: ");
+    }
+}
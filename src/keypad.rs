@@ -0,0 +1,45 @@
+// state of the 16-key keypad as of the last time it was sampled,
+// plus which keys changed since the sample before that
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Keypad {
+    // keys held down
+    pub down: [bool; 16],
+    // keys that went down since the previous sample
+    pub pressed: [bool; 16],
+    // keys that came up since the previous sample
+    pub released: [bool; 16]
+}
+
+impl Keypad {
+    pub fn update(&mut self, now: [bool; 16]) {
+        for (j, is_down) in now.iter().enumerate() {
+            self.pressed[j] = *is_down && !self.down[j];
+            self.released[j] = !*is_down && self.down[j];
+        }
+        self.down = now;
+    }
+
+    // the lowest numbered key pressed since the previous sample, if any
+    pub fn first_pressed(&self) -> Option<usize> {
+        self.pressed.iter().position(|p| *p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_since_previous_sample() {
+        let mut keypad = Keypad::default();
+        let mut now = [false; 16];
+        now[3] = true;
+        keypad.update(now);
+        assert_eq!(keypad.first_pressed(), Some(3));
+        keypad.update(now);
+        assert_eq!(keypad.first_pressed(), None);
+        assert!(keypad.down[3]);
+        keypad.update([false; 16]);
+        assert!(keypad.released[3] && !keypad.down[3]);
+    }
+}
@@ -0,0 +1,68 @@
+// the emulator core: everything needed to run a CHIP-8 program
+// without tying it to a particular window or audio backend
+
+pub mod achievements;
+pub mod annotate;
+pub mod asm;
+pub mod batch;
+pub mod chatplays;
+pub mod cheats;
+pub mod chip8x;
+pub mod config;
+pub mod control;
+pub mod coop;
+pub mod cpu;
+pub mod crash;
+pub mod deadcode;
+pub mod decode;
+pub mod decompile;
+pub mod detect;
+pub mod dev;
+pub mod diff;
+pub mod disasm;
+pub mod dump;
+pub mod educator;
+pub mod error;
+pub mod flags;
+pub mod framebuffer;
+pub mod frontend;
+pub mod gif;
+pub mod headless;
+pub mod hooks;
+pub mod http;
+pub mod i18n;
+pub mod keypad;
+pub mod megachip;
+pub mod menu;
+pub mod native;
+pub mod octotrace;
+pub mod overlay;
+pub mod pacing;
+pub mod patch;
+pub mod png;
+pub mod prompt;
+pub mod quirks;
+pub mod rammap;
+#[cfg(feature = "websocket")]
+pub mod remote;
+pub mod replay;
+pub mod rom;
+#[cfg(feature = "lua")]
+pub mod script;
+#[cfg(unix)]
+pub mod shm;
+#[cfg(unix)]
+pub mod socket;
+pub mod spectator;
+pub mod splash;
+pub mod sprites;
+pub mod states;
+pub mod stats;
+pub mod steps;
+pub mod testgen;
+pub mod tone;
+mod trace;
+pub mod util;
+
+#[cfg(test)]
+mod testsuite;
@@ -1,67 +1,1033 @@
+#[cfg(feature = "window")]
 extern crate minifb;
 extern crate rand;
+#[cfg(feature = "audio")]
 extern crate rodio;
 
 use std::{
-    fs,
-    env
+    any::Any,
+    cell::RefCell,
+    cmp::Reverse,
+    fs::{
+        self,
+        File
+    },
+    env,
+    io::LineWriter,
+    net::TcpListener,
+    panic,
+    path::{
+        Path,
+        PathBuf
+    },
+    process,
+    rc::Rc,
+    sync::Mutex,
+    time::Duration
 };
 
-mod cpu;
-use cpu::CPU;
+use chip8_rust::achievements::{
+    parse_achievements,
+    AchievementWatcher
+};
+use chip8_rust::annotate::Annotator;
+use chip8_rust::chatplays::{
+    ChatMode,
+    ChatPlays
+};
+use chip8_rust::cheats::parse_cheats;
+use chip8_rust::coop::Coop;
+use chip8_rust::cpu::{
+    CPU,
+    Exit
+};
+use chip8_rust::crash::write_crash_file;
+use chip8_rust::detect::{
+    self,
+    Guess,
+    GUESS_EXTENSION
+};
+use chip8_rust::educator::Educator;
+use chip8_rust::flags::{
+    parse_flags,
+    FlagsFile,
+    FLAGS_EXTENSION
+};
+use chip8_rust::frontend::{
+    Buzzer,
+    Frontend,
+    REFRESH_INTERVAL,
+    SCALES
+};
+use chip8_rust::headless::{
+    parse_input_script,
+    Headless,
+    Silence
+};
+use chip8_rust::hooks::{
+    Hooks,
+    Notice
+};
+use chip8_rust::http::HttpInspector;
+use chip8_rust::i18n::{
+    Language,
+    Text,
+    LANGUAGES
+};
+use chip8_rust::native::{
+    describe_calls,
+    native_calls
+};
+use chip8_rust::octotrace::OctoTrace;
+use chip8_rust::patch::apply_patch;
+use chip8_rust::quirks::{
+    Quirk,
+    Quirks,
+    PRESETS
+};
+use chip8_rust::replay::{
+    Recorder,
+    Replay
+};
+use chip8_rust::rom::sanity_check;
+use chip8_rust::spectator::SpectatorServer;
+use chip8_rust::splash::{
+    self,
+    Splash
+};
+use chip8_rust::states::STATES_EXTENSION;
+#[cfg(feature = "websocket")]
+use chip8_rust::remote::WebSocketControl;
+#[cfg(unix)]
+use chip8_rust::shm::SharedFramebuffer;
+#[cfg(unix)]
+use chip8_rust::socket::InputSocket;
+#[cfg(feature = "lua")]
+use chip8_rust::script::LuaScript;
+#[cfg(feature = "tracing")]
+use tracing_flame::FlameLayer;
+#[cfg(feature = "tracing")]
+use tracing_subscriber::{
+    filter::LevelFilter,
+    prelude::*
+};
+use chip8_rust::stats;
+use chip8_rust::config::{
+    Config,
+    FontLayout,
+    KeySampling,
+    MemoryPolicy,
+    Platform,
+    UnknownOpcodePolicy,
+    WriteProtection
+};
 
+#[cfg(feature = "audio")]
 mod audio;
+#[cfg(feature = "audio")]
 use audio::Audio;
 
+#[cfg(feature = "window")]
 mod window;
+#[cfg(feature = "window")]
 use window::Window;
 
-mod util;
+mod tools;
 
-fn main() {
-    println!("chip8-rust: CHIP-8 emulator written in Rust");
+#[cfg(feature = "window")]
+mod viewer;
+#[cfg(feature = "window")]
+use viewer::RamView;
+
+// how long a headless run waits on Fx0A unless told otherwise
+const HEADLESS_KEY_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how long educator mode shows each stage of an instruction for
+const DEFAULT_EDUCATOR_STAGE_SECS: f64 = 1.5;
+// and how many instructions a second --annotate runs
+const DEFAULT_ANNOTATE_SPEED: u32 = 10;
+// how long the quirks guessed for a new ROM are shown, in frames
+const GUESS_NOTICE_FRAMES: u32 = 5 * 480;
 
+// message of the last panic, saved by the panic hook for the crash file
+static PANIC_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        return eprintln!("Usage: {} <rom-file-name>", args[0]);
+    // tools print their results, so they don't get the banner
+    if let Some(result) = tools::run(&args) {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
     }
 
-    let filename = String::from(&args[1]);
-
-    let rom = match fs::read(&filename) {
-        Err(why) => {
-            return eprintln!("Could not open file: {}", why.to_string());
+    // messages are in the language asked for, or else the system's
+    let lang = match args.iter().find_map(|arg| arg.strip_prefix("--lang=")) {
+        Some(name) => match Language::from_name(name) {
+            Some(lang) => lang,
+            None => {
+                let names: Vec<&str> = LANGUAGES.iter().map(|l| l.name()).collect();
+                return eprintln!("{}", Language::English.fill(Text::InvalidValue, &[&"--lang", &Language::English.one_of(&names)]));
+            }
         },
-        Ok(file) => file
+        None => Language::from_env()
     };
 
-    let audio = match Audio::new() {
-        Ok(a) => a,
-        Err(err) => {
-            return eprintln!("Could not initialize audio device: {}", err);
+    println!("{}", lang.text(Text::Banner));
+
+    let mut config = Config {
+        language: lang,
+        ..Config::default()
+    };
+    let mut filename = None;
+    // run without a window or audio for this many frames
+    let mut headless_frames: Option<usize> = None;
+    // key presses to feed a headless run
+    let mut input_script: Option<String> = None;
+    // Lua script to run alongside the ROM
+    let mut lua_script: Option<String> = None;
+    // address to accept remote control connections on
+    let mut websocket: Option<String> = None;
+    // file of conditions to announce when first met
+    let mut achievements_file: Option<String> = None;
+    // file listing addresses to freeze
+    let mut cheats_file: Option<String> = None;
+    // IPS or BPS patches to apply to the ROM, in order
+    let mut patches: Vec<String> = Vec::new();
+    // address to serve the inspection endpoints on
+    let mut http: Option<String> = None;
+    // path of the socket to take keypad events from
+    let mut input_socket: Option<String> = None;
+    // file to publish the screen into
+    let mut shared_framebuffer: Option<String> = None;
+    // how to combine key votes, and where they come from (stdin if not set)
+    let mut chat_mode: Option<ChatMode> = None;
+    let mut chat_feed: Option<String> = None;
+    // address to stream the screen to viewers from
+    let mut spectators: Option<String> = None;
+    // address to wait for, or connect to, a co-op partner on
+    let mut coop_host: Option<String> = None;
+    let mut coop_join: Option<String> = None;
+    // file to write tracing-flame's folded stacks to
+    let mut trace_flame: Option<String> = None;
+    // file to save a replay of the session to
+    let mut record: Option<String> = None;
+    // screen pixels per CHIP-8 pixel, if not worked out from the desktop
+    let mut scale: Option<usize> = None;
+    // seconds to show each stage of an instruction for, in educator mode
+    let mut educator: Option<f64> = None;
+    // instructions a second to run at while showing them, if at all
+    let mut annotate: Option<u32> = None;
+    // whether to show the splash before the ROM
+    let mut show_splash = true;
+    // whether any quirks were given, so there's nothing to guess
+    let mut quirks_given = false;
+    // whether to show RAM in a second window
+    let mut ram_view = false;
+    // file to log every instruction run to, as Octo would
+    let mut trace_log: Option<String> = None;
+
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--check-invariants" => config.check_invariants = true,
+            "--strict" => config.strict = true,
+            "--truncate-rom" => config.truncate_rom = true,
+            "--opcode-stats" => config.opcode_stats = true,
+            "--no-focus-pause" => config.pause_on_focus_loss = false,
+            "--skip-delay-loops" => config.skip_delay_loops = true,
+            "--stub-native" => config.stub_native_calls = true,
+            "--no-splash" => show_splash = false,
+            "--educator" => educator = Some(DEFAULT_EDUCATOR_STAGE_SECS),
+            "--annotate" => annotate = Some(DEFAULT_ANNOTATE_SPEED),
+            "--ram-view" => {
+                ram_view = true;
+                // for the map to show the sprites being drawn
+                config.record_sprites = true;
+            },
+            _ if arg.starts_with("--quirks=") => {
+                config.quirks = match Quirks::preset(&arg["--quirks=".len()..]) {
+                    Some(quirks) => quirks,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--quirks", &lang.one_of(&PRESETS)]));
+                    }
+                };
+                quirks_given = true;
+            },
+            _ if arg.starts_with("--quirk-") => {
+                match Quirk::from_name(&arg["--quirk-".len()..]) {
+                    Some(quirk) => config.quirks.set(quirk, true),
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::UnknownOption, &[arg]));
+                    }
+                }
+                quirks_given = true;
+            },
+            _ if arg.starts_with("--protect-font=") => {
+                config.font_protection = match WriteProtection::from_name(&arg["--protect-font=".len()..]) {
+                    Some(p) => p,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--protect-font", &lang.one_of(&["off", "log", "reject"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--unknown-opcode=") => {
+                config.unknown_opcode = match UnknownOpcodePolicy::from_name(&arg["--unknown-opcode=".len()..]) {
+                    Some(p) => p,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--unknown-opcode", &lang.one_of(&["warn", "halt", "ignore"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--memory=") => {
+                config.memory = match MemoryPolicy::from_name(&arg["--memory=".len()..]) {
+                    Some(p) => p,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--memory", &lang.one_of(&["wrap", "error"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--font=") => {
+                config.font_layout = match FontLayout::from_name(&arg["--font=".len()..]) {
+                    Some(f) => f,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--font", &lang.one_of(&["spaced", "standard"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--platform=") => {
+                config.platform = match Platform::from_name(&arg["--platform=".len()..]) {
+                    Some(p) => p,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--platform", &lang.one_of(&["chip8", "chip8x", "megachip"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--key-sampling=") => {
+                config.key_sampling = match KeySampling::from_name(&arg["--key-sampling=".len()..]) {
+                    Some(k) => k,
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--key-sampling", &lang.one_of(&["frame", "instruction"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--speed=") => {
+                config.speed = match arg["--speed=".len()..].parse::<u32>() {
+                    Ok(speed) if speed > 0 => speed,
+                    _ => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--speed", &lang.text(Text::InstructionsPerFrame)]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--headless=") => {
+                headless_frames = match arg["--headless=".len()..].parse() {
+                    Ok(n) => Some(n),
+                    Err(_) => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--headless", &lang.text(Text::NumberOfFrames)]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--input-script=") => {
+                input_script = Some(String::from(&arg["--input-script=".len()..]));
+            },
+            _ if arg.starts_with("--script=") => {
+                lua_script = Some(String::from(&arg["--script=".len()..]));
+            },
+            _ if arg.starts_with("--websocket=") => {
+                websocket = Some(String::from(&arg["--websocket=".len()..]));
+            },
+            _ if arg.starts_with("--input-socket=") => {
+                input_socket = Some(String::from(&arg["--input-socket=".len()..]));
+            },
+            _ if arg.starts_with("--shared-framebuffer=") => {
+                shared_framebuffer = Some(String::from(&arg["--shared-framebuffer=".len()..]));
+            },
+            _ if arg.starts_with("--chat-plays=") => {
+                chat_mode = match ChatMode::from_name(&arg["--chat-plays=".len()..]) {
+                    Some(mode) => Some(mode),
+                    None => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--chat-plays", &lang.one_of(&["majority", "queue"])]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--chat-feed=") => {
+                chat_feed = Some(String::from(&arg["--chat-feed=".len()..]));
+            },
+            _ if arg.starts_with("--spectators=") => {
+                spectators = Some(String::from(&arg["--spectators=".len()..]));
+            },
+            _ if arg.starts_with("--coop-host=") => {
+                coop_host = Some(String::from(&arg["--coop-host=".len()..]));
+            },
+            _ if arg.starts_with("--coop-join=") => {
+                coop_join = Some(String::from(&arg["--coop-join=".len()..]));
+            },
+            _ if arg.starts_with("--trace-flame=") => {
+                trace_flame = Some(String::from(&arg["--trace-flame=".len()..]));
+            },
+            _ if arg.starts_with("--scale=") => {
+                scale = match &arg["--scale=".len()..] {
+                    "auto" => None,
+                    value => match value.parse() {
+                        Ok(n) if SCALES.contains(&n) => Some(n),
+                        _ => {
+                            let mut choices: Vec<String> = SCALES.iter().map(|s| s.to_string()).collect();
+                            choices.push(String::from("auto"));
+                            let choices: Vec<&str> = choices.iter().map(|s| s.as_str()).collect();
+                            return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--scale", &lang.one_of(&choices)]));
+                        }
+                    }
+                };
+            },
+            _ if arg.starts_with("--trace-log=") => {
+                trace_log = Some(String::from(&arg["--trace-log=".len()..]));
+            },
+            _ if arg.starts_with("--record=") => {
+                record = Some(String::from(&arg["--record=".len()..]));
+            },
+            _ if arg.starts_with("--achievements=") => {
+                achievements_file = Some(String::from(&arg["--achievements=".len()..]));
+            },
+            _ if arg.starts_with("--cheats=") => {
+                cheats_file = Some(String::from(&arg["--cheats=".len()..]));
+            },
+            _ if arg.starts_with("--patch=") => {
+                patches.push(String::from(&arg["--patch=".len()..]));
+            },
+            _ if arg.starts_with("--http=") => {
+                http = Some(String::from(&arg["--http=".len()..]));
+            },
+            _ if arg.starts_with("--educator=") => {
+                educator = match arg["--educator=".len()..].parse::<f64>() {
+                    Ok(secs) if secs > 0.0 => Some(secs),
+                    _ => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--educator", &lang.text(Text::NumberOfSeconds)]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--annotate=") => {
+                annotate = match arg["--annotate=".len()..].parse::<u32>() {
+                    Ok(ips) if ips > 0 => Some(ips),
+                    _ => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--annotate", &lang.text(Text::InstructionsPerSecond)]));
+                    }
+                };
+            },
+            _ if arg.starts_with("--key-wait-timeout=") => {
+                config.key_wait_timeout = match arg["--key-wait-timeout=".len()..].parse::<f64>() {
+                    Ok(secs) if secs >= 0.0 => Some(Duration::from_secs_f64(secs)),
+                    _ => {
+                        return eprintln!("{}", lang.fill(Text::InvalidValue, &[&"--key-wait-timeout", &lang.text(Text::NumberOfSeconds)]));
+                    }
+                };
+            },
+            // read before the rest
+            _ if arg.starts_with("--lang=") => (),
+            _ if arg.starts_with("--") => {
+                return eprintln!("{}", lang.fill(Text::UnknownOption, &[arg]));
+            },
+            _ if filename.is_none() => filename = Some(String::from(arg)),
+            _ => {
+                return eprintln!("{}", lang.fill(Text::Usage, &[&args[0]]));
+            }
+        }
+    }
+
+    let filename = match filename {
+        Some(f) => f,
+        None => {
+            return eprintln!("{}", lang.fill(Text::Usage, &[&args[0]]));
         }
     };
 
-    let win = match Window::new(&format!("chip8-rust: {}", filename)) {
-        Ok(win) => win,
+    // kept until we exit, so the profile gets written out
+    let _tracing = match init_tracing(&trace_flame, lang) {
+        Ok(guard) => guard,
         Err(err) => {
-            return eprintln!("Could not initialize window: {}", &err.to_string());
+            return eprintln!("{}", lang.fill(Text::CouldNotStartTracing, &[&err]));
         }
     };
 
-    let mut cpu = CPU::new(win, audio);
-    match cpu.load_rom(&rom) {
-        Ok(()) => (),
-        Err(err) => {
-            return eprintln!("Could not initialize CPU: {}", err);
+    // Octo sources are assembled on the fly
+    let octo = filename.ends_with(".8o");
+    let mut rom = if octo {
+        match tools::assemble_file(&filename) {
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotAssemble, &[&err]));
+            },
+            Ok(rom) => rom
+        }
+    }
+    else {
+        match fs::read(&filename) {
+            Err(why) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotOpenFile, &[&why]));
+            },
+            Ok(file) => file
         }
     };
 
-    match cpu.run_loop() {
-        Ok(()) => (),
-        Err(err) => {
-            return eprintln!("CPU crashed: {}", err);
+    for path in &patches {
+        let patch = match fs::read(path) {
+            Ok(patch) => patch,
+            Err(why) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotOpenPatch, &[path, &why]));
+            }
+        };
+        rom = match apply_patch(&rom, &patch) {
+            Ok(rom) => rom,
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotApplyPatch, &[path, &err]));
+            }
+        };
+    }
+
+    if !octo {
+        match sanity_check(&rom) {
+            Ok(None) => (),
+            Ok(Some(warning)) => eprintln!("{}", lang.fill(Text::Warning, &[&warning])),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotLoadRom, &[&err]));
+            }
+        }
+        // hybrid ROMs for the COSMAC VIP, with bits of 1802 machine code
+        let calls = native_calls(&rom);
+        if !calls.is_empty() {
+            let warning = lang.fill(Text::CallsMachineCode, &[&describe_calls(&calls)]);
+            eprintln!("{}", lang.fill(Text::Warning, &[&warning]));
+        }
+    }
+
+    // a ROM run without quirks gets them guessed, the first time, and kept
+    // next to it to be used from then on and corrected by hand
+    let mut guess_notice = None;
+    if !quirks_given && headless_frames.is_none() {
+        let path = format!("{}.{}", filename, GUESS_EXTENSION);
+        match fs::read_to_string(&path) {
+            Ok(text) => match Guess::parse(&text) {
+                Ok(guess) => config.quirks = guess.quirks,
+                Err(err) => {
+                    return eprintln!("{}", lang.fill(Text::InvalidQuirksFile, &[&path, &err]));
+                }
+            },
+            Err(_) => {
+                let guess = detect::guess(&rom);
+                config.quirks = guess.quirks;
+                let quirks = match guess.quirks_on() {
+                    on if on.is_empty() => String::from(lang.text(Text::NoQuirks)),
+                    on => on.join(", ")
+                };
+                match fs::write(&path, guess.to_text()) {
+                    Ok(()) => println!("{}", lang.fill(Text::QuirksSaved, &[&guess.variant.name(), &quirks, &path])),
+                    Err(why) => eprintln!("{}", lang.fill(Text::Warning, &[&why]))
+                }
+                guess_notice = Some(vec![String::from(lang.text(Text::QuirksGuessed)), String::from(guess.variant.name()), quirks]);
+            }
+        }
+    }
+
+    let script = match (&input_script, headless_frames) {
+        (Some(path), Some(_)) => {
+            let text = match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(why) => {
+                    return eprintln!("{}", lang.fill(Text::CouldNotOpenInputScript, &[&why]));
+                }
+            };
+            match parse_input_script(&text) {
+                Ok(script) => script,
+                Err(err) => {
+                    return eprintln!("{}", lang.fill(Text::InvalidInputScript, &[&err]));
+                }
+            }
+        },
+        (Some(_), None) => {
+            return eprintln!("{}", lang.fill(Text::OnlyWith, &[&"--input-script", &"--headless"]));
+        },
+        (None, _) => Vec::new()
+    };
+
+    // without a window nobody can press a key, so don't wait forever
+    if headless_frames.is_some() && config.key_wait_timeout.is_none() {
+        config.key_wait_timeout = Some(HEADLESS_KEY_WAIT_TIMEOUT);
+    }
+
+    let (win, audio): (Box<dyn Frontend>, Box<dyn Buzzer>) = match headless_frames {
+        Some(frames) => (Box::new(Headless::with_script(frames, script)), Box::new(Silence)),
+        None => {
+            let audio = match open_audio() {
+                Ok(audio) => audio,
+                Err(err) => {
+                    return eprintln!("{}", lang.fill(Text::CouldNotStartAudio, &[&err]));
+                }
+            };
+
+            let name = Path::new(&filename).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            let win = match open_window(&format!("chip8-rust: {}", name), scale) {
+                Ok(win) => win,
+                Err(err) => {
+                    return eprintln!("{}", lang.fill(Text::CouldNotOpenWindow, &[&err]));
+                }
+            };
+
+            (win, audio)
+        }
+    };
+
+    let win: Box<dyn Frontend> = if coop_host.is_none() && coop_join.is_none() {
+        win
+    }
+    else {
+        match start_coop(&coop_host, &coop_join, win, &rom, lang) {
+            Ok((coop, seed)) => {
+                config.random_seed = Some(seed);
+                Box::new(coop)
+            },
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotStartCoop, &[&err]));
+            }
         }
+    };
+
+    // a recording needs a seed it can write down
+    let recording = record.as_ref().map(|_| {
+        config.random_seed.get_or_insert_with(rand::random);
+        Rc::new(RefCell::new(Replay::new(&rom, &filename, &patches, &config)))
+    });
+    let win: Box<dyn Frontend> = match &recording {
+        Some(replay) => Box::new(Recorder::new(win, replay.clone())),
+        None => win
+    };
+
+    // only for people watching, and not when the session has to match
+    // from the first frame on
+    let show_splash = show_splash && headless_frames.is_none() && record.is_none()
+        && coop_host.is_none() && coop_join.is_none() && educator.is_none();
+
+    // SUPER-CHIP's flag registers, kept next to the ROM between runs
+    // unless the session has to match from the first frame on
+    let saved_flags = if headless_frames.is_none() && record.is_none() && coop_host.is_none() && coop_join.is_none() {
+        let path = format!("{}.{}", filename, FLAGS_EXTENSION);
+        let flags = match fs::read_to_string(&path) {
+            Ok(text) => match parse_flags(&text) {
+                Ok(flags) => flags,
+                Err(err) => {
+                    return eprintln!("{}", lang.fill(Text::InvalidFlagsFile, &[&path, &err]));
+                }
+            },
+            Err(_) => Default::default()
+        };
+        Some((path, flags))
+    }
+    else {
+        None
+    };
+
+    let mut cpu = CPU::new(win, audio, config);
+    // the ROM is loaded even when the splash comes first, so that one that
+    // can't be is reported now rather than once the splash is over
+    let loaded = cpu.load_rom(&rom).and_then(|()| {
+        if show_splash {
+            // as loaded, which may be truncated
+            let rom = cpu.rom().to_vec();
+            cpu.load_rom(&splash::splash())?;
+            cpu.add_hooks(Box::new(Splash::new(rom)));
+        }
+        Ok(())
+    });
+    if let Err(err) = loaded {
+        eprintln!("{}", lang.fill(Text::CouldNotStartCpu, &[&err.localized(lang)]));
+        if let Some(option) = err.option() {
+            eprintln!("{}", lang.fill(Text::TryOption, &[&option]));
+        }
+        return;
+    }
+
+    // the numbered save slots, in a directory next to the ROM, under the
+    // same conditions as the flags
+    if saved_flags.is_some() {
+        cpu.set_state_dir(PathBuf::from(format!("{}.{}", filename, STATES_EXTENSION)));
+    }
+
+    if let Some((path, flags)) = saved_flags {
+        cpu.set_flags(flags);
+        cpu.add_hooks(Box::new(FlagsFile::new(PathBuf::from(path), flags)));
+    }
+
+    if let Some(lines) = guess_notice {
+        cpu.add_hooks(Box::new(Notice::new(lines, GUESS_NOTICE_FRAMES)));
+    }
+
+    if let Some(path) = &lua_script {
+        match load_script(path, lang) {
+            Ok(hooks) => cpu.add_hooks(hooks),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotLoadScript, &[&err]));
+            }
+        }
+    }
+
+    if let Some(addr) = &websocket {
+        match listen_websocket(addr, lang) {
+            Ok(hooks) => cpu.add_hooks(hooks),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotStartRemote, &[&err]));
+            }
+        }
+    }
+
+    if let Some(path) = &cheats_file {
+        let frozen = match fs::read_to_string(path).map_err(|why| why.to_string()).and_then(|text| parse_cheats(&text)) {
+            Ok(frozen) => frozen,
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotLoadCheats, &[path, &err]));
+            }
+        };
+        for (addr, value) in frozen {
+            cpu.cheats_mut().freeze(addr, value);
+        }
+    }
+
+    if let Some(path) = &achievements_file {
+        let achievements = match fs::read_to_string(path).map_err(|why| why.to_string()).and_then(|text| parse_achievements(&text)) {
+            Ok(achievements) => achievements,
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotLoadAchievements, &[path, &err]));
+            }
+        };
+        // remembered next to the definitions, eg. game.ach.log
+        let log = PathBuf::from(format!("{}.log", path));
+        cpu.add_hooks(Box::new(AchievementWatcher::new(achievements, Some(log))));
+    }
+
+    if let Some(secs) = educator {
+        // paused from the start, so the educator shows every instruction
+        cpu.set_paused(true);
+        let frames = (secs / REFRESH_INTERVAL.as_secs_f64()).round() as u32;
+        cpu.add_hooks(Box::new(Educator::new(frames)));
+    }
+
+    if let Some(ips) = annotate {
+        cpu.set_slow_motion(Some(ips));
+        cpu.add_hooks(Box::new(Annotator::new()));
+    }
+
+    if let Some(path) = &trace_log {
+        match File::create(path) {
+            // a line at a time, so a crash doesn't lose the end of it
+            Ok(file) => cpu.add_hooks(Box::new(OctoTrace::new(Box::new(LineWriter::new(file))))),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotOpenFile, &[&err]));
+            }
+        }
+    }
+
+    if ram_view && headless_frames.is_none() {
+        match open_ram_view() {
+            Ok(view) => cpu.add_hooks(view),
+            Err(err) => {
+                return eprintln!("{}", err);
+            }
+        }
+    }
+
+    if let Some(addr) = &http {
+        match HttpInspector::listen(addr) {
+            Ok(inspector) => {
+                println!("{}", lang.fill(Text::InspectionEndpoints, &[&inspector.addr()]));
+                cpu.add_hooks(Box::new(inspector));
+            },
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotStartHttp, &[&err]));
+            }
+        }
+    }
+
+    match (chat_mode, &chat_feed) {
+        (Some(mode), Some(addr)) => match ChatPlays::listen(mode, addr) {
+            Ok((chat, addr)) => {
+                println!("{}", lang.fill(Text::TakingVotes, &[&addr]));
+                cpu.add_hooks(Box::new(chat));
+            },
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotStartChatPlays, &[&err]));
+            }
+        },
+        (Some(mode), None) => cpu.add_hooks(Box::new(ChatPlays::from_stdin(mode))),
+        (None, Some(_)) => {
+            return eprintln!("{}", lang.fill(Text::OnlyWith, &[&"--chat-feed", &"--chat-plays"]));
+        },
+        (None, None) => ()
+    }
+
+    if let Some(addr) = &spectators {
+        match SpectatorServer::listen(addr) {
+            Ok(server) => {
+                println!("{}", lang.fill(Text::StreamingTo, &[&server.addr()]));
+                cpu.add_hooks(Box::new(server));
+            },
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotStartStreaming, &[&err]));
+            }
+        }
+    }
+
+    if let Some(path) = &input_socket {
+        match listen_input_socket(path, lang) {
+            Ok(hooks) => cpu.add_hooks(hooks),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotOpenInputSocket, &[&err]));
+            }
+        }
+    }
+
+    if let Some(path) = &shared_framebuffer {
+        match create_shared_framebuffer(path, lang) {
+            Ok(hooks) => cpu.add_hooks(hooks),
+            Err(err) => {
+                return eprintln!("{}", lang.fill(Text::CouldNotShareScreen, &[&err]));
+            }
+        }
+    }
+
+    // remember what a panic inside the emulator was about, so we can
+    // still write a crash file with the machine state after unwinding
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(mut msg) = PANIC_MESSAGE.lock() {
+            *msg = Some(info.to_string());
+        }
+        default_hook(info);
+    }));
+
+    let exit = match panic::catch_unwind(panic::AssertUnwindSafe(|| cpu.run_loop())) {
+        Ok(Ok(exit)) => exit,
+        Ok(Err(err)) => {
+            print_unknown_opcodes(&cpu, lang);
+            print_native_calls(&cpu, lang);
+            print_opcode_stats(&cpu, lang);
+            eprintln!("{}", lang.fill(Text::CpuCrashed, &[&err]));
+            if let Some(option) = err.option() {
+                eprintln!("{}", lang.fill(Text::TryOption, &[&option]));
+            }
+            save_recording(&record, &recording, lang);
+            match write_crash_file(&err.to_string(), &cpu, &rom) {
+                Ok(path) => eprintln!("{}", lang.fill(Text::CrashReportSaved, &[&path.display()])),
+                Err(why) => eprintln!("{}", lang.fill(Text::CouldNotWriteCrashReport, &[&why]))
+            }
+            process::exit(1);
+        },
+        Err(_) => {
+            let reason = match PANIC_MESSAGE.lock() {
+                Ok(msg) => msg.clone().unwrap_or_else(|| String::from("unknown panic")),
+                Err(_) => String::from("unknown panic")
+            };
+            match write_crash_file(&reason, &cpu, &rom) {
+                Ok(path) => eprintln!("{}", lang.fill(Text::EmulatorPanicked, &[&path.display()])),
+                Err(err) => eprintln!("{}", lang.fill(Text::PanicNotSaved, &[&err]))
+            }
+            process::exit(101);
+        }
+    };
+
+    print_unknown_opcodes(&cpu, lang);
+    print_native_calls(&cpu, lang);
+    print_opcode_stats(&cpu, lang);
+    save_recording(&record, &recording, lang);
+
+    if headless_frames.is_some() {
+        // there was no window, so show the final screen instead
+        print!("{}", cpu.framebuffer());
+        println!("{}", lang.fill(Text::FramebufferHash, &[&format!("{:016x}", cpu.framebuffer().hash())]));
+    }
+
+    if exit == Exit::RomExited {
+        let counters = cpu.counters();
+        let seconds = format!("{:.1}", (REFRESH_INTERVAL * counters.frames as u32).as_secs_f64());
+        println!("{}", lang.fill(Text::RomExited, &[&counters.instructions, &counters.frames, &seconds]));
+    }
+
+    if let Exit::WaitingForInput(pc) = exit {
+        eprintln!("{}", lang.fill(Text::WaitingForInput, &[&format!("{:03x}", pc)]));
+        process::exit(2);
+    }
+}
+
+#[cfg(feature = "lua")]
+fn load_script(path: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    let source = fs::read_to_string(path).map_err(|why| lang.fill(Text::CouldNotOpenPath, &[&path, &why]))?;
+    Ok(Box::new(LuaScript::load(&source, path)?))
+}
+
+#[cfg(not(feature = "lua"))]
+fn load_script(_path: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Err(lang.fill(Text::NotBuiltWith, &[&"Lua", &"lua"]))
+}
+
+// send the core's tracing spans and events to the terminal, filtered by the
+// CHIP8_LOG environment variable (eg. `trace` for every instruction, default
+// `info`), and to a tracing-flame file if asked for one
+#[cfg(feature = "tracing")]
+fn init_tracing(flame: &Option<String>, _lang: Language) -> Result<Option<Box<dyn Any>>, String> {
+    let level = env::var("CHIP8_LOG").ok().and_then(|l| l.parse().ok()).unwrap_or(LevelFilter::INFO);
+    let (flame, guard) = match flame {
+        Some(path) => {
+            let (layer, guard) = FlameLayer::with_file(path).map_err(|why| why.to_string())?;
+            (Some(layer), Some(Box::new(guard) as Box<dyn Any>))
+        },
+        None => (None, None)
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(level))
+        .with(flame)
+        .init();
+    Ok(guard)
+}
+
+#[cfg(not(feature = "tracing"))]
+fn init_tracing(flame: &Option<String>, lang: Language) -> Result<Option<Box<dyn Any>>, String> {
+    match flame {
+        Some(_) => Err(lang.fill(Text::NotBuiltWith, &[&"tracing", &"tracing"])),
+        None => Ok(None)
+    }
+}
+
+#[cfg(feature = "websocket")]
+fn listen_websocket(addr: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    let control = WebSocketControl::listen(addr)?;
+    println!("{}", lang.fill(Text::RemoteListening, &[&control.addr()]));
+    Ok(Box::new(control))
+}
+
+#[cfg(not(feature = "websocket"))]
+fn listen_websocket(_addr: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Err(lang.fill(Text::NotBuiltWith, &[&"WebSocket", &"websocket"]))
+}
+
+// wait for or connect to the other player, whichever was asked for
+fn start_coop(host: &Option<String>, join: &Option<String>, win: Box<dyn Frontend>, rom: &[u8], lang: Language) -> Result<(Coop, u64), String> {
+    match (host, join) {
+        (Some(addr), None) => {
+            let listener = TcpListener::bind(addr).map_err(|why| lang.fill(Text::CouldNotListen, &[addr, &why]))?;
+            println!("{}", lang.fill(Text::WaitingForPlayer, &[&listener.local_addr().map_err(|why| why.to_string())?]));
+            Coop::host(&listener, win, rom)
+        },
+        (None, Some(addr)) => Coop::join(addr, win, rom),
+        _ => Err(lang.fill(Text::NotBoth, &[&"--coop-host", &"--coop-join"]))
+    }
+}
+
+#[cfg(unix)]
+fn listen_input_socket(path: &str, _lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Ok(Box::new(InputSocket::listen(path.as_ref())?))
+}
+
+#[cfg(not(unix))]
+fn listen_input_socket(_path: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Err(lang.fill(Text::NeedsUnix, &[&"--input-socket"]))
+}
+
+#[cfg(unix)]
+fn create_shared_framebuffer(path: &str, _lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Ok(Box::new(SharedFramebuffer::create(path.as_ref())?))
+}
+
+#[cfg(not(unix))]
+fn create_shared_framebuffer(_path: &str, lang: Language) -> Result<Box<dyn Hooks>, String> {
+    Err(lang.fill(Text::NeedsUnix, &[&"--shared-framebuffer"]))
+}
+
+// the window for a ROM, scale screen pixels to a CHIP-8 pixel or sized for
+// the desktop
+#[cfg(feature = "window")]
+pub(crate) fn open_window(title: &str, scale: Option<usize>) -> Result<Box<dyn Frontend>, String> {
+    let win = match scale {
+        Some(scale) => Window::with_scale(title, scale),
+        None => Window::new(title)
+    };
+    win.map(|win| Box::new(win) as Box<dyn Frontend>).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "window"))]
+pub(crate) fn open_window(_title: &str, _scale: Option<usize>) -> Result<Box<dyn Frontend>, String> {
+    Err(no_window())
+}
+
+#[cfg(not(feature = "window"))]
+fn no_window() -> String {
+    String::from("this build has no window (see the `window` feature); run the ROM with --headless=<frames>")
+}
+
+#[cfg(feature = "window")]
+fn open_ram_view() -> Result<Box<dyn Hooks>, String> {
+    RamView::new().map(|view| Box::new(view) as Box<dyn Hooks>)
+}
+
+#[cfg(not(feature = "window"))]
+fn open_ram_view() -> Result<Box<dyn Hooks>, String> {
+    Err(no_window())
+}
+
+// the buzzer, silent in builds without sound
+#[cfg(feature = "audio")]
+pub(crate) fn open_audio() -> Result<Box<dyn Buzzer>, String> {
+    Audio::new().map(|audio| Box::new(audio) as Box<dyn Buzzer>)
+}
+
+#[cfg(not(feature = "audio"))]
+pub(crate) fn open_audio() -> Result<Box<dyn Buzzer>, String> {
+    Ok(Box::new(Silence))
+}
+
+// write out the session recorded with --record, if it was
+fn save_recording(path: &Option<String>, recording: &Option<Rc<RefCell<Replay>>>, lang: Language) {
+    if let (Some(path), Some(replay)) = (path, recording) {
+        match fs::write(path, replay.borrow().to_text()) {
+            Ok(()) => println!("{}", lang.fill(Text::RecordingSaved, &[path])),
+            Err(why) => eprintln!("{}", lang.fill(Text::CouldNotSaveRecording, &[path, &why]))
+        }
+    }
+}
+
+// table of the unrecognized instructions the ROM ran, if any
+fn print_unknown_opcodes(cpu: &CPU, lang: Language) {
+    if cpu.unknown_opcodes().is_empty() {
+        return;
+    }
+    println!("{}", lang.text(Text::UnknownOpcodesTable));
+    println!("{}", lang.text(Text::TableHeader));
+    for ((pc, instruction), count) in cpu.unknown_opcodes() {
+        println!("{:03x}      {:04x}         {}", pc, instruction, count);
+    }
+}
+
+fn print_native_calls(cpu: &CPU, lang: Language) {
+    if cpu.native_calls().is_empty() {
+        return;
+    }
+    println!("{}", lang.text(if cpu.config().stub_native_calls { Text::NativeCallsStubbed } else { Text::NativeCallsIgnored }));
+    println!("{}", lang.text(Text::TableHeader));
+    for ((pc, instruction), count) in cpu.native_calls() {
+        println!("{:03x}      {:04x}         {}", pc, instruction, count);
+    }
+}
+
+// what --opcode-stats collected: instructions executed by family, and the
+// addresses the ROM spent most of its time at
+fn print_opcode_stats(cpu: &CPU, lang: Language) {
+    if !cpu.config().opcode_stats {
+        return;
+    }
+    println!("{}", lang.text(Text::OpcodeFamilies));
+    print!("{}", stats::report(cpu.executed().iter().map(|((_, op), count)| (*op, *count))));
+
+    let mut busiest: Vec<_> = cpu.executed().iter().collect();
+    busiest.sort_by_key(|(_, count)| Reverse(**count));
+    println!("{}", lang.text(Text::BusiestAddresses));
+    println!("{}", lang.text(Text::TableHeader));
+    for ((pc, instruction), count) in busiest.iter().take(10) {
+        println!("{:03x}      {:04x}         {}", pc, instruction, count);
     }
 }
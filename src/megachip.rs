@@ -0,0 +1,320 @@
+// MegaChip, the Mega8 emulator's extension of SUPER-CHIP: 0011 switches to
+// a 256x192 screen of sprites a byte a pixel, each byte picking one of 255
+// colours from a palette the program loads (0 is see-through). sprites are
+// drawn to a frame that isn't shown until the next 00E0, so programs
+// redraw everything every frame. I takes 24-bit addresses, into 16MB of
+// memory, and sounds sampled at up to 64 kHz can play from it
+
+use crate::framebuffer::{
+    MEGA_HEIGHT,
+    MEGA_WIDTH
+};
+
+// memory, all of it addressable through I (01nn nnnn); programs still run
+// from the first 4K, since jumps only take 12-bit addresses
+pub const MEGA_RAM_SIZE: usize = 0x1000000;
+// the palette entry set bits of the built-in font's sprites are drawn in,
+// white unless the program loads another colour there
+pub const FONT_COLOUR: u8 = 0xff;
+// a digitised sound (060n) starts with its rate and length, then has a
+// byte for every sample, unsigned, 128 being silence:
+//
+//   rate (2 bytes) length (3 bytes) 00
+pub const SAMPLE_HEADER_LEN: usize = 6;
+
+const PIXELS: usize = MEGA_WIDTH * MEGA_HEIGHT;
+// the palette's colours, then sprite width and height, alpha, blend mode
+// and collision colour, then the frame being drawn, as indices and as
+// colours, and the frame shown
+const STATE_LEN: usize = 256 * 3 + 5 + PIXELS * 7;
+
+// how a sprite's colours are mixed with what's under them (080n)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    // the sprite's colour
+    Normal,
+    // 25%, 50% or 75% of the sprite's colour, the rest of what's under it
+    Quarter,
+    Half,
+    ThreeQuarters,
+    // the two added together, up to white
+    Add,
+    // the two multiplied, as fractions of full brightness
+    Multiply
+}
+
+impl Blend {
+    // the mode 080n picks with n
+    pub fn from_number(n: usize) -> Option<Blend> {
+        [Blend::Normal, Blend::Quarter, Blend::Half, Blend::ThreeQuarters, Blend::Add, Blend::Multiply].get(n).copied()
+    }
+
+    pub fn number(&self) -> usize {
+        *self as usize
+    }
+
+    // sprite colour src over dst, both 0xRRGGBB
+    pub fn mix(&self, src: u32, dst: u32) -> u32 {
+        let channel = |shift: u32| {
+            let (s, d) = ((src >> shift) & 0xff, (dst >> shift) & 0xff);
+            let mixed = match self {
+                Blend::Normal => s,
+                Blend::Quarter => (s + 3 * d) / 4,
+                Blend::Half => (s + d) / 2,
+                Blend::ThreeQuarters => (3 * s + d) / 4,
+                Blend::Add => (s + d).min(0xff),
+                Blend::Multiply => s * d / 0xff
+            };
+            mixed << shift
+        };
+        channel(16) | channel(8) | channel(0)
+    }
+}
+
+// the MegaChip screen, on top of the Framebuffer, which keeps the palette
+// indices of the frame shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct MegaScreen {
+    // 0xRRGGBB for each palette index; 0 is never drawn
+    palette: [u32; 256],
+    // the size sprites are drawn at (03nn and 04nn)
+    sprite_width: usize,
+    sprite_height: usize,
+    // 05nn, kept but not shown
+    alpha: u8,
+    blend: Blend,
+    // drawing over a pixel of this palette index sets VF (09nn)
+    collision: u8,
+    // the frame being drawn, as palette indices and as the colours they
+    // were blended to
+    indices: Vec<u8>,
+    drawn: Vec<u32>,
+    // the frame shown by the last 00E0
+    shown: Vec<u32>
+}
+
+impl MegaScreen {
+    pub fn new() -> MegaScreen {
+        let mut palette = [0; 256];
+        palette[FONT_COLOUR as usize] = 0xffffff;
+        MegaScreen {
+            palette,
+            sprite_width: 1,
+            sprite_height: 1,
+            alpha: 0xff,
+            blend: Blend::Normal,
+            collision: 0,
+            indices: vec![0; PIXELS],
+            drawn: vec![0; PIXELS],
+            shown: vec![0; PIXELS]
+        }
+    }
+
+    // 02nn: colours from index 1 on, 4 bytes each, alpha red green blue;
+    // alpha is ignored
+    pub fn load_palette(&mut self, argb: &[u8]) {
+        for (entry, colour) in self.palette[1..].iter_mut().zip(argb.chunks_exact(4)) {
+            *entry = u32::from_be_bytes([0, colour[1], colour[2], colour[3]]);
+        }
+    }
+
+    pub fn sprite_size(&self) -> (usize, usize) {
+        (self.sprite_width, self.sprite_height)
+    }
+
+    // 03nn, where 0 means 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = if width == 0 { 256 } else { width as usize };
+    }
+
+    // 04nn, the same
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = if height == 0 { 256 } else { height as usize };
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    pub fn set_collision_colour(&mut self, index: u8) {
+        self.collision = index;
+    }
+
+    // palette indices, width to a row, drawn with their top left corner at
+    // (x, y); whatever is past the right or bottom edge is left off.
+    // returns 1 if any went over a pixel of the collision colour
+    pub fn draw(&mut self, sprite: &[u8], width: usize, x: usize, y: usize) -> u8 {
+        let mut collision = 0;
+        for (k, row) in sprite.chunks(width.max(1)).enumerate() {
+            for (j, index) in row.iter().enumerate() {
+                if *index == 0 || x + j >= MEGA_WIDTH || y + k >= MEGA_HEIGHT {
+                    continue;
+                }
+                let coord = (y + k) * MEGA_WIDTH + x + j;
+                if self.indices[coord] == self.collision {
+                    collision = 1;
+                }
+                self.indices[coord] = *index;
+                self.drawn[coord] = self.blend.mix(self.palette[*index as usize], self.drawn[coord]);
+            }
+        }
+        collision
+    }
+
+    // 00E0: show the frame drawn, and start on a blank one; returns the
+    // palette indices of the frame now shown
+    pub fn present(&mut self) -> Vec<u8> {
+        self.shown.copy_from_slice(&self.drawn);
+        self.drawn.fill(0);
+        std::mem::replace(&mut self.indices, vec![0; PIXELS])
+    }
+
+    // move the frame being drawn by (dx, dy), blank pixels coming in
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (indices, drawn) = (self.indices.clone(), self.drawn.clone());
+        for y in 0..MEGA_HEIGHT {
+            for x in 0..MEGA_WIDTH {
+                let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                let coord = y * MEGA_WIDTH + x;
+                if (0..MEGA_WIDTH as isize).contains(&from_x) && (0..MEGA_HEIGHT as isize).contains(&from_y) {
+                    let from = from_y as usize * MEGA_WIDTH + from_x as usize;
+                    self.indices[coord] = indices[from];
+                    self.drawn[coord] = drawn[from];
+                }
+                else {
+                    self.indices[coord] = 0;
+                    self.drawn[coord] = 0;
+                }
+            }
+        }
+    }
+
+    // the frame shown, MEGA_WIDTH to a row, as 0xRRGGBB
+    pub fn shown(&self) -> &[u32] {
+        &self.shown
+    }
+
+    // everything above as hex, to keep in a save state
+    pub fn to_hex(&self) -> String {
+        let mut bytes = Vec::with_capacity(STATE_LEN);
+        let rgb = |colour: &u32| colour.to_be_bytes()[1..].to_vec();
+        bytes.extend(self.palette.iter().flat_map(rgb));
+        bytes.extend([
+            (self.sprite_width - 1) as u8,
+            (self.sprite_height - 1) as u8,
+            self.alpha,
+            self.blend.number() as u8,
+            self.collision
+        ]);
+        bytes.extend(&self.indices);
+        bytes.extend(self.drawn.iter().flat_map(rgb));
+        bytes.extend(self.shown.iter().flat_map(rgb));
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Option<MegaScreen> {
+        if hex.len() != STATE_LEN * 2 || !hex.is_ascii() {
+            return None;
+        }
+        let bytes: Vec<u8> = (0..STATE_LEN).map(|j| u8::from_str_radix(&hex[j * 2..j * 2 + 2], 16).ok()).collect::<Option<_>>()?;
+        let colours = |bytes: &[u8]| -> Vec<u32> {
+            bytes.chunks(3).map(|c| u32::from_be_bytes([0, c[0], c[1], c[2]])).collect()
+        };
+        let (palette, rest) = bytes.split_at(256 * 3);
+        let (settings, rest) = rest.split_at(5);
+        let (indices, rest) = rest.split_at(PIXELS);
+        let (drawn, shown) = rest.split_at(PIXELS * 3);
+        let mut screen = MegaScreen {
+            sprite_width: settings[0] as usize + 1,
+            sprite_height: settings[1] as usize + 1,
+            alpha: settings[2],
+            blend: Blend::from_number(settings[3] as usize)?,
+            collision: settings[4],
+            indices: indices.to_vec(),
+            drawn: colours(drawn),
+            shown: colours(shown),
+            ..MegaScreen::new()
+        };
+        screen.palette.copy_from_slice(&colours(palette));
+        Some(screen)
+    }
+}
+
+impl Default for MegaScreen {
+    fn default() -> MegaScreen {
+        MegaScreen::new()
+    }
+}
+
+// a 1-bit sprite, eg. from the font, as palette indices 8 to a row, set
+// bits in FONT_COLOUR
+pub fn font_sprite(rows: &[u8]) -> Vec<u8> {
+    rows.iter()
+        .flat_map(|row| (0..8).map(move |j| if row & (0x80 >> j) != 0 { FONT_COLOUR } else { 0 }))
+        .collect()
+}
+
+// the rate in Hz and length in bytes of a digitised sound, from its header
+pub fn sample_header(header: [u8; SAMPLE_HEADER_LEN]) -> (u32, usize) {
+    let rate = u16::from_be_bytes([header[0], header[1]]) as u32;
+    let len = u32::from_be_bytes([0, header[2], header[3], header[4]]) as usize;
+    (rate, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_blend_and_collide() {
+        let mut screen = MegaScreen::new();
+        screen.load_palette(&[0xff, 0x80, 0x00, 0x00, 0xff, 0x00, 0x00, 0xff]);
+        screen.set_collision_colour(2);
+        // index 0 is see-through, and the bottom row is off the screen
+        assert_eq!(screen.draw(&[1, 0, 2, 2], 2, 10, MEGA_HEIGHT - 1), 0);
+        assert_eq!(screen.draw(&[2, 1], 2, 20, 0), 0);
+        assert_eq!(screen.draw(&[1], 1, 20, 0), 1);
+        screen.set_blend(Blend::Add);
+        screen.draw(&[2], 1, 10, MEGA_HEIGHT - 1);
+        // nothing shows until it's presented
+        assert!(screen.shown().iter().all(|c| *c == 0));
+        let indices = screen.present();
+        let at = |x: usize, y: usize| y * MEGA_WIDTH + x;
+        assert_eq!((indices[at(10, MEGA_HEIGHT - 1)], indices[at(11, MEGA_HEIGHT - 1)]), (2, 0));
+        assert_eq!(screen.shown()[at(10, MEGA_HEIGHT - 1)], 0x8000ff);
+        assert_eq!(screen.shown()[at(21, 0)], 0x800000);
+        // and drawing starts over on a blank frame
+        assert_eq!(screen.draw(&[1], 1, 20, 0), 0);
+        assert_eq!(screen.present()[at(20, 0)], 1);
+    }
+
+    #[test]
+    fn blend_modes() {
+        let (src, dst) = (0x804020, 0x408000);
+        assert_eq!(Blend::Normal.mix(src, dst), src);
+        assert_eq!(Blend::Half.mix(src, dst), 0x606010);
+        assert_eq!(Blend::Quarter.mix(src, dst), 0x507008);
+        assert_eq!(Blend::Add.mix(0xff4020, dst), 0xffc020);
+        assert_eq!(Blend::Multiply.mix(0x80ff00, 0x8080ff), 0x408000);
+        assert_eq!(Blend::from_number(6), None);
+    }
+
+    #[test]
+    fn scrolls_and_state() {
+        let mut screen = MegaScreen::new();
+        screen.set_sprite_width(0);
+        screen.set_sprite_height(16);
+        screen.draw(&font_sprite(&[0x81]), 8, 0, 0);
+        screen.scroll(4, 2);
+        let indices = screen.present();
+        assert_eq!((indices[2 * MEGA_WIDTH + 4], indices[2 * MEGA_WIDTH + 11]), (FONT_COLOUR, FONT_COLOUR));
+        assert_eq!(indices.iter().filter(|i| **i != 0).count(), 2);
+        assert_eq!(screen.sprite_size(), (256, 16));
+        assert_eq!(MegaScreen::from_hex(&screen.to_hex()), Some(screen));
+        assert_eq!(sample_header([0x1f, 0x40, 0x00, 0x01, 0x00, 0x00]), (8000, 256));
+    }
+}
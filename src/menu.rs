@@ -0,0 +1,89 @@
+// the pause menu: opened with the pause key, drawn with the overlay, and
+// worked with the arrow keys and enter, so nobody has to remember flags or
+// hotkeys for the everyday things
+
+use crate::framebuffer::HEIGHT;
+use crate::i18n::{
+    Language,
+    Text
+};
+use crate::overlay::LINE_PITCH;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuItem {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    Palette,
+    Quit
+}
+
+pub const ITEMS: [MenuItem; 6] = [
+    MenuItem::Resume,
+    MenuItem::Reset,
+    MenuItem::SaveState,
+    MenuItem::LoadState,
+    MenuItem::Palette,
+    MenuItem::Quit
+];
+
+// lines of overlay text that fit on the screen
+const LINES_SHOWN: usize = (HEIGHT + 1) / LINE_PITCH;
+
+impl MenuItem {
+    pub fn label(&self, language: Language) -> &'static str {
+        language.text(match self {
+            MenuItem::Resume => Text::Resume,
+            MenuItem::Reset => Text::Reset,
+            MenuItem::SaveState => Text::SaveState,
+            MenuItem::LoadState => Text::LoadState,
+            MenuItem::Palette => Text::Palette,
+            MenuItem::Quit => Text::Quit
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Menu {
+    selected: usize
+}
+
+impl Menu {
+    pub fn up(&mut self) {
+        self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1) % ITEMS.len();
+    }
+
+    pub fn selected(&self) -> MenuItem {
+        ITEMS[self.selected]
+    }
+
+    // the overlay text: as many items as fit, scrolled to show the
+    // selected one, which is marked with a >
+    pub fn lines(&self, language: Language) -> Vec<String> {
+        let first = (self.selected + 1).saturating_sub(LINES_SHOWN);
+        ITEMS.iter().enumerate().skip(first).take(LINES_SHOWN)
+            .map(|(n, item)| format!("{} {}", if n == self.selected { '>' } else { ' ' }, item.label(language)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrolls_to_the_selection() {
+        let mut menu = Menu::default();
+        assert_eq!(menu.lines(Language::English), ["> Resume", "  Reset", "  Save state", "  Load state", "  Palette"]);
+        menu.up();
+        assert_eq!(menu.selected(), MenuItem::Quit);
+        assert_eq!(menu.lines(Language::Spanish)[4], "> Salir");
+        menu.down();
+        assert_eq!(menu.selected(), MenuItem::Resume);
+    }
+}
@@ -0,0 +1,77 @@
+// 0nnn: on the COSMAC VIP, a call into the 1802 machine code at nnn, which
+// some early ROMs used for things CHIP-8 couldn't do. we can't run 1802
+// code, but we can say which routines a ROM calls, and stand in for the
+// few whose effect is well known
+
+use std::collections::BTreeMap;
+
+use crate::disasm::{
+    analyze,
+    fetch
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NativeRoutine {
+    // the 64x64 hi-res CHIP-8 interpreter's screen clear
+    HiresClear
+}
+
+impl NativeRoutine {
+    pub fn description(&self) -> &'static str {
+        match self {
+            NativeRoutine::HiresClear => "hi-res clear screen"
+        }
+    }
+}
+
+// the well-known routine at addr, if it is one
+pub fn known_routine(addr: usize) -> Option<NativeRoutine> {
+    match addr {
+        0x230 => Some(NativeRoutine::HiresClear),
+        _ => None
+    }
+}
+
+// whether op is a call to machine code; 0000 is more likely a ROM running
+// into empty memory, and 00cn and 00fb-00ff are SUPER-CHIP instructions
+pub fn is_native_call(op: u16) -> bool {
+    op != 0x0000 && op >> 12 == 0 && op != 0x00e0 && op != 0x00ee && !matches!(op, 0x00c0..=0x00cf | 0x00fb..=0x00ff)
+}
+
+// the routines the ROM's code calls, with the addresses calling each
+pub fn native_calls(rom: &[u8]) -> BTreeMap<usize, Vec<usize>> {
+    let mut calls: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &addr in &analyze(rom).unknown {
+        if let Some(op) = fetch(rom, addr).filter(|&op| is_native_call(op)) {
+            calls.entry((op & 0xfff) as usize).or_default().push(addr);
+        }
+    }
+    calls
+}
+
+// eg. "230 (hi-res clear screen) from 204, 21a; 2f0 from 240"
+pub fn describe_calls(calls: &BTreeMap<usize, Vec<usize>>) -> String {
+    let described: Vec<String> = calls.iter().map(|(target, from)| {
+        let name = known_routine(*target).map(|r| format!(" ({})", r.description())).unwrap_or_default();
+        let from: Vec<String> = from.iter().map(|addr| format!("{:03x}", addr)).collect();
+        format!("{:03x}{} from {}", target, name, from.join(", "))
+    }).collect();
+    described.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_calls_into_machine_code() {
+        // 00e0 and 00ee are CHIP-8's own; the calls end their paths, so
+        // each is reached by a branch
+        let rom = [0x00, 0xe0, 0x30, 0x00, 0x02, 0x30, 0x02, 0xf0];
+        let calls = native_calls(&rom);
+        assert_eq!(calls.keys().copied().collect::<Vec<_>>(), [0x230, 0x2f0]);
+        assert_eq!(describe_calls(&calls), "230 (hi-res clear screen) from 204; 2f0 from 206");
+        assert!(!is_native_call(0x0000));
+        assert!(native_calls(&[0x12, 0x00]).is_empty());
+    }
+}
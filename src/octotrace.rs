@@ -0,0 +1,64 @@
+// a log of every instruction run, a line each, laid out like Octo's
+// debugger shows the machine so a trace from here can be lined up with one
+// taken in Octo and diffed to find where the two first disagree:
+//
+//   0200 6005 v0=05 v1=00 v2=00 ... vf=00 i=0000 dt=00 st=00
+//
+// the address and the instruction, then the registers after it ran, all in
+// lowercase hex
+
+use std::io::Write;
+
+use crate::cpu::CPU;
+use crate::hooks::Hooks;
+
+pub struct OctoTrace {
+    out: Box<dyn Write>
+}
+
+impl OctoTrace {
+    pub fn new(out: Box<dyn Write>) -> OctoTrace {
+        OctoTrace { out }
+    }
+}
+
+// the line for the instruction just run, see above
+fn line(cpu: &CPU) -> Option<String> {
+    let (addr, op) = *cpu.history().last()?;
+    let registers: Vec<String> = cpu.v().iter().enumerate()
+        .map(|(r, value)| format!("v{:x}={:02x}", r, value))
+        .collect();
+    Some(format!(
+        "{:04x} {:04x} {} i={:04x} dt={:02x} st={:02x}",
+        addr, op, registers.join(" "), cpu.i(), cpu.delay_timer(), cpu.sound_timer()
+    ))
+}
+
+impl Hooks for OctoTrace {
+    fn on_step(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        match line(cpu) {
+            Some(line) => writeln!(self.out, "{}", line).map_err(|err| format!("Could not write the trace: {}", err)),
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn lines_like_octo() {
+        let mut cpu = CPU::new(Box::new(Headless::new(1)), Box::new(Silence), Config::default());
+        cpu.load_rom(&[0x60, 0x05, 0xa3, 0x21, 0x12, 0x04]).unwrap();
+        assert_eq!(line(&cpu), None);
+        cpu.run_loop().unwrap();
+        assert_eq!(line(&cpu).unwrap(), "0204 1204 v0=05 v1=00 v2=00 v3=00 v4=00 v5=00 v6=00 v7=00 \
+            v8=00 v9=00 va=00 vb=00 vc=00 vd=00 ve=00 vf=00 i=0321 dt=00 st=00");
+    }
+}
@@ -0,0 +1,145 @@
+// text drawn over the screen, eg. by scripts: a 3x5 pixel font at the
+// CHIP-8's own resolution, so 16 characters by 5 lines fit on the screen
+
+use crate::framebuffer::{
+    HEIGHT,
+    WIDTH
+};
+
+// pixels from one character's left edge to the next, and between lines
+pub const CHAR_PITCH: usize = 4;
+pub const LINE_PITCH: usize = 6;
+
+// the letter under an accent, for translated text
+fn without_accent(c: char) -> char {
+    match c {
+        'á' | 'à' | 'â' | 'ä' | 'Á' | 'À' | 'Â' | 'Ä' => 'A',
+        'é' | 'è' | 'ê' | 'ë' | 'É' | 'È' | 'Ê' | 'Ë' => 'E',
+        'í' | 'ì' | 'î' | 'ï' | 'Í' | 'Ì' | 'Î' | 'Ï' => 'I',
+        'ó' | 'ò' | 'ô' | 'ö' | 'Ó' | 'Ò' | 'Ô' | 'Ö' => 'O',
+        'ú' | 'ù' | 'û' | 'ü' | 'Ú' | 'Ù' | 'Û' | 'Ü' => 'U',
+        'ñ' | 'Ñ' => 'N',
+        'ç' | 'Ç' => 'C',
+        c => c
+    }
+}
+
+// rows of the glyph for c, 3 bits each with the leftmost pixel in bit 2;
+// lowercase letters look like uppercase ones, accented letters like the
+// plain ones, and anything we have no glyph for is drawn as a question mark
+pub fn glyph(c: char) -> [u8; 5] {
+    match without_accent(c).to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '¡' => [0b010, 0b000, 0b010, 0b010, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010]
+    }
+}
+
+// the lines of text as on/off pixels the size of the screen, starting in
+// the top left corner; whatever doesn't fit is cut off
+pub fn render(lines: &[String]) -> Vec<bool> {
+    let mut pixels = vec![false; WIDTH * HEIGHT];
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            for (dy, bits) in glyph(c).iter().enumerate() {
+                for dx in 0..3 {
+                    let (x, y) = (column * CHAR_PITCH + dx, row * LINE_PITCH + dy);
+                    if x < WIDTH && y < HEIGHT && bits & (0b100 >> dx) != 0 {
+                        pixels[y * WIDTH + x] = true;
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_text() {
+        let pixels = render(&[String::from("Hi"), String::from("1")]);
+        let rows: Vec<String> = pixels.chunks(WIDTH).take(11)
+            .map(|r| r[..8].iter().map(|&b| if b { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(rows, [
+            "#.#.###.",
+            "#.#..#..",
+            "###..#..",
+            "#.#..#..",
+            "#.#.###.",
+            "........",
+            ".#......",
+            "##......",
+            ".#......",
+            ".#......",
+            "###....."
+        ]);
+    }
+
+    #[test]
+    fn accents_are_dropped() {
+        assert_eq!(glyph('ó'), glyph('O'));
+        assert_eq!(glyph('Ñ'), glyph('n'));
+    }
+
+    #[test]
+    fn long_lines_are_cut_off() {
+        let line = "8".repeat(40);
+        assert_eq!(render(&[line]).len(), WIDTH * HEIGHT);
+    }
+}
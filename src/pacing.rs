@@ -0,0 +1,115 @@
+// keeping real-time frontends at a steady frame rate without burning a
+// core: sleep until just before the next frame is due, and spin only for
+// the last moment, since sleeps can overshoot by a fraction of a millisecond
+
+use std::{
+    hint,
+    thread,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+// how long before a deadline we stop sleeping and spin instead
+const SPIN_MARGIN: Duration = Duration::from_micros(200);
+// how many frames behind we can be and still catch up, by the caller
+// doing less for a while; further behind, the game slows down instead
+const CATCH_UP_FRAMES: u32 = 8;
+
+pub struct Pacer {
+    interval: Duration,
+    // when the next frame should be shown
+    next: Option<Instant>,
+    // nothing is going on, so a frame a little late doesn't matter
+    idle: bool
+}
+
+impl Pacer {
+    pub fn new(interval: Duration) -> Pacer {
+        Pacer { interval, next: None, idle: false }
+    }
+
+    // while idle, wait by sleeping alone, which can overshoot a little but
+    // leaves the core alone
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    // wait until the next frame is due; false if it was already overdue,
+    // so the caller is running behind and could skip some work
+    pub fn wait(&mut self) -> bool {
+        let now = Instant::now();
+        let on_time = self.next.is_none_or(|deadline| deadline >= now);
+        let deadline = match self.next {
+            // too far behind, eg. after the host stalled: start over from
+            // now rather than rushing through the missed frames
+            Some(deadline) if deadline + self.interval * CATCH_UP_FRAMES < now => now,
+            Some(deadline) => deadline,
+            None => now
+        };
+        let margin = if self.idle { Duration::ZERO } else { SPIN_MARGIN };
+        if let Some(sleep) = deadline.checked_duration_since(now + margin) {
+            thread::sleep(sleep);
+        }
+        while !self.idle && Instant::now() < deadline {
+            hint::spin_loop();
+        }
+        self.next = Some(deadline + self.interval);
+        on_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_spaced_by_the_interval() {
+        let interval = Duration::from_millis(2);
+        let mut pacer = Pacer::new(interval);
+        let started = Instant::now();
+        for _ in 0..11 {
+            pacer.wait();
+        }
+        // the first frame is shown straight away
+        assert!(started.elapsed() >= interval * 10);
+    }
+
+    #[test]
+    fn idle_frames_are_spaced_too() {
+        let interval = Duration::from_millis(2);
+        let mut pacer = Pacer::new(interval);
+        pacer.set_idle(true);
+        let started = Instant::now();
+        for _ in 0..11 {
+            pacer.wait();
+        }
+        assert!(started.elapsed() >= interval * 10);
+    }
+
+    #[test]
+    fn stalls_are_not_made_up_for() {
+        let interval = Duration::from_millis(2);
+        let mut pacer = Pacer::new(interval);
+        pacer.wait();
+        thread::sleep(interval * (CATCH_UP_FRAMES + 2));
+        let resumed = Instant::now();
+        assert!(!pacer.wait());
+        assert!(pacer.wait());
+        assert!(resumed.elapsed() >= interval);
+    }
+
+    #[test]
+    fn short_delays_are_caught_up() {
+        let interval = Duration::from_millis(10);
+        let mut pacer = Pacer::new(interval);
+        pacer.wait();
+        thread::sleep(interval * 3);
+        // the missed frames are due straight away
+        let started = Instant::now();
+        assert!(!pacer.wait());
+        assert!(!pacer.wait());
+        assert!(started.elapsed() < interval);
+    }
+}
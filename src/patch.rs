@@ -0,0 +1,217 @@
+// IPS and BPS patches, the formats translations and bug fixes for ROMs are
+// usually shared in, so they can be applied when loading instead of
+// passing patched ROMs around
+
+use std::convert::TryFrom;
+
+use crate::png::crc32;
+
+// the ROM with the patch applied, whichever format it's in
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    }
+    else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    }
+    else {
+        Err(String::from("not an IPS or BPS patch"))
+    }
+}
+
+fn truncated() -> String {
+    String::from("patch ends in the middle of a record")
+}
+
+// IPS: after "PATCH", records of a 3-byte offset and a 2-byte length, both
+// big-endian, then that many bytes to write; a length of 0 means a 2-byte
+// count and a byte to repeat. "EOF" ends the records, and may be followed
+// by a 3-byte size to cut the result down to
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = rom.to_vec();
+    let mut pos = 5;
+    let take = |pos: &mut usize, n: usize| -> Result<&[u8], String> {
+        let bytes = patch.get(*pos..*pos + n).ok_or_else(truncated)?;
+        *pos += n;
+        Ok(bytes)
+    };
+    let number = |bytes: &[u8]| bytes.iter().fold(0usize, |n, &b| (n << 8) | b as usize);
+
+    loop {
+        let offset = take(&mut pos, 3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = number(offset);
+        let (length, fill) = match number(take(&mut pos, 2)?) {
+            0 => (number(take(&mut pos, 2)?), Some(take(&mut pos, 1)?[0])),
+            n => (n, None)
+        };
+        if out.len() < offset + length {
+            out.resize(offset + length, 0);
+        }
+        match fill {
+            Some(byte) => out[offset..offset + length].fill(byte),
+            None => out[offset..offset + length].copy_from_slice(take(&mut pos, length)?)
+        }
+    }
+    if let Ok(size) = take(&mut pos, 3) {
+        out.truncate(number(size));
+    }
+    Ok(out)
+}
+
+// BPS numbers: 7 bits a byte, the last byte marked by its top bit, with
+// each extra byte also adding one to what came before
+fn bps_number(patch: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let mut number = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *patch.get(*pos).ok_or_else(truncated)?;
+        *pos += 1;
+        number = number.checked_add((byte & 0x7f) as usize * shift).ok_or_else(|| String::from("number too large in patch"))?;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        }
+        shift = shift.checked_shl(7).ok_or_else(|| String::from("number too large in patch"))?;
+        number += shift;
+    }
+}
+
+// a BPS relative offset: the magnitude, with the sign in the lowest bit
+fn bps_offset(patch: &[u8], pos: &mut usize) -> Result<isize, String> {
+    let n = bps_number(patch, pos)?;
+    let magnitude = (n >> 1) as isize;
+    Ok(if n & 1 != 0 { -magnitude } else { magnitude })
+}
+
+// BPS: after "BPS1", the source and target sizes and some metadata, then
+// actions building the target from the source, the patch and itself, and
+// the CRC-32s of the source, target and patch, which are all checked
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 16 {
+        return Err(truncated());
+    }
+    let footer = patch.len() - 12;
+    let crc = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    if crc32(&patch[..patch.len() - 4]) != crc(footer + 8) {
+        return Err(String::from("the patch is damaged (its checksum doesn't match)"));
+    }
+    if crc32(rom) != crc(footer) {
+        return Err(String::from("the patch is for a different ROM"));
+    }
+
+    let mut pos = 4;
+    let source_size = bps_number(patch, &mut pos)?;
+    let target_size = bps_number(patch, &mut pos)?;
+    let metadata_size = bps_number(patch, &mut pos)?;
+    pos += metadata_size;
+    if source_size != rom.len() {
+        return Err(String::from("the patch is for a different ROM"));
+    }
+
+    let bad = || String::from("the patch reaches outside the ROM");
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0isize, 0isize);
+    while pos < footer {
+        let action = bps_number(patch, &mut pos)?;
+        let length = (action >> 2) + 1;
+        if out.len() + length > target_size {
+            return Err(bad());
+        }
+        match action & 3 {
+            // the same bytes as in the source
+            0 => {
+                let at = out.len();
+                out.extend_from_slice(rom.get(at..at + length).ok_or_else(bad)?);
+            },
+            // bytes from the patch
+            1 => {
+                out.extend_from_slice(patch.get(pos..pos + length).filter(|_| pos + length <= footer).ok_or_else(truncated)?);
+                pos += length;
+            },
+            // bytes from elsewhere in the source
+            2 => {
+                source_at += bps_offset(patch, &mut pos)?;
+                let at = usize::try_from(source_at).map_err(|_| bad())?;
+                out.extend_from_slice(rom.get(at..at + length).ok_or_else(bad)?);
+                source_at += length as isize;
+            },
+            // bytes from earlier in the target, one at a time since the
+            // copy may overlap what it produces
+            _ => {
+                target_at += bps_offset(patch, &mut pos)?;
+                for _ in 0..length {
+                    let at = usize::try_from(target_at).map_err(|_| bad())?;
+                    let byte = *out.get(at).ok_or_else(bad)?;
+                    out.push(byte);
+                    target_at += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size || crc32(&out) != crc(footer + 4) {
+        return Err(String::from("the patched ROM doesn't come out as the patch expects"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ips_records() {
+        let rom = [0x00, 0xe0, 0x12, 0x00];
+        let mut patch = b"PATCH".to_vec();
+        // write 60 05 at 2, then three 0xff from 4
+        patch.extend_from_slice(&[0, 0, 2, 0, 2, 0x60, 0x05]);
+        patch.extend_from_slice(&[0, 0, 4, 0, 0, 0, 3, 0xff]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0x00, 0xe0, 0x60, 0x05, 0xff, 0xff, 0xff]);
+
+        // and cut back down to 3 bytes
+        patch.extend_from_slice(&[0, 0, 3]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0x00, 0xe0, 0x60]);
+
+        assert!(apply_patch(&rom, b"PATCH\x00\x00\x02\x00\x05\x60").is_err());
+        assert!(apply_patch(&rom, b"garbage").is_err());
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend_from_slice(&[0x80 | source.len() as u8, 0x80 | target.len() as u8, 0x80]);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn bps_actions() {
+        let rom = [0x60, 0x01, 0x61, 0x02, 0x12, 0x00];
+        let target = [0x60, 0x01, 0x61, 0x07, 0x12, 0x00, 0x12, 0x00, 0x12, 0x00];
+        let actions = [
+            // keep 3 bytes
+            0x80 | (2 << 2),
+            // 1 new byte
+            0x80 | 1,
+            0x07,
+            // copy 2 bytes from source offset 4
+            0x80 | (1 << 2) | 2,
+            0x80 | (4 << 1),
+            // repeat the last 2 bytes of the target, twice over
+            0x80 | (3 << 2) | 3,
+            0x80 | (4 << 1)
+        ];
+        let patch = bps(&rom, &target, &actions);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), target);
+
+        assert_eq!(apply_patch(&[0; 6], &patch).unwrap_err(), "the patch is for a different ROM");
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(apply_patch(&rom, &damaged).unwrap_err().contains("damaged"));
+    }
+}
@@ -0,0 +1,209 @@
+// the command palette: opened with ctrl+p, it takes the same commands as
+// the control protocol (see control), including its longer forms like
+// `set speed 2`, typed in with fuzzy completion, so everything the
+// emulator can do at runtime is a few keys away
+
+use crate::framebuffer::{
+    HEIGHT,
+    WIDTH
+};
+use crate::overlay::{
+    CHAR_PITCH,
+    LINE_PITCH
+};
+use crate::quirks::QUIRKS;
+
+// what fits on a line of overlay text, and how many lines fit
+const COLUMNS: usize = (WIDTH + 1) / CHAR_PITCH;
+const LINES_SHOWN: usize = (HEIGHT + 1) / LINE_PITCH;
+
+// every command, with a <placeholder> for each word the user fills in
+fn commands() -> Vec<String> {
+    let mut commands: Vec<String> = [
+        "pause", "resume", "step", "reset", "save state", "load state", "palette", "quit", "dump",
+        "break <addr>", "clear <addr>", "key <key> down", "key <key> up",
+        "search <value>", "search changed", "search unchanged", "search increased",
+        "search decreased", "search reset", "bookmark <addr>", "unbookmark <addr>",
+        "freeze <addr> <value>", "thaw <addr>", "speed <ipf>", "save state <n>", "load state <n>",
+        "save slot <n>", "load slot <n>", "set speed <ipf>", "toggle quirk <name>"
+    ].iter().map(|c| String::from(*c)).collect();
+    commands.extend(QUIRKS.iter().map(|quirk| format!("quirk {}", quirk.name())));
+    commands
+}
+
+fn is_placeholder(word: &str) -> bool {
+    word.starts_with('<')
+}
+
+// whether the letters of typed appear in word in order, eg. "sv" in "save"
+fn fuzzy_match(typed: &str, word: &str) -> bool {
+    let mut letters = word.chars();
+    typed.chars().all(|c| letters.any(|l| l.eq_ignore_ascii_case(&c)))
+}
+
+// whether each typed word matches the command's word in the same place;
+// a placeholder matches anything, and the typed text may stop short
+fn matches(typed: &[&str], command: &[&str]) -> bool {
+    typed.len() <= command.len()
+        && typed.iter().zip(command).all(|(t, c)| is_placeholder(c) || fuzzy_match(t, c))
+}
+
+// text split into lines that fit, at spaces where it can be
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split(' ') {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= COLUMNS => {
+                line.push(' ');
+                line.push_str(word);
+            },
+            _ => lines.push(String::from(word))
+        }
+    }
+    lines
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Prompt {
+    input: String,
+    // which of the matching commands is selected
+    selected: usize,
+    // the outcome of the last command, until something is typed
+    message: Option<String>
+}
+
+impl Prompt {
+    pub fn type_char(&mut self, c: char) {
+        self.input.push(c);
+        self.selected = 0;
+        self.message = None;
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+        self.selected = 0;
+        self.message = None;
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.matching().len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    // the commands the input could be, those where every word so far
+    // starts the same way first
+    pub fn matching(&self) -> Vec<String> {
+        let typed: Vec<&str> = self.input.split_whitespace().collect();
+        let (mut prefixed, mut others): (Vec<String>, Vec<String>) = commands().into_iter()
+            .filter(|command| matches(&typed, &command.split(' ').collect::<Vec<_>>()))
+            .partition(|command| typed.iter().zip(command.split(' '))
+                .all(|(t, c)| is_placeholder(c) || c.starts_with(&t.to_ascii_lowercase())));
+        prefixed.append(&mut others);
+        prefixed
+    }
+
+    // fill in the selected command's words, keeping what was typed for
+    // the placeholders, up to the next placeholder
+    pub fn complete(&mut self) {
+        let command = match self.matching().get(self.selected) {
+            Some(command) => command.clone(),
+            None => return
+        };
+        let typed: Vec<&str> = self.input.split_whitespace().collect();
+        let mut words: Vec<&str> = Vec::new();
+        for (n, word) in command.split(' ').enumerate() {
+            match typed.get(n) {
+                Some(t) if is_placeholder(word) => words.push(t),
+                Some(_) => words.push(word),
+                None if is_placeholder(word) => break,
+                None => words.push(word)
+            }
+        }
+        let mut completed = words.join(" ");
+        if completed.split(' ').count() < command.split(' ').count() {
+            completed.push(' ');
+        }
+        self.input = completed;
+        self.selected = 0;
+    }
+
+    // complete the input, and hand back the command to run
+    pub fn take(&mut self) -> String {
+        self.complete();
+        let input = self.input.trim().to_string();
+        self.input.clear();
+        input
+    }
+
+    // the overlay text: the input after a colon, then the matching
+    // commands with the selected one marked, or the last command's outcome
+    pub fn lines(&self) -> Vec<String> {
+        let input: Vec<char> = format!(":{}_", self.input).chars().collect();
+        let mut lines = vec![input[input.len().saturating_sub(COLUMNS)..].iter().collect()];
+        if let Some(message) = &self.message {
+            lines.extend(wrap(message).into_iter().take(LINES_SHOWN - 1));
+            return lines;
+        }
+        let matching = self.matching();
+        let first = (self.selected + 1).saturating_sub(LINES_SHOWN - 1);
+        lines.extend(matching.iter().enumerate().skip(first).take(LINES_SHOWN - 1)
+            .map(|(n, command)| format!("{}{}", if n == self.selected { '>' } else { ' ' }, command)));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Prompt {
+        let mut prompt = Prompt::default();
+        text.chars().for_each(|c| prompt.type_char(c));
+        prompt
+    }
+
+    #[test]
+    fn fuzzy_completion() {
+        assert_eq!(typed("sv st").matching(), ["save state", "save state <n>", "save slot <n>"]);
+        assert_eq!(typed("ld sl 4").take(), "load slot 4");
+        assert_eq!(typed("sv st 3").take(), "save state 3");
+        assert_eq!(typed("set sp 2").take(), "set speed 2");
+        assert_eq!(typed("tog q shift").take(), "toggle quirk shift");
+        // prefixes come before other matches
+        assert_eq!(typed("re").matching()[..2], ["resume", "reset"]);
+        assert_eq!(typed("fr 3a0").take(), "freeze 3a0");
+        assert_eq!(typed("brk 2a0").take(), "break 2a0");
+        assert_eq!(typed("qui").take(), "quit");
+        let mut prompt = typed("qu");
+        prompt.down();
+        assert_eq!(prompt.take(), "quirk i-overflow");
+        let mut prompt = typed("ky");
+        prompt.complete();
+        assert_eq!(prompt.input, "key ");
+        assert!(typed("zzz").matching().is_empty());
+        assert_eq!(typed("zzz").take(), "zzz");
+    }
+
+    #[test]
+    fn overlay_lines() {
+        let mut prompt = typed("s");
+        let lines = prompt.lines();
+        assert_eq!(lines.len(), LINES_SHOWN);
+        assert_eq!(lines[..2], [":s_", ">step"]);
+        prompt.set_message(String::from("bad"));
+        assert_eq!(prompt.lines(), [":s_", "bad"]);
+        prompt.set_message(String::from("No state has been saved yet"));
+        assert_eq!(prompt.lines(), [":s_", "No state has", "been saved yet"]);
+        // the end of a long input stays in view
+        assert_eq!(typed("freeze 3a0 12 34").lines()[0], "reeze 3a0 12 34_");
+    }
+}
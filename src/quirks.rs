@@ -0,0 +1,149 @@
+// toggles for behaviour that differs between CHIP-8 interpreters;
+// the defaults follow Cowgod's reference
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    // Fx1E sets VF to 1 if I + Vx goes past 0xfff and to 0 otherwise,
+    // like the Amiga interpreter (Spacefight 2091! relies on this)
+    pub i_overflow_sets_vf: bool,
+    // 8xy6 and 8xyE shift Vy into Vx rather than shifting Vx in place,
+    // as on the COSMAC VIP
+    pub shift_uses_vy: bool,
+    // Fx55 and Fx65 leave I pointing past the last register stored or
+    // loaded, as on the COSMAC VIP
+    pub load_store_increments_i: bool,
+    // 8xy1, 8xy2 and 8xy3 set VF to 0, as on the COSMAC VIP
+    pub logic_resets_vf: bool,
+    // Bnnn jumps to nnn + Vx, x being nnn's top nibble, rather than to
+    // nnn + V0, as on SUPER-CHIP
+    pub jump_uses_vx: bool,
+    // sprites are cut off at the edges of the screen instead of wrapping
+    // around to the other side (where a sprite starts still wraps)
+    pub clip_sprites: bool,
+    // Dxyn waits for the next frame before going on, so a ROM draws at
+    // most one sprite a frame, as on the COSMAC VIP
+    pub display_wait: bool,
+    // in low resolution, 00Cn, 00FB and 00FC scroll half as far, as on
+    // SUPER-CHIP 1.1, which draws low resolution with double-size pixels
+    pub half_scroll: bool
+}
+
+// a single quirk, for turning it on and off while a ROM runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quirk {
+    IOverflow,
+    ShiftVy,
+    LoadStoreI,
+    VfReset,
+    JumpVx,
+    Clip,
+    DisplayWait,
+    HalfScroll
+}
+
+pub const QUIRKS: [Quirk; 8] = [
+    Quirk::IOverflow,
+    Quirk::ShiftVy,
+    Quirk::LoadStoreI,
+    Quirk::VfReset,
+    Quirk::JumpVx,
+    Quirk::Clip,
+    Quirk::DisplayWait,
+    Quirk::HalfScroll
+];
+
+// the names --quirks=<preset> takes
+pub const PRESETS: [&str; 4] = ["cowgod", "vip", "schip", "modern"];
+
+impl Quirk {
+    // the name of its --quirk-<name> flag
+    pub fn from_name(name: &str) -> Option<Quirk> {
+        QUIRKS.iter().copied().find(|quirk| quirk.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quirk::IOverflow => "i-overflow",
+            Quirk::ShiftVy => "shift-vy",
+            Quirk::LoadStoreI => "load-store-i",
+            Quirk::VfReset => "vf-reset",
+            Quirk::JumpVx => "jump-vx",
+            Quirk::Clip => "clip",
+            Quirk::DisplayWait => "display-wait",
+            Quirk::HalfScroll => "half-scroll"
+        }
+    }
+}
+
+impl Quirks {
+    // the quirks of a well-known interpreter, by one of the names in
+    // PRESETS: Cowgod's reference (no quirks), the original COSMAC VIP
+    // interpreter, SUPER-CHIP 1.1 on the HP48, and what Octo and most
+    // newer ROMs expect
+    pub fn preset(name: &str) -> Option<Quirks> {
+        let none = Quirks::default();
+        let quirks = match name {
+            "cowgod" => none,
+            "vip" => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                logic_resets_vf: true,
+                clip_sprites: true,
+                display_wait: true,
+                ..none
+            },
+            "schip" => Quirks { jump_uses_vx: true, clip_sprites: true, half_scroll: true, ..none },
+            "modern" => Quirks { shift_uses_vy: true, load_store_increments_i: true, ..none },
+            _ => return None
+        };
+        Some(quirks)
+    }
+
+    fn flag(&mut self, quirk: Quirk) -> &mut bool {
+        match quirk {
+            Quirk::IOverflow => &mut self.i_overflow_sets_vf,
+            Quirk::ShiftVy => &mut self.shift_uses_vy,
+            Quirk::LoadStoreI => &mut self.load_store_increments_i,
+            Quirk::VfReset => &mut self.logic_resets_vf,
+            Quirk::JumpVx => &mut self.jump_uses_vx,
+            Quirk::Clip => &mut self.clip_sprites,
+            Quirk::DisplayWait => &mut self.display_wait,
+            Quirk::HalfScroll => &mut self.half_scroll
+        }
+    }
+
+    pub fn get(&self, quirk: Quirk) -> bool {
+        let mut quirks = *self;
+        *quirks.flag(quirk)
+    }
+
+    pub fn set(&mut self, quirk: Quirk, on: bool) {
+        *self.flag(quirk) = on;
+    }
+
+    // flip a quirk, and return whether it is now on
+    pub fn toggle(&mut self, quirk: Quirk) -> bool {
+        let flag = self.flag(quirk);
+        *flag = !*flag;
+        *flag
+    }
+
+    // the names of the quirks turned on, eg. ["i-overflow"]
+    pub fn names_on(&self) -> Vec<&'static str> {
+        QUIRKS.iter().filter(|quirk| self.get(**quirk)).map(|quirk| quirk.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_named() {
+        for name in PRESETS.iter() {
+            assert!(Quirks::preset(name).is_some(), "{}", name);
+        }
+        assert_eq!(Quirks::preset("cowgod"), Some(Quirks::default()));
+        assert_eq!(Quirks::preset("schip").unwrap().names_on(), vec!["jump-vx", "clip", "half-scroll"]);
+        assert_eq!(Quirks::preset("amiga"), None);
+    }
+}
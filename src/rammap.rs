@@ -0,0 +1,96 @@
+// all of RAM as a picture, a pixel per byte in rows of 64, to watch a
+// program move data around and modify itself; brighter is a higher value,
+// and what the CPU is using right now is tinted:
+//
+//   red      the instruction at PC
+//   green    the byte at I
+//   blue     the calls on the call stack
+//   yellow   sprites drawn to the display (with config.record_sprites)
+
+use crate::cpu::CPU;
+use crate::sprites::sprite_len;
+
+pub const MAP_WIDTH: usize = 64;
+pub const MAP_HEIGHT: usize = 64;
+
+const RED: u32 = 0xff0000;
+const GREEN: u32 = 0x00ff00;
+const BLUE: u32 = 0x0000ff;
+const YELLOW: u32 = 0xffff00;
+
+// the grey for a byte
+fn grey(value: u8) -> u32 {
+    let v = value as u32;
+    v << 16 | v << 8 | v
+}
+
+// a byte's grey, turned towards colour: the colour's channels from a
+// quarter up, the others from nothing to a quarter, so a zero byte still
+// shows where it is
+fn tint(value: u8, colour: u32) -> u32 {
+    let v = value as u32;
+    [16, 8, 0].iter().fold(0, |px, shift| {
+        let channel = if (colour >> shift) & 0xff != 0 { 0x40 + v * 3 / 4 } else { v / 4 };
+        px | channel << shift
+    })
+}
+
+// MAP_WIDTH * MAP_HEIGHT pixels, row by row, from address 0
+pub fn ram_map(cpu: &CPU) -> Vec<u32> {
+    let ram = cpu.ram();
+    let mut colours: Vec<Option<u32>> = vec![None; MAP_WIDTH * MAP_HEIGHT];
+    let mut mark = |addr: usize, colour: u32| {
+        if let Some(c) = colours.get_mut(addr) {
+            *c = Some(colour);
+        }
+    };
+    // the least interesting first, so the rest show through
+    for &(addr, n) in cpu.sprites_drawn() {
+        (addr..addr + sprite_len(n)).for_each(|a| mark(a, YELLOW));
+    }
+    for &addr in cpu.stack() {
+        mark(addr, BLUE);
+        mark(addr + 1, BLUE);
+    }
+    mark(cpu.i(), GREEN);
+    mark(cpu.pc(), RED);
+    mark(cpu.pc() + 1, RED);
+
+    colours.iter().enumerate().map(|(addr, colour)| {
+        let value = ram.get(addr).copied().unwrap_or(0);
+        match colour {
+            Some(colour) => tint(value, *colour),
+            None => grey(value)
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn highlights_what_the_cpu_uses() {
+        let config = Config { record_sprites: true, ..Config::default() };
+        let mut cpu = CPU::new(Box::new(Headless::new(1)), Box::new(Silence), config);
+        // call 206; 206: ld i, 300; drw v0, v0, 1; jp 20a
+        cpu.load_rom(&[0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0xa3, 0x00, 0xd0, 0x01, 0x12, 0x0a]).unwrap();
+        cpu.run_loop().unwrap();
+        let map = ram_map(&cpu);
+        assert_eq!(map.len(), 4096);
+        assert_eq!(map[0x20a], tint(0x12, RED));
+        assert_eq!(map[0x20b], tint(0x0a, RED));
+        assert_eq!(map[0x200], tint(0x22, BLUE));
+        assert_eq!(map[0x300], tint(0x00, GREEN));
+        // the first byte of the font, 0xf0
+        assert_eq!(map[0x000], grey(0xf0));
+        assert_eq!(map[0x202], grey(0x00));
+        assert_eq!(tint(0xff, YELLOW), 0xffff3f);
+        assert_eq!(tint(0x00, BLUE), 0x000040);
+    }
+}
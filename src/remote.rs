@@ -0,0 +1,223 @@
+// remote control over a WebSocket (with the `websocket` feature): clients
+// send the commands from the control module as text messages, and get the
+// machine state and screen as JSON whenever they change, at most 60 times
+// a second
+
+use std::{
+    io::ErrorKind,
+    net::{
+        SocketAddr,
+        TcpListener,
+        TcpStream
+    },
+    sync::{
+        mpsc::{
+            self,
+            Receiver,
+            Sender
+        },
+        Arc,
+        Mutex
+    },
+    thread,
+    time::Duration
+};
+
+use tungstenite::{
+    Error,
+    Message
+};
+
+use crate::control::{
+    apply,
+    cheats_json,
+    error_json,
+    frame_json,
+    parse_command,
+    state_json,
+    Command
+};
+use crate::cpu::CPU;
+use crate::hooks::Hooks;
+
+// how long a client's connection waits for a command before checking for
+// updates to send
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// frames (at 480 Hz) between updates
+const FRAMES_PER_UPDATE: u32 = 8;
+
+pub struct WebSocketControl {
+    addr: SocketAddr,
+    commands: Receiver<Command>,
+    // one channel per connected client, for the updates it should get
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    known_clients: usize,
+    frames: u32,
+    last_state: String,
+    last_cheats: String,
+    last_frame: Option<u64>
+}
+
+impl WebSocketControl {
+    // start accepting connections on addr, eg. 127.0.0.1:8480
+    pub fn listen(addr: &str) -> Result<WebSocketControl, String> {
+        let listener = TcpListener::bind(addr).map_err(|why| format!("Could not listen on {}: {}", addr, why))?;
+        let addr = listener.local_addr().map_err(|why| why.to_string())?;
+        let (commands_in, commands) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (updates_in, updates) = mpsc::channel();
+                if let Ok(mut clients) = accepted.lock() {
+                    clients.push(updates_in);
+                }
+                let commands_in = commands_in.clone();
+                thread::spawn(move || serve(stream, commands_in, updates));
+            }
+        });
+
+        Ok(WebSocketControl {
+            addr,
+            commands,
+            clients,
+            known_clients: 0,
+            frames: 0,
+            last_state: String::new(),
+            last_cheats: String::new(),
+            last_frame: None
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn broadcast(&mut self, message: &str) {
+        if let Ok(mut clients) = self.clients.lock() {
+            // a client whose connection ended has dropped its receiver
+            clients.retain(|c| c.send(String::from(message)).is_ok());
+            self.known_clients = clients.len();
+        }
+    }
+}
+
+// talk to one client until it goes away
+fn serve(stream: TcpStream, commands: Sender<Command>, updates: Receiver<String>) {
+    let mut ws = match tungstenite::accept(stream) {
+        Ok(ws) => ws,
+        Err(_) => return
+    };
+    if ws.get_ref().set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+    loop {
+        match ws.read() {
+            Ok(Message::Text(text)) => match parse_command(&text) {
+                Ok(command) => {
+                    if commands.send(command).is_err() {
+                        return;
+                    }
+                },
+                Err(err) => {
+                    if ws.send(Message::Text(error_json(&err))).is_err() {
+                        return;
+                    }
+                }
+            },
+            Ok(Message::Close(_)) => return,
+            Ok(_) => (),
+            Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => (),
+            Err(_) => return
+        }
+        for update in updates.try_iter() {
+            if ws.send(Message::Text(update)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Hooks for WebSocketControl {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let commands: Vec<Command> = self.commands.try_iter().collect();
+        for command in commands {
+            if let Err(err) = apply(command, cpu) {
+                self.broadcast(&error_json(&err));
+            }
+        }
+
+        self.frames += 1;
+        if !self.frames.is_multiple_of(FRAMES_PER_UPDATE) {
+            return Ok(());
+        }
+        // someone new connected, so send them everything
+        let clients = self.clients.lock().map(|c| c.len()).unwrap_or(0);
+        if clients != self.known_clients {
+            self.last_state.clear();
+            self.last_cheats.clear();
+            self.last_frame = None;
+        }
+        let state = state_json(cpu);
+        if state != self.last_state {
+            self.broadcast(&state);
+            self.last_state = state;
+        }
+        let cheats = cheats_json(cpu);
+        if cheats != self.last_cheats {
+            self.broadcast(&cheats);
+            self.last_cheats = cheats;
+        }
+        let hash = cpu.framebuffer().hash();
+        if self.last_frame != Some(hash) {
+            self.broadcast(&frame_json(cpu));
+            self.last_frame = Some(hash);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn clients_control_the_cpu_and_get_its_state() {
+        let mut control = WebSocketControl::listen("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", control.addr());
+        let client = thread::spawn(move || {
+            let (mut ws, _) = tungstenite::connect(url).unwrap();
+            ws.send(Message::Text(String::from("break 2a4"))).unwrap();
+            ws.send(Message::Text(String::from("bogus"))).unwrap();
+            let mut seen = Vec::new();
+            while seen.len() < 4 {
+                if let Message::Text(text) = ws.read().unwrap() {
+                    seen.push(text);
+                }
+            }
+            seen
+        });
+
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        let started = Instant::now();
+        while !client.is_finished() && started.elapsed() < Duration::from_secs(10) {
+            control.on_frame(&mut cpu).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        let seen = client.join().unwrap();
+        assert!(cpu.breakpoints().contains(&0x2a4));
+        assert!(seen.iter().any(|m| m.starts_with("{\"type\": \"error\"")));
+        assert!(seen.iter().any(|m| m.starts_with("{\"type\": \"frame\"")));
+        assert!(seen.iter().any(|m| m.starts_with("{\"type\": \"state\"")));
+        assert!(seen.iter().any(|m| m.starts_with("{\"type\": \"cheats\"")));
+    }
+}
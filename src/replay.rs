@@ -0,0 +1,507 @@
+// recordings of whole sessions that can be played back exactly: a .c8r
+// file holds what's needed to run the same ROM the same way, and the keys
+// pressed on every frame. it's text, a setting per line, then the input in
+// the same form as an input script:
+//
+//   chip8-replay 1
+//   rom 5e1c0a8fb6e3c251 games/pong.ch8
+//   patch fixes.ips
+//   seed 00000000deadbeef
+//   platform chip8
+//   quirk-i-overflow off
+//   font-layout spaced
+//   key-sampling frame
+//   speed 72
+//   memory wrap
+//   unknown-opcode warn
+//   font-protection off
+//   truncate-rom off
+//   stub-native off
+//   frames 5321
+//   screen 3f0a9c1d22b7e845
+//   input
+//   120 a down
+//   135 a up
+//
+// with a quirk-<name> line for every quirk. the ROM is named by the hash
+// of its contents (after patching) and where it was loaded from. while
+// recording and playing back, time is counted in frames rather than read
+// from the clock, and the seed for Cxkk is fixed, so the machine ends up
+// in the same state; `screen` is the hash of the last frame, to check
+// that it did
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::Duration
+};
+
+use crate::config::{
+    Config,
+    FontLayout,
+    KeySampling,
+    MemoryPolicy,
+    Platform,
+    UnknownOpcodePolicy,
+    WriteProtection
+};
+use crate::cpu::CPU;
+use crate::framebuffer::Framebuffer;
+use crate::frontend::{
+    Display,
+    Frontend,
+    Hotkey,
+    Input,
+    Status,
+    REFRESH_INTERVAL
+};
+use crate::headless::{
+    held_keys,
+    parse_input_script,
+    KeyEvent,
+    SECOND_KEYPAD
+};
+use crate::hooks::Hooks;
+use crate::quirks::{
+    Quirk,
+    QUIRKS
+};
+use crate::util::fnv1a;
+
+const GREETING: &str = "chip8-replay 1";
+
+// hundredths of a second between frames of an exported animation; browsers
+// show anything shorter at 10
+const CAPTURE_INTERVAL: u64 = 2;
+
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub rom_hash: u64,
+    pub rom_path: String,
+    // applied to the ROM in order, before hashing
+    pub patches: Vec<String>,
+    // the settings that change how the ROM runs, including the seed
+    pub config: Config,
+    pub frames: u32,
+    // hash of the screen after the last frame
+    pub screen: Option<u64>,
+    pub input: Vec<KeyEvent>
+}
+
+pub(crate) fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+pub(crate) fn parse_on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None
+    }
+}
+
+impl Replay {
+    // an empty recording of rom, loaded from rom_path with patches, to be
+    // run with config; config needs a seed so that Cxkk can be repeated
+    pub fn new(rom: &[u8], rom_path: &str, patches: &[String], config: &Config) -> Replay {
+        let config = Config {
+            platform: config.platform,
+            quirks: config.quirks,
+            font_protection: config.font_protection,
+            unknown_opcode: config.unknown_opcode,
+            memory: config.memory,
+            font_layout: config.font_layout,
+            key_sampling: config.key_sampling,
+            speed: config.speed,
+            truncate_rom: config.truncate_rom,
+            stub_native_calls: config.stub_native_calls,
+            random_seed: Some(config.random_seed.unwrap_or(0)),
+            ..Config::default()
+        };
+        Replay {
+            rom_hash: fnv1a(rom.iter().copied()),
+            rom_path: String::from(rom_path),
+            patches: patches.to_vec(),
+            config,
+            frames: 0,
+            screen: None,
+            input: Vec::new()
+        }
+    }
+
+    // an error if rom isn't the one that was recorded
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), String> {
+        if fnv1a(rom.iter().copied()) != self.rom_hash {
+            return Err(format!("the ROM is not the one that was recorded (from {})", self.rom_path));
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let c = &self.config;
+        let mut text = format!("{}\nrom {:016x} {}\n", GREETING, self.rom_hash, self.rom_path);
+        for patch in &self.patches {
+            text += &format!("patch {}\n", patch);
+        }
+        text += &format!("seed {:016x}\n", c.random_seed.unwrap_or(0));
+        text += &format!("platform {}\n", c.platform.name());
+        for quirk in QUIRKS.iter() {
+            text += &format!("quirk-{} {}\n", quirk.name(), on_off(c.quirks.get(*quirk)));
+        }
+        text += &format!("font-layout {}\n", c.font_layout.name());
+        text += &format!("key-sampling {}\n", c.key_sampling.name());
+        text += &format!("speed {}\n", c.speed);
+        text += &format!("memory {}\n", c.memory.name());
+        text += &format!("unknown-opcode {}\n", c.unknown_opcode.name());
+        text += &format!("font-protection {}\n", c.font_protection.name());
+        text += &format!("truncate-rom {}\n", on_off(c.truncate_rom));
+        text += &format!("stub-native {}\n", on_off(c.stub_native_calls));
+        text += &format!("frames {}\n", self.frames);
+        if let Some(screen) = self.screen {
+            text += &format!("screen {:016x}\n", screen);
+        }
+        text += "input\n";
+        for e in &self.input {
+            text += &format!("{} {:x} {}\n", e.frame, e.key, if e.down { "down" } else { "up" });
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim_end) != Some(GREETING) {
+            return Err(format!("not a replay file: it should start with `{}`", GREETING));
+        }
+        let mut replay = Replay::new(&[], "", &[], &Config::default());
+        let mut rom = false;
+        for (n, line) in lines.by_ref().enumerate() {
+            let line = line.trim();
+            if line == "input" {
+                break;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            let hex = || u64::from_str_radix(value, 16).ok();
+            let ok = match name {
+                "rom" => {
+                    let (hash, path) = value.split_once(' ').unwrap_or((value, ""));
+                    replay.rom_path = String::from(path.trim());
+                    rom = true;
+                    u64::from_str_radix(hash, 16).map(|h| replay.rom_hash = h).is_ok()
+                },
+                "patch" => {
+                    replay.patches.push(String::from(value));
+                    true
+                },
+                "seed" => hex().map(|s| replay.config.random_seed = Some(s)).is_some(),
+                "platform" => Platform::from_name(value).map(|p| replay.config.platform = p).is_some(),
+                _ if name.starts_with("quirk-") => match (Quirk::from_name(&name["quirk-".len()..]), parse_on_off(value)) {
+                    (Some(quirk), Some(on)) => {
+                        replay.config.quirks.set(quirk, on);
+                        true
+                    },
+                    _ => false
+                },
+                "font-layout" => FontLayout::from_name(value).map(|l| replay.config.font_layout = l).is_some(),
+                "key-sampling" => KeySampling::from_name(value).map(|k| replay.config.key_sampling = k).is_some(),
+                "speed" => value.parse().ok().filter(|s| *s > 0).map(|s| replay.config.speed = s).is_some(),
+                "memory" => MemoryPolicy::from_name(value).map(|m| replay.config.memory = m).is_some(),
+                "unknown-opcode" => UnknownOpcodePolicy::from_name(value).map(|u| replay.config.unknown_opcode = u).is_some(),
+                "font-protection" => WriteProtection::from_name(value).map(|w| replay.config.font_protection = w).is_some(),
+                "truncate-rom" => parse_on_off(value).map(|on| replay.config.truncate_rom = on).is_some(),
+                "stub-native" => parse_on_off(value).map(|on| replay.config.stub_native_calls = on).is_some(),
+                "frames" => value.parse().map(|f| replay.frames = f).is_ok(),
+                "screen" => hex().map(|s| replay.screen = Some(s)).is_some(),
+                _ => false
+            };
+            if !ok {
+                return Err(format!("line {}: unexpected `{}`", n + 2, line));
+            }
+        }
+        if !rom {
+            return Err(String::from("the replay doesn't say which ROM it is for"));
+        }
+        let input: Vec<&str> = lines.collect();
+        replay.input = parse_input_script(&input.join("\n")).map_err(|err| format!("in the input, {}", err))?;
+        Ok(replay)
+    }
+}
+
+// a frontend that notes the keys held on every frame of another into a
+// replay, and counts time in frames so the replay can be repeated
+pub struct Recorder {
+    inner: Box<dyn Frontend>,
+    replay: Rc<RefCell<Replay>>,
+    keys: [bool; 16],
+    second: [bool; 16]
+}
+
+impl Recorder {
+    // record into replay, which can be saved once the CPU stops
+    pub fn new(inner: Box<dyn Frontend>, replay: Rc<RefCell<Replay>>) -> Recorder {
+        Recorder { inner, replay, keys: [false; 16], second: [false; 16] }
+    }
+}
+
+impl Display for Recorder {
+    fn refresh(&mut self, framebuffer: &Framebuffer) -> Result<(), String> {
+        self.inner.refresh(framebuffer)?;
+        let mut replay = self.replay.borrow_mut();
+        replay.frames += 1;
+        replay.screen = Some(framebuffer.hash());
+        // keys only change between frames, so playing back sees the same
+        let keys = self.inner.handle_key_events();
+        let second = self.inner.second_keypad();
+        let pads = [(0, &keys, &self.keys), (SECOND_KEYPAD, &second, &self.second)];
+        for (first, now, before) in pads {
+            for (key, (&now, &before)) in now.iter().zip(before).enumerate() {
+                if now != before {
+                    let frame = replay.frames;
+                    replay.input.push(KeyEvent { frame, key: first + key, down: now });
+                }
+            }
+        }
+        self.keys = keys;
+        self.second = second;
+        Ok(())
+    }
+
+    fn elapsed(&self) -> Duration {
+        REFRESH_INTERVAL * self.replay.borrow().frames
+    }
+
+    fn set_overlay(&mut self, lines: &[String]) {
+        self.inner.set_overlay(lines);
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.inner.set_status(Status { recording: true, ..status });
+    }
+
+    fn next_palette(&mut self) {
+        self.inner.next_palette();
+    }
+}
+
+impl Input for Recorder {
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn handle_key_events(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    fn second_keypad(&self) -> [bool; 16] {
+        self.second
+    }
+
+    // the pause menu could pause, reset or rewind the machine without
+    // that being recorded
+    fn hotkeys(&self) -> Vec<Hotkey> {
+        self.inner.hotkeys().into_iter().filter(|k| *k == Hotkey::DumpMemory).collect()
+    }
+}
+
+// a frontend that shows frames on another, but presses keys as a replay
+// did, and stops when the replay ends
+pub struct Playback {
+    inner: Box<dyn Frontend>,
+    input: Vec<KeyEvent>,
+    frames: u32,
+    frames_shown: u32
+}
+
+impl Playback {
+    pub fn new(inner: Box<dyn Frontend>, replay: &Replay) -> Playback {
+        Playback { inner, input: replay.input.clone(), frames: replay.frames, frames_shown: 0 }
+    }
+}
+
+impl Display for Playback {
+    fn refresh(&mut self, framebuffer: &Framebuffer) -> Result<(), String> {
+        self.inner.refresh(framebuffer)?;
+        self.frames_shown += 1;
+        Ok(())
+    }
+
+    fn elapsed(&self) -> Duration {
+        REFRESH_INTERVAL * self.frames_shown
+    }
+
+    fn set_overlay(&mut self, lines: &[String]) {
+        self.inner.set_overlay(lines);
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.inner.set_status(status);
+    }
+
+    fn next_palette(&mut self) {
+        self.inner.next_palette();
+    }
+}
+
+impl Input for Playback {
+    fn is_open(&self) -> bool {
+        self.frames_shown < self.frames && self.inner.is_open()
+    }
+
+    fn handle_key_events(&self) -> [bool; 16] {
+        held_keys(&self.input, self.frames_shown, false)
+    }
+
+    fn second_keypad(&self) -> [bool; 16] {
+        held_keys(&self.input, self.frames_shown, true)
+    }
+}
+
+// screens to make an animation of, each with its width, as the ROM may
+// switch resolution, and how long it's shown in hundredths of a second
+pub type Frames = Rc<RefCell<Vec<(Vec<bool>, usize, u16)>>>;
+
+// hooks that take a picture of the screen every CAPTURE_INTERVAL, keeping
+// only the ones that changed
+pub struct Capture {
+    frames: Frames,
+    frames_shown: u64,
+    captured_until: u64
+}
+
+impl Capture {
+    pub fn new(frames: Frames) -> Capture {
+        Capture { frames, frames_shown: 0, captured_until: 0 }
+    }
+}
+
+impl Hooks for Capture {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames_shown += 1;
+        let now = self.frames_shown * REFRESH_INTERVAL.as_micros() as u64 / 10_000;
+        if now < self.captured_until + CAPTURE_INTERVAL {
+            return Ok(());
+        }
+        let (pixels, width) = (cpu.framebuffer().pixels(), cpu.framebuffer().width());
+        let mut frames = self.frames.borrow_mut();
+        match frames.last_mut() {
+            Some((last, last_width, delay)) if last.as_slice() == pixels && *last_width == width => {
+                *delay += (now - self.captured_until) as u16;
+            },
+            _ => frames.push((pixels.to_vec(), width, (now - self.captured_until) as u16))
+        }
+        self.captured_until = now;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    // waits for a key, then puts a random number in V1 and draws it
+    const ROM: [u8; 10] = [
+        0xf0, 0x0a, // V0 = key
+        0xc1, 0xff, // V1 = random
+        0xf1, 0x29, // I = sprite for the digit in V1
+        0xd0, 0x05, // draw it at (V0, V0)
+        0x12, 0x08  // loop forever
+    ];
+
+    fn record(config: &Config, script: Vec<KeyEvent>) -> (Replay, CPU) {
+        let replay = Rc::new(RefCell::new(Replay::new(&ROM, "waits.ch8", &[], config)));
+        let recorder = Recorder::new(Box::new(Headless::with_script(40, script)), replay.clone());
+        let mut cpu = CPU::new(Box::new(recorder), Box::new(Silence), replay.borrow().config);
+        cpu.load_rom(&ROM).unwrap();
+        cpu.run_loop().unwrap();
+        let replay = replay.borrow().clone();
+        (replay, cpu)
+    }
+
+    #[test]
+    fn plays_back_what_was_recorded() {
+        let config = Config { random_seed: Some(7), ..Config::default() };
+        let script = parse_input_script("10 5 down\n14 5 up\n").unwrap();
+        let (replay, recorded) = record(&config, script);
+        assert_eq!(replay.frames, 40);
+        assert_eq!(replay.input, parse_input_script("10 5 down\n14 5 up\n").unwrap());
+
+        let replay = Replay::parse(&replay.to_text()).unwrap();
+        replay.check_rom(&ROM).unwrap();
+        assert!(replay.check_rom(&ROM[1..]).is_err());
+        let playback = Playback::new(Box::new(Headless::new(usize::MAX)), &replay);
+        let mut cpu = CPU::new(Box::new(playback), Box::new(Silence), replay.config);
+        cpu.load_rom(&ROM).unwrap();
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.v(), recorded.v());
+        assert_eq!(Some(cpu.framebuffer().hash()), replay.screen);
+    }
+
+    #[test]
+    fn records_the_second_keypad() {
+        // on CHIP-8X, waits for key 5 on the second keypad, then sets V1
+        let rom = [
+            0x60, 0x05, // 300: V0 = 5
+            0xe0, 0xf2, // 302: skip if second keypad key V0 is down
+            0x13, 0x02, // 304: jump back to 302
+            0x61, 0x01, // 306: V1 = 1
+            0x13, 0x08  // 308: loop forever
+        ];
+        let config = Config { platform: Platform::Chip8X, random_seed: Some(1), ..Config::default() };
+        let replay = Rc::new(RefCell::new(Replay::new(&rom, "pad.ch8", &[], &config)));
+        let script = parse_input_script("5 15 down\n").unwrap();
+        let recorder = Recorder::new(Box::new(Headless::with_script(20, script)), replay.clone());
+        let mut cpu = CPU::new(Box::new(recorder), Box::new(Silence), config);
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.v()[1], 1);
+        assert_eq!(replay.borrow().input, parse_input_script("5 15 down\n").unwrap());
+
+        let replay = Replay::parse(&replay.borrow().to_text()).unwrap();
+        let playback = Playback::new(Box::new(Headless::new(usize::MAX)), &replay);
+        let mut cpu = CPU::new(Box::new(playback), Box::new(Silence), replay.config);
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_loop().unwrap();
+        assert_eq!(cpu.v()[1], 1);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut config = Config { random_seed: Some(0xdead_beef), ..Config::default() };
+        config.quirks.i_overflow_sets_vf = true;
+        config.key_sampling = KeySampling::Instruction;
+        config.platform = Platform::Chip8X;
+        config.speed = 15;
+        config.stub_native_calls = true;
+        let mut replay = Replay::new(&ROM, "games/pong 2.ch8", &[String::from("fix.ips")], &config);
+        replay.frames = 3;
+        replay.screen = Some(9);
+        replay.input = parse_input_script("1 f down").unwrap();
+        let text = replay.to_text();
+        assert!(text.contains("\nstub-native on\n"));
+        assert_eq!(Replay::parse(&text).unwrap().to_text(), text);
+        // replays from before it was recorded didn't stub them
+        let old = Replay::parse(&text.replace("stub-native on\n", "")).unwrap();
+        assert!(!old.config.stub_native_calls);
+        assert!(Replay::parse("chip8-replay 1\nframes 3\ninput\n").is_err());
+        assert!(Replay::parse("chip8-replay 1\nrom 00 x\nspeed 0\ninput\n").is_err());
+    }
+
+    #[test]
+    fn captures_changed_screens() {
+        let frames = Frames::default();
+        let mut capture = Capture::new(frames.clone());
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        // 2 hundredths of a second is 9.6 frames, and an unchanged screen
+        // is shown for longer rather than again
+        for _ in 0..30 {
+            capture.on_frame(&mut cpu).unwrap();
+        }
+        assert_eq!(frames.borrow().len(), 1);
+        assert_eq!(frames.borrow()[0].1, 64);
+        assert_eq!(frames.borrow()[0].2, 6);
+    }
+}
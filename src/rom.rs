@@ -0,0 +1,111 @@
+// checks on a file before we try to run it as a ROM
+
+use crate::cpu::{
+    PROGRAM_START,
+    RAM_SIZE
+};
+
+// anything shorter can't hold a single instruction
+const MIN_ROM_SIZE: usize = 2;
+
+// reject files that can't be a ROM, and return a warning for files that
+// probably aren't one (eg. Octo source or a saved web page)
+pub fn sanity_check(rom: &[u8]) -> Result<Option<String>, String> {
+    if rom.is_empty() {
+        return Err(String::from("ROM file is empty"));
+    }
+    if rom.len() < MIN_ROM_SIZE {
+        return Err(format!("ROM file is only {} byte long, too short to hold an instruction", rom.len()));
+    }
+    if !looks_like_text(rom) {
+        return Ok(None);
+    }
+
+    let text = String::from_utf8_lossy(rom);
+    let start = text.trim_start().to_lowercase();
+    if start.starts_with("<!doctype") || start.starts_with("<html") {
+        return Ok(Some(String::from(
+            "this file looks like an HTML page, not a ROM; if it was downloaded, check the link points at the raw .ch8 file"
+        )));
+    }
+    if is_octo_source(&text) {
+        return Ok(Some(String::from(
+            "this file looks like Octo source code, not a ROM; name it .8o to run it directly, or build it with `asm`"
+        )));
+    }
+    Ok(Some(String::from("this file looks like text, not a binary ROM")))
+}
+
+// ROMs are full of opcodes and sprite data, so nearly all printable
+// bytes is a strong hint that we've been given a text file
+fn looks_like_text(rom: &[u8]) -> bool {
+    let printable = rom.iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+    rom.len() >= 16 && printable * 100 >= rom.len() * 95
+}
+
+fn is_octo_source(text: &str) -> bool {
+    text.lines()
+        .map(|l| l.trim())
+        .any(|l| l.starts_with(": ") || l.starts_with(":const") || l.starts_with(":alias") || l.starts_with("i := "))
+}
+
+// the ROM without the zero bytes dumps often carry at the end; memory
+// after the ROM is zeroed when it's loaded, so this runs exactly the same
+pub fn trim(rom: &[u8]) -> &[u8] {
+    let end = rom.iter().rposition(|&b| b != 0).map_or(0, |j| j + 1);
+    &rom[..end.max(MIN_ROM_SIZE).min(rom.len())]
+}
+
+// the ROM followed by zeros up to size bytes
+pub fn pad(rom: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let available = RAM_SIZE - PROGRAM_START;
+    if size > available {
+        return Err(format!("{} bytes is more than the {} bytes available for a program", size, available));
+    }
+    if size < rom.len() {
+        return Err(format!("ROM is already {} bytes, more than {}", rom.len(), size));
+    }
+    let mut padded = rom.to_vec();
+    padded.resize(size, 0);
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_binary_rom() {
+        assert_eq!(sanity_check(&[0x00, 0xe0, 0xa2, 0x2a, 0x60, 0x0c, 0xd0, 0x1f]), Ok(None));
+    }
+
+    #[test]
+    fn rejects_tiny_files() {
+        assert!(sanity_check(&[]).is_err());
+        assert!(sanity_check(&[0x12]).is_err());
+    }
+
+    #[test]
+    fn warns_about_text() {
+        let octo = b": main\n  v0 := 5\n  loop again\n";
+        assert!(sanity_check(octo).unwrap().unwrap().contains("Octo"));
+        let html = b"<!DOCTYPE html>\n<html><body>Not Found</body></html>";
+        assert!(sanity_check(html).unwrap().unwrap().contains("HTML"));
+    }
+
+    #[test]
+    fn trims_trailing_zeros() {
+        assert_eq!(trim(&[0x60, 0x00, 0x12, 0x00, 0x00, 0x00]), [0x60, 0x00, 0x12]);
+        assert_eq!(trim(&[0x00, 0x00, 0x00]), [0x00, 0x00]);
+        assert_eq!(trim(&[0x12, 0x00]), [0x12, 0x00]);
+    }
+
+    #[test]
+    fn pads_to_size() {
+        assert_eq!(pad(&[0x12, 0x00], 4).unwrap(), [0x12, 0x00, 0x00, 0x00]);
+        assert!(pad(&[0x12, 0x00, 0x00], 2).is_err());
+        assert!(pad(&[0x12, 0x00], 4096).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+// Lua scripts that run alongside a ROM (with the `lua` feature): a script
+// defines `on_frame()` and/or `on_step()`, which the emulator calls after
+// every frame and every instruction, and uses the `emu` table to look at
+// and change the machine while they run:
+//
+//   emu.peek(addr), emu.poke(addr, value)   read/write a byte of RAM
+//   emu.reg(x), emu.set_reg(x, value)       read/write register Vx
+//   emu.pc(), emu.set_pc(addr)              the program counter
+//   emu.i(), emu.set_i(addr)                the I register
+//   emu.press(key), emu.release(key)        hold down/let go of a key 0-f
+//   emu.text(line, ...)                     show text over the screen; no lines hides it
+//   emu.pause(), emu.resume(), emu.paused() stop and start the CPU
+
+use std::cell::RefCell;
+
+use mlua::{
+    Function,
+    Lua,
+    Variadic
+};
+
+use crate::cpu::{
+    CPU,
+    RAM_SIZE
+};
+use crate::hooks::Hooks;
+
+pub struct LuaScript {
+    lua: Lua
+}
+
+impl LuaScript {
+    // run the script's top level, which should define the callbacks
+    pub fn load(source: &str, name: &str) -> Result<LuaScript, String> {
+        let lua = Lua::new();
+        lua.load(source).set_name(name).exec().map_err(|err| err.to_string())?;
+        let script = LuaScript { lua };
+        if script.callback("on_frame")?.is_none() && script.callback("on_step")?.is_none() {
+            return Err(format!("{} defines neither on_frame nor on_step", name));
+        }
+        Ok(script)
+    }
+
+    fn callback(&self, name: &str) -> Result<Option<Function>, String> {
+        self.lua.globals().get(name).map_err(|err| format!("{} is not a function: {}", name, err))
+    }
+
+    // call the named callback, with `emu` working on cpu until it returns
+    fn call(&self, name: &str, cpu: &mut CPU) -> Result<(), String> {
+        let callback = match self.callback(name)? {
+            Some(f) => f,
+            None => return Ok(())
+        };
+        let cpu = RefCell::new(cpu);
+        let lua = &self.lua;
+        lua.scope(|scope| {
+            let emu = lua.create_table()?;
+            emu.set("peek", scope.create_function(|_, addr: usize| Ok(cpu.borrow().ram()[addr % RAM_SIZE]))?)?;
+            emu.set("poke", scope.create_function(|_, (addr, value): (usize, u8)| {
+                cpu.borrow_mut().set_ram(addr, value);
+                Ok(())
+            })?)?;
+            emu.set("reg", scope.create_function(|_, x: usize| Ok(cpu.borrow().v()[x & 0xf]))?)?;
+            emu.set("set_reg", scope.create_function(|_, (x, value): (usize, u8)| {
+                cpu.borrow_mut().set_v(x, value);
+                Ok(())
+            })?)?;
+            emu.set("pc", scope.create_function(|_, ()| Ok(cpu.borrow().pc()))?)?;
+            emu.set("set_pc", scope.create_function(|_, pc: usize| {
+                cpu.borrow_mut().set_pc(pc);
+                Ok(())
+            })?)?;
+            emu.set("i", scope.create_function(|_, ()| Ok(cpu.borrow().i()))?)?;
+            emu.set("set_i", scope.create_function(|_, i: usize| {
+                cpu.borrow_mut().set_i(i);
+                Ok(())
+            })?)?;
+            emu.set("press", scope.create_function(|_, key: usize| {
+                cpu.borrow_mut().inject_key(key, true);
+                Ok(())
+            })?)?;
+            emu.set("release", scope.create_function(|_, key: usize| {
+                cpu.borrow_mut().inject_key(key, false);
+                Ok(())
+            })?)?;
+            emu.set("text", scope.create_function(|_, lines: Variadic<String>| {
+                cpu.borrow_mut().set_overlay(lines.into_iter().collect());
+                Ok(())
+            })?)?;
+            emu.set("pause", scope.create_function(|_, ()| {
+                cpu.borrow_mut().set_paused(true);
+                Ok(())
+            })?)?;
+            emu.set("resume", scope.create_function(|_, ()| {
+                cpu.borrow_mut().set_paused(false);
+                Ok(())
+            })?)?;
+            emu.set("paused", scope.create_function(|_, ()| Ok(cpu.borrow().paused()))?)?;
+            lua.globals().set("emu", emu)?;
+            callback.call::<()>(())
+        }).map_err(|err| format!("{}: {}", name, err))
+    }
+}
+
+impl Hooks for LuaScript {
+    fn on_step(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.call("on_step", cpu)
+    }
+
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.call("on_frame", cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    fn run_script(program: &[u8], frames: usize, source: &str) -> CPU {
+        let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), Config::default());
+        cpu.load_rom(program).unwrap();
+        cpu.add_hooks(Box::new(LuaScript::load(source, "test.lua").unwrap()));
+        cpu.run_loop().unwrap();
+        cpu
+    }
+
+    #[test]
+    fn scripts_see_and_change_the_machine() {
+        // 200: V0 += 1, 202: jump back
+        let cpu = run_script(&[0x70, 0x01, 0x12, 0x00], 2, "
+            function on_step()
+                if emu.reg(0) == 3 then
+                    emu.set_reg(1, emu.peek(0x201) + 1)
+                    emu.text('v0 is 3')
+                    emu.pause()
+                end
+            end
+        ");
+        assert_eq!(cpu.v()[0], 3);
+        assert_eq!(cpu.v()[1], 2);
+        assert!(cpu.paused());
+        assert_eq!(cpu.overlay(), ["v0 is 3"]);
+    }
+
+    #[test]
+    fn scripts_press_keys() {
+        // 200: wait for a key into V2, which takes a press and a release
+        let cpu = run_script(&[0xf2, 0x0a, 0x12, 0x02], 4, "
+            held = false
+            function on_frame()
+                if held then emu.release(0xb) else emu.press(0xb) end
+                held = not held
+            end
+        ");
+        assert_eq!(cpu.v()[2], 0xb);
+    }
+
+    #[test]
+    fn scripts_need_a_callback() {
+        assert!(LuaScript::load("x = 1", "empty.lua").is_err());
+        assert!(LuaScript::load("function on_frame(", "broken.lua").is_err());
+    }
+}
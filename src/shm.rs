@@ -0,0 +1,137 @@
+// publishes the screen into a file for OBS plugins and other visualizers to
+// map into memory, so they can capture it without grabbing the window;
+// put it on a RAM-backed filesystem like /dev/shm and it's shared memory.
+// the layout, all numbers little-endian u32:
+//
+//   0   magic, the bytes "CH8F"
+//   4   width in pixels
+//   8   height in pixels, both changing when the ROM switches resolution
+//   12  sequence number: odd while a frame is being written, and two more
+//       for every frame, so readers can retry if it changed under them
+//   16  the pixels, one byte each (0 off, 255 on), row by row; the file
+//       has room for the biggest screen, MegaChip's 256x192
+
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions
+    },
+    os::unix::fs::FileExt,
+    path::{
+        Path,
+        PathBuf
+    }
+};
+
+use crate::cpu::CPU;
+use crate::framebuffer::{
+    HEIGHT,
+    MEGA_HEIGHT,
+    MEGA_WIDTH,
+    WIDTH
+};
+use crate::hooks::Hooks;
+
+pub const MAGIC: &[u8; 4] = b"CH8F";
+pub const HEADER_SIZE: usize = 16;
+
+pub struct SharedFramebuffer {
+    path: PathBuf,
+    file: File,
+    sequence: u32,
+    last_frame: Option<u64>
+}
+
+impl SharedFramebuffer {
+    pub fn create(path: &Path) -> Result<SharedFramebuffer, String> {
+        let fail = |why: std::io::Error| format!("Could not create {}: {}", path.display(), why);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path).map_err(fail)?;
+        file.set_len((HEADER_SIZE + MEGA_WIDTH * MEGA_HEIGHT) as u64).map_err(fail)?;
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&(WIDTH as u32).to_le_bytes());
+        header.extend_from_slice(&(HEIGHT as u32).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        file.write_all_at(&header, 0).map_err(fail)?;
+        Ok(SharedFramebuffer { path: path.to_path_buf(), file, sequence: 0, last_frame: None })
+    }
+
+    // bump the sequence number around writing the size and pixels, so
+    // it's odd meanwhile
+    fn write(&mut self, width: usize, height: usize, pixels: &[u8]) -> std::io::Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.file.write_all_at(&self.sequence.to_le_bytes(), 12)?;
+        let mut size = (width as u32).to_le_bytes().to_vec();
+        size.extend_from_slice(&(height as u32).to_le_bytes());
+        self.file.write_all_at(&size, 4)?;
+        self.file.write_all_at(pixels, HEADER_SIZE as u64)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.file.write_all_at(&self.sequence.to_le_bytes(), 12)
+    }
+}
+
+impl Drop for SharedFramebuffer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Hooks for SharedFramebuffer {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let hash = cpu.framebuffer().hash();
+        if self.last_frame == Some(hash) {
+            return Ok(());
+        }
+        self.last_frame = Some(hash);
+
+        let framebuffer = cpu.framebuffer();
+        let pixels: Vec<u8> = framebuffer.pixels().iter().map(|&on| if on { 0xff } else { 0 }).collect();
+        self.write(framebuffer.width(), framebuffer.height(), &pixels).map_err(|why| format!("Could not write the screen to {}: {}", self.path.display(), why))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+    use crate::testsuite::run_headless;
+
+    #[test]
+    fn publishes_changed_frames() {
+        let path = env::temp_dir().join(format!("chip8-shm-test-{}", std::process::id()));
+        let mut shared = SharedFramebuffer::create(&path).unwrap();
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        shared.on_frame(&mut cpu).unwrap();
+        shared.on_frame(&mut cpu).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE + MEGA_WIDTH * MEGA_HEIGHT);
+        assert_eq!(&bytes[..12], b"CH8F\x40\x00\x00\x00\x20\x00\x00\x00");
+        // one frame written, the unchanged one skipped
+        assert_eq!(&bytes[12..16], &[2, 0, 0, 0]);
+        assert!(bytes[HEADER_SIZE..HEADER_SIZE + WIDTH * HEIGHT].iter().all(|&b| b == 0));
+
+        // draw the 0 digit at the top left
+        let mut cpu = run_headless(&[0xd0, 0x05, 0x12, 0x02], 1, Config::default());
+        shared.on_frame(&mut cpu).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[12..16], &[4, 0, 0, 0]);
+        assert_eq!(&bytes[HEADER_SIZE..HEADER_SIZE + 5], &[0xff, 0xff, 0xff, 0xff, 0]);
+
+        // switch to 128x64
+        let mut cpu = run_headless(&[0x00, 0xff, 0x12, 0x02], 1, Config::default());
+        shared.on_frame(&mut cpu).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[4..16], &[0x80, 0, 0, 0, 0x40, 0, 0, 0, 6, 0, 0, 0]);
+
+        drop(shared);
+        assert!(!path.exists());
+    }
+}
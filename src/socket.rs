@@ -0,0 +1,188 @@
+// keypad input from a local Unix socket, for test scripts and accessibility
+// tools that want to drive a game without faking OS keyboard events. each
+// line is an input script event, `<frame> <key> <down|up>`, where frame
+// counts screen updates (480 a second) since the emulator started and
+// `now` means as soon as possible; events for frames that have already
+// been shown happen right away
+
+use std::{
+    fs,
+    io::{
+        BufRead,
+        BufReader,
+        Write
+    },
+    os::unix::{
+        fs::FileTypeExt,
+        net::{
+            UnixListener,
+            UnixStream
+        }
+    },
+    path::{
+        Path,
+        PathBuf
+    },
+    sync::mpsc::{
+        self,
+        Receiver,
+        Sender
+    },
+    thread
+};
+
+use crate::cpu::CPU;
+use crate::headless::{
+    parse_input_script,
+    KeyEvent,
+    SECOND_KEYPAD
+};
+use crate::hooks::Hooks;
+
+pub struct InputSocket {
+    path: PathBuf,
+    events: Receiver<KeyEvent>,
+    // received but waiting for their frame, in order
+    pending: Vec<KeyEvent>,
+    frames: u32
+}
+
+// a single line of the protocol as an event
+pub fn parse_event(line: &str) -> Result<KeyEvent, String> {
+    let line = line.trim();
+    let script = match line.strip_prefix("now ") {
+        Some(rest) => format!("0 {}", rest),
+        None => String::from(line)
+    };
+    match parse_input_script(&script) {
+        // only the first keypad can be pressed from here
+        Ok(events) if events.len() == 1 && events[0].key < SECOND_KEYPAD => Ok(events[0]),
+        _ => Err(format!("expected `<frame|now> <key 0-f> <down|up>`, got `{}`", line))
+    }
+}
+
+impl InputSocket {
+    // start accepting connections on a socket at path; a socket left there
+    // by an earlier run is replaced, anything else is an error
+    pub fn listen(path: &Path) -> Result<InputSocket, String> {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if meta.file_type().is_socket() {
+                let _ = fs::remove_file(path);
+            }
+        }
+        let listener = UnixListener::bind(path).map_err(|why| format!("Could not listen on {}: {}", path.display(), why))?;
+        let (events_in, events) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let events_in = events_in.clone();
+                thread::spawn(move || serve(stream, events_in));
+            }
+        });
+        Ok(InputSocket { path: path.to_path_buf(), events, pending: Vec::new(), frames: 0 })
+    }
+}
+
+impl Drop for InputSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// read events from one client until it goes away, answering bad lines
+fn serve(stream: UnixStream, events: Sender<KeyEvent>) {
+    let mut replies = match stream.try_clone() {
+        Ok(s) => s,
+        Err(_) => return
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return
+        };
+        if line.trim().is_empty() || line.trim().starts_with('#') {
+            continue;
+        }
+        match parse_event(&line) {
+            Ok(event) => {
+                if events.send(event).is_err() {
+                    return;
+                }
+            },
+            Err(err) => {
+                if writeln!(replies, "error: {}", err).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Hooks for InputSocket {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames += 1;
+        self.pending.extend(self.events.try_iter());
+        let frames = self.frames;
+        // keep the order events arrived in among those that are due
+        for event in self.pending.iter().filter(|e| e.frame <= frames) {
+            cpu.inject_key(event.key, event.down);
+        }
+        self.pending.retain(|e| e.frame > frames);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        time::{
+            Duration,
+            Instant
+        }
+    };
+
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn parses_lines() {
+        assert_eq!(parse_event("now a down"), Ok(KeyEvent { frame: 0, key: 0xa, down: true }));
+        assert_eq!(parse_event("120 3 up\n"), Ok(KeyEvent { frame: 120, key: 3, down: false }));
+        assert!(parse_event("now g down").is_err());
+        assert!(parse_event("now 1a down").is_err());
+        assert!(parse_event("1 a down\n2 a up").is_err());
+    }
+
+    #[test]
+    fn presses_keys_from_the_socket() {
+        let path = env::temp_dir().join(format!("chip8-input-test-{}.sock", std::process::id()));
+        let mut socket = InputSocket::listen(&path).unwrap();
+        let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"now 5 down\n3 5 up\nbad\n").unwrap();
+        let mut reply = String::new();
+        BufReader::new(&client).read_line(&mut reply).unwrap();
+        assert!(reply.starts_with("error: "));
+
+        // the good lines were read before the bad one was answered
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while socket.pending.len() < 2 && Instant::now() < deadline {
+            socket.pending.extend(socket.events.try_iter());
+            thread::sleep(Duration::from_millis(1));
+        }
+        socket.on_frame(&mut cpu).unwrap();
+        assert!(cpu.injected_keys()[5]);
+        socket.on_frame(&mut cpu).unwrap();
+        assert!(cpu.injected_keys()[5]);
+        socket.on_frame(&mut cpu).unwrap();
+        assert!(!cpu.injected_keys()[5]);
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
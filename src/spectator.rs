@@ -0,0 +1,277 @@
+// spectating over TCP: a host streams its screen and buzzer to any number
+// of viewers, which show them but can't press keys; for teaching demos and
+// remote pair-debugging. the host sends a line for each change:
+//
+//   size <w> <h>       the screen switched resolution, eg. to 128 64; the
+//                      rows after it are that wide
+//   row <y> <pixels>   a row of the screen changed: its pixels as hex
+//                      digits, 4 to a digit, the leftmost in the top bit
+//   sound <on|off>     the buzzer started or stopped
+//
+// and a viewer that connects gets the size, every row and the buzzer first
+
+use std::{
+    io::{
+        BufRead,
+        BufReader,
+        Write
+    },
+    net::{
+        SocketAddr,
+        TcpListener,
+        TcpStream
+    },
+    sync::{
+        mpsc::{
+            self,
+            Sender,
+            TryRecvError
+        },
+        Arc,
+        Mutex
+    },
+    thread
+};
+
+use crate::cpu::CPU;
+use crate::framebuffer::{
+    Framebuffer,
+    Resolution,
+    MEGA_HEIGHT
+};
+use crate::frontend::{
+    Buzzer,
+    Frontend
+};
+use crate::hooks::Hooks;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Update {
+    Size(Resolution),
+    // a row's pixels, leftmost first
+    Row(usize, Vec<bool>),
+    Sound(bool)
+}
+
+pub fn parse_update(line: &str) -> Result<Update, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let update = match fields.as_slice() {
+        ["size", width, height] => match (width.parse::<usize>(), height.parse::<usize>()) {
+            (Ok(width), Ok(height)) => Resolution::from_size(width, height).map(Update::Size),
+            _ => None
+        },
+        ["row", y, pixels] => {
+            let digits: Option<Vec<u32>> = pixels.chars().map(|c| c.to_digit(16)).collect();
+            match (y.parse::<usize>(), digits) {
+                (Ok(y), Some(digits)) if y < MEGA_HEIGHT => {
+                    Some(Update::Row(y, digits.iter().flat_map(|d| (0..4).map(move |j| d & (8 >> j) != 0)).collect()))
+                },
+                _ => None
+            }
+        },
+        ["sound", "on"] => Some(Update::Sound(true)),
+        ["sound", "off"] => Some(Update::Sound(false)),
+        _ => None
+    };
+    update.ok_or_else(|| format!("unexpected update from the host: `{}`", line))
+}
+
+// row y of the screen as hex, a digit for every 4 pixels, the leftmost
+// pixel in the top bit
+fn row_hex(framebuffer: &Framebuffer, y: usize) -> String {
+    let width = framebuffer.width();
+    framebuffer.pixels()[y * width..(y + 1) * width]
+        .chunks(4)
+        .map(|px| px.iter().fold(0, |bits, &on| (bits << 1) | on as u32))
+        .map(|bits| char::from_digit(bits, 16).unwrap_or('0'))
+        .collect()
+}
+
+pub struct SpectatorServer {
+    addr: SocketAddr,
+    // one channel per connected viewer, for the updates it should get
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    known_clients: usize,
+    // what the viewers were last sent, if anything
+    resolution: Option<Resolution>,
+    rows: Option<Vec<String>>,
+    sound: Option<bool>
+}
+
+impl SpectatorServer {
+    // start accepting viewers on addr, eg. 0.0.0.0:8483
+    pub fn listen(addr: &str) -> Result<SpectatorServer, String> {
+        let listener = TcpListener::bind(addr).map_err(|why| format!("Could not listen on {}: {}", addr, why))?;
+        let addr = listener.local_addr().map_err(|why| why.to_string())?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let (updates_in, updates) = mpsc::channel::<String>();
+                if let Ok(mut clients) = accepted.lock() {
+                    clients.push(updates_in);
+                }
+                // a slow viewer holds up its own thread, not the emulator
+                thread::spawn(move || {
+                    let _ = stream.set_nodelay(true);
+                    for update in updates {
+                        if stream.write_all(update.as_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(SpectatorServer { addr, clients, known_clients: 0, resolution: None, rows: None, sound: None })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Hooks for SpectatorServer {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let mut clients = match self.clients.lock() {
+            Ok(clients) => clients,
+            Err(_) => return Ok(())
+        };
+        // someone new is watching, so send everyone everything
+        if clients.len() != self.known_clients {
+            self.resolution = None;
+            self.sound = None;
+        }
+
+        let mut message = String::new();
+        let framebuffer = cpu.framebuffer();
+        let resolution = framebuffer.resolution();
+        if self.resolution != Some(resolution) {
+            let (width, height) = resolution.size();
+            message.push_str(&format!("size {} {}\n", width, height));
+            self.resolution = Some(resolution);
+            self.rows = None;
+        }
+        let rows: Vec<String> = (0..framebuffer.height()).map(|y| row_hex(framebuffer, y)).collect();
+        for (y, bits) in rows.iter().enumerate() {
+            if self.rows.as_ref().map(|r| &r[y]) != Some(bits) {
+                message.push_str(&format!("row {} {}\n", y, bits));
+            }
+        }
+        self.rows = Some(rows);
+        let sound = cpu.sound_timer() > 0;
+        if self.sound != Some(sound) {
+            message.push_str(if sound { "sound on\n" } else { "sound off\n" });
+            self.sound = Some(sound);
+        }
+
+        if !message.is_empty() {
+            // a viewer whose connection ended has dropped its receiver
+            clients.retain(|c| c.send(message.clone()).is_ok());
+        }
+        self.known_clients = clients.len();
+        Ok(())
+    }
+}
+
+// show what the host at addr streams until the window is closed or the
+// host goes away
+pub fn watch(addr: &str, win: &mut dyn Frontend, audio: &dyn Buzzer) -> Result<(), String> {
+    let stream = TcpStream::connect(addr).map_err(|why| format!("Could not connect to {}: {}", addr, why))?;
+    let (updates_in, updates) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let update = match line {
+                Ok(line) => parse_update(&line),
+                Err(why) => Err(why.to_string())
+            };
+            let failed = update.is_err();
+            if updates_in.send(update).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    let mut framebuffer = Framebuffer::new();
+    while win.is_open() {
+        loop {
+            match updates.try_recv() {
+                Ok(Ok(Update::Size(resolution))) => framebuffer.set_resolution(resolution),
+                Ok(Ok(Update::Row(y, pixels))) => {
+                    for (x, on) in pixels.into_iter().take(framebuffer.width()).enumerate() {
+                        framebuffer.set(x, y, on);
+                    }
+                },
+                Ok(Ok(Update::Sound(true))) => audio.play(),
+                Ok(Ok(Update::Sound(false))) => audio.pause(),
+                Ok(Err(err)) => return Err(err),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    audio.pause();
+                    return Err(String::from("The host stopped streaming"));
+                }
+            }
+        }
+        win.refresh(&framebuffer)?;
+        framebuffer.mark_clean();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::framebuffer::HEIGHT;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+    use crate::testsuite::run_headless;
+
+    #[test]
+    fn parses_updates() {
+        let pixels: Vec<bool> = (0..64).map(|x| x < 4 || x == 63).collect();
+        assert_eq!(parse_update("row 3 f000000000000001"), Ok(Update::Row(3, pixels)));
+        assert_eq!(parse_update("size 128 64"), Ok(Update::Size(Resolution::High)));
+        assert_eq!(parse_update("sound on"), Ok(Update::Sound(true)));
+        assert!(parse_update("row 192 0").is_err());
+        assert!(parse_update("row 1 0g").is_err());
+        assert!(parse_update("size 64 48").is_err());
+        assert!(parse_update("hello").is_err());
+    }
+
+    #[test]
+    fn viewers_see_the_host_screen() {
+        let mut server = SpectatorServer::listen("127.0.0.1:0").unwrap();
+        let viewer = TcpStream::connect(server.addr()).unwrap();
+        let mut lines = BufReader::new(viewer).lines();
+
+        // draw the 0 digit at the top left
+        let mut cpu = run_headless(&[0xd0, 0x05, 0x12, 0x02], 1, Config::default());
+        // wait for the server to have seen the viewer
+        while server.clients.lock().unwrap().is_empty() {
+            thread::yield_now();
+        }
+        server.on_frame(&mut cpu).unwrap();
+        let first: Vec<String> = lines.by_ref().take(HEIGHT + 2).map(Result::unwrap).collect();
+        assert_eq!(first[0], "size 64 32");
+        assert_eq!(first[1], "row 0 f000000000000000");
+        assert_eq!(first[2], "row 1 9000000000000000");
+        assert_eq!(first[6], "row 5 0000000000000000");
+        assert_eq!(first[HEIGHT + 1], "sound off");
+
+        // after that, only changes
+        let mut blank = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+        server.on_frame(&mut blank).unwrap();
+        let changed: Vec<String> = lines.take(5).map(Result::unwrap).collect();
+        assert_eq!(changed, [
+            "row 0 0000000000000000",
+            "row 1 0000000000000000",
+            "row 2 0000000000000000",
+            "row 3 0000000000000000",
+            "row 4 0000000000000000"
+        ]);
+    }
+}
@@ -0,0 +1,109 @@
+// what's shown for a second before the ROM starts, like the boot screens
+// of real interpreters: a tiny CHIP-8 program of its own, drawing "C8" and
+// our version in the built-in font, after which the ROM is swapped in and
+// the machine reset
+
+use crate::cpu::CPU;
+use crate::framebuffer::WIDTH;
+use crate::hooks::Hooks;
+
+// how long the splash stays up, in frames (a second)
+pub const SPLASH_FRAMES: u32 = 480;
+
+// pixels from one character to the next, the font being 4 wide
+const ADVANCE: usize = 5;
+// where the lines of text go
+const TITLE_Y: u8 = 8;
+const VERSION_Y: u8 = 18;
+// the splash's own sprite for a full stop, right after the first jump
+const DOT_ADDR: u16 = 0x202;
+
+// draws text centred at y: hex digits from the font, full stops from
+// DOT_ADDR, anything else left as a gap
+fn draw_line(ops: &mut Vec<u16>, text: &str, y: u8) {
+    let width = text.chars().count() * ADVANCE - 1;
+    let left = (WIDTH.saturating_sub(width) / 2) as u16;
+    ops.push(0x6200 | y as u16);
+    for (n, c) in text.chars().enumerate() {
+        let x = left + (n * ADVANCE) as u16;
+        match c {
+            '.' => ops.extend_from_slice(&[0xa000 | DOT_ADDR, 0x6100 | x, 0xd121]),
+            _ => if let Some(digit) = c.to_digit(16) {
+                ops.extend_from_slice(&[0x6000 | digit as u16, 0xf029, 0x6100 | x, 0xd125]);
+            }
+        }
+    }
+}
+
+// the splash program for a version, eg. "0.1.0"
+pub fn splash_rom(version: &str) -> Vec<u8> {
+    // jump over the dot sprite
+    let mut ops: Vec<u16> = vec![0x1204, 0x8000];
+    draw_line(&mut ops, "C8", TITLE_Y);
+    draw_line(&mut ops, version, VERSION_Y);
+    // then wait to be swapped out
+    let end = 0x200 + ops.len() as u16 * 2;
+    ops.push(0x1000 | end);
+    ops.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+// the splash for this build
+pub fn splash() -> Vec<u8> {
+    splash_rom(env!("CARGO_PKG_VERSION"))
+}
+
+// swaps the ROM in once the splash has been up long enough; the ROM should
+// be one the CPU has already loaded (before the splash), so that anything
+// wrong with it has been reported by then
+pub struct Splash {
+    rom: Option<Vec<u8>>,
+    frames_left: u32
+}
+
+impl Splash {
+    pub fn new(rom: Vec<u8>) -> Splash {
+        Splash { rom: Some(rom), frames_left: SPLASH_FRAMES }
+    }
+}
+
+impl Hooks for Splash {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if self.rom.is_none() {
+            return Ok(());
+        }
+        self.frames_left = self.frames_left.saturating_sub(1);
+        if self.frames_left == 0 {
+            if let Some(rom) = self.rom.take() {
+                cpu.swap_rom(&rom).map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn shows_then_hands_over() {
+        let run = |frames| {
+            let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), Config::default());
+            cpu.load_rom(&splash_rom("0.1.0")).unwrap();
+            cpu.add_hooks(Box::new(Splash::new(vec![0x60, 0x2a, 0x12, 0x02])));
+            cpu.run_loop().unwrap();
+            cpu
+        };
+        let cpu = run(SPLASH_FRAMES as usize - 1);
+        assert_eq!(cpu.framebuffer().pixels().iter().filter(|&&on| on).count(), 65);
+        let cpu = run(SPLASH_FRAMES as usize + 1);
+        assert!(cpu.framebuffer().pixels().iter().all(|&on| !on));
+        assert_eq!((cpu.v()[0], cpu.pc()), (0x2a, 0x202));
+        assert_eq!(cpu.rom(), [0x60, 0x2a, 0x12, 0x02]);
+    }
+}
@@ -0,0 +1,148 @@
+// finding the sprites a ROM draws, so they can be saved as pictures
+
+use std::collections::BTreeSet;
+
+use crate::decode::nnn;
+use crate::disasm::{
+    analyze,
+    fetch
+};
+use crate::png::encode_bits;
+
+// sprites are kept as (address, n), n being the last nibble of the Dxyn
+// that drew them: the number of rows of 8 pixels, or 0 for SUPER-CHIP's
+// 16x16 sprites
+
+// the width and height in pixels of a sprite drawn by Dxyn
+pub fn sprite_size(n: usize) -> (usize, usize) {
+    if n == 0 { (16, 16) } else { (8, n) }
+}
+
+// how many bytes of memory it takes up
+pub fn sprite_len(n: usize) -> usize {
+    let (width, height) = sprite_size(n);
+    width / 8 * height
+}
+
+// (address, n) of every sprite drawn with I set by an Annn earlier in
+// the same straight-line run of code; anything that might change I in a
+// way we can't follow (Fx1E, Fx29, a call, arriving from elsewhere)
+// forgets it
+pub fn find_sprites(rom: &[u8]) -> BTreeSet<(usize, usize)> {
+    let analysis = analyze(rom);
+    let mut sprites = BTreeSet::new();
+    let mut i = None;
+    let mut last_pc = None;
+
+    for &pc in &analysis.code {
+        if last_pc.map(|p| p + 2) != Some(pc) || analysis.label(pc).is_some() {
+            i = None;
+        }
+        last_pc = Some(pc);
+
+        let op = fetch(rom, pc).unwrap();
+        match op >> 12 {
+            0xa => i = Some(nnn(op) as usize),
+            0xd => {
+                if let Some(addr) = i {
+                    sprites.insert((addr, (op & 0xf) as usize));
+                }
+            },
+            0x2 => i = None,
+            0xf if op & 0xff == 0x1e || op & 0xff == 0x29 => i = None,
+            _ => ()
+        }
+    }
+    sprites
+}
+
+// the sprite's pixels, row by row, most significant bit on the left
+pub fn sprite_bits(ram: &[u8], addr: usize, n: usize) -> Vec<bool> {
+    (0..sprite_len(n))
+        .flat_map(|j| {
+            let byte = ram[(addr + j) % ram.len()];
+            (0..8).map(move |bit| byte & (0x80 >> bit) != 0)
+        })
+        .collect()
+}
+
+// the sprite at addr in ram as a PNG, each pixel scale x scale big
+pub fn sprite_png(ram: &[u8], addr: usize, n: usize, scale: usize) -> Vec<u8> {
+    let (width, height) = sprite_size(n);
+    encode_bits(width, height, &sprite_bits(ram, addr, n), scale)
+}
+
+// space between sprites in a sheet, in pixels
+pub const SHEET_GAP: usize = 1;
+
+// consecutive sprites of the given height starting at start, laid out
+// columns across and rows down, for browsing memory for graphics;
+// returns the pixels with the sheet's width and height
+pub fn sprite_sheet(ram: &[u8], start: usize, height: usize, columns: usize, rows: usize) -> (Vec<bool>, usize, usize) {
+    let cell_width = 8 + SHEET_GAP;
+    let cell_height = height + SHEET_GAP;
+    let (width, total_height) = (columns * cell_width, rows * cell_height);
+    let mut pixels = vec![false; width * total_height];
+    for row in 0..rows {
+        for column in 0..columns {
+            let addr = start + (row * columns + column) * height;
+            for (j, &on) in sprite_bits(ram, addr, height).iter().enumerate() {
+                let x = column * cell_width + j % 8;
+                let y = row * cell_height + j / 8;
+                pixels[y * width + x] = on;
+            }
+        }
+    }
+    (pixels, width, total_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_sprites_drawn_after_i_loads() {
+        let rom = [
+            0xa2, 0x0c, // 200: LD I, 20c
+            0xd0, 0x12, // 202: DRW V0, V1, 2
+            0xf0, 0x1e, // 204: ADD I, V0
+            0xd0, 0x12, // 206: DRW V0, V1, 2 with an unknown I
+            0x12, 0x0a, // 208: JP 20a
+            0xd0, 0x13, // 20a: DRW with I from somewhere else
+            0xf0, 0x90  // 20c: sprite data
+        ];
+        assert_eq!(find_sprites(&rom).into_iter().collect::<Vec<_>>(), [(0x20c, 2)]);
+        let large = [
+            0xa2, 0x04, // 200: LD I, 204
+            0xd0, 0x10, // 202: DRW V0, V1, 0
+        ];
+        assert_eq!(find_sprites(&large).into_iter().collect::<Vec<_>>(), [(0x204, 0)]);
+    }
+
+    #[test]
+    fn sprite_pixels() {
+        let ram = [0xf0, 0x90];
+        let bits = sprite_bits(&ram, 0, 2);
+        let rows: Vec<String> = bits.chunks(8).map(|r| r.iter().map(|&b| if b { '#' } else { '.' }).collect()).collect();
+        assert_eq!(rows, ["####....", "#..#...."]);
+        let mut ram = [0; 32];
+        ram[..2].copy_from_slice(&[0x80, 0x01]);
+        let bits = sprite_bits(&ram, 0, 0);
+        assert_eq!(bits.len(), 16 * 16);
+        assert!(bits[0] && bits[15] && bits[16..].iter().all(|&b| !b));
+    }
+
+    #[test]
+    fn sheet_layout() {
+        let ram = [0x80, 0x01, 0xff, 0x00];
+        let (pixels, width, height) = sprite_sheet(&ram, 0, 1, 2, 2);
+        assert_eq!((width, height), (18, 4));
+        let rows: Vec<String> = pixels.chunks(width).map(|r| r.iter().map(|&b| if b { '#' } else { '.' }).collect()).collect();
+        assert_eq!(rows, [
+            "#...............#.",
+            "..................",
+            "########..........",
+            ".................."
+        ]);
+    }
+}
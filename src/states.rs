@@ -0,0 +1,322 @@
+// save states: the whole machine, as kept in memory by the pause menu, or
+// in one of ten numbered slots in a directory next to the ROM (eg.
+// game.ch8.states/3.state), which F1-F10 load and Shift+F1-F10 save. a
+// slot is text, a field per line, then RAM and the screen in hex:
+//
+//   chip8-state 1
+//   rom 5e1c0a8fb6e3c251
+//   platform chip8
+//   pc 2a4
+//   i 3f0
+//   v 00 01 00 00 00 00 00 00 00 00 00 00 00 00 00 01
+//   dt 00
+//   st 00
+//   stack 202 2a0
+//   screen 64x32
+//   planes 1
+//   zones 2111...
+//   mega 0000...
+//   ram
+//   (128 lines of 32 bytes)
+//   high fff000
+//   (a line of address and 32 bytes for each 32 that aren't all zero)
+//   end
+//   pixels
+//   (a line of colours per row, a hex digit each, or two for MegaChip)
+//
+// the ROM is named by the hash of its contents, so a slot isn't loaded
+// into a different version of it, or on a different platform, which
+// would change how much memory there is; zones is only there for CHIP-8X, mega
+// in MegaChip mode and high on the MegaChip platform, with the size of
+// its memory past the first 4K
+
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use crate::chip8x::ColourZones;
+use crate::config::Platform;
+use crate::cpu::{
+    RAM_SIZE,
+    REGISTER_COUNT,
+    STACK_SIZE
+};
+use crate::framebuffer::{
+    Framebuffer,
+    Resolution
+};
+use crate::megachip::{
+    MegaScreen,
+    MEGA_RAM_SIZE
+};
+
+pub const STATES_EXTENSION: &str = "states";
+// how many numbered slots there are, from 1 on
+pub const SLOT_COUNT: usize = 10;
+const GREETING: &str = "chip8-state 1";
+const RAM_LINE: usize = 32;
+
+#[derive(Clone)]
+pub struct SaveState {
+    pub ram: [u8; RAM_SIZE],
+    // MegaChip's memory past the first 4K, if the platform has it
+    pub high_ram: Vec<u8>,
+    pub v: [u8; REGISTER_COUNT],
+    pub i: usize,
+    pub dt: u8,
+    pub st: u8,
+    pub stack: [usize; STACK_SIZE],
+    pub sp: usize,
+    pub pc: usize,
+    pub framebuffer: Framebuffer,
+    // what it was saved on; a state only fits that platform's memory
+    pub platform: Platform
+}
+
+// the file for a slot in dir
+pub fn slot_path(dir: &Path, slot: usize) -> PathBuf {
+    dir.join(format!("{}.state", slot))
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl SaveState {
+    pub fn to_text(&self, rom_hash: u64) -> String {
+        let fb = &self.framebuffer;
+        let mut text = format!("{}\nrom {:016x}\nplatform {}\n", GREETING, rom_hash, self.platform.name());
+        text += &format!("pc {:03x}\ni {:03x}\n", self.pc, self.i);
+        let v: Vec<String> = self.v.iter().map(|b| format!("{:02x}", b)).collect();
+        text += &format!("v {}\ndt {:02x}\nst {:02x}\n", v.join(" "), self.dt, self.st);
+        let stack: Vec<String> = self.stack[..self.sp].iter().map(|addr| format!("{:03x}", addr)).collect();
+        text += &format!("stack {}\n", stack.join(" "));
+        text += &format!("screen {}x{}\nplanes {}\n", fb.width(), fb.height(), fb.planes());
+        if let Some(zones) = fb.colour_zones() {
+            text += &format!("zones {}\n", zones.to_hex());
+        }
+        if let Some(mega) = fb.mega_screen() {
+            text += &format!("mega {}\n", mega.to_hex());
+        }
+        text += "ram\n";
+        for line in self.ram.chunks(RAM_LINE) {
+            text += &hex_bytes(line);
+            text += "\n";
+        }
+        if !self.high_ram.is_empty() {
+            text += &format!("high {:x}\n", self.high_ram.len());
+            for (j, line) in self.high_ram.chunks(RAM_LINE).enumerate().filter(|(_, line)| line.iter().any(|b| *b != 0)) {
+                text += &format!("{:06x} {}\n", RAM_SIZE + j * RAM_LINE, hex_bytes(line));
+            }
+            text += "end\n";
+        }
+        text += "pixels\n";
+        let mega = fb.resolution() == Resolution::Mega;
+        for row in fb.colours().chunks(fb.width()) {
+            if mega {
+                text += &hex_bytes(row);
+            }
+            else {
+                text.extend(row.iter().map(|colour| char::from_digit(*colour as u32, 16).unwrap_or('0')));
+            }
+            text += "\n";
+        }
+        text
+    }
+
+    // an error if text isn't a save state of the ROM hashing to rom_hash,
+    // saved on platform
+    pub fn parse(text: &str, rom_hash: u64, platform: Platform) -> Result<SaveState, String> {
+        let mut lines = text.lines().map(str::trim);
+        if lines.next() != Some(GREETING) {
+            return Err(format!("not a save state: it should start with `{}`", GREETING));
+        }
+        let mut state = SaveState {
+            ram: [0; RAM_SIZE],
+            high_ram: Vec::new(),
+            v: [0; REGISTER_COUNT],
+            i: 0,
+            dt: 0,
+            st: 0,
+            stack: [0; STACK_SIZE],
+            sp: 0,
+            pc: 0,
+            framebuffer: Framebuffer::new(),
+            platform: Platform::Chip8
+        };
+        let mut rom = None;
+        let mut planes = 1;
+        let mut zones = None;
+        let mut mega = None;
+        while let Some(line) = lines.next() {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let hex = || usize::from_str_radix(value, 16).ok();
+            let ok = match name {
+                "rom" => u64::from_str_radix(value, 16).map(|h| rom = Some(h)).is_ok(),
+                "platform" => Platform::from_name(value).map(|p| state.platform = p).is_some(),
+                "pc" => hex().filter(|pc| *pc < RAM_SIZE).map(|pc| state.pc = pc).is_some(),
+                "i" => hex().filter(|i| *i < MEGA_RAM_SIZE).map(|i| state.i = i).is_some(),
+                "dt" => u8::from_str_radix(value, 16).map(|dt| state.dt = dt).is_ok(),
+                "st" => u8::from_str_radix(value, 16).map(|st| state.st = st).is_ok(),
+                "v" => {
+                    let v: Option<Vec<u8>> = value.split_whitespace().map(|b| u8::from_str_radix(b, 16).ok()).collect();
+                    v.filter(|v| v.len() == REGISTER_COUNT).map(|v| state.v.copy_from_slice(&v)).is_some()
+                },
+                "stack" => {
+                    let stack: Option<Vec<usize>> = value.split_whitespace().map(|a| usize::from_str_radix(a, 16).ok()).collect();
+                    // returns go to these, so they have to be where pc can be
+                    let stack = stack.filter(|s| s.iter().all(|addr| *addr < RAM_SIZE));
+                    stack.filter(|s| s.len() <= STACK_SIZE).map(|s| {
+                        state.stack[..s.len()].copy_from_slice(&s);
+                        state.sp = s.len();
+                    }).is_some()
+                },
+                "screen" => value.split_once('x')
+                    .and_then(|(w, h)| Resolution::from_size(w.parse().ok()?, h.parse().ok()?))
+                    .map(|r| state.framebuffer.set_resolution(r))
+                    .is_some(),
+                "planes" => value.parse().map(|p| planes = p).is_ok(),
+                "zones" => ColourZones::from_hex(value).map(|z| zones = Some(z)).is_some(),
+                "mega" => MegaScreen::from_hex(value).map(|m| mega = Some(m)).is_some(),
+                "high" => {
+                    let size = hex().filter(|size| *size <= MEGA_RAM_SIZE - RAM_SIZE).ok_or_else(|| format!("unexpected `{}`", line))?;
+                    state.high_ram = vec![0; size];
+                    loop {
+                        let line = lines.next().unwrap_or("end");
+                        if line == "end" {
+                            break;
+                        }
+                        let (addr, bytes) = line.split_once(' ').unwrap_or((line, ""));
+                        let at = usize::from_str_radix(addr, 16).ok()
+                            .and_then(|addr| addr.checked_sub(RAM_SIZE))
+                            .filter(|at| at + bytes.len() / 2 <= size && bytes.len() % 2 == 0 && bytes.is_ascii())
+                            .ok_or_else(|| format!("unexpected `{}` in high RAM", line))?;
+                        for (k, byte) in state.high_ram[at..at + bytes.len() / 2].iter_mut().enumerate() {
+                            *byte = u8::from_str_radix(&bytes[k * 2..k * 2 + 2], 16).map_err(|_| format!("unexpected `{}` in high RAM", line))?;
+                        }
+                    }
+                    true
+                },
+                "ram" => {
+                    for (j, chunk) in state.ram.chunks_mut(RAM_LINE).enumerate() {
+                        let line = lines.next().unwrap_or_default();
+                        if line.len() != chunk.len() * 2 || !line.is_ascii() {
+                            return Err(format!("line {} of RAM is not {} hex bytes", j + 1, chunk.len()));
+                        }
+                        for (k, byte) in chunk.iter_mut().enumerate() {
+                            *byte = u8::from_str_radix(&line[k * 2..k * 2 + 2], 16).map_err(|_| format!("line {} of RAM is not hex", j + 1))?;
+                        }
+                    }
+                    true
+                },
+                "pixels" => {
+                    let fb = &mut state.framebuffer;
+                    let mega = fb.resolution() == Resolution::Mega;
+                    for y in 0..fb.height() {
+                        let line = lines.next().unwrap_or_default();
+                        let colours: Option<Vec<u32>> = if mega {
+                            (0..line.len() / 2).map(|x| line.get(x * 2..x * 2 + 2).and_then(|b| u32::from_str_radix(b, 16).ok())).collect()
+                        }
+                        else {
+                            line.chars().map(|c| c.to_digit(4)).collect()
+                        };
+                        match colours {
+                            Some(row) if row.len() == fb.width() => {
+                                for (x, colour) in row.into_iter().enumerate() {
+                                    fb.set_colour(x, y, colour as u8);
+                                }
+                            },
+                            _ => return Err(format!("row {} of the screen is not {} colours", y, fb.width()))
+                        }
+                    }
+                    true
+                },
+                _ => false
+            };
+            if !ok {
+                return Err(format!("unexpected `{}`", line));
+            }
+        }
+        match rom {
+            Some(hash) if hash == rom_hash => (),
+            Some(_) => return Err(String::from("it was saved with a different version of the ROM")),
+            None => return Err(String::from("it doesn't say which ROM it is for"))
+        }
+        if state.platform != platform {
+            return Err(format!("it was saved on {}, not {}", state.platform.name(), platform.name()));
+        }
+        let memory = platform.memory_size();
+        if RAM_SIZE + state.high_ram.len() != memory || state.i >= memory {
+            return Err(format!("its memory doesn't fit {}'s {} bytes", platform.name(), memory));
+        }
+        state.framebuffer.select_planes(planes);
+        state.framebuffer.set_colour_zones(zones);
+        state.framebuffer.set_mega_screen(mega);
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        Config,
+        Platform
+    };
+    use crate::cpu::CPU;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn round_trips_through_text() {
+        // CHIP-8X, to have colour zones: call a subroutine that draws a
+        // digit in colour, then wait
+        let rom = [0x23, 0x06, 0x00, 0x00, 0x00, 0x00, 0x60, 0x05, 0x61, 0x03, 0xf0, 0x29, 0xd0, 0x15, 0xb0, 0x10, 0x13, 0x10];
+        let config = Config { platform: Platform::Chip8X, log_steps: false, ..Config::default() };
+        let mut cpu = CPU::new(Box::new(Headless::new(4)), Box::new(Silence), config);
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_loop().unwrap();
+        cpu.save_state();
+        let state = cpu.saved_state().unwrap();
+        let text = state.to_text(7);
+        let parsed = SaveState::parse(&text, 7, Platform::Chip8X).unwrap();
+        assert_eq!(parsed.to_text(7), text);
+        assert_eq!((parsed.pc, parsed.sp, parsed.stack[0]), (0x310, 1, 0x300));
+        assert!(parsed.framebuffer.colour_zones().is_some());
+        assert_eq!(parsed.framebuffer.hash(), cpu.framebuffer().hash());
+        assert_eq!(&parsed.ram[..], cpu.ram());
+        assert!(SaveState::parse(&text, 8, Platform::Chip8X).err().unwrap().contains("different version"));
+        assert_eq!(SaveState::parse(&text, 7, Platform::Chip8).err().unwrap(), "it was saved on chip8x, not chip8");
+        assert!(SaveState::parse(&text.replace("pc 310", "pc 1000"), 7, Platform::Chip8X).is_err());
+        assert!(SaveState::parse(&text.replace("stack 300", "stack 1000"), 7, Platform::Chip8X).is_err());
+        let deep = format!("stack{}", " 300".repeat(STACK_SIZE + 1));
+        assert!(SaveState::parse(&text.replace("stack 300", &deep), 7, Platform::Chip8X).is_err());
+        assert!(SaveState::parse("chip8-state 1\nrom 07\nram\n00\n", 7, Platform::Chip8).is_err());
+    }
+
+    #[test]
+    fn megachip_round_trips() {
+        // MegaChip mode, a digit drawn and shown, and a byte stored past 4K
+        let rom = [0x00, 0x11, 0x60, 0x07, 0xf0, 0x29, 0xd0, 0x05, 0x00, 0xe0, 0x01, 0x00, 0x20, 0x00, 0xf0, 0x55, 0x12, 0x10];
+        let config = Config { platform: Platform::MegaChip, log_steps: false, ..Config::default() };
+        let mut cpu = CPU::new(Box::new(Headless::new(8)), Box::new(Silence), config);
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_loop().unwrap();
+        cpu.save_state();
+        let state = cpu.saved_state().unwrap();
+        let text = state.to_text(7);
+        assert!(text.contains("\nhigh fff000\n002000 07000000"));
+        let parsed = SaveState::parse(&text, 7, Platform::MegaChip).unwrap();
+        assert_eq!(parsed.to_text(7), text);
+        assert_eq!(parsed.high_ram, state.high_ram);
+        // the memory has to match the platform as well as the name
+        assert!(SaveState::parse(&text, 7, Platform::Chip8).is_err());
+        let small = text.replace("platform megachip", "platform chip8");
+        assert_eq!(SaveState::parse(&small, 7, Platform::Chip8).err().unwrap(), "its memory doesn't fit chip8's 4096 bytes");
+        assert_eq!(parsed.framebuffer.mega_screen(), cpu.framebuffer().mega_screen());
+        assert_eq!(parsed.framebuffer.hash(), cpu.framebuffer().hash());
+    }
+}
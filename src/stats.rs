@@ -0,0 +1,171 @@
+// opcode usage statistics: which instructions a ROM uses (statically) or
+// runs (at runtime), grouped by the instruction pattern from Cowgod's
+// reference, along with which platform each pattern comes from
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap
+};
+
+use crate::disasm::{
+    analyze,
+    fetch
+};
+
+const CHIP8: &str = "CHIP-8";
+const VIP: &str = "VIP machine code";
+const SCHIP: &str = "SCHIP";
+const XO_CHIP: &str = "XO-CHIP";
+const MEGACHIP: &str = "MegaChip";
+const UNKNOWN: &str = "unknown";
+
+// the pattern an opcode matches (eg. 8xy4) and the platform that has it,
+// which tells which extensions a ROM was written for
+pub fn family(op: u16) -> (&'static str, &'static str) {
+    let x = (op >> 8) & 0xf;
+    let n = op & 0xf;
+    let kk = op & 0xff;
+    match op >> 12 {
+        0x0 => match op {
+            0x00e0 => ("00E0", CHIP8),
+            0x00ee => ("00EE", CHIP8),
+            0x00fb => ("00FB", SCHIP),
+            0x00fc => ("00FC", SCHIP),
+            0x00fd => ("00FD", SCHIP),
+            0x00fe => ("00FE", SCHIP),
+            0x00ff => ("00FF", SCHIP),
+            _ if op & 0xfff0 == 0x00c0 => ("00Cn", SCHIP),
+            _ if op & 0xfff0 == 0x00d0 => ("00Dn", XO_CHIP),
+            // MegaChip's mode switches; its other instructions look just
+            // like calls into the VIP's machine code
+            0x0010 => ("0010", MEGACHIP),
+            0x0011 => ("0011", MEGACHIP),
+            _ => ("0nnn", VIP)
+        },
+        0x1 => ("1nnn", CHIP8),
+        0x2 => ("2nnn", CHIP8),
+        0x3 => ("3xkk", CHIP8),
+        0x4 => ("4xkk", CHIP8),
+        0x5 => match n {
+            0x0 => ("5xy0", CHIP8),
+            0x2 => ("5xy2", XO_CHIP),
+            0x3 => ("5xy3", XO_CHIP),
+            _ => ("5xy?", UNKNOWN)
+        },
+        0x6 => ("6xkk", CHIP8),
+        0x7 => ("7xkk", CHIP8),
+        0x8 => match n {
+            0x0 => ("8xy0", CHIP8),
+            0x1 => ("8xy1", CHIP8),
+            0x2 => ("8xy2", CHIP8),
+            0x3 => ("8xy3", CHIP8),
+            0x4 => ("8xy4", CHIP8),
+            0x5 => ("8xy5", CHIP8),
+            0x6 => ("8xy6", CHIP8),
+            0x7 => ("8xy7", CHIP8),
+            0xe => ("8xyE", CHIP8),
+            _ => ("8xy?", UNKNOWN)
+        },
+        0x9 if n == 0 => ("9xy0", CHIP8),
+        0x9 => ("9xy?", UNKNOWN),
+        0xa => ("Annn", CHIP8),
+        0xb => ("Bnnn", CHIP8),
+        0xc => ("Cxkk", CHIP8),
+        0xd if n == 0 => ("Dxy0", SCHIP),
+        0xd => ("Dxyn", CHIP8),
+        0xe => match kk {
+            0x9e => ("Ex9E", CHIP8),
+            0xa1 => ("ExA1", CHIP8),
+            _ => ("Ex??", UNKNOWN)
+        },
+        _ => match kk {
+            0x00 if x == 0 => ("F000", XO_CHIP),
+            0x01 => ("Fn01", XO_CHIP),
+            0x02 if x == 0 => ("F002", XO_CHIP),
+            0x07 => ("Fx07", CHIP8),
+            0x0a => ("Fx0A", CHIP8),
+            0x15 => ("Fx15", CHIP8),
+            0x18 => ("Fx18", CHIP8),
+            0x1e => ("Fx1E", CHIP8),
+            0x29 => ("Fx29", CHIP8),
+            0x30 => ("Fx30", SCHIP),
+            0x33 => ("Fx33", CHIP8),
+            0x3a => ("Fx3A", XO_CHIP),
+            0x55 => ("Fx55", CHIP8),
+            0x65 => ("Fx65", CHIP8),
+            0x75 => ("Fx75", SCHIP),
+            0x85 => ("Fx85", SCHIP),
+            _ => ("Fx??", UNKNOWN)
+        }
+    }
+}
+
+// counts per family for (opcode, count) pairs, most used first
+pub fn histogram<I: IntoIterator<Item = (u16, u64)>>(counts: I) -> Vec<(&'static str, &'static str, u64)> {
+    let mut families: BTreeMap<(&'static str, &'static str), u64> = BTreeMap::new();
+    for (op, count) in counts {
+        *families.entry(family(op)).or_insert(0) += count;
+    }
+    let mut result: Vec<_> = families.into_iter().map(|((pattern, platform), count)| (pattern, platform, count)).collect();
+    result.sort_by_key(|f| Reverse(f.2));
+    result
+}
+
+// the histogram as a table, followed by the platforms it needs
+pub fn report<I: IntoIterator<Item = (u16, u64)>>(counts: I) -> String {
+    let families = histogram(counts);
+    let total: u64 = families.iter().map(|f| f.2).sum();
+    let mut out = String::from("instruction  platform          count       %\n");
+    for (pattern, platform, count) in &families {
+        out.push_str(&format!(
+            "{:<12} {:<16} {:>6} {:>6.1}%\n",
+            pattern, platform, count, *count as f64 * 100.0 / total.max(1) as f64
+        ));
+    }
+    let mut platforms: Vec<&str> = families.iter().map(|f| f.1).collect();
+    platforms.sort_unstable();
+    platforms.dedup();
+    out.push_str(&format!("Platforms: {}\n", platforms.join(", ")));
+    out
+}
+
+// static statistics for a ROM: every instruction reachable from the entry
+// point counted once, plus the unrecognized ones where paths stopped
+pub fn static_report(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let ops = analysis.code.iter().chain(analysis.unknown.iter()).map(|&addr| (fetch(rom, addr).unwrap(), 1));
+    report(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn families() {
+        assert_eq!(family(0x8124), ("8xy4", CHIP8));
+        assert_eq!(family(0x00ff), ("00FF", SCHIP));
+        assert_eq!(family(0xd120), ("Dxy0", SCHIP));
+        assert_eq!(family(0xf000), ("F000", XO_CHIP));
+        assert_eq!(family(0x0011), ("0011", MEGACHIP));
+        assert_eq!(family(0x0230), ("0nnn", VIP));
+        assert_eq!(family(0x0123), ("0nnn", VIP));
+        assert_eq!(family(0xe1ff), ("Ex??", UNKNOWN));
+    }
+
+    #[test]
+    fn static_stats_include_where_paths_stop() {
+        let rom = [
+            0x60, 0x01, // 200: LD V0, 1
+            0x60, 0x02, // 202: LD V0, 2
+            0x00, 0xfd, // 204: SCHIP exit
+            0x12, 0x00  // 206: never reached
+        ];
+        let families = {
+            let analysis = analyze(&rom);
+            histogram(analysis.code.iter().chain(analysis.unknown.iter()).map(|&a| (fetch(&rom, a).unwrap(), 1)))
+        };
+        assert_eq!(families, [("6xkk", CHIP8, 2), ("00FD", SCHIP, 1)]);
+        assert!(static_report(&rom).ends_with("Platforms: CHIP-8, SCHIP\n"));
+    }
+}
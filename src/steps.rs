@@ -0,0 +1,127 @@
+// execution as a stream, for analysis tools and notebooks: CPU::iter_steps
+// runs the machine and hands back each instruction executed along with
+// what it changed, without anything having to hook into the run loop
+
+use crate::cpu::CPU;
+use crate::disasm::mnemonic;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SideEffect {
+    // register, old value, new value
+    Register(usize, u8, u8),
+    I(usize, usize),
+    DelayTimer(u8, u8),
+    SoundTimer(u8, u8),
+    // the address called from
+    Call(usize),
+    // the address returned to the call at
+    Return(usize),
+    // address, old value, new value
+    Memory(usize, u8, u8),
+    // something was drawn or the screen cleared
+    Screen
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutedOp {
+    pub pc: usize,
+    pub opcode: u16,
+    // as assembly, eg. "LD V0, 0x05", or None if it's not an instruction
+    pub instruction: Option<String>,
+    pub side_effects: Vec<SideEffect>
+}
+
+// what an instruction can change, to compare before and after
+pub(crate) struct Watch {
+    v: Vec<u8>,
+    i: usize,
+    dt: u8,
+    st: u8,
+    stack: Vec<usize>,
+    ram: Vec<u8>,
+    screen: u64
+}
+
+impl Watch {
+    pub(crate) fn of(cpu: &CPU) -> Watch {
+        Watch {
+            v: cpu.v().to_vec(),
+            i: cpu.i(),
+            dt: cpu.delay_timer(),
+            st: cpu.sound_timer(),
+            stack: cpu.stack().to_vec(),
+            ram: cpu.ram().to_vec(),
+            screen: cpu.framebuffer().hash()
+        }
+    }
+
+    // what changed since, in the order listed in SideEffect
+    pub(crate) fn effects(&self, after: &Watch) -> Vec<SideEffect> {
+        let mut effects: Vec<SideEffect> = (0..self.v.len())
+            .filter(|&r| self.v[r] != after.v[r])
+            .map(|r| SideEffect::Register(r, self.v[r], after.v[r]))
+            .collect();
+        if self.i != after.i {
+            effects.push(SideEffect::I(self.i, after.i));
+        }
+        if self.dt != after.dt {
+            effects.push(SideEffect::DelayTimer(self.dt, after.dt));
+        }
+        if self.st != after.st {
+            effects.push(SideEffect::SoundTimer(self.st, after.st));
+        }
+        if after.stack.len() > self.stack.len() {
+            effects.extend(after.stack[self.stack.len()..].iter().map(|&addr| SideEffect::Call(addr)));
+        }
+        if after.stack.len() < self.stack.len() {
+            effects.extend(self.stack[after.stack.len()..].iter().map(|&addr| SideEffect::Return(addr)));
+        }
+        effects.extend((0..self.ram.len())
+            .filter(|&addr| self.ram[addr] != after.ram[addr])
+            .map(|addr| SideEffect::Memory(addr, self.ram[addr], after.ram[addr])));
+        if self.screen != after.screen {
+            effects.push(SideEffect::Screen);
+        }
+        effects
+    }
+}
+
+impl ExecutedOp {
+    pub(crate) fn new(pc: usize, opcode: u16, side_effects: Vec<SideEffect>) -> ExecutedOp {
+        ExecutedOp { pc, opcode, instruction: mnemonic(opcode), side_effects }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::headless::{
+        Headless,
+        Silence
+    };
+
+    #[test]
+    fn streams_what_each_instruction_did() {
+        let mut cpu = CPU::new(Box::new(Headless::new(usize::MAX)), Box::new(Silence), Config::default());
+        // ld v0, 5; ld i, 300; ld [i], v0; call 20a; jp 208; 20a: cls; ret
+        cpu.load_rom(&[0x60, 0x05, 0xa3, 0x00, 0xf0, 0x55, 0x22, 0x0a, 0x12, 0x08, 0x00, 0xe0, 0x00, 0xee]).unwrap();
+        let steps: Vec<ExecutedOp> = cpu.iter_steps().take(7).collect();
+        assert_eq!(steps[0], ExecutedOp {
+            pc: 0x200,
+            opcode: 0x6005,
+            instruction: Some(String::from("LD V0, 0x05")),
+            side_effects: vec![SideEffect::Register(0, 0, 5)]
+        });
+        let effects: Vec<Vec<SideEffect>> = steps.iter().map(|step| step.side_effects.clone()).collect();
+        assert_eq!(effects[1..], [
+            vec![SideEffect::I(0, 0x300)],
+            vec![SideEffect::Memory(0x300, 0, 5)],
+            vec![SideEffect::Call(0x206)],
+            vec![],
+            vec![SideEffect::Return(0x206)],
+            vec![]
+        ]);
+        assert_eq!(steps.iter().map(|step| step.pc).collect::<Vec<_>>(), [0x200, 0x202, 0x204, 0x206, 0x20a, 0x20c, 0x208]);
+    }
+}
@@ -0,0 +1,212 @@
+// small generated ROMs that check one family of instructions (or a quirk)
+// each, for our own tests and for trying out other emulators. every check
+// runs a few instructions that leave a result in VA and compares it to
+// what Cowgod's reference says; a pass draws the check's number along the
+// top of the screen, a failure draws it in the bottom half and adds one
+// to VE. V0-V2 are free for the checks, VA-VE belong to the harness
+
+use crate::asm::assemble;
+
+struct Check {
+    name: &'static str,
+    // assembly leaving the result in VA; {k} is replaced by the check's
+    // number, to keep labels unique
+    setup: &'static str,
+    expected: u8
+}
+
+// checks per row on the screen
+const PER_ROW: usize = 12;
+
+const ARITHMETIC: &[Check] = &[
+    Check { name: "8xy1 OR", setup: "LD V0, 0x05\nLD V1, 0x03\nOR V0, V1\nLD VA, V0", expected: 0x07 },
+    Check { name: "8xy2 AND", setup: "LD V0, 0x0c\nLD V1, 0x0a\nAND V0, V1\nLD VA, V0", expected: 0x08 },
+    Check { name: "8xy3 XOR", setup: "LD V0, 0x0c\nLD V1, 0x0a\nXOR V0, V1\nLD VA, V0", expected: 0x06 },
+    Check { name: "8xy4 ADD", setup: "LD V0, 0xff\nLD V1, 0x02\nADD V0, V1\nLD VA, V0", expected: 0x01 },
+    Check { name: "8xy4 carry", setup: "LD V0, 0xff\nLD V1, 0x02\nADD V0, V1\nLD VA, VF", expected: 0x01 },
+    Check { name: "8xy5 SUB", setup: "LD V0, 0x05\nLD V1, 0x07\nSUB V0, V1\nLD VA, V0", expected: 0xfe },
+    Check { name: "8xy5 borrow", setup: "LD V0, 0x05\nLD V1, 0x07\nSUB V0, V1\nLD VA, VF", expected: 0x00 },
+    Check { name: "8xy6 SHR", setup: "LD V0, 0x05\nSHR V0, V0\nLD VA, V0", expected: 0x02 },
+    Check { name: "8xy6 shifted out", setup: "LD V0, 0x05\nSHR V0, V0\nLD VA, VF", expected: 0x01 },
+    Check { name: "8xy7 SUBN", setup: "LD V0, 0x03\nLD V1, 0x05\nSUBN V0, V1\nLD VA, V0", expected: 0x02 },
+    Check { name: "8xyE SHL", setup: "LD V0, 0x81\nSHL V0, V0\nLD VA, V0", expected: 0x02 },
+    Check { name: "8xyE shifted out", setup: "LD V0, 0x81\nSHL V0, V0\nLD VA, VF", expected: 0x01 },
+    Check { name: "7xkk leaves VF alone", setup: "LD VF, 0x07\nLD V0, 0xff\nADD V0, 0x02\nLD VA, VF", expected: 0x07 }
+];
+
+const SKIPS: &[Check] = &[
+    Check { name: "3xkk equal", setup: "LD VA, 0x01\nLD V0, 0x05\nSE V0, 0x05\nLD VA, 0x00", expected: 0x01 },
+    Check { name: "3xkk not equal", setup: "LD VA, 0x01\nLD V0, 0x05\nSE V0, 0x06\nLD VA, 0x02", expected: 0x02 },
+    Check { name: "4xkk", setup: "LD VA, 0x01\nLD V0, 0x05\nSNE V0, 0x06\nLD VA, 0x00", expected: 0x01 },
+    Check { name: "5xy0", setup: "LD VA, 0x01\nLD V0, 0x05\nLD V1, 0x05\nSE V0, V1\nLD VA, 0x00", expected: 0x01 },
+    Check { name: "9xy0", setup: "LD VA, 0x01\nLD V0, 0x05\nLD V1, 0x06\nSNE V0, V1\nLD VA, 0x00", expected: 0x01 },
+    Check { name: "ExA1 with no key down", setup: "LD VA, 0x01\nLD V0, 0x05\nSKNP V0\nLD VA, 0x00", expected: 0x01 },
+    Check { name: "Ex9E with no key down", setup: "LD VA, 0x01\nLD V0, 0x05\nSKP V0\nLD VA, 0x02", expected: 0x02 }
+];
+
+const FLOW: &[Check] = &[
+    Check { name: "1nnn", setup: "LD VA, 0x01\nJP over_{k}\nLD VA, 0x00\nover_{k}:", expected: 0x01 },
+    Check {
+        name: "2nnn and 00EE",
+        setup: "LD VA, 0x00\nCALL sub_{k}\nJP done_{k}\nsub_{k}:\nLD VA, 0x2a\nRET\ndone_{k}:",
+        expected: 0x2a
+    },
+    Check {
+        name: "nested calls",
+        setup: "LD VA, 0x00\nCALL outer_{k}\nJP done_{k}\nouter_{k}:\nADD VA, 0x01\nCALL inner_{k}\nADD VA, 0x01\nRET\n\
+                inner_{k}:\nADD VA, 0x10\nRET\ndone_{k}:",
+        expected: 0x12
+    },
+    Check {
+        name: "Bnnn",
+        setup: "LD VA, 0x01\nLD V0, 0x04\nJP V0, here_{k}\nhere_{k}:\nLD VA, 0x00\nLD VA, 0x00",
+        expected: 0x01
+    }
+];
+
+const MEMORY: &[Check] = &[
+    Check { name: "Fx33 hundreds", setup: "LD I, scratch\nLD V0, 0x9c\nLD B, V0\nLD V2, [I]\nLD VA, V0", expected: 1 },
+    Check { name: "Fx33 tens", setup: "LD I, scratch\nLD V0, 0x9c\nLD B, V0\nLD V2, [I]\nLD VA, V1", expected: 5 },
+    Check { name: "Fx33 units", setup: "LD I, scratch\nLD V0, 0x9c\nLD B, V0\nLD V2, [I]\nLD VA, V2", expected: 6 },
+    Check {
+        name: "Fx55 and Fx65",
+        setup: "LD I, scratch\nLD V0, 0x11\nLD V1, 0x22\nLD [I], V1\nLD V0, 0x00\nLD V1, 0x00\nLD I, scratch\nLD V1, [I]\nLD VA, V1",
+        expected: 0x22
+    },
+    Check {
+        name: "Fx1E",
+        setup: "LD I, scratch\nLD V0, 0x33\nLD V1, 0x44\nLD [I], V1\nLD I, scratch\nLD V0, 0x01\nADD I, V0\nLD V0, [I]\nLD VA, V0",
+        expected: 0x44
+    }
+];
+
+const TIMER: &[Check] = &[
+    Check {
+        name: "delay timer counts down to 0",
+        setup: "LD V0, 0x02\nLD DT, V0\nwait_{k}:\nLD V1, DT\nSE V1, 0x00\nJP wait_{k}\nLD VA, V1",
+        expected: 0
+    }
+];
+
+const RANDOM: &[Check] = &[
+    Check { name: "Cxkk masked to 0", setup: "RND VA, 0x00", expected: 0 },
+    Check { name: "Cxkk masked to the low bits", setup: "RND V0, 0x0f\nLD V1, 0xf0\nAND V0, V1\nLD VA, V0", expected: 0 }
+];
+
+// in the bottom right corner, away from the results, and drawn twice so
+// they leave nothing behind
+const DRAW: &[Check] = &[
+    Check {
+        name: "Dxyn collision",
+        setup: "LD I, sprite\nLD V0, 0x3c\nLD V1, 0x1e\nDRW V0, V1, 1\nDRW V0, V1, 1\nLD VA, VF",
+        expected: 1
+    },
+    Check {
+        name: "Dxyn no collision",
+        setup: "LD I, sprite\nLD V0, 0x3c\nLD V1, 0x1e\nDRW V0, V1, 1\nLD VA, VF\nDRW V0, V1, 1",
+        expected: 0
+    },
+    Check {
+        name: "Dxyn wraps around the right edge",
+        setup: "LD I, sprite\nLD V0, 0x3f\nLD V1, 0x1e\nLD V2, 0x00\nDRW V0, V1, 1\nDRW V2, V1, 1\nLD VA, VF\n\
+                DRW V2, V1, 1\nDRW V0, V1, 1",
+        expected: 1
+    }
+];
+
+const QUIRK_I_OVERFLOW: &[Check] = &[
+    Check { name: "Fx1E past 0xfff sets VF", setup: "LD VF, 0x00\nLD I, 0xfff\nLD V0, 0x01\nADD I, V0\nLD VA, VF", expected: 1 },
+    Check { name: "Fx1E up to 0xfff clears VF", setup: "LD VF, 0x01\nLD I, 0xffe\nLD V0, 0x01\nADD I, V0\nLD VA, VF", expected: 0 }
+];
+
+// the families a ROM can be generated for, and what they check
+pub const FAMILIES: &[(&str, &str)] = &[
+    ("arithmetic", "8xyn register arithmetic and VF"),
+    ("skips", "3xkk, 4xkk, 5xy0, 9xy0, Ex9E and ExA1"),
+    ("flow", "jumps, calls and returns"),
+    ("memory", "Fx33, Fx55, Fx65 and Fx1E"),
+    ("timer", "the delay timer"),
+    ("random", "Cxkk masking"),
+    ("draw", "Dxyn collisions and wrapping"),
+    ("quirk-i-overflow", "Fx1E setting VF past 0xfff, like the Amiga interpreter")
+];
+
+fn checks(family: &str) -> Option<&'static [Check]> {
+    match family {
+        "arithmetic" => Some(ARITHMETIC),
+        "skips" => Some(SKIPS),
+        "flow" => Some(FLOW),
+        "memory" => Some(MEMORY),
+        "timer" => Some(TIMER),
+        "random" => Some(RANDOM),
+        "draw" => Some(DRAW),
+        "quirk-i-overflow" => Some(QUIRK_I_OVERFLOW),
+        _ => None
+    }
+}
+
+// assembly source for the family's test ROM
+pub fn source(family: &str) -> Result<String, String> {
+    let checks = checks(family).ok_or_else(|| format!("Unknown test family {}", family))?;
+    let mut source = format!("; generated test ROM: {}\n    CLS\n    LD VE, 0x00\n", family);
+    for (k, check) in checks.iter().enumerate() {
+        let (x, row) = ((k % PER_ROW) * 5, (k / PER_ROW) * 6);
+        source.push_str(&format!("\n; {}: {}, expecting {:#04x}\n", k, check.name, check.expected));
+        for line in check.setup.replace("{k}", &k.to_string()).lines() {
+            if line.ends_with(':') {
+                source.push_str(&format!("{}\n", line));
+            }
+            else {
+                source.push_str(&format!("    {}\n", line));
+            }
+        }
+        source.push_str(&format!(
+            "    SE VA, {expected:#04x}\n    JP fail_{k}\n\
+             \x20   LD VB, {digit:#04x}\n    LD F, VB\n    LD VC, {x:#04x}\n    LD VD, {pass:#04x}\n    DRW VC, VD, 5\n    JP next_{k}\n\
+             fail_{k}:\n    ADD VE, 0x01\n\
+             \x20   LD VB, {digit:#04x}\n    LD F, VB\n    LD VC, {x:#04x}\n    LD VD, {fail:#04x}\n    DRW VC, VD, 5\n\
+             next_{k}:\n",
+            expected = check.expected, k = k, digit = k & 0xf, x = x, pass = 1 + row, fail = 17 + row
+        ));
+    }
+    source.push_str("\nhalt:\n    JP halt\nsprite:\n    DB 0xff\nscratch:\n    DB 0x00, 0x00, 0x00, 0x00\n");
+    Ok(source)
+}
+
+// the family's test ROM
+pub fn generate(family: &str) -> Result<Vec<u8>, String> {
+    assemble(&source(family)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::testsuite::run_headless;
+
+    #[test]
+    fn every_family_passes() {
+        for (family, _) in FAMILIES {
+            let mut config = Config::default();
+            config.quirks.i_overflow_sets_vf = *family == "quirk-i-overflow";
+            let cpu = run_headless(&generate(family).unwrap(), 120, config);
+            assert_eq!(cpu.v()[0xe], 0, "{} failures in {}", cpu.v()[0xe], family);
+            // every check drew its number along the top, and nothing is left below
+            let pixels = cpu.framebuffer().pixels();
+            let drawn = |k: usize| {
+                let (x, y) = ((k % PER_ROW) * 5, 1 + (k / PER_ROW) * 6);
+                (y..y + 5).any(|y| (x..x + 4).any(|x| pixels[y * 64 + x]))
+            };
+            let passed = (0..checks(family).unwrap().len()).filter(|&k| drawn(k)).count();
+            assert_eq!(passed, checks(family).unwrap().len(), "{}", family);
+            assert!(pixels[17 * 64..].iter().all(|px| !px), "{}", family);
+        }
+    }
+
+    #[test]
+    fn failures_are_counted() {
+        // without the quirk, both of its checks fail
+        let cpu = run_headless(&generate("quirk-i-overflow").unwrap(), 60, Config::default());
+        assert_eq!(cpu.v()[0xe], 2);
+    }
+}
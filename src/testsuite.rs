@@ -0,0 +1,203 @@
+// runs test ROMs headless and checks what ends up on the screen;
+// the Timendus suite (https://github.com/Timendus/chip8-test-suite) and
+// corax89 (https://github.com/corax89/chip8-test-rom) ROMs are not bundled
+// and have to be copied into tests/roms, see the README there
+
+use std::{
+    env,
+    fs,
+    path::PathBuf
+};
+
+use crate::config::Config;
+use crate::cpu::CPU;
+use crate::headless::{
+    Headless,
+    Silence
+};
+
+fn roms_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms")
+}
+
+// load rom into a fresh CPU and run it headless for the given number of frames
+pub fn run_headless(rom: &[u8], frames: usize, config: Config) -> CPU {
+    let mut cpu = CPU::new(Box::new(Headless::new(frames)), Box::new(Silence), config);
+    cpu.load_rom(rom).unwrap();
+    cpu.run_loop().unwrap();
+    cpu
+}
+
+#[test]
+fn headless_draws_font_digit() {
+    let rom = [
+        0x60, 0x00, // V0 = 0
+        0x61, 0x00, // V1 = 0
+        0xf0, 0x29, // I = sprite for digit V0
+        0xd0, 0x15, // draw 5 rows at (V0, V1)
+        0x12, 0x08  // loop forever
+    ];
+    let cpu = run_headless(&rom, 2, Config::default());
+    let screen = cpu.framebuffer().to_string();
+    let rows: Vec<&str> = screen.lines().map(|l| &l[..8]).take(6).collect();
+    assert_eq!(rows, ["####....", "#..#....", "#..#....", "#..#....", "####....", "........"]);
+}
+
+// every line of expected.txt is `<rom file> <frames> <framebuffer hash>`;
+// a hash of `-` means not yet recorded, run with CHIP8_BLESS=1 to record it.
+// runs the ROMs named there (all of them when only is None) and checks
+// their screens; every ROM asked for has to be present
+fn check_roms(only: Option<&str>) {
+    let manifest_path = roms_dir().join("expected.txt");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    let bless = env::var("CHIP8_BLESS").is_ok();
+    let mut updated = Vec::new();
+    let mut failures = Vec::new();
+    let mut checked = 0;
+
+    for line in manifest.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if line.starts_with('#') || fields.len() != 3 {
+            updated.push(line.to_string());
+            continue;
+        }
+        let (name, frames, expected) = (fields[0], fields[1], fields[2]);
+        if only.is_some_and(|only| only != name) {
+            updated.push(line.to_string());
+            continue;
+        }
+        checked += 1;
+        let rom = match fs::read(roms_dir().join(name)) {
+            Ok(rom) => rom,
+            Err(why) => {
+                failures.push(format!("{}: ROM required in tests/roms: {}", name, why));
+                updated.push(line.to_string());
+                continue;
+            }
+        };
+
+        let cpu = run_headless(&rom, frames.parse().unwrap(), Config::default());
+        let hash = format!("{:016x}", cpu.framebuffer().hash());
+        if bless {
+            updated.push(format!("{} {} {}", name, frames, hash));
+        } else {
+            if expected == "-" {
+                failures.push(format!("{}: no hash recorded yet, got {}\n{}", name, hash, cpu.framebuffer()));
+            }
+            else if hash != expected {
+                failures.push(format!("{}: expected {}, got {}\n{}", name, expected, hash, cpu.framebuffer()));
+            }
+            updated.push(line.to_string());
+        }
+    }
+
+    if bless {
+        fs::write(&manifest_path, updated.join("\n") + "\n").unwrap();
+    }
+    assert!(checked > 0, "no ROM in {} matches {:?}", manifest_path.display(), only);
+    assert!(failures.is_empty(), "framebuffer mismatches:\n{}", failures.join("\n"));
+}
+
+// the ROMs aren't bundled, so these only run when asked for with
+// `cargo test -- --ignored`
+#[test]
+#[ignore = "needs the test ROMs copied into tests/roms, see the README there"]
+fn test_roms() {
+    check_roms(None);
+}
+
+// corax89's ROM draws OK (or NO) next to each of its opcode groups; the
+// recorded hash is of the screen with OK beside every one of them
+#[test]
+#[ignore = "needs test_opcode.ch8 copied into tests/roms, see the README there"]
+fn corax89_opcodes() {
+    check_roms(Some("test_opcode.ch8"));
+}
+
+fn assemble(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+// compare the screen after running program against the text bitmap in
+// tests/snapshots/<name>.txt, or (re)write that file with CHIP8_BLESS=1
+fn check_snapshot(name: &str, program: &[u16], frames: usize) {
+    let cpu = run_headless(&assemble(program), frames, Config::default());
+    let actual = cpu.framebuffer().to_string();
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.txt", name));
+    if env::var("CHIP8_BLESS").is_ok() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no snapshot at {}, run with CHIP8_BLESS=1 to create it", path.display()));
+    assert!(actual == expected, "{} differs from its snapshot, got:\n{}", name, actual);
+}
+
+#[test]
+fn snapshot_font() {
+    // all 16 digits, 8 per row
+    check_snapshot("font", &[
+        0x6000, // 200: V0 = 0 (digit)
+        0x6100, // 202: V1 = 0 (x)
+        0x6200, // 204: V2 = 0 (y)
+        0xf029, // 206: I = sprite for V0
+        0xd125, // 208: draw at (V1, V2)
+        0x7001, // 20a: V0 += 1
+        0x7108, // 20c: V1 += 8
+        0x3140, // 20e: end of row?
+        0x1206, // 210: no, next digit
+        0x6100, // 212: V1 = 0
+        0x7206, // 214: V2 += 6
+        0x3010, // 216: all digits drawn?
+        0x1206, // 218: no, next digit
+        0x121a  // 21a: loop forever
+    ], 30);
+}
+
+#[test]
+fn snapshot_edges() {
+    // a 4x4 box drawn across the right, bottom, and bottom-right edges
+    check_snapshot("edges", &[
+        0xa21a, // 200: I = box sprite
+        0x6a3e, // 202: VA = 62
+        0x6b0a, // 204: VB = 10
+        0xdab4, // 206: draw across the right edge
+        0x6a0a, // 208: VA = 10
+        0x6b1e, // 20a: VB = 30
+        0xdab4, // 20c: draw across the bottom edge
+        0x6a3e, // 20e: VA = 62
+        0x6b1e, // 210: VB = 30
+        0xdab4, // 212: draw across both
+        0x1214, // 214: loop forever
+        0x0000,
+        0x0000,
+        0xf090, // 21a: box sprite
+        0x90f0
+    ], 10);
+}
+
+#[test]
+fn snapshot_collisions() {
+    // draws overlapping and separate "0" glyphs, then shows the
+    // collision flag after each as a digit on the top row
+    check_snapshot("collisions", &[
+        0x6000, // 200: V0 = 0
+        0x6104, // 202: V1 = 4
+        0x6202, // 204: V2 = 2
+        0x6514, // 206: V5 = 20
+        0x6620, // 208: V6 = 32
+        0x6728, // 20a: V7 = 40
+        0xf029, // 20c: I = sprite for 0
+        0xd005, // 20e: draw at (0, 0)
+        0xd125, // 210: draw at (4, 2), overlapping
+        0x8af0, // 212: VA = VF
+        0xfa29, // 214: I = sprite for VA
+        0xd505, // 216: show it at (20, 0)
+        0xf029, // 218: I = sprite for 0
+        0xd625, // 21a: draw at (32, 2), no overlap
+        0x8af0, // 21c: VA = VF
+        0xfa29, // 21e: I = sprite for VA
+        0xd705, // 220: show it at (40, 0)
+        0x1222  // 222: loop forever
+    ], 10);
+}
@@ -0,0 +1,109 @@
+// the buzzer's waveform: a 440 Hz tone, or the 128 one-bit samples an
+// XO-CHIP program loaded with F002, looped; kept apart from any audio
+// backend so it can be tested, the backend just asks it for samples
+
+use std::f32::consts::PI;
+
+// bytes in an F002 pattern, 8 samples to a byte, first sample in the top bit
+pub const PATTERN_LEN: usize = 16;
+// pattern samples played a second, at the default pitch
+pub const PATTERN_RATE: f32 = 4000.0;
+// the pitch register's value until a program sets it (Fx3A)
+pub const DEFAULT_PITCH: u8 = 64;
+// the tone played until a program loads a pattern
+const TONE_HZ: f32 = 440.0;
+// how loud a pattern plays next to the tone, which is a sine wave from
+// -1 to 1; a square wave at full height sounds much louder
+const PATTERN_VOLUME: f32 = 0.5;
+
+pub struct Voice {
+    pattern: Option<[u8; PATTERN_LEN]>,
+    // pattern samples a second, from the pitch
+    rate: f32,
+    // how far through a cycle of the tone, or through the pattern, from 0
+    // to 1
+    phase: f32
+}
+
+impl Voice {
+    pub fn new() -> Voice {
+        Voice { pattern: None, rate: PATTERN_RATE, phase: 0.0 }
+    }
+
+    pub fn set_pattern(&mut self, pattern: [u8; PATTERN_LEN]) {
+        self.pattern = Some(pattern);
+    }
+
+    // play the pattern faster or slower from the next sample on: every 48
+    // above the default doubles the rate, every 48 below halves it
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.rate = pattern_rate(pitch);
+    }
+
+    // the next sample, from -1 to 1, for output at sample_rate samples a second
+    pub fn next_sample(&mut self, sample_rate: u32) -> f32 {
+        let sample = match &self.pattern {
+            Some(pattern) => {
+                let bit = (self.phase * (PATTERN_LEN * 8) as f32) as usize % (PATTERN_LEN * 8);
+                if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { PATTERN_VOLUME } else { -PATTERN_VOLUME }
+            },
+            None => (2.0 * PI * self.phase).sin()
+        };
+        let cycles_a_second = match self.pattern {
+            Some(_) => self.rate / (PATTERN_LEN * 8) as f32,
+            None => TONE_HZ
+        };
+        self.phase = (self.phase + cycles_a_second / sample_rate as f32).fract();
+        sample
+    }
+}
+
+// pattern samples a second at the given pitch, as in XO-CHIP's spec
+pub fn pattern_rate(pitch: u8) -> f32 {
+    PATTERN_RATE * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
+
+impl Default for Voice {
+    fn default() -> Voice {
+        Voice::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_loop_at_4000_samples_a_second() {
+        let mut voice = Voice::new();
+        // a sine wave to start with
+        assert_eq!(voice.next_sample(44100), 0.0);
+        assert!(voice.next_sample(44100) > 0.0);
+        // on for the first half, off for the second
+        let mut pattern = [0; PATTERN_LEN];
+        for byte in pattern[..PATTERN_LEN / 2].iter_mut() {
+            *byte = 0xff;
+        }
+        voice.set_pattern(pattern);
+        voice.phase = 0.0;
+        // at 8000 samples a second, each bit of the pattern is played twice
+        let samples: Vec<f32> = (0..512).map(|_| voice.next_sample(8000)).collect();
+        assert!(samples[..128].iter().all(|s| *s == PATTERN_VOLUME));
+        assert!(samples[128..256].iter().all(|s| *s == -PATTERN_VOLUME));
+        assert_eq!(&samples[..256], &samples[256..]);
+        // an octave up, each bit is played once
+        voice.set_pitch(DEFAULT_PITCH + 48);
+        voice.phase = 0.0;
+        let samples: Vec<f32> = (0..256).map(|_| voice.next_sample(8000)).collect();
+        assert!(samples[..64].iter().all(|s| *s == PATTERN_VOLUME));
+        assert!(samples[64..128].iter().all(|s| *s == -PATTERN_VOLUME));
+        assert_eq!(&samples[..128], &samples[128..]);
+    }
+
+    #[test]
+    fn pitches() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert_eq!(pattern_rate(16), 2000.0);
+        assert!((pattern_rate(255) - 63_000.0).abs() < 1000.0);
+    }
+}
@@ -0,0 +1,540 @@
+// subcommands for working with ROMs rather than running them
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{
+        Path,
+        PathBuf
+    },
+    thread
+};
+
+use chip8_rust::asm::{
+    assemble,
+    assemble_octo
+};
+use chip8_rust::batch::{
+    run_all,
+    Status
+};
+use chip8_rust::config::Config;
+use chip8_rust::cpu::{
+    CPU,
+    PROGRAM_START
+};
+use chip8_rust::deadcode::dead_code;
+use chip8_rust::decompile::decompile;
+use chip8_rust::dev::SourceWatcher;
+use chip8_rust::diff::diff;
+use chip8_rust::disasm::{
+    control_flow_graph,
+    disassemble
+};
+use chip8_rust::framebuffer::{
+    scale_pixels,
+    HEIGHT,
+    WIDTH
+};
+use chip8_rust::frontend::{
+    Buzzer,
+    Frontend
+};
+use chip8_rust::gif::encode_animation;
+use chip8_rust::headless::{
+    parse_input_script,
+    Headless,
+    Silence
+};
+use chip8_rust::patch::apply_patch;
+use chip8_rust::replay::{
+    Capture,
+    Frames,
+    Playback,
+    Replay
+};
+use chip8_rust::sprites::{
+    find_sprites,
+    sprite_png,
+    sprite_size
+};
+use chip8_rust::rom::{
+    pad,
+    sanity_check,
+    trim
+};
+use chip8_rust::spectator::watch;
+use chip8_rust::stats::static_report;
+use chip8_rust::testgen::{
+    generate,
+    FAMILIES
+};
+
+#[cfg(feature = "window")]
+use crate::viewer;
+use crate::{
+    open_audio,
+    open_window
+};
+
+// run the subcommand named by args[1], if it is one
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    let rest = &args[2.min(args.len())..];
+    let result = match args.get(1).map(String::as_str) {
+        Some("disasm") => disasm(rest),
+        Some("asm") => asm(rest),
+        Some("decompile") => decompile_rom(rest),
+        Some("diff") => diff_roms(rest),
+        Some("cfg") => cfg(rest),
+        Some("stats") => stats(rest),
+        Some("sprites") => sprites(rest),
+        Some("view-sprites") => view_sprites(rest),
+        Some("deadcode") => deadcode(rest),
+        Some("trim") => trim_rom(rest),
+        Some("pad") => pad_rom(rest),
+        Some("watch") => watch_host(rest),
+        Some("gen-test") => gen_test(rest),
+        Some("replay") => replay(rest),
+        Some("dev") => dev(rest),
+        Some("batch") => batch(rest),
+        _ => return None
+    };
+    Some(result)
+}
+
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|why| format!("Could not open {}: {}", path, why))
+}
+
+// assemble a source file: Octo if it's named .8o, otherwise the
+// syntax the disassembler prints
+pub fn assemble_file(path: &str) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(path).map_err(|why| format!("Could not open {}: {}", path, why))?;
+    let result = if path.ends_with(".8o") {
+        assemble_octo(&source)
+    }
+    else {
+        assemble(&source)
+    };
+    result.map_err(|err| format!("{}: {}", path, err))
+}
+
+// chip8-rust disasm <rom>
+fn disasm(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(String::from("Usage: disasm <rom-file-name>"))
+    };
+    print!("{}", disassemble(&read_rom(path)?));
+    Ok(())
+}
+
+// chip8-rust diff <rom> <rom>
+fn diff_roms(args: &[String]) -> Result<(), String> {
+    let (a, b) = match args {
+        [a, b] => (a, b),
+        _ => return Err(String::from("Usage: diff <old-rom-file-name> <new-rom-file-name>"))
+    };
+    let changes = diff(&read_rom(a)?, &read_rom(b)?);
+    if changes.is_empty() {
+        println!("No differences");
+    }
+    print!("{}", changes);
+    Ok(())
+}
+
+// chip8-rust decompile <rom>
+fn decompile_rom(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(String::from("Usage: decompile <rom-file-name>"))
+    };
+    print!("{}", decompile(&read_rom(path)?));
+    Ok(())
+}
+
+// chip8-rust cfg <rom>
+fn cfg(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(String::from("Usage: cfg <rom-file-name>"))
+    };
+    print!("{}", control_flow_graph(&read_rom(path)?));
+    Ok(())
+}
+
+// chip8-rust stats <rom>
+fn stats(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(String::from("Usage: stats <rom-file-name>"))
+    };
+    print!("{}", static_report(&read_rom(path)?));
+    Ok(())
+}
+
+// chip8-rust sprites [--scale=<n>] [--frames=<n>] <rom> <directory>
+fn sprites(args: &[String]) -> Result<(), String> {
+    let usage = || String::from("Usage: sprites [--scale=<n>] [--frames=<n>] <rom-file-name> <output-directory>");
+    let mut scale = 8;
+    let mut frames = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--scale=") {
+            scale = match value.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(String::from("Invalid value for --scale: expected a positive number"))
+            };
+        }
+        else if let Some(value) = arg.strip_prefix("--frames=") {
+            frames = Some(value.parse().map_err(|_| String::from("Invalid value for --frames: expected a number of frames"))?);
+        }
+        else {
+            paths.push(arg);
+        }
+    }
+    let (rom_path, out_dir) = match paths.as_slice() {
+        [rom, dir] => (rom, Path::new(dir.as_str())),
+        _ => return Err(usage())
+    };
+    let rom = read_rom(rom_path)?;
+
+    // sprites found by looking at the code, plus those drawn while
+    // running headless for a while if asked to
+    let config = Config { record_sprites: true, ..Config::default() };
+    let mut cpu = CPU::new(Box::new(Headless::new(frames.unwrap_or(0))), Box::new(Silence), config);
+    cpu.load_rom(&rom).map_err(|err| err.to_string())?;
+    let mut found = find_sprites(&rom);
+    if frames.is_some() {
+        if let Err(err) = cpu.run_loop() {
+            eprintln!("Warning: ROM stopped early: {}", err);
+        }
+        found.extend(cpu.sprites_drawn());
+    }
+
+    fs::create_dir_all(out_dir).map_err(|why| format!("Could not create {}: {}", out_dir.display(), why))?;
+    for &(addr, n) in &found {
+        let (width, height) = sprite_size(n);
+        let path = out_dir.join(format!("sprite-{:03x}-{}x{}.png", addr, width, height));
+        fs::write(&path, sprite_png(cpu.ram(), addr, n, scale))
+            .map_err(|why| format!("Could not write {}: {}", path.display(), why))?;
+    }
+    println!("Wrote {} sprites to {}", found.len(), out_dir.display());
+    Ok(())
+}
+
+// chip8-rust view-sprites <rom> [<start-address>]
+fn view_sprites(args: &[String]) -> Result<(), String> {
+    let (path, start) = match args {
+        [path] => (path, PROGRAM_START),
+        [path, start] => {
+            let start = usize::from_str_radix(start.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid start address {}: expected hex, eg. 2a0", start))?;
+            (path, start)
+        },
+        _ => return Err(String::from("Usage: view-sprites <rom-file-name> [<start-address>]"))
+    };
+    // browse the whole of memory as the ROM sees it, font included
+    let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+    cpu.load_rom(&read_rom(path)?).map_err(|err| err.to_string())?;
+    show_sprites(cpu.ram(), start)
+}
+
+#[cfg(feature = "window")]
+fn show_sprites(ram: &[u8], start: usize) -> Result<(), String> {
+    viewer::view_sprites(ram, start)
+}
+
+#[cfg(not(feature = "window"))]
+fn show_sprites(_ram: &[u8], _start: usize) -> Result<(), String> {
+    Err(String::from("this build has no window (see the `window` feature); try the sprites subcommand instead"))
+}
+
+// chip8-rust deadcode [--frames=<n>] [--input-script=<file>] <rom>
+fn deadcode(args: &[String]) -> Result<(), String> {
+    let mut frames = None;
+    let mut script = Vec::new();
+    let mut paths = Vec::new();
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--frames=") {
+            frames = Some(value.parse().map_err(|_| String::from("Invalid value for --frames: expected a number of frames"))?);
+        }
+        else if let Some(file) = arg.strip_prefix("--input-script=") {
+            let text = fs::read_to_string(file).map_err(|why| format!("Could not open input script: {}", why))?;
+            script = parse_input_script(&text).map_err(|err| format!("Invalid input script: {}", err))?;
+        }
+        else {
+            paths.push(arg);
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => return Err(String::from("Usage: deadcode [--frames=<n>] [--input-script=<file>] <rom-file-name>"))
+    };
+    let rom = read_rom(path)?;
+
+    // record which instructions run, if asked to run the ROM at all
+    let mut executed = BTreeSet::new();
+    if let Some(frames) = frames {
+        let config = Config { opcode_stats: true, ..Config::default() };
+        let mut cpu = CPU::new(Box::new(Headless::with_script(frames, script)), Box::new(Silence), config);
+        cpu.load_rom(&rom).map_err(|err| err.to_string())?;
+        if let Err(err) = cpu.run_loop() {
+            eprintln!("Warning: ROM stopped early: {}", err);
+        }
+        executed.extend(cpu.executed().keys().map(|(pc, _)| *pc));
+    }
+    print!("{}", dead_code(&rom, &executed).report(rom.len(), frames.is_some()));
+    Ok(())
+}
+
+// write a trimmed or padded ROM, once we know it still loads
+fn write_resized(rom: &[u8], output: &str, original: usize) -> Result<(), String> {
+    sanity_check(rom)?;
+    let mut cpu = CPU::new(Box::new(Headless::new(0)), Box::new(Silence), Config::default());
+    cpu.load_rom(rom).map_err(|err| err.to_string())?;
+    fs::write(output, rom).map_err(|why| format!("Could not write {}: {}", output, why))?;
+    println!("Wrote {} bytes to {} (was {})", rom.len(), output, original);
+    Ok(())
+}
+
+// chip8-rust trim <rom> <output>
+fn trim_rom(args: &[String]) -> Result<(), String> {
+    let (path, output) = match args {
+        [path, output] => (path, output),
+        _ => return Err(String::from("Usage: trim <rom-file-name> <output-file-name>"))
+    };
+    let rom = read_rom(path)?;
+    write_resized(trim(&rom), output, rom.len())
+}
+
+// chip8-rust pad <rom> <output> <size>
+fn pad_rom(args: &[String]) -> Result<(), String> {
+    let (path, output, size) = match args {
+        [path, output, size] => (path, output, size),
+        _ => return Err(String::from("Usage: pad <rom-file-name> <output-file-name> <size-in-bytes>"))
+    };
+    let size = match size.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => size.parse()
+    }.map_err(|_| format!("Invalid size {}: expected a number of bytes", size))?;
+    let rom = read_rom(path)?;
+    write_resized(&pad(&rom, size)?, output, rom.len())
+}
+
+// chip8-rust gen-test <family> <output>
+fn gen_test(args: &[String]) -> Result<(), String> {
+    let (family, output) = match args {
+        [family, output] => (family, output),
+        _ => {
+            let families: Vec<String> = FAMILIES.iter().map(|(name, what)| format!("  {:<18}{}", name, what)).collect();
+            return Err(format!("Usage: gen-test <family> <output-file-name>\nFamilies:\n{}", families.join("\n")));
+        }
+    };
+    let rom = generate(family)?;
+    fs::write(output, &rom).map_err(|why| format!("Could not write {}: {}", output, why))?;
+    println!("Wrote {} bytes to {}", rom.len(), output);
+    Ok(())
+}
+
+// chip8-rust asm <source> <rom>
+fn asm(args: &[String]) -> Result<(), String> {
+    let (source, output) = match args {
+        [source, output] => (source, output),
+        _ => return Err(String::from("Usage: asm <source-file-name> <rom-file-name>"))
+    };
+    let rom = assemble_file(source)?;
+    fs::write(output, &rom).map_err(|why| format!("Could not write {}: {}", output, why))?;
+    println!("Wrote {} bytes to {}", rom.len(), output);
+    Ok(())
+}
+
+// chip8-rust watch <address>
+fn watch_host(args: &[String]) -> Result<(), String> {
+    match args {
+        [addr] => {
+            let audio = open_audio().map_err(|err| format!("Could not initialize audio device: {}", err))?;
+            let mut win = open_window(&format!("chip8-rust: watching {}", addr), None)
+                .map_err(|err| format!("Could not initialize window: {}", err))?;
+            watch(addr, &mut *win, &*audio)
+        },
+        _ => Err(String::from("Usage: watch <host-address>"))
+    }
+}
+
+// chip8-rust replay [--rom=<file>] [--gif=<file>] [--scale=<n>] <replay>
+fn replay(args: &[String]) -> Result<(), String> {
+    let usage = || String::from("Usage: replay [--rom=<rom-file-name>] [--gif=<output>] [--scale=<n>] <replay-file-name>");
+    let mut rom_path = None;
+    let mut gif = None;
+    let mut scale = 4;
+    let mut paths = Vec::new();
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--rom=") {
+            rom_path = Some(value);
+        }
+        else if let Some(value) = arg.strip_prefix("--gif=") {
+            gif = Some(value);
+        }
+        else if let Some(value) = arg.strip_prefix("--scale=") {
+            scale = match value.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(String::from("Invalid value for --scale: expected a positive number"))
+            };
+        }
+        else {
+            paths.push(arg);
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => return Err(usage())
+    };
+    let text = fs::read_to_string(path).map_err(|why| format!("Could not open {}: {}", path, why))?;
+    let replay = Replay::parse(&text).map_err(|err| format!("Invalid replay {}: {}", path, err))?;
+
+    // the ROM as it was when recorded, unless told where to find it
+    let rom_path = rom_path.unwrap_or(&replay.rom_path);
+    let mut rom = if rom_path.ends_with(".8o") { assemble_file(rom_path)? } else { read_rom(rom_path)? };
+    for patch in &replay.patches {
+        rom = apply_patch(&rom, &read_rom(patch)?).map_err(|err| format!("Could not apply patch {}: {}", patch, err))?;
+    }
+    replay.check_rom(&rom)?;
+
+    // exporting doesn't need to show anything, so runs as fast as it can
+    let frames = Frames::default();
+    let (win, audio): (Box<dyn Frontend>, Box<dyn Buzzer>) = if gif.is_some() {
+        (Box::new(Headless::new(usize::MAX)), Box::new(Silence))
+    }
+    else {
+        let audio = open_audio().map_err(|err| format!("Could not initialize audio device: {}", err))?;
+        let win = open_window(&format!("chip8-rust: replay of {}", rom_path), None)
+            .map_err(|err| format!("Could not initialize window: {}", err))?;
+        (win, audio)
+    };
+    let mut cpu = CPU::new(Box::new(Playback::new(win, &replay)), audio, replay.config);
+    cpu.load_rom(&rom).map_err(|err| err.to_string())?;
+    if gif.is_some() {
+        cpu.add_hooks(Box::new(Capture::new(frames.clone())));
+    }
+    cpu.run_loop().map_err(|err| err.to_string())?;
+
+    // only a replay watched to the end can be checked
+    let finished = cpu.counters().frames >= replay.frames as u64;
+    match replay.screen {
+        Some(screen) if finished && screen != cpu.framebuffer().hash() => {
+            eprintln!("Warning: the screen at the end differs from the recording");
+        },
+        Some(_) if finished => println!("Replayed {} frames, ending on the same screen as the recording", replay.frames),
+        _ => ()
+    }
+    if let Some(output) = gif {
+        // the ROM may have switched resolution, so every frame is blown up
+        // to the biggest screen it had
+        let frames = frames.borrow();
+        let width = frames.iter().map(|(_, width, _)| *width).max().unwrap_or(WIDTH);
+        let height = frames.iter().map(|(pixels, width, _)| pixels.len() / width).max().unwrap_or(HEIGHT);
+        let scaled: Vec<(Vec<bool>, u16)> = frames.iter()
+            .map(|(pixels, frame_width, delay)| (scale_pixels(pixels, *frame_width, width, height), *delay))
+            .collect();
+        fs::write(output, encode_animation(width, height, &scaled, scale))
+            .map_err(|why| format!("Could not write {}: {}", output, why))?;
+        println!("Wrote {} frames to {}", frames.len(), output);
+    }
+    Ok(())
+}
+
+// chip8-rust dev [--keep-state] <source.8o>
+fn dev(args: &[String]) -> Result<(), String> {
+    let usage = || String::from("Usage: dev [--keep-state] <source-file-name>");
+    let mut keep_state = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--keep-state" => keep_state = true,
+            _ if arg.starts_with("--") => return Err(usage()),
+            _ => paths.push(arg.clone())
+        }
+    }
+    let path = match paths.as_slice() {
+        [path] => path.clone(),
+        _ => return Err(usage())
+    };
+    let rom = assemble_file(&path)?;
+
+    let audio = open_audio().map_err(|err| format!("Could not initialize audio device: {}", err))?;
+    let win = open_window(&format!("chip8-rust: {}", path), None)
+        .map_err(|err| format!("Could not initialize window: {}", err))?;
+    let mut cpu = CPU::new(win, audio, Config::default());
+    cpu.load_rom(&rom).map_err(|err| err.to_string())?;
+    let source = path.clone();
+    cpu.add_hooks(Box::new(SourceWatcher::new(PathBuf::from(&path), keep_state, Box::new(move || assemble_file(&source)))));
+    println!("Running {}, rebuilding it whenever it changes", path);
+    cpu.run_loop().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// the file extensions batch treats as ROMs
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+// chip8-rust batch [--frames=<n>] [--threads=<n>] <dir>
+fn batch(args: &[String]) -> Result<(), String> {
+    let usage = || String::from("Usage: batch [--frames=<n>] [--threads=<n>] <rom-directory>");
+    let mut frames = 600;
+    let mut threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut dirs = Vec::new();
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--frames=") {
+            frames = value.parse().map_err(|_| String::from("Invalid value for --frames: expected a number of frames"))?;
+        }
+        else if let Some(value) = arg.strip_prefix("--threads=") {
+            threads = match value.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(String::from("Invalid value for --threads: expected a positive number"))
+            };
+        }
+        else if arg.starts_with("--") {
+            return Err(usage());
+        }
+        else {
+            dirs.push(arg);
+        }
+    }
+    let dir = match dirs.as_slice() {
+        [dir] => Path::new(dir.as_str()),
+        _ => return Err(usage())
+    };
+
+    let entries = fs::read_dir(dir).map_err(|why| format!("Could not open {}: {}", dir.display(), why))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+            ROM_EXTENSIONS.contains(&ext.as_str())
+        })
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("No ROMs ({}) in {}", ROM_EXTENSIONS.join(", "), dir.display()));
+    }
+
+    let outcomes = run_all(&paths, frames, &Config::default(), threads, |path| read_rom(&path.to_string_lossy()));
+    let width = paths.iter().map(|path| path.display().to_string().len()).max().unwrap_or(0);
+    println!("{:width$}  {:7}  {:>6}  {:>7}  screen", "rom", "status", "frames", "unknown", width = width);
+    for (path, outcome) in paths.iter().zip(&outcomes) {
+        let detail = match &outcome.status {
+            Status::Finished | Status::Exited => String::new(),
+            Status::WaitingForInput(pc) => format!("  (Fx0A at {:03x})", pc),
+            Status::Crashed(err) => format!("  ({})", err)
+        };
+        println!("{:width$}  {:7}  {:>6}  {:>7}  {:016x}{}", path.display().to_string(), outcome.status.name(),
+            outcome.frames, outcome.unknown_opcodes, outcome.screen, detail, width = width);
+    }
+
+    let crashed = outcomes.iter().filter(|outcome| matches!(outcome.status, Status::Crashed(_))).count();
+    if crashed > 0 {
+        return Err(format!("{} of {} ROMs crashed", crashed, outcomes.len()));
+    }
+    Ok(())
+}
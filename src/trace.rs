@@ -0,0 +1,49 @@
+// instrumentation for the core: with the `tracing` feature, the CPU's
+// output goes out as tracing events and its work is split into spans
+// (frame, step, draw) that profilers like tracing-flame can show; without
+// it, the events are printed as they always were and spans cost nothing
+
+// something is probably wrong with the ROM, eg. an unknown instruction
+#[cfg(feature = "tracing")]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { tracing::warn!($($arg)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { println!("Warning: {}", format_args!($($arg)*)) };
+}
+
+// something the user would want to know about, eg. a breakpoint
+#[cfg(feature = "tracing")]
+macro_rules! log_info {
+    ($($arg:tt)*) => { tracing::info!($($arg)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_info {
+    ($($arg:tt)*) => { println!($($arg)*) };
+}
+
+// the blow-by-blow account: every instruction, every key
+#[cfg(feature = "tracing")]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { tracing::trace!($($arg)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { println!($($arg)*) };
+}
+
+// a span named name, lasting until the end of the enclosing block
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:literal) => { let _span = tracing::trace_span!($name).entered(); };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($name:literal) => {};
+}
+
+pub(crate) use log_info;
+pub(crate) use log_trace;
+pub(crate) use log_warn;
+pub(crate) use span;
@@ -12,7 +12,7 @@ pub fn get_hex_digits(n: &u16, d: u32, o: u32) -> usize {
 // check if nth bit of a byte is set,
 // zero-indexed, least significant first
 pub fn is_bit_set(byte: &u8, n: u8) -> bool {
-    if byte & (1 << n) == 0 { false } else { true }
+    byte & (1 << n) != 0
 }
 
 // return nth bit of a byte, zero-indexed, 
@@ -20,3 +20,14 @@ pub fn is_bit_set(byte: &u8, n: u8) -> bool {
 pub fn get_bit(byte: &u8, n: u8) -> u8 {
     if is_bit_set(byte, n) { 1 } else { 0 }
 }
+
+// FNV-1a hash of a sequence of bytes; not cryptographic,
+// just a cheap way to tell ROMs and screens apart
+pub fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
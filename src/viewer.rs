@@ -0,0 +1,129 @@
+// windows for looking at memory: browsing it as sprites, to find the
+// graphics in a ROM, and a live map of all of it as the game runs
+
+use std::time::Duration;
+
+use minifb::{
+    Key,
+    KeyRepeat,
+    Scale,
+    WindowOptions
+};
+
+use chip8_rust::cpu::CPU;
+use chip8_rust::hooks::Hooks;
+use chip8_rust::rammap::{
+    ram_map,
+    MAP_HEIGHT,
+    MAP_WIDTH
+};
+use chip8_rust::sprites::{
+    sprite_sheet,
+    SHEET_GAP
+};
+
+use crate::window::{
+    PX_OFF,
+    PX_ON
+};
+
+// sprites across and pixels down the sheet
+const COLUMNS: usize = 16;
+const SHEET_HEIGHT: usize = 64;
+
+// show ram as sprites of an adjustable height until the window is closed:
+// up/down scroll a row of sprites, page up/down a screenful, left/right a
+// single byte, +/- change the sprite height
+pub fn view_sprites(ram: &[u8], start: usize) -> Result<(), String> {
+    let width = COLUMNS * (8 + SHEET_GAP);
+    let mut win = minifb::Window::new(
+        "chip8-rust sprite viewer",
+        width,
+        SHEET_HEIGHT,
+        WindowOptions {
+            scale: Scale::X4,
+            ..WindowOptions::default()
+        }
+    ).map_err(|err| format!("Could not initialize window: {}", err))?;
+    win.limit_update_rate(Some(Duration::from_millis(16)));
+
+    let mut start = start;
+    let mut height = 5;
+    while win.is_open() && !win.is_key_down(Key::Escape) {
+        let rows = SHEET_HEIGHT / (height + SHEET_GAP);
+        let row_bytes = COLUMNS * height;
+        for key in win.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Down => start += row_bytes,
+                Key::Up => start = start.saturating_sub(row_bytes),
+                Key::PageDown => start += rows * row_bytes,
+                Key::PageUp => start = start.saturating_sub(rows * row_bytes),
+                Key::Right => start += 1,
+                Key::Left => start = start.saturating_sub(1),
+                Key::Equal | Key::NumPadPlus if height < 15 => height += 1,
+                Key::Minus | Key::NumPadMinus if height > 1 => height -= 1,
+                _ => ()
+            }
+        }
+        start = start.min(ram.len() - 1);
+
+        let rows = SHEET_HEIGHT / (height + SHEET_GAP);
+        // the sheet is as wide as the window, and no taller
+        let (pixels, _, _) = sprite_sheet(ram, start, height, COLUMNS, rows);
+        let mut buffer = vec![PX_OFF; width * SHEET_HEIGHT];
+        for (px, &on) in buffer.iter_mut().zip(&pixels) {
+            if on {
+                *px = PX_ON;
+            }
+        }
+        let end = (start + COLUMNS * rows * height - 1).min(ram.len() - 1);
+        win.set_title(&format!("chip8-rust sprite viewer: {:03x}-{:03x}, height {}", start, end, height));
+        win.update_with_buffer(&buffer, width, SHEET_HEIGHT).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+// frames between redraws of the RAM map, so it's shown 60 times a second
+const MAP_FRAMES: u32 = 8;
+
+// a second window showing RAM as a picture while the game runs, see rammap;
+// closing it leaves the game running
+pub struct RamView {
+    win: Option<minifb::Window>,
+    frames: u32
+}
+
+impl RamView {
+    pub fn new() -> Result<RamView, String> {
+        let mut win = minifb::Window::new(
+            "chip8-rust RAM",
+            MAP_WIDTH,
+            MAP_HEIGHT,
+            WindowOptions {
+                scale: Scale::X8,
+                ..WindowOptions::default()
+            }
+        ).map_err(|err| format!("Could not initialize window: {}", err))?;
+        // the game's window keeps time
+        win.limit_update_rate(None);
+        Ok(RamView { win: Some(win), frames: 0 })
+    }
+}
+
+impl Hooks for RamView {
+    fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames += 1;
+        if self.frames < MAP_FRAMES {
+            return Ok(());
+        }
+        self.frames = 0;
+        if !self.win.as_ref().is_some_and(|win| win.is_open()) {
+            self.win = None;
+            return Ok(());
+        }
+        if let Some(win) = &mut self.win {
+            win.update_with_buffer(&ram_map(cpu), MAP_WIDTH, MAP_HEIGHT).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -1,30 +1,153 @@
+use std::cell::RefCell;
+use std::env;
+use std::rc::Rc;
+use std::time::{
+    Duration,
+    Instant
+};
+
 use minifb::{
+    InputCallback,
     Key,
+    KeyRepeat,
     WindowOptions,
     Scale,
+    ScaleMode,
     Error
 };
 
-use crate::util::is_bit_set;
+use chip8_rust::framebuffer::{
+    scale_pixels,
+    Framebuffer,
+    WIDTH,
+    HEIGHT
+};
+use chip8_rust::frontend::{
+    Display,
+    Hotkey,
+    Input,
+    Status,
+    REFRESH_INTERVAL,
+    SCALES
+};
+use chip8_rust::overlay;
+use chip8_rust::pacing::Pacer;
+
+// how big a CHIP-8 pixel is at a desktop scale of 1
+const BASE_SCALE: usize = 8;
+
+// frames in a row we may leave undrawn when running behind, so a slow host
+// still shows 60 of the 480 a second rather than slowing the game down
+const MAX_SKIPPED_FRAMES: u32 = 7;
+// while idle, how often to look for input when there's nothing new to show,
+// in frames (60 times a second), rather than on every one
+const IDLE_POLL_FRAMES: u32 = 8;
+
+pub const PX_OFF: u32 = 0x81c784;
+pub const PX_ON: u32 = 0x29302a;
+// overlay text, in a colour the game can't draw
+const PX_TEXT: u32 = 0xf8f8f0;
+// the colours the pause menu cycles through, starting with the default
+// green: off, then on in XO-CHIP's plane 1, plane 2 and both planes (a
+// program that never picks a plane only uses the first two)
+const PALETTES: [[u32; 4]; 4] = [
+    [PX_OFF, PX_ON, 0x4e7a50, 0xd4ecc2],
+    [0x000000, 0xffffff, 0xff5555, 0x5555ff],
+    [0x1a0f00, 0xffb000, 0xff6600, 0x662200],
+    [0x001a10, 0x33ff66, 0x119944, 0xccffdd]
+];
+
+// collects what's typed, for the command palette
+struct Typed(Rc<RefCell<Vec<char>>>);
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-const PX_OFF: u32 = 0x81c784;
-const PX_ON: u32 = 0x29302a;
+impl InputCallback for Typed {
+    fn add_char(&mut self, c: u32) {
+        if let Some(c) = char::from_u32(c).filter(|c| c.is_ascii_graphic() || *c == ' ') {
+            self.0.borrow_mut().push(c);
+        }
+    }
+}
 
 pub struct Window {
     win: minifb::Window,
-    framebuffer: [u32; WIDTH * HEIGHT]
+    // sized to the framebuffer, which a program can switch to another
+    // resolution; minifb fits it to the window keeping its shape, so a
+    // 64x64 screen shows square in the middle
+    buffer: Vec<u32>,
+    // pixels of overlay text, if there is any
+    overlay: Option<Vec<bool>>,
+    // the overlay changed, so the screen needs redrawing
+    overlay_changed: bool,
+    // the screen changed during frames skipped to catch up, so the next
+    // one drawn has to show it
+    changed_while_skipping: bool,
+    // frames skipped in a row, and in the last second
+    skipped: u32,
+    skipped_recently: u32,
+    // which of PALETTES the screen is drawn in
+    palette: usize,
+    // whether the window had focus at the last refresh
+    focused: bool,
+    // what the title starts with, eg. the ROM's name
+    name: String,
+    title: String,
+    status: Status,
+    // percentage of full speed over the last second, and the frames shown
+    // since it was measured
+    speed: u32,
+    frames: u32,
+    measured: Instant,
+    started: Instant,
+    // what paces the CPU
+    pacer: Pacer,
+    // characters typed since the last frame
+    typed: Rc<RefCell<Vec<char>>>
+}
+
+// the desktop's scale factor, as Linux desktops tell toolkits about it
+// (eg. GDK_SCALE=2 on a 4K monitor); macOS and Windows scale windows of
+// programs like us themselves, so it's 1 there
+fn desktop_scale() -> f64 {
+    ["GDK_SCALE", "QT_SCALE_FACTOR", "ELM_SCALE"].iter()
+        .filter_map(|var| env::var(var).ok()?.parse::<f64>().ok())
+        .find(|factor| *factor > 0.0)
+        .unwrap_or(1.0)
+}
+
+// the window scale closest to BASE_SCALE at the desktop's scale factor
+pub fn auto_scale() -> usize {
+    let wanted = (BASE_SCALE as f64 * desktop_scale()).log2();
+    let distance = |scale: &usize| ((*scale as f64).log2() - wanted).abs();
+    SCALES.iter().copied()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(BASE_SCALE)
 }
 
 impl Window {
+    // a window sized for the desktop, see auto_scale
     pub fn new(title: &str) -> Result<Window, Error> {
+        Window::with_scale(title, auto_scale())
+    }
+
+    // a window scale screen pixels to a CHIP-8 pixel, one of SCALES; it
+    // can be resized from there
+    pub fn with_scale(title: &str, scale: usize) -> Result<Window, Error> {
+        let scale = match scale {
+            1 => Scale::X1,
+            2 => Scale::X2,
+            4 => Scale::X4,
+            16 => Scale::X16,
+            32 => Scale::X32,
+            _ => Scale::X8
+        };
         let mut win = match minifb::Window::new(
             title,
             WIDTH,
             HEIGHT,
             WindowOptions {
-                scale: Scale::X8,
+                scale,
+                resize: true,
+                scale_mode: ScaleMode::AspectRatioStretch,
                 ..WindowOptions::default()
             }
         ) {
@@ -33,12 +156,149 @@ impl Window {
                 return Err(err);
             }
         };
-        // 480 Hz
-        win.limit_update_rate(Some(std::time::Duration::from_micros(2083)));
-        Ok(Window { win, framebuffer: [PX_OFF; WIDTH * HEIGHT] })
+        // we keep time ourselves, minifb's limiter sleeps too coarsely
+        win.limit_update_rate(None);
+        let typed = Rc::new(RefCell::new(Vec::new()));
+        win.set_input_callback(Box::new(Typed(typed.clone())));
+        Ok(Window {
+            win,
+            buffer: vec![PX_OFF; WIDTH * HEIGHT],
+            overlay: None,
+            overlay_changed: false,
+            changed_while_skipping: false,
+            skipped: 0,
+            skipped_recently: 0,
+            palette: 0,
+            focused: true,
+            name: String::from(title),
+            title: String::from(title),
+            status: Status::default(),
+            speed: 100,
+            frames: 0,
+            measured: Instant::now(),
+            started: Instant::now(),
+            pacer: Pacer::new(REFRESH_INTERVAL),
+            typed
+        })
     }
+}
 
-    pub fn handle_key_events(&self) -> [bool; 16] {
+impl Window {
+    // eg. "chip8-rust: pong.ch8 - 100% - 72 IPF - paused", set only when it changes
+    fn update_title(&mut self) {
+        let mut title = format!("{} - {}% - {} IPF", self.name, self.speed, self.status.speed);
+        if self.skipped_recently > 0 {
+            title += " - skipping frames";
+        }
+        if self.status.paused {
+            title += " - paused";
+        }
+        if self.status.recording {
+            title += " - recording";
+        }
+        if title != self.title {
+            self.win.set_title(&title);
+            self.title = title;
+        }
+    }
+}
+
+impl Display for Window {
+    fn refresh(&mut self, framebuffer: &Framebuffer) -> Result<(), String> {
+        let on_time = self.pacer.wait();
+        self.frames += 1;
+        let since = self.measured.elapsed();
+        if since >= Duration::from_secs(1) {
+            self.speed = ((REFRESH_INTERVAL * self.frames).as_micros() * 100 / since.as_micros()) as u32;
+            self.frames = 0;
+            self.measured = Instant::now();
+            self.update_title();
+            self.skipped_recently = 0;
+        }
+        // behind, so leave the window alone this time and let the CPU catch
+        // up; the timers run on real time either way
+        if !on_time && self.skipped < MAX_SKIPPED_FRAMES {
+            self.skipped += 1;
+            self.skipped_recently += 1;
+            self.changed_while_skipping |= framebuffer.is_dirty();
+            return Ok(());
+        }
+        self.skipped = 0;
+        self.focused = self.win.is_active();
+        // nothing new to show, but keep up with input
+        if !framebuffer.is_dirty() && !self.overlay_changed && !self.changed_while_skipping {
+            if !self.status.idle || self.frames.is_multiple_of(IDLE_POLL_FRAMES) {
+                self.win.update();
+            }
+            return Ok(());
+        }
+        self.overlay_changed = false;
+        self.changed_while_skipping = false;
+        let palette = PALETTES[self.palette];
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+        self.buffer.resize(width * height, palette[0]);
+        match (framebuffer.mega_screen(), framebuffer.colour_zones()) {
+            // MegaChip programs bring their own colours
+            (Some(mega), _) => {
+                for (px, colour) in self.buffer.iter_mut().zip(mega.shown()) {
+                    *px = *colour;
+                }
+            },
+            // and so does CHIP-8X
+            (None, Some(zones)) => {
+                for (j, (px, lit)) in self.buffer.iter_mut().zip(framebuffer.pixels()).enumerate() {
+                    *px = zones.rgb(j % width, j / width, *lit);
+                }
+            },
+            (None, None) => {
+                for (px, colour) in self.buffer.iter_mut().zip(framebuffer.colours()) {
+                    *px = palette[*colour as usize];
+                }
+            }
+        }
+        // the overlay is drawn at low resolution, so each of its pixels
+        // covers several in high resolution
+        if let Some(overlay) = &self.overlay {
+            for (px, text) in self.buffer.iter_mut().zip(scale_pixels(overlay, WIDTH, width, height)) {
+                if text {
+                    *px = PX_TEXT;
+                }
+            }
+        }
+        self.win.update_with_buffer(&self.buffer, width, height)
+            .map_err(|err| format!("Could not update the window: {}", err))
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn set_status(&mut self, status: Status) {
+        if status != self.status {
+            self.pacer.set_idle(status.idle);
+            self.status = status;
+            self.update_title();
+        }
+    }
+
+    fn set_overlay(&mut self, lines: &[String]) {
+        let overlay = if lines.is_empty() { None } else { Some(overlay::render(lines)) };
+        self.overlay_changed |= overlay != self.overlay;
+        self.overlay = overlay;
+    }
+
+    fn next_palette(&mut self) {
+        self.palette = (self.palette + 1) % PALETTES.len();
+        self.overlay_changed = true;
+    }
+}
+
+impl Input for Window {
+    fn is_open(&self) -> bool {
+        self.win.is_open() && !self.win.is_key_down(Key::Escape)
+    }
+
+    fn handle_key_events(&self) -> [bool; 16] {
         let mut keys = [false; 16];
         self.win.get_keys().iter().for_each(|k| {
             match k {
@@ -64,40 +324,70 @@ impl Window {
         keys
     }
 
-    pub fn is_key_down(&self, key: Key) -> bool {
-        self.win.is_key_down(key)
-    }
-
-    pub fn is_open(&self) -> bool {
-        self.win.is_open()
+    // on the number pad: its digits, then / * - + Enter . for A to F
+    fn second_keypad(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        self.win.get_keys().iter().for_each(|k| {
+            let key = match k {
+                Key::NumPad0 => 0x0,
+                Key::NumPad1 => 0x1,
+                Key::NumPad2 => 0x2,
+                Key::NumPad3 => 0x3,
+                Key::NumPad4 => 0x4,
+                Key::NumPad5 => 0x5,
+                Key::NumPad6 => 0x6,
+                Key::NumPad7 => 0x7,
+                Key::NumPad8 => 0x8,
+                Key::NumPad9 => 0x9,
+                Key::NumPadSlash => 0xa,
+                Key::NumPadAsterisk => 0xb,
+                Key::NumPadMinus => 0xc,
+                Key::NumPadPlus => 0xd,
+                Key::NumPadEnter => 0xe,
+                Key::NumPadDot => 0xf,
+                _ => return
+            };
+            keys[key] = true;
+        });
+        keys
     }
 
-    pub fn clear_screen(&mut self) {
-        for j in 0..self.framebuffer.len() {
-            self.framebuffer[j] = PX_OFF;
-        }
+    fn focused(&self) -> bool {
+        self.focused
     }
 
-    pub fn draw(&mut self, bytes: &Vec<u8>, init_x: u8, init_y: u8) -> u8 {
-        let mut collision: u8 = 0;
-        for (k, b) in bytes.iter().enumerate() {
-            for j in 0..8 {
-                let x = (init_x as usize + j) % WIDTH;
-                let y = (init_y as usize + k) % HEIGHT;
-                let coord = (y * WIDTH) + x;
-                let is_old_set = self.framebuffer[coord] == PX_ON;
-                // xor pixels bits only if they are set
-                // if existing bit erased then set collision bit to true
-                self.framebuffer[coord] = if is_bit_set(b, (8-j-1) as u8) {
-                    if is_old_set { collision = 1; PX_OFF }
-                    else { PX_ON }
-                } else { self.framebuffer[coord] };
-            }
+    fn hotkeys(&self) -> Vec<Hotkey> {
+        let ctrl = self.win.is_key_down(Key::LeftCtrl) || self.win.is_key_down(Key::RightCtrl);
+        let shift = self.win.is_key_down(Key::LeftShift) || self.win.is_key_down(Key::RightShift);
+        let slot = |n| if shift { Hotkey::SaveSlot(n) } else { Hotkey::LoadSlot(n) };
+        let typed: Vec<char> = self.typed.borrow_mut().drain(..).collect();
+        let mut keys: Vec<Hotkey> = self.win.get_keys_pressed(KeyRepeat::No).iter().filter_map(|k| match k {
+            Key::F1 => Some(slot(1)),
+            Key::F2 => Some(slot(2)),
+            Key::F3 => Some(slot(3)),
+            Key::F4 => Some(slot(4)),
+            Key::F5 => Some(slot(5)),
+            Key::F6 => Some(slot(6)),
+            Key::F7 => Some(slot(7)),
+            Key::F8 => Some(slot(8)),
+            Key::F9 => Some(slot(9)),
+            Key::F10 => Some(slot(10)),
+            Key::F12 => Some(Hotkey::DumpMemory),
+            Key::Equal => Some(Hotkey::Faster),
+            Key::Minus => Some(Hotkey::Slower),
+            Key::P if ctrl => Some(Hotkey::Palette),
+            Key::P => Some(Hotkey::Menu),
+            Key::Up => Some(Hotkey::MenuUp),
+            Key::Down => Some(Hotkey::MenuDown),
+            Key::Enter => Some(Hotkey::MenuSelect),
+            Key::Backspace => Some(Hotkey::Backspace),
+            Key::Tab => Some(Hotkey::Complete),
+            _ => None
+        }).collect();
+        // the p of ctrl+p isn't meant as typing
+        if !ctrl {
+            keys.extend(typed.into_iter().map(Hotkey::Type));
         }
-        collision
-    }
-
-    pub fn refresh(&mut self) {
-        self.win.update_with_buffer(&self.framebuffer, WIDTH, HEIGHT).unwrap();
+        keys
     }
 }